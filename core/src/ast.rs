@@ -1,6 +1,7 @@
 //! Abstract Syntax Tree definitions for Susumu language
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Program {
@@ -14,17 +15,68 @@ pub enum Statement {
     FunctionDef(FunctionDef),
 }
 
+/// Location of a node in the source text. Lines and columns are 1-based and the end
+/// position is exclusive; nodes created outside the parser have an unknown span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, end_line: usize, end_column: usize) -> Self {
+        Self {
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+
+    /// Span running from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        if self.is_unknown() {
+            return other;
+        }
+        if other.is_unknown() {
+            return self;
+        }
+        Span {
+            end_line: other.end_line,
+            end_column: other.end_column,
+            ..self
+        }
+    }
+
+    /// True for nodes that were not parsed from source
+    pub fn is_unknown(&self) -> bool {
+        self.line == 0
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionDef {
     pub name: String,
     pub params: Vec<FunctionParam>,
     pub return_type: Option<ReturnType>,
     pub body: Expression,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionParam {
+    /// The parameter's name, or the source of its pattern when it is destructured
     pub name: String,
+    /// `(a, b)` or `{name, age}`: binds the parts of the argument instead of a name
+    pub pattern: Option<Pattern>,
     pub type_annotation: Option<TypeAnnotation>,
 }
 
@@ -41,11 +93,21 @@ pub enum TypeAnnotation {
     Union(Vec<TypeAnnotation>),           // string | number | null
 }
 
+/// An expression node together with the source range it was parsed from
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Expression {
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExpressionKind {
     // Literals
-    Number(f64),
+    Integer(i64),
+    Number(f64), // Literals with a fractional part
     String(String),
+    /// `"Hello {user.name}"`: text with embedded expressions, concatenated as displayed
+    InterpolatedString(Vec<StringPart>),
     Boolean(bool),
     Null,
 
@@ -75,6 +137,12 @@ pub enum Expression {
         args: Vec<Expression>,
     },
 
+    // Anonymous function literal: (x, y) => { body }
+    Lambda {
+        params: Vec<FunctionParam>,
+        body: Box<Expression>,
+    },
+
     // Control flow
     Conditional {
         condition_type: ConditionType,
@@ -90,16 +158,30 @@ pub enum Expression {
     Success(Box<Expression>),
     ErrorReturn(Box<Expression>),
 
-    // Iteration
+    // Iteration. A loop labelled `outer: fe ...` can be left with `break outer`.
     ForEach {
-        variable: String,
+        label: Option<String>,
+        pattern: Pattern,
         iterable: Box<Expression>,
         body: Box<Expression>,
     },
     While {
+        label: Option<String>,
         condition: Box<Expression>,
         body: Box<Expression>,
     },
+    /// `break`, `break outer` or `break <- value`: leave a loop, which then evaluates
+    /// to `value` when one is given
+    Break {
+        label: Option<String>,
+        value: Option<Box<Expression>>,
+    },
+    /// `continue` skips to the next iteration; `continue <- value` makes `value` the
+    /// iteration's result
+    Continue {
+        label: Option<String>,
+        value: Option<Box<Expression>>,
+    },
 
     // Blocks
     Block(Vec<Expression>),
@@ -125,11 +207,33 @@ pub enum Expression {
         value: Box<Expression>,
         mutable: bool,
     },
+    /// `(a, b) = pair` or `{name, age} = user`: binds every name in the pattern
+    Destructure {
+        pattern: Pattern,
+        value: Box<Expression>,
+        mutable: bool,
+    },
 
-    // Property access
+    // Property access: obj.name, or obj?.name which is null when obj is null or none
     PropertyAccess {
         object: Box<Expression>,
         property: String,
+        optional: bool,
+    },
+
+    // Index access: xs[0], xs[-1], obj["key"], xs?[0]
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
+        optional: bool,
+    },
+
+    // Slice: xs[1:3], with either bound left out
+    Slice {
+        object: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        optional: bool,
     },
 
     // Binary operations
@@ -139,6 +243,12 @@ pub enum Expression {
         right: Box<Expression>,
     },
 
+    // Prefix operations: -x, !x
+    UnaryOp {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
+
     // Annotations
     Annotated {
         annotation: Annotation,
@@ -188,6 +298,12 @@ pub enum Pattern {
     },
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StringPart {
+    Text(String),
+    Expression(Expression),
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LiteralValue {
     Number(f64),
@@ -202,12 +318,23 @@ pub enum BinaryOperator {
     Subtract,  // -
     Multiply,  // *
     Divide,    // /
+    Modulo,    // %
+    Power,     // **
+    Range,     // .. (integers from the left up to, not including, the right)
     Equal,     // ==
     NotEqual,  // !=
     Less,      // <
     Greater,   // >
     LessEq,    // <=
     GreaterEq, // >=
+    And,       // && (short-circuiting)
+    Or,        // || (short-circuiting)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    Negate, // -
+    Not,    // !
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -243,13 +370,14 @@ impl Program {
 }
 
 impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
     /// Create a simple arrow chain with forward direction
     pub fn arrow_chain(expressions: Vec<Expression>) -> Self {
         let directions = vec![ArrowDirection::Forward; expressions.len().saturating_sub(1)];
-        Self::ArrowChain {
-            expressions,
-            directions,
-        }
+        Self::convergence_chain(expressions, directions)
     }
 
     /// Create an arrow chain with mixed directions for convergence
@@ -257,10 +385,17 @@ impl Expression {
         expressions: Vec<Expression>,
         directions: Vec<ArrowDirection>,
     ) -> Self {
-        Self::ArrowChain {
-            expressions,
-            directions,
-        }
+        let span = match (expressions.first(), expressions.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => Span::default(),
+        };
+        Self::new(
+            ExpressionKind::ArrowChain {
+                expressions,
+                directions,
+            },
+            span,
+        )
     }
 
     /// Create a success/error conditional
@@ -269,13 +404,17 @@ impl Expression {
         then_branch: Expression,
         else_branch: Option<Expression>,
     ) -> Self {
-        Self::Conditional {
-            condition_type: ConditionType::Success,
-            condition: Box::new(condition),
-            then_branch: Box::new(then_branch),
-            else_if_branches: Vec::new(),
-            else_branch: else_branch.map(Box::new),
-        }
+        let span = condition.span;
+        Self::new(
+            ExpressionKind::Conditional {
+                condition_type: ConditionType::Success,
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_if_branches: Vec::new(),
+                else_branch: else_branch.map(Box::new),
+            },
+            span,
+        )
     }
 
     /// Create a custom conditional (e.g., i customCheck { ... } e { ... })
@@ -285,40 +424,52 @@ impl Expression {
         then_branch: Expression,
         else_branch: Option<Expression>,
     ) -> Self {
-        Self::Conditional {
-            condition_type: ConditionType::Custom(condition_name),
-            condition: Box::new(condition),
-            then_branch: Box::new(then_branch),
-            else_if_branches: Vec::new(),
-            else_branch: else_branch.map(Box::new),
-        }
+        let span = condition.span;
+        Self::new(
+            ExpressionKind::Conditional {
+                condition_type: ConditionType::Custom(condition_name),
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_if_branches: Vec::new(),
+                else_branch: else_branch.map(Box::new),
+            },
+            span,
+        )
     }
 
     /// Check if this expression is a literal value
     pub fn is_literal(&self) -> bool {
         matches!(
-            self,
-            Expression::Number(_)
-                | Expression::String(_)
-                | Expression::Boolean(_)
-                | Expression::Null
+            self.kind,
+            ExpressionKind::Integer(_)
+                | ExpressionKind::Number(_)
+                | ExpressionKind::String(_)
+                | ExpressionKind::Boolean(_)
+                | ExpressionKind::Null
         )
     }
 
     /// Check if this expression is an identifier
     pub fn is_identifier(&self) -> bool {
-        matches!(self, Expression::Identifier(_))
+        matches!(self.kind, ExpressionKind::Identifier(_))
     }
 
     /// Extract identifier name if this is an identifier
     pub fn as_identifier(&self) -> Option<&str> {
-        match self {
-            Expression::Identifier(name) => Some(name),
+        match &self.kind {
+            ExpressionKind::Identifier(name) => Some(name),
             _ => None,
         }
     }
 }
 
+/// Expressions built outside the parser, e.g. by embedders or desugaring, have no location
+impl From<ExpressionKind> for Expression {
+    fn from(kind: ExpressionKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Annotation {
     Trace(String),             // @trace <- "payment-flow"
@@ -326,6 +477,7 @@ pub enum Annotation {
     Config(serde_json::Value), // @config <- {trace: "payment-flow", timeout: "30s"}
     Parallel(Option<usize>),   // @parallel or @parallel <- 4 (number of cores)
     Debug(Option<String>),     // @debug or @debug <- "checkpoint"
+    Test(Option<String>),      // @test or @test <- "adds two numbers"
 }
 
 impl Default for Program {
@@ -0,0 +1,10 @@
+//! Susumu debug adapter binary
+//!
+//! Lets editors debug Susumu programs through the Debug Adapter Protocol over stdio
+
+fn main() {
+    if let Err(err) = susumu::dap::run_dap_server() {
+        eprintln!("susumu-dap: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -1,15 +1,31 @@
 //! Built-in functions for Susumu language
 
 use crate::error::{SusumuError, SusumuResult};
-use serde_json::{json, Value};
+use crate::native::{CallContext, NativeFunction};
+use crate::permissions::Capability;
+use crate::value::{Map, NumericOp, Value};
+use serde_json::json;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub type BuiltinFunction = fn(&[Value]) -> SusumuResult<Value>;
 
+/// An HTTP builtin, given a client that only follows redirects to allowed hosts
+#[cfg(not(target_arch = "wasm32"))]
+type HttpBuiltin = fn(&reqwest::blocking::Client, &[Value]) -> SusumuResult<Value>;
+
+/// A registered builtin: either a pure function of its arguments or a native
+/// function that needs the interpreter (see [`crate::native`])
+#[derive(Clone)]
+pub enum Builtin {
+    Function(BuiltinFunction),
+    Native(NativeFunction),
+}
+
 /// Registry of all built-in functions
 #[derive(Clone)]
 pub struct BuiltinRegistry {
-    functions: HashMap<String, BuiltinFunction>,
+    functions: HashMap<String, Builtin>,
 }
 
 impl BuiltinRegistry {
@@ -30,15 +46,61 @@ impl BuiltinRegistry {
 
     /// Register a builtin function
     pub fn register(&mut self, name: &str, func: BuiltinFunction) {
-        self.functions.insert(name.to_string(), func);
+        self.functions
+            .insert(name.to_string(), Builtin::Function(func));
     }
 
-    /// Call a builtin function
-    pub fn call(&self, name: &str, args: &[Value]) -> SusumuResult<Value> {
-        if let Some(func) = self.functions.get(name) {
+    /// Register a native function that receives a [`CallContext`]
+    pub fn register_native<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&mut CallContext<'_>, &[Value]) -> SusumuResult<Value> + Send + Sync + 'static,
+    {
+        self.functions
+            .insert(name.to_string(), Builtin::Native(Arc::new(func)));
+    }
+
+    /// Register a builtin that needs `capability`. The interpreter's permissions are
+    /// checked against the call's arguments before `func` runs.
+    pub fn register_guarded(&mut self, name: &str, capability: Capability, func: BuiltinFunction) {
+        self.register_native(name, move |ctx, args| {
+            ctx.permissions()
+                .check(capability, ctx.function_name(), args)?;
             func(args)
-        } else {
-            Err(SusumuError::undefined_function(name))
+        });
+    }
+
+    /// Register an HTTP builtin. Like [`register_guarded`](Self::register_guarded) its
+    /// URLs are checked against the net permissions, and so is every redirect it follows.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn register_http(&mut self, name: &str, func: HttpBuiltin) {
+        self.register_native(name, move |ctx, args| {
+            let permissions = ctx.permissions();
+            permissions.check(Capability::Net, ctx.function_name(), args)?;
+            let client = reqwest::blocking::Client::builder()
+                .redirect(permissions.redirect_policy())
+                .build()
+                .map_err(|e| {
+                    SusumuError::io_error(format!("Failed to create HTTP client: {}", e))
+                })?;
+            func(&client, args)
+        });
+    }
+
+    /// Look up a registered builtin
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.functions.get(name)
+    }
+
+    /// Call a builtin function. Native functions need an interpreter and are
+    /// called through it instead.
+    pub fn call(&self, name: &str, args: &[Value]) -> SusumuResult<Value> {
+        match self.functions.get(name) {
+            Some(Builtin::Function(func)) => func(args),
+            Some(Builtin::Native(_)) => Err(SusumuError::function_call_error(format!(
+                "Native function '{}' must be called through the interpreter",
+                name
+            ))),
+            None => Err(SusumuError::undefined_function(name)),
         }
     }
 
@@ -151,32 +213,47 @@ impl BuiltinRegistry {
 
         // === CORE UTILITIES (Auto-available) ===
         self.register("length", builtin_core_length);
+        self.register("entries", builtin_core_entries);
         self.register("type", builtin_core_type);
         self.register("equals", builtin_core_equals);
+        self.register("exit", builtin_core_exit);
 
         // === CORE I/O FUNCTIONS (Auto-available for productivity) ===
         #[cfg(not(target_arch = "wasm32"))]
         {
-            self.register("readFile", builtin_core_read_file);
-            self.register("writeFile", builtin_core_write_file);
-            self.register("appendFile", builtin_core_append_file);
-            self.register("fileExists", builtin_core_file_exists);
-            self.register("fileInfo", builtin_core_file_info);
-            self.register("listDir", builtin_core_list_dir);
+            use Capability::{Read, Write};
+            self.register_guarded("readFile", Read, builtin_core_read_file);
+            self.register_guarded("writeFile", Write, builtin_core_write_file);
+            self.register_guarded("appendFile", Write, builtin_core_append_file);
+            self.register_guarded("fileExists", Read, builtin_core_file_exists);
+            self.register_guarded("fileInfo", Read, builtin_core_file_info);
+            self.register_guarded("listDir", Read, builtin_core_list_dir);
         }
+        self.register_guarded("getEnv", Capability::Env, builtin_core_get_env);
 
         // === CORE JSON/DATA PROCESSING (Auto-available) ===
         self.register("parseJSON", builtin_core_parse_json);
         self.register("toJSON", builtin_core_to_json);
 
-        // === CORE ARRAY PROCESSING (Auto-available) ===
-        self.register("filter", builtin_core_filter);
-        self.register("map", builtin_core_map);
-        self.register("reduce", builtin_core_reduce);
+        // === HIGHER-ORDER ARRAY FUNCTIONS (Call back into Susumu functions) ===
+        self.register_native("map", native_map);
+        self.register_native("filter", native_filter);
+        self.register_native("reduce", native_reduce);
+        self.register_native("sortBy", native_sort_by);
+        self.register_native("find", native_find);
+        self.register_native("any", native_any);
+        self.register_native("all", native_all);
+        self.register_native("flatMap", native_flat_map);
+        self.register_native("groupBy", native_group_by);
+
+        // === TEST ASSERTIONS (Auto-available, used by `susumu test`) ===
+        self.register("assertEqual", builtin_assert_equal);
+        self.register_native("assertError", native_assert_error);
+        self.register_native("assertMatches", native_assert_matches);
 
         // === CORE DATE/TIME FUNCTIONS (Auto-available) ===
-        self.register("now", builtin_core_now);
-        self.register("nowMillis", builtin_core_now_millis);
+        self.register_guarded("now", Capability::Clock, builtin_core_now);
+        self.register_guarded("nowMillis", Capability::Clock, builtin_core_now_millis);
         self.register("formatDate", builtin_core_format_date);
         self.register("parseDate", builtin_core_parse_date);
         self.register("addTime", builtin_core_add_time);
@@ -190,7 +267,7 @@ impl BuiltinRegistry {
 
         // String module implementations
         self.register("split_string", builtin_split_string);
-        self.register("join_array", builtin_join_array);
+        self.register_native("join_array", native_join_array);
         self.register("to_title_case", builtin_to_title_case);
 
         // Core math functions
@@ -206,16 +283,20 @@ impl BuiltinRegistry {
         // === CORE HTTP CLIENT (Auto-available) ===
         #[cfg(not(target_arch = "wasm32"))]
         {
-            self.register("httpGet", builtin_core_http_get);
-            self.register("httpPost", builtin_core_http_post);
-            self.register("httpRequest", builtin_core_http_request);
-            self.register("httpGetParallel", builtin_core_http_get_parallel);
-            self.register("httpPostParallel", builtin_core_http_post_parallel);
+            self.register_http("httpGet", builtin_core_http_get);
+            self.register_http("httpPost", builtin_core_http_post);
+            self.register_http("httpRequest", builtin_core_http_request);
+            self.register_http("httpGetParallel", builtin_core_http_get_parallel);
+            self.register_http("httpPostParallel", builtin_core_http_post_parallel);
         }
 
         // === PARALLEL OPERATIONS (Auto-available for performance) ===
         #[cfg(not(target_arch = "wasm32"))]
-        self.register("readFilesParallel", builtin_core_read_files_parallel);
+        self.register_guarded(
+            "readFilesParallel",
+            Capability::Read,
+            builtin_core_read_files_parallel,
+        );
         self.register("mapParallel", builtin_core_map_parallel);
 
         // === STDLIB SUPPORT FUNCTIONS (Auto-available for modules) ===
@@ -239,7 +320,7 @@ impl Default for BuiltinRegistry {
     }
 }
 
-/// Helper function to create JSON numbers that preserve integer type when appropriate
+/// Helper function to create numbers that preserve integer type when appropriate
 fn create_number_value(value: f64) -> Value {
     if value.fract() == 0.0
         && value.is_finite()
@@ -247,13 +328,19 @@ fn create_number_value(value: f64) -> Value {
         && value <= i64::MAX as f64
     {
         // Return as integer if it's a whole number within i64 range
-        json!(value as i64)
+        Value::Int(value as i64)
     } else {
         // Return as float
-        json!(value)
+        Value::Float(value)
     }
 }
 
+/// Extract a numeric argument as f64 or report a type error
+fn number_arg(arg: &Value) -> SusumuResult<f64> {
+    arg.as_f64()
+        .ok_or_else(|| SusumuError::type_error("number", &format!("{:?}", arg)))
+}
+
 // Math functions
 fn builtin_add(args: &[Value]) -> SusumuResult<Value> {
     if args.is_empty() {
@@ -262,28 +349,17 @@ fn builtin_add(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let mut result = 0.0;
+    let mut result = Value::Int(0);
     for arg in args {
-        match arg {
-            Value::Number(n) => result += n.as_f64().unwrap(),
-            _ => return Err(SusumuError::type_error("number", &format!("{:?}", arg))),
-        }
+        result = result.arithmetic(NumericOp::Add, arg)?;
     }
-    Ok(create_number_value(result))
+    Ok(result)
 }
 
 fn builtin_subtract(args: &[Value]) -> SusumuResult<Value> {
     match args.len() {
-        1 => match &args[0] {
-            Value::Number(n) => Ok(create_number_value(-n.as_f64().unwrap())),
-            _ => Err(SusumuError::type_error("number", &format!("{:?}", args[0]))),
-        },
-        2 => match (&args[0], &args[1]) {
-            (Value::Number(a), Value::Number(b)) => Ok(create_number_value(
-                a.as_f64().unwrap() - b.as_f64().unwrap(),
-            )),
-            _ => Err(SusumuError::type_error("number", "non-number")),
-        },
+        1 => Value::Int(0).arithmetic(NumericOp::Subtract, &args[0]),
+        2 => args[0].arithmetic(NumericOp::Subtract, &args[1]),
         _ => Err(SusumuError::function_call_error(
             "subtract requires 1 or 2 arguments",
         )),
@@ -297,14 +373,11 @@ fn builtin_multiply(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let mut result = 1.0;
+    let mut result = Value::Int(1);
     for arg in args {
-        match arg {
-            Value::Number(n) => result *= n.as_f64().unwrap(),
-            _ => return Err(SusumuError::type_error("number", &format!("{:?}", arg))),
-        }
+        result = result.arithmetic(NumericOp::Multiply, arg)?;
     }
-    Ok(create_number_value(result))
+    Ok(result)
 }
 
 fn builtin_divide(args: &[Value]) -> SusumuResult<Value> {
@@ -314,16 +387,7 @@ fn builtin_divide(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match (&args[0], &args[1]) {
-        (Value::Number(a), Value::Number(b)) => {
-            let b_val = b.as_f64().unwrap();
-            if b_val == 0.0 {
-                return Err(SusumuError::runtime_error("Division by zero"));
-            }
-            Ok(create_number_value(a.as_f64().unwrap() / b_val))
-        }
-        _ => Err(SusumuError::type_error("number", "non-number")),
-    }
+    args[0].arithmetic(NumericOp::Divide, &args[1])
 }
 
 fn builtin_modulo(args: &[Value]) -> SusumuResult<Value> {
@@ -333,16 +397,7 @@ fn builtin_modulo(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match (&args[0], &args[1]) {
-        (Value::Number(a), Value::Number(b)) => {
-            let b_val = b.as_f64().unwrap();
-            if b_val == 0.0 {
-                return Err(SusumuError::runtime_error("Modulo by zero"));
-            }
-            Ok(create_number_value(a.as_f64().unwrap() % b_val))
-        }
-        _ => Err(SusumuError::type_error("number", "non-number")),
-    }
+    args[0].arithmetic(NumericOp::Modulo, &args[1])
 }
 
 fn builtin_power(args: &[Value]) -> SusumuResult<Value> {
@@ -352,12 +407,7 @@ fn builtin_power(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match (&args[0], &args[1]) {
-        (Value::Number(a), Value::Number(b)) => Ok(create_number_value(
-            a.as_f64().unwrap().powf(b.as_f64().unwrap()),
-        )),
-        _ => Err(SusumuError::type_error("number", "non-number")),
-    }
+    args[0].arithmetic(NumericOp::Power, &args[1])
 }
 
 fn builtin_sqrt(args: &[Value]) -> SusumuResult<Value> {
@@ -367,18 +417,13 @@ fn builtin_sqrt(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match &args[0] {
-        Value::Number(n) => {
-            let val = n.as_f64().unwrap();
-            if val < 0.0 {
-                return Err(SusumuError::runtime_error(
-                    "Cannot take square root of negative number",
-                ));
-            }
-            Ok(create_number_value(val.sqrt()))
-        }
-        _ => Err(SusumuError::type_error("number", &format!("{:?}", args[0]))),
+    let val = number_arg(&args[0])?;
+    if val < 0.0 {
+        return Err(SusumuError::runtime_error(
+            "Cannot take square root of negative number",
+        ));
     }
+    Ok(create_number_value(val.sqrt()))
 }
 
 fn builtin_abs(args: &[Value]) -> SusumuResult<Value> {
@@ -389,7 +434,10 @@ fn builtin_abs(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::Number(n) => Ok(create_number_value(n.as_f64().unwrap().abs())),
+        Value::Int(n) => Ok(n
+            .checked_abs()
+            .map_or(Value::Float((*n as f64).abs()), Value::Int)),
+        Value::Float(n) => Ok(Value::Float(n.abs())),
         _ => Err(SusumuError::type_error("number", &format!("{:?}", args[0]))),
     }
 }
@@ -401,19 +449,14 @@ fn builtin_min(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let mut min_val = f64::INFINITY;
+    let mut min = &args[0];
     for arg in args {
-        match arg {
-            Value::Number(n) => {
-                let val = n.as_f64().unwrap();
-                if val < min_val {
-                    min_val = val;
-                }
-            }
-            _ => return Err(SusumuError::type_error("number", &format!("{:?}", arg))),
+        number_arg(arg)?;
+        if arg < min {
+            min = arg;
         }
     }
-    Ok(json!(min_val))
+    Ok(min.clone())
 }
 
 fn builtin_max(args: &[Value]) -> SusumuResult<Value> {
@@ -423,19 +466,14 @@ fn builtin_max(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let mut max_val = f64::NEG_INFINITY;
+    let mut max = &args[0];
     for arg in args {
-        match arg {
-            Value::Number(n) => {
-                let val = n.as_f64().unwrap();
-                if val > max_val {
-                    max_val = val;
-                }
-            }
-            _ => return Err(SusumuError::type_error("number", &format!("{:?}", arg))),
+        number_arg(arg)?;
+        if arg > max {
+            max = arg;
         }
     }
-    Ok(json!(max_val))
+    Ok(max.clone())
 }
 
 fn builtin_sum(args: &[Value]) -> SusumuResult<Value> {
@@ -449,11 +487,8 @@ fn builtin_average(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let sum = builtin_add(args)?;
-    match sum {
-        Value::Number(s) => Ok(create_number_value(s.as_f64().unwrap() / args.len() as f64)),
-        _ => Err(SusumuError::runtime_error("Sum calculation failed")),
-    }
+    let sum = number_arg(&builtin_add(args)?)?;
+    Ok(create_number_value(sum / args.len() as f64))
 }
 
 // String functions
@@ -465,7 +500,7 @@ fn builtin_concat(args: &[Value]) -> SusumuResult<Value> {
             _ => result.push_str(&arg.to_string()),
         }
     }
-    Ok(json!(result))
+    Ok(Value::from(result))
 }
 
 fn builtin_length(args: &[Value]) -> SusumuResult<Value> {
@@ -476,8 +511,8 @@ fn builtin_length(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::String(s) => Ok(json!(s.len())),
-        Value::Array(a) => Ok(json!(a.len())),
+        Value::String(s) => Ok(Value::from(s.len())),
+        Value::Array(a) => Ok(Value::from(a.len())),
         _ => Err(SusumuError::type_error(
             "string or array",
             &format!("{:?}", args[0]),
@@ -493,25 +528,25 @@ fn builtin_substring(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match (&args[0], &args[1]) {
-        (Value::String(s), Value::Number(start)) => {
-            let start_idx = start.as_f64().unwrap() as usize;
+        (Value::String(s), start) if start.is_number() => {
+            let start_idx = number_arg(start)? as usize;
             if start_idx > s.len() {
-                return Ok(json!(""));
+                return Ok(Value::from(""));
             }
 
             if args.len() == 3 {
-                match &args[2] {
-                    Value::Number(end) => {
-                        let end_idx = (end.as_f64().unwrap() as usize).min(s.len());
+                match args[2].as_f64() {
+                    Some(end) => {
+                        let end_idx = (end as usize).min(s.len());
                         if start_idx >= end_idx {
-                            return Ok(json!(""));
+                            return Ok(Value::from(""));
                         }
-                        Ok(json!(s[start_idx..end_idx].to_string()))
+                        Ok(Value::from(s[start_idx..end_idx].to_string()))
                     }
                     _ => Err(SusumuError::type_error("number", &format!("{:?}", args[2]))),
                 }
             } else {
-                Ok(json!(s[start_idx..].to_string()))
+                Ok(Value::from(s[start_idx..].to_string()))
             }
         }
         _ => Err(SusumuError::type_error("string, number", "other types")),
@@ -526,7 +561,7 @@ fn builtin_to_upper(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::String(s) => Ok(json!(s.to_uppercase())),
+        Value::String(s) => Ok(Value::from(s.to_uppercase())),
         _ => Err(SusumuError::type_error("string", &format!("{:?}", args[0]))),
     }
 }
@@ -539,7 +574,7 @@ fn builtin_to_lower(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::String(s) => Ok(json!(s.to_lowercase())),
+        Value::String(s) => Ok(Value::from(s.to_lowercase())),
         _ => Err(SusumuError::type_error("string", &format!("{:?}", args[0]))),
     }
 }
@@ -552,7 +587,7 @@ fn builtin_trim(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::String(s) => Ok(json!(s.trim())),
+        Value::String(s) => Ok(Value::from(s.trim())),
         _ => Err(SusumuError::type_error("string", &format!("{:?}", args[0]))),
     }
 }
@@ -566,7 +601,7 @@ fn builtin_split(args: &[Value]) -> SusumuResult<Value> {
 
     match (&args[0], &args[1]) {
         (Value::String(s), Value::String(delimiter)) => {
-            let parts: Vec<Value> = s.split(delimiter).map(|part| json!(part)).collect();
+            let parts: Vec<Value> = s.split(delimiter).map(Value::from).collect();
             Ok(Value::Array(parts))
         }
         _ => Err(SusumuError::type_error("string, string", "other types")),
@@ -581,7 +616,7 @@ fn builtin_contains(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match (&args[0], &args[1]) {
-        (Value::String(s), Value::String(substr)) => Ok(json!(s.contains(substr))),
+        (Value::String(s), Value::String(substr)) => Ok(Value::from(s.contains(substr))),
         _ => Err(SusumuError::type_error("string, string", "other types")),
     }
 }
@@ -707,15 +742,7 @@ fn builtin_sort(args: &[Value]) -> SusumuResult<Value> {
     match &args[0] {
         Value::Array(a) => {
             let mut sorted = a.clone();
-            sorted.sort_by(|a, b| match (a, b) {
-                (Value::Number(n1), Value::Number(n2)) => n1
-                    .as_f64()
-                    .unwrap()
-                    .partial_cmp(&n2.as_f64().unwrap())
-                    .unwrap(),
-                (Value::String(s1), Value::String(s2)) => s1.cmp(s2),
-                _ => std::cmp::Ordering::Equal,
-            });
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
             Ok(Value::Array(sorted))
         }
         _ => Err(SusumuError::type_error("array", &format!("{:?}", args[0]))),
@@ -775,15 +802,7 @@ fn builtin_type_of(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let type_name = match &args[0] {
-        Value::Null => "null",
-        Value::Bool(_) => "boolean",
-        Value::Number(_) => "number",
-        Value::String(_) => "string",
-        Value::Array(_) => "array",
-        Value::Object(_) => "object",
-    };
-    Ok(json!(type_name))
+    Ok(Value::from(args[0].type_name()))
 }
 
 fn builtin_is_null(args: &[Value]) -> SusumuResult<Value> {
@@ -792,7 +811,7 @@ fn builtin_is_null(args: &[Value]) -> SusumuResult<Value> {
             "is_null requires exactly 1 argument",
         ));
     }
-    Ok(json!(args[0] == Value::Null))
+    Ok(Value::from(args[0] == Value::Null))
 }
 
 fn builtin_is_number(args: &[Value]) -> SusumuResult<Value> {
@@ -801,7 +820,7 @@ fn builtin_is_number(args: &[Value]) -> SusumuResult<Value> {
             "is_number requires exactly 1 argument",
         ));
     }
-    Ok(json!(matches!(args[0], Value::Number(_))))
+    Ok(Value::from(args[0].is_number()))
 }
 
 fn builtin_is_string(args: &[Value]) -> SusumuResult<Value> {
@@ -810,7 +829,7 @@ fn builtin_is_string(args: &[Value]) -> SusumuResult<Value> {
             "is_string requires exactly 1 argument",
         ));
     }
-    Ok(json!(matches!(args[0], Value::String(_))))
+    Ok(Value::from(matches!(args[0], Value::String(_))))
 }
 
 fn builtin_is_boolean(args: &[Value]) -> SusumuResult<Value> {
@@ -819,7 +838,7 @@ fn builtin_is_boolean(args: &[Value]) -> SusumuResult<Value> {
             "is_boolean requires exactly 1 argument",
         ));
     }
-    Ok(json!(matches!(args[0], Value::Bool(_))))
+    Ok(Value::from(matches!(args[0], Value::Bool(_))))
 }
 
 fn builtin_is_array(args: &[Value]) -> SusumuResult<Value> {
@@ -828,7 +847,7 @@ fn builtin_is_array(args: &[Value]) -> SusumuResult<Value> {
             "is_array requires exactly 1 argument",
         ));
     }
-    Ok(json!(matches!(args[0], Value::Array(_))))
+    Ok(Value::from(matches!(args[0], Value::Array(_))))
 }
 
 fn builtin_is_object(args: &[Value]) -> SusumuResult<Value> {
@@ -837,7 +856,7 @@ fn builtin_is_object(args: &[Value]) -> SusumuResult<Value> {
             "is_object requires exactly 1 argument",
         ));
     }
-    Ok(json!(matches!(args[0], Value::Object(_))))
+    Ok(Value::from(matches!(args[0], Value::Object(_))))
 }
 
 fn builtin_to_string(args: &[Value]) -> SusumuResult<Value> {
@@ -846,7 +865,7 @@ fn builtin_to_string(args: &[Value]) -> SusumuResult<Value> {
             "to_string requires exactly 1 argument",
         ));
     }
-    Ok(json!(value_to_display_string(&args[0])))
+    Ok(Value::from(value_to_display_string(&args[0])))
 }
 
 fn builtin_to_number(args: &[Value]) -> SusumuResult<Value> {
@@ -857,9 +876,9 @@ fn builtin_to_number(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::Number(_n) => Ok(args[0].clone()),
+        Value::Int(_) | Value::Float(_) => Ok(args[0].clone()),
         Value::String(s) => match s.parse::<f64>() {
-            Ok(n) => Ok(json!(n)),
+            Ok(n) => Ok(Value::from(n)),
             Err(_) => Err(SusumuError::type_error("valid number string", s)),
         },
         _ => Err(SusumuError::type_error(
@@ -869,36 +888,9 @@ fn builtin_to_number(args: &[Value]) -> SusumuResult<Value> {
     }
 }
 
-/// Convert a JSON value to a human-readable display string
+/// Convert a value to a human-readable display string
 pub fn value_to_display_string(value: &Value) -> String {
-    match value {
-        Value::Null => "null".to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Number(n) => {
-            if n.is_f64() {
-                let f = n.as_f64().unwrap();
-                if f.fract() == 0.0 {
-                    format!("{}", f as i64)
-                } else {
-                    f.to_string()
-                }
-            } else {
-                n.to_string()
-            }
-        }
-        Value::String(s) => s.clone(),
-        Value::Array(a) => {
-            let items: Vec<String> = a.iter().map(value_to_display_string).collect();
-            format!("[{}]", items.join(", "))
-        }
-        Value::Object(o) => {
-            let pairs: Vec<String> = o
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, value_to_display_string(v)))
-                .collect();
-            format!("{{{}}}", pairs.join(", "))
-        }
-    }
+    value.to_string()
 }
 
 // Maybe/Result constructor functions
@@ -909,16 +901,11 @@ fn builtin_some(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    Ok(json!({
-        "type": "some",
-        "value": args[0].clone()
-    }))
+    Ok(Value::some(args[0].clone()))
 }
 
 fn builtin_none(_args: &[Value]) -> SusumuResult<Value> {
-    Ok(json!({
-        "type": "none"
-    }))
+    Ok(Value::none())
 }
 
 fn builtin_success(args: &[Value]) -> SusumuResult<Value> {
@@ -928,10 +915,7 @@ fn builtin_success(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    Ok(json!({
-        "type": "success",
-        "value": args[0].clone()
-    }))
+    Ok(Value::success(args[0].clone()))
 }
 
 fn builtin_error(args: &[Value]) -> SusumuResult<Value> {
@@ -941,10 +925,7 @@ fn builtin_error(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    Ok(json!({
-        "type": "error",
-        "value": args[0].clone()
-    }))
+    Ok(Value::error(args[0].clone()))
 }
 
 // Maybe/Result type check functions
@@ -955,16 +936,7 @@ fn builtin_is_some(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                Ok(json!(type_str == "some"))
-            } else {
-                Ok(json!(false))
-            }
-        }
-        _ => Ok(json!(false)),
-    }
+    Ok(Value::from(matches!(args[0], Value::Maybe(Some(_)))))
 }
 
 fn builtin_is_none(args: &[Value]) -> SusumuResult<Value> {
@@ -974,16 +946,7 @@ fn builtin_is_none(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                Ok(json!(type_str == "none"))
-            } else {
-                Ok(json!(false))
-            }
-        }
-        _ => Ok(json!(false)),
-    }
+    Ok(Value::from(matches!(args[0], Value::Maybe(None))))
 }
 
 fn builtin_is_success(args: &[Value]) -> SusumuResult<Value> {
@@ -993,16 +956,7 @@ fn builtin_is_success(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                Ok(json!(type_str == "success"))
-            } else {
-                Ok(json!(false))
-            }
-        }
-        _ => Ok(json!(false)),
-    }
+    Ok(Value::from(matches!(args[0], Value::Result(Ok(_)))))
 }
 
 fn builtin_is_error(args: &[Value]) -> SusumuResult<Value> {
@@ -1012,16 +966,7 @@ fn builtin_is_error(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                Ok(json!(type_str == "error"))
-            } else {
-                Ok(json!(false))
-            }
-        }
-        _ => Ok(json!(false)),
-    }
+    Ok(Value::from(matches!(args[0], Value::Result(Err(_)))))
 }
 
 // Value extraction functions
@@ -1033,28 +978,9 @@ fn builtin_unwrap(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                match type_str.as_str() {
-                    "some" | "success" => {
-                        if let Some(value) = obj.get("value") {
-                            Ok(value.clone())
-                        } else {
-                            Err(SusumuError::runtime_error("Cannot unwrap: missing value"))
-                        }
-                    }
-                    "none" => Err(SusumuError::runtime_error("Cannot unwrap none")),
-                    "error" => Err(SusumuError::runtime_error("Cannot unwrap error")),
-                    _ => Err(SusumuError::runtime_error(
-                        "Cannot unwrap: not a Maybe or Result type",
-                    )),
-                }
-            } else {
-                Err(SusumuError::runtime_error(
-                    "Cannot unwrap: not a Maybe or Result type",
-                ))
-            }
-        }
+        Value::Maybe(Some(value)) | Value::Result(Ok(value)) => Ok((**value).clone()),
+        Value::Maybe(None) => Err(SusumuError::runtime_error("Cannot unwrap none")),
+        Value::Result(Err(_)) => Err(SusumuError::runtime_error("Cannot unwrap error")),
         _ => Err(SusumuError::runtime_error(
             "Cannot unwrap: not a Maybe or Result type",
         )),
@@ -1069,47 +995,28 @@ fn builtin_unwrap_or(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::Object(obj) => {
-            if let Some(Value::String(type_str)) = obj.get("type") {
-                match type_str.as_str() {
-                    "some" | "success" => {
-                        if let Some(value) = obj.get("value") {
-                            Ok(value.clone())
-                        } else {
-                            Ok(args[1].clone()) // fallback
-                        }
-                    }
-                    "none" | "error" => Ok(args[1].clone()), // Return default value
-                    _ => Ok(args[1].clone()),                // Not Maybe/Result, return default
-                }
-            } else {
-                Ok(args[1].clone()) // Not Maybe/Result, return default
-            }
-        }
-        _ => Ok(args[1].clone()), // Not Maybe/Result, return default
+        Value::Maybe(Some(value)) | Value::Result(Ok(value)) => Ok((**value).clone()),
+        _ => Ok(args[1].clone()), // none, error or not Maybe/Result: return default
     }
 }
 
 /// Combine multiple values by concatenating them as strings (useful for debugging convergent flows)
 fn builtin_combine(args: &[Value]) -> SusumuResult<Value> {
     if args.is_empty() {
-        return Ok(json!(""));
+        return Ok(Value::from(""));
     }
 
     let combined = args
         .iter()
         .map(|v| match v {
-            Value::String(s) => s.clone(),
-            Value::Number(n) => n.to_string(),
-            Value::Bool(b) => b.to_string(),
-            Value::Null => "null".to_string(),
             Value::Array(a) => format!("[{} items]", a.len()),
             Value::Object(o) => format!("{{object with {} fields}}", o.len()),
+            other => other.to_string(),
         })
         .collect::<Vec<String>>()
         .join("");
 
-    Ok(json!(combined))
+    Ok(Value::from(combined))
 }
 
 impl BuiltinRegistry {
@@ -1143,10 +1050,10 @@ fn builtin_from(args: &[Value]) -> SusumuResult<Value> {
     }
 
     // Create a module reference that import() can use
-    Ok(json!({
+    Ok(Value::from(json!({
         "type": "module_reference",
         "module_name": module_name
-    }))
+    })))
 }
 
 /// Module function: import(module_import, function_list) -> imported_functions
@@ -1195,12 +1102,12 @@ fn builtin_import(args: &[Value]) -> SusumuResult<Value> {
     // 4. Return function references or inject them into current scope
 
     // For now, return a placeholder that shows what was imported
-    Ok(json!({
+    Ok(Value::from(json!({
         "type": "imported_functions",
         "module": module_name,
         "functions": functions,
         "status": "placeholder - not yet implemented"
-    }))
+    })))
 }
 
 /// Module function: export(function_list) -> export_declaration  
@@ -1235,11 +1142,11 @@ fn builtin_export(args: &[Value]) -> SusumuResult<Value> {
     // 2. Mark them as exported in module metadata
     // 3. Make them available for import by other modules
 
-    Ok(json!({
+    Ok(Value::from(json!({
         "type": "export_declaration",
         "functions": exported_functions,
         "status": "placeholder - not yet implemented"
-    }))
+    })))
 }
 
 // =============================================================================
@@ -1255,100 +1162,74 @@ fn builtin_core_add(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let mut result = 0.0;
+    let mut result = Value::Int(0);
     for (i, arg) in args.iter().enumerate() {
-        let num = arg.as_f64().ok_or_else(|| {
-            SusumuError::runtime_error(&format!("Argument {} must be a number", i + 1))
-        })?;
-        result += num;
+        if !arg.is_number() {
+            return Err(SusumuError::runtime_error(&format!(
+                "Argument {} must be a number",
+                i + 1
+            )));
+        }
+        result = result.arithmetic(NumericOp::Add, arg)?;
     }
 
-    if result.is_finite() {
-        Ok(json!(result))
-    } else {
-        Err(SusumuError::runtime_error(
-            "Arithmetic overflow in addition",
-        ))
-    }
+    check_finite(result, "Arithmetic overflow in addition")
 }
 
-/// Core subtract function
-fn builtin_core_subtract(args: &[Value]) -> SusumuResult<Value> {
+/// Check the two operands of a binary core math function
+fn core_operands<'v>(name: &str, args: &'v [Value]) -> SusumuResult<(&'v Value, &'v Value)> {
     if args.len() != 2 {
+        return Err(SusumuError::runtime_error(format!(
+            "{}() expects exactly 2 arguments",
+            name
+        )));
+    }
+    if !args[0].is_number() {
         return Err(SusumuError::runtime_error(
-            "subtract() expects exactly 2 arguments",
+            "First argument must be a number",
         ));
     }
-
-    let a = args[0]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("First argument must be a number"))?;
-    let b = args[1]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("Second argument must be a number"))?;
-
-    let result = a - b;
-    if result.is_finite() {
-        Ok(json!(result))
-    } else {
-        Err(SusumuError::runtime_error(
-            "Arithmetic underflow in subtraction",
-        ))
+    if !args[1].is_number() {
+        return Err(SusumuError::runtime_error(
+            "Second argument must be a number",
+        ));
     }
+    Ok((&args[0], &args[1]))
 }
 
-/// Core multiply function  
-fn builtin_core_multiply(args: &[Value]) -> SusumuResult<Value> {
-    if args.len() != 2 {
-        return Err(SusumuError::runtime_error(
-            "multiply() expects exactly 2 arguments",
-        ));
+/// Reject float results that overflowed to infinity or NaN
+fn check_finite(result: Value, message: &str) -> SusumuResult<Value> {
+    match result {
+        Value::Float(f) if !f.is_finite() => Err(SusumuError::runtime_error(message)),
+        other => Ok(other),
     }
+}
 
-    let a = args[0]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("First argument must be a number"))?;
-    let b = args[1]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("Second argument must be a number"))?;
+/// Core subtract function
+fn builtin_core_subtract(args: &[Value]) -> SusumuResult<Value> {
+    let (a, b) = core_operands("subtract", args)?;
+    check_finite(
+        a.arithmetic(NumericOp::Subtract, b)?,
+        "Arithmetic underflow in subtraction",
+    )
+}
 
-    let result = a * b;
-    if result.is_finite() {
-        Ok(json!(result))
-    } else {
-        Err(SusumuError::runtime_error(
-            "Arithmetic overflow in multiplication",
-        ))
-    }
+/// Core multiply function  
+fn builtin_core_multiply(args: &[Value]) -> SusumuResult<Value> {
+    let (a, b) = core_operands("multiply", args)?;
+    check_finite(
+        a.arithmetic(NumericOp::Multiply, b)?,
+        "Arithmetic overflow in multiplication",
+    )
 }
 
 /// Core divide function with division by zero protection
 fn builtin_core_divide(args: &[Value]) -> SusumuResult<Value> {
-    if args.len() != 2 {
-        return Err(SusumuError::runtime_error(
-            "divide() expects exactly 2 arguments",
-        ));
-    }
-
-    let a = args[0]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("First argument must be a number"))?;
-    let b = args[1]
-        .as_f64()
-        .ok_or_else(|| SusumuError::runtime_error("Second argument must be a number"))?;
-
-    if b == 0.0 {
-        return Err(SusumuError::runtime_error("Division by zero"));
-    }
-
-    let result = a / b;
-    if result.is_finite() {
-        Ok(json!(result))
-    } else {
-        Err(SusumuError::runtime_error(
-            "Arithmetic overflow in division",
-        ))
-    }
+    let (a, b) = core_operands("divide", args)?;
+    check_finite(
+        a.arithmetic(NumericOp::Divide, b)?,
+        "Arithmetic overflow in division",
+    )
 }
 
 /// Core print function with automatic newline and memory-efficient output
@@ -1357,7 +1238,7 @@ fn builtin_core_print(args: &[Value]) -> SusumuResult<Value> {
 
     if args.is_empty() {
         println!();
-        return Ok(json!(null));
+        return Ok(Value::Null);
     }
 
     // Use stdout directly for better performance and memory management
@@ -1379,7 +1260,7 @@ fn builtin_core_print(args: &[Value]) -> SusumuResult<Value> {
         .flush()
         .map_err(|e| SusumuError::io_error(format!("Print flush error: {}", e)))?;
 
-    Ok(json!(null))
+    Ok(Value::Null)
 }
 
 /// Core println function (alias for print with explicit newline semantics)
@@ -1395,62 +1276,8 @@ fn builtin_core_to_string(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let result = match &args[0] {
-        Value::String(s) => s.clone(),
-        Value::Number(n) => {
-            // serde_json::Number handles integer/float formatting automatically
-            n.to_string()
-        }
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
-        Value::Array(arr) => {
-            if arr.is_empty() {
-                "[]".to_string()
-            } else {
-                let elements: Result<Vec<String>, SusumuError> = arr
-                    .iter()
-                    .map(|v| {
-                        builtin_core_to_string(&[v.clone()]).and_then(|json_val| {
-                            json_val.as_str().map(|s| s.to_string()).ok_or_else(|| {
-                                SusumuError::runtime_error("Internal toString error")
-                            })
-                        })
-                    })
-                    .collect();
-
-                match elements {
-                    Ok(strs) => format!("[{}]", strs.join(", ")),
-                    Err(_) => "[?]".to_string(),
-                }
-            }
-        }
-        Value::Object(obj) => {
-            if obj.is_empty() {
-                "{}".to_string()
-            } else {
-                let pairs: Result<Vec<String>, SusumuError> = obj
-                    .iter()
-                    .map(|(k, v)| {
-                        builtin_core_to_string(&[v.clone()]).and_then(|json_val| {
-                            json_val
-                                .as_str()
-                                .map(|s| format!("{}: {}", k, s))
-                                .ok_or_else(|| {
-                                    SusumuError::runtime_error("Internal toString error")
-                                })
-                        })
-                    })
-                    .collect();
-
-                match pairs {
-                    Ok(strs) => format!("{{{}}}", strs.join(", ")),
-                    Err(_) => "{?}".to_string(),
-                }
-            }
-        }
-    };
-
-    Ok(json!(result))
+    // Strings are returned as-is; everything else uses the display form
+    Ok(Value::from(args[0].to_string()))
 }
 
 /// Core toNumber function with validation and edge case handling
@@ -1462,30 +1289,35 @@ fn builtin_core_to_number(args: &[Value]) -> SusumuResult<Value> {
     }
 
     match &args[0] {
-        Value::Number(n) => Ok(json!(n)),
+        Value::Int(_) | Value::Float(_) => Ok(args[0].clone()),
         Value::String(s) => {
             let trimmed = s.trim();
             if trimmed.is_empty() {
-                return Ok(json!(0.0));
+                return Ok(Value::Int(0));
+            }
+
+            if let Ok(n) = trimmed.parse::<i64>() {
+                return Ok(Value::Int(n));
             }
 
             trimmed
                 .parse::<f64>()
                 .map(|n| {
                     if n.is_finite() {
-                        json!(n)
+                        Value::from(n)
                     } else {
-                        json!(null) // Return null for invalid numbers rather than error
+                        Value::Null // Return null for invalid numbers rather than error
                     }
                 })
                 .map_err(|_| {
                     SusumuError::runtime_error(format!("Cannot convert '{}' to number", s))
                 })
         }
-        Value::Bool(b) => Ok(json!(if *b { 1.0 } else { 0.0 })),
-        Value::Null => Ok(json!(0.0)),
-        Value::Array(arr) => Ok(json!(arr.len() as f64)),
-        Value::Object(obj) => Ok(json!(obj.len() as f64)),
+        Value::Bool(b) => Ok(Value::Int(i64::from(*b))),
+        Value::Null => Ok(Value::Int(0)),
+        Value::Array(arr) => Ok(Value::from(arr.len())),
+        Value::Object(obj) => Ok(Value::from(obj.len())),
+        other => Err(SusumuError::type_error("number", other.type_name())),
     }
 }
 
@@ -1496,7 +1328,7 @@ fn builtin_core_is_null(args: &[Value]) -> SusumuResult<Value> {
             "isNull() expects exactly 1 argument",
         ));
     }
-    Ok(json!(args[0].is_null()))
+    Ok(Value::from(args[0].is_null()))
 }
 
 fn builtin_core_is_empty(args: &[Value]) -> SusumuResult<Value> {
@@ -1511,11 +1343,15 @@ fn builtin_core_is_empty(args: &[Value]) -> SusumuResult<Value> {
         Value::String(s) => s.is_empty(),
         Value::Array(arr) => arr.is_empty(),
         Value::Object(obj) => obj.is_empty(),
-        Value::Number(n) => n.as_f64().unwrap_or(1.0) == 0.0,
+        Value::Int(n) => *n == 0,
+        Value::Float(n) => *n == 0.0,
         Value::Bool(b) => !b,
+        Value::Bytes(bytes) => bytes.is_empty(),
+        Value::Maybe(None) => true,
+        _ => false,
     };
 
-    Ok(json!(is_empty))
+    Ok(Value::from(is_empty))
 }
 
 fn builtin_core_is_number(args: &[Value]) -> SusumuResult<Value> {
@@ -1524,7 +1360,7 @@ fn builtin_core_is_number(args: &[Value]) -> SusumuResult<Value> {
             "isNumber() expects exactly 1 argument",
         ));
     }
-    Ok(json!(args[0].is_number()))
+    Ok(Value::from(args[0].is_number()))
 }
 
 fn builtin_core_is_string(args: &[Value]) -> SusumuResult<Value> {
@@ -1533,7 +1369,7 @@ fn builtin_core_is_string(args: &[Value]) -> SusumuResult<Value> {
             "isString() expects exactly 1 argument",
         ));
     }
-    Ok(json!(args[0].is_string()))
+    Ok(Value::from(args[0].is_string()))
 }
 
 fn builtin_core_length(args: &[Value]) -> SusumuResult<Value> {
@@ -1544,9 +1380,10 @@ fn builtin_core_length(args: &[Value]) -> SusumuResult<Value> {
     }
 
     let len = match &args[0] {
-        Value::String(s) => s.chars().count() as f64, // UTF-8 safe character count
-        Value::Array(arr) => arr.len() as f64,
-        Value::Object(obj) => obj.len() as f64,
+        Value::String(s) => s.chars().count(), // UTF-8 safe character count
+        Value::Array(arr) => arr.len(),
+        Value::Object(obj) => obj.len(),
+        Value::Bytes(bytes) => bytes.len(),
         _ => {
             return Err(SusumuError::runtime_error(
                 "length() only works with strings, arrays, or objects",
@@ -1554,7 +1391,22 @@ fn builtin_core_length(args: &[Value]) -> SusumuResult<Value> {
         }
     };
 
-    Ok(json!(len))
+    Ok(Value::from(len))
+}
+
+/// `[key, value]` pairs of an object in key order, for `fe (k, v) in entries(obj)`
+fn builtin_core_entries(args: &[Value]) -> SusumuResult<Value> {
+    match args {
+        [Value::Object(obj)] => Ok(Value::Array(
+            obj.iter()
+                .map(|(key, value)| Value::Array(vec![Value::from(key.as_str()), value.clone()]))
+                .collect(),
+        )),
+        [other] => Err(SusumuError::type_error("object", other.type_name())),
+        _ => Err(SusumuError::runtime_error(
+            "entries() expects exactly 1 argument",
+        )),
+    }
 }
 
 fn builtin_core_type(args: &[Value]) -> SusumuResult<Value> {
@@ -1564,16 +1416,7 @@ fn builtin_core_type(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let type_name = match &args[0] {
-        Value::Null => "null",
-        Value::Bool(_) => "boolean",
-        Value::Number(_) => "number",
-        Value::String(_) => "string",
-        Value::Array(_) => "array",
-        Value::Object(_) => "object",
-    };
-
-    Ok(json!(type_name))
+    Ok(Value::from(args[0].type_name()))
 }
 
 fn builtin_core_equals(args: &[Value]) -> SusumuResult<Value> {
@@ -1583,8 +1426,23 @@ fn builtin_core_equals(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    // Deep equality comparison with JSON values
-    Ok(json!(args[0] == args[1]))
+    // Deep equality comparison; numbers compare by value
+    Ok(Value::from(args[0] == args[1]))
+}
+
+/// Stop the program with an exit status (default 0). Hosts see `SusumuError::Exit`;
+/// the CLI exits the process with the status.
+fn builtin_core_exit(args: &[Value]) -> SusumuResult<Value> {
+    let code = match args {
+        [] => 0,
+        [Value::Int(code @ 0..=255)] => *code as i32,
+        _ => {
+            return Err(SusumuError::runtime_error(
+                "exit() expects an optional whole-number status from 0 to 255",
+            ))
+        }
+    };
+    Err(SusumuError::exit(code))
 }
 
 // =============================================================================
@@ -1607,7 +1465,7 @@ fn builtin_core_read_file(args: &[Value]) -> SusumuResult<Value> {
 
     // Automatic resource management - file handle is dropped at end of scope
     match std::fs::read_to_string(file_path) {
-        Ok(content) => Ok(json!(content)),
+        Ok(content) => Ok(Value::from(content)),
         Err(e) => Err(SusumuError::io_error(format!(
             "Failed to read file '{}': {}",
             file_path, e
@@ -1633,7 +1491,9 @@ fn builtin_core_write_file(args: &[Value]) -> SusumuResult<Value> {
 
     // Automatic resource management with explicit error handling
     match std::fs::write(file_path, content) {
-        Ok(()) => Ok(json!({"success": true, "bytes_written": content.len()})),
+        Ok(()) => Ok(Value::from(
+            json!({"success": true, "bytes_written": content.len()}),
+        )),
         Err(e) => Err(SusumuError::io_error(format!(
             "Failed to write file '{}': {}",
             file_path, e
@@ -1670,7 +1530,9 @@ fn builtin_core_append_file(args: &[Value]) -> SusumuResult<Value> {
             file.flush()?; // Ensure data is written
             Ok(())
         }) {
-        Ok(()) => Ok(json!({"success": true, "bytes_appended": content.len()})),
+        Ok(()) => Ok(Value::from(
+            json!({"success": true, "bytes_appended": content.len()}),
+        )),
         Err(e) => Err(SusumuError::io_error(format!(
             "Failed to append to file '{}': {}",
             file_path, e
@@ -1691,7 +1553,7 @@ fn builtin_core_file_exists(args: &[Value]) -> SusumuResult<Value> {
         .as_str()
         .ok_or_else(|| SusumuError::runtime_error("fileExists() expects a string file path"))?;
 
-    Ok(json!(std::path::Path::new(file_path).exists()))
+    Ok(Value::from(std::path::Path::new(file_path).exists()))
 }
 
 /// Get file metadata with automatic resource management
@@ -1708,7 +1570,7 @@ fn builtin_core_file_info(args: &[Value]) -> SusumuResult<Value> {
         .ok_or_else(|| SusumuError::runtime_error("fileInfo() expects a string file path"))?;
 
     match std::fs::metadata(file_path) {
-        Ok(metadata) => Ok(json!({
+        Ok(metadata) => Ok(Value::from(json!({
             "size": metadata.len(),
             "is_file": metadata.is_file(),
             "is_directory": metadata.is_dir(),
@@ -1718,7 +1580,7 @@ fn builtin_core_file_info(args: &[Value]) -> SusumuResult<Value> {
                 .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
                 .map(|dur| dur.as_secs())
                 .unwrap_or(0)
-        })),
+        }))),
         Err(e) => Err(SusumuError::io_error(format!(
             "Failed to get file info for '{}': {}",
             file_path, e
@@ -1746,7 +1608,7 @@ fn builtin_core_list_dir(args: &[Value]) -> SusumuResult<Value> {
                 match entry {
                     Ok(entry) => {
                         if let Some(name) = entry.file_name().to_str() {
-                            file_list.push(json!(name));
+                            file_list.push(Value::from(name));
                         }
                     }
                     Err(e) => {
@@ -1757,7 +1619,7 @@ fn builtin_core_list_dir(args: &[Value]) -> SusumuResult<Value> {
                     }
                 }
             }
-            Ok(json!(file_list))
+            Ok(Value::from(file_list))
         }
         Err(e) => Err(SusumuError::io_error(format!(
             "Failed to list directory '{}': {}",
@@ -1766,6 +1628,21 @@ fn builtin_core_list_dir(args: &[Value]) -> SusumuResult<Value> {
     }
 }
 
+/// Read an environment variable, or null when it is not set
+fn builtin_core_get_env(args: &[Value]) -> SusumuResult<Value> {
+    if args.len() != 1 {
+        return Err(SusumuError::runtime_error(
+            "getEnv() expects exactly 1 argument (variable name)",
+        ));
+    }
+
+    let name = args[0]
+        .as_str()
+        .ok_or_else(|| SusumuError::runtime_error("getEnv() expects a string variable name"))?;
+
+    Ok(std::env::var(name).map_or(Value::Null, Value::from))
+}
+
 // =============================================================================
 // JSON/DATA PROCESSING FUNCTIONS (Auto-available)
 // =============================================================================
@@ -1805,7 +1682,7 @@ fn builtin_core_to_json(args: &[Value]) -> SusumuResult<Value> {
     };
 
     match json_str {
-        Ok(json) => Ok(json!(json)),
+        Ok(json) => Ok(Value::from(json)),
         Err(e) => Err(SusumuError::runtime_error(format!(
             "Failed to serialize to JSON: {}",
             e
@@ -1814,70 +1691,312 @@ fn builtin_core_to_json(args: &[Value]) -> SusumuResult<Value> {
 }
 
 // =============================================================================
-// ARRAY PROCESSING FUNCTIONS (Auto-available)
+// HIGHER-ORDER ARRAY FUNCTIONS (Native, call back into Susumu functions)
 // =============================================================================
 
-/// Filter array with predicate function
-fn builtin_core_filter(args: &[Value]) -> SusumuResult<Value> {
-    if args.len() != 2 {
-        return Err(SusumuError::runtime_error(
-            "filter() expects exactly 2 arguments (array, predicate)",
-        ));
+/// Check the (array, function) arguments shared by the higher-order functions
+fn higher_order_args<'v>(
+    name: &str,
+    args: &'v [Value],
+    max_args: usize,
+) -> SusumuResult<(&'v Vec<Value>, &'v Value)> {
+    if args.len() < 2 || args.len() > max_args {
+        return Err(SusumuError::function_call_error(format!(
+            "{}() expects an array and a function{}",
+            name,
+            if max_args > 2 {
+                " (and optional initial value)"
+            } else {
+                ""
+            }
+        )));
     }
 
-    let array = args[0]
-        .as_array()
-        .ok_or_else(|| SusumuError::runtime_error("filter() expects an array as first argument"))?;
+    match &args[0] {
+        Value::Array(items) => Ok((items, &args[1])),
+        other => Err(SusumuError::type_error(
+            "array",
+            value_to_display_string(other),
+        )),
+    }
+}
 
-    // For now, simple value-based filtering (in future: function predicates)
-    let filter_value = &args[1];
+fn expect_callable(ctx: &CallContext, callback: &Value) -> SusumuResult<()> {
+    if ctx.is_callable(callback) {
+        Ok(())
+    } else {
+        Err(SusumuError::function_call_error(format!(
+            "{}() expects a function, got {}",
+            ctx.function_name(),
+            value_to_display_string(callback)
+        )))
+    }
+}
 
-    let filtered: Vec<Value> = array
-        .iter()
-        .filter(|&item| item == filter_value)
-        .cloned()
-        .collect();
+/// Apply a function to every item
+fn native_map(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("map", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        results.push(ctx.call(callback, std::slice::from_ref(item))?);
+    }
+    Ok(Value::Array(results))
+}
+
+/// Apply a function to every item and flatten array results one level
+fn native_flat_map(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("flatMap", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    let mut results = Vec::new();
+    for item in items {
+        match ctx.call(callback, std::slice::from_ref(item))? {
+            Value::Array(inner) => results.extend(inner),
+            other => results.push(other),
+        }
+        ctx.check_size(results.len())?;
+    }
+    Ok(Value::Array(results))
+}
+
+/// Keep items the predicate accepts (or items equal to a non-function argument)
+fn native_filter(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("filter", args, 2)?;
 
-    Ok(json!(filtered))
+    if !ctx.is_callable(callback) {
+        // filter predates function values and compares items by equality
+        let filtered = items.iter().filter(|&item| item == callback).cloned();
+        return Ok(Value::Array(filtered.collect()));
+    }
+
+    let mut results = Vec::new();
+    for item in items {
+        let keep = ctx.call(callback, std::slice::from_ref(item))?;
+        if ctx.is_truthy(&keep) {
+            results.push(item.clone());
+        }
+    }
+    Ok(Value::Array(results))
+}
+
+/// Fold items with (accumulator, item), starting from the initial value or first item
+fn native_reduce(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("reduce", args, 3)?;
+    expect_callable(ctx, callback)?;
+
+    let mut remaining = items.iter();
+    let mut accumulator = match args.get(2) {
+        Some(initial) => initial.clone(),
+        None => match remaining.next() {
+            Some(first) => first.clone(),
+            None => return Ok(Value::Null),
+        },
+    };
+    for item in remaining {
+        accumulator = ctx.call(callback, &[accumulator, item.clone()])?;
+    }
+    Ok(accumulator)
 }
 
-/// Map array with transformation function
-fn builtin_core_map(args: &[Value]) -> SusumuResult<Value> {
+/// Stable sort by the key the function returns
+fn native_sort_by(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("sortBy", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    let mut keyed = Vec::with_capacity(items.len());
+    for item in items {
+        let key = ctx.call(callback, std::slice::from_ref(item))?;
+        keyed.push((key, item.clone()));
+    }
+    keyed.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(Value::Array(
+        keyed.into_iter().map(|(_, item)| item).collect(),
+    ))
+}
+
+/// First item the predicate accepts, or null
+fn native_find(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("find", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    for item in items {
+        let found = ctx.call(callback, std::slice::from_ref(item))?;
+        if ctx.is_truthy(&found) {
+            return Ok(item.clone());
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// Whether the predicate accepts at least one item
+fn native_any(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("any", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    for item in items {
+        let result = ctx.call(callback, std::slice::from_ref(item))?;
+        if ctx.is_truthy(&result) {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// Whether the predicate accepts every item
+fn native_all(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("all", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    for item in items {
+        let result = ctx.call(callback, std::slice::from_ref(item))?;
+        if !ctx.is_truthy(&result) {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// Group items into an object keyed by the function's result
+fn native_group_by(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    let (items, callback) = higher_order_args("groupBy", args, 2)?;
+    expect_callable(ctx, callback)?;
+
+    let mut groups = Map::new();
+    for item in items {
+        let key = ctx.call(callback, std::slice::from_ref(item))?;
+        let group = groups
+            .entry(value_to_display_string(&key))
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(members) = group {
+            members.push(item.clone());
+        }
+    }
+    Ok(Value::Object(groups))
+}
+
+// =============================================================================
+// TEST ASSERTIONS (Fail with an AssertionError that `susumu test` reports)
+// =============================================================================
+
+/// Values in assertion messages are shown as source-like literals, so `"5"` and `5` differ
+fn assertion_value(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| value_to_display_string(value))
+}
+
+/// Pass when the two values are deeply equal: `total -> assertEqual <- 42`
+fn builtin_assert_equal(args: &[Value]) -> SusumuResult<Value> {
     if args.len() != 2 {
-        return Err(SusumuError::runtime_error(
-            "map() expects exactly 2 arguments (array, transform)",
+        return Err(SusumuError::function_call_error(
+            "assertEqual() expects an actual and an expected value",
         ));
     }
 
-    let array = args[0]
-        .as_array()
-        .ok_or_else(|| SusumuError::runtime_error("map() expects an array as first argument"))?;
-
-    // For now, simple transformation (in future: function application)
-    // This is a placeholder - real implementation would apply transform function
-    Ok(json!(array.clone()))
+    if args[0] == args[1] {
+        Ok(Value::Bool(true))
+    } else {
+        Err(SusumuError::assertion_error(format!(
+            "expected {}, found {}",
+            assertion_value(&args[1]),
+            assertion_value(&args[0])
+        )))
+    }
 }
 
-/// Reduce array to single value
-fn builtin_core_reduce(args: &[Value]) -> SusumuResult<Value> {
-    if args.len() < 2 || args.len() > 3 {
-        return Err(SusumuError::runtime_error(
-            "reduce() expects 2-3 arguments (array, reducer, initial?)",
+/// Pass when calling the function fails, returning the error value. An optional second
+/// argument must equal the error value, or be a string contained in the error message.
+fn native_assert_error(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(SusumuError::function_call_error(
+            "assertError() expects a function and an optional expected error",
         ));
     }
+    expect_callable(ctx, &args[0])?;
 
-    let array = args[0]
-        .as_array()
-        .ok_or_else(|| SusumuError::runtime_error("reduce() expects an array as first argument"))?;
+    let (value, message) = match ctx.call(&args[0], &[]) {
+        // A failed assertion, exhausted limit or denied permission inside the function is
+        // not the expected error
+        Err(err) if matches!(err, SusumuError::AssertionError { .. }) || err.is_uncatchable() => {
+            return Err(err)
+        }
+        Err(SusumuError::UserError { value, .. }) => {
+            let message = value_to_display_string(&value);
+            (value, message)
+        }
+        Err(err) => (Value::String(err.to_string()), err.to_string()),
+        Ok(Value::Result(Err(value))) => {
+            let message = value_to_display_string(&value);
+            (*value, message)
+        }
+        Ok(value) => {
+            return Err(SusumuError::assertion_error(format!(
+                "expected an error, but the function returned {}",
+                assertion_value(&value)
+            )))
+        }
+    };
 
-    if array.is_empty() {
-        return Ok(args.get(2).cloned().unwrap_or(json!(null)));
+    match args.get(1) {
+        Some(Value::String(expected)) if message.contains(expected.as_str()) => Ok(value),
+        Some(expected) if *expected != value => Err(SusumuError::assertion_error(format!(
+            "expected error {}, found {}",
+            assertion_value(expected),
+            assertion_value(&value)
+        ))),
+        _ => Ok(value),
     }
+}
 
-    // For now, simple sum operation (in future: custom reducer functions)
-    let sum: f64 = array.iter().filter_map(|v| v.as_f64()).sum();
+/// Pass when the value matches the pattern: `user -> assertMatches <- {role: "admin"}`.
+/// Objects match when every key in the pattern matches, arrays element by element, and
+/// a function pattern is a predicate the value must satisfy.
+fn native_assert_matches(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
+    if args.len() != 2 {
+        return Err(SusumuError::function_call_error(
+            "assertMatches() expects a value and a pattern",
+        ));
+    }
 
-    Ok(json!(sum))
+    if matches_pattern(ctx, &args[0], &args[1])? {
+        Ok(Value::Bool(true))
+    } else {
+        Err(SusumuError::assertion_error(format!(
+            "{} does not match {}",
+            assertion_value(&args[0]),
+            assertion_value(&args[1])
+        )))
+    }
+}
+
+fn matches_pattern(ctx: &mut CallContext, value: &Value, pattern: &Value) -> SusumuResult<bool> {
+    match (value, pattern) {
+        (Value::Object(fields), Value::Object(expected)) => {
+            for (key, expected) in expected {
+                match fields.get(key) {
+                    Some(field) if matches_pattern(ctx, field, expected)? => {}
+                    _ => return Ok(false),
+                }
+            }
+            Ok(true)
+        }
+        (Value::Array(items), Value::Array(expected)) => {
+            if items.len() != expected.len() {
+                return Ok(false);
+            }
+            for (item, expected) in items.iter().zip(expected) {
+                if !matches_pattern(ctx, item, expected)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (_, Value::Function(_) | Value::Lambda(_)) => {
+            let result = ctx.call(pattern, std::slice::from_ref(value))?;
+            Ok(ctx.is_truthy(&result))
+        }
+        _ => Ok(value == pattern),
+    }
 }
 
 // =============================================================================
@@ -1893,7 +2012,7 @@ fn builtin_core_now(args: &[Value]) -> SusumuResult<Value> {
     use std::time::{SystemTime, UNIX_EPOCH};
 
     match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => Ok(json!(duration.as_secs())),
+        Ok(duration) => Ok(Value::from(duration.as_secs())),
         Err(e) => Err(SusumuError::runtime_error(format!(
             "Failed to get current time: {}",
             e
@@ -1912,7 +2031,7 @@ fn builtin_core_now_millis(args: &[Value]) -> SusumuResult<Value> {
     use std::time::{SystemTime, UNIX_EPOCH};
 
     match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => Ok(json!(duration.as_millis() as u64)),
+        Ok(duration) => Ok(Value::from(duration.as_millis() as u64)),
         Err(e) => Err(SusumuError::runtime_error(format!(
             "Failed to get current time: {}",
             e
@@ -1935,7 +2054,7 @@ fn builtin_core_format_date(args: &[Value]) -> SusumuResult<Value> {
     use chrono::{TimeZone, Utc};
 
     match Utc.timestamp_opt(timestamp as i64, 0) {
-        chrono::LocalResult::Single(datetime) => Ok(json!(datetime.to_rfc3339())),
+        chrono::LocalResult::Single(datetime) => Ok(Value::from(datetime.to_rfc3339())),
         _ => Err(SusumuError::runtime_error("Invalid timestamp")),
     }
 }
@@ -1955,11 +2074,11 @@ fn builtin_core_parse_date(args: &[Value]) -> SusumuResult<Value> {
     use chrono::{DateTime, Utc};
 
     match DateTime::parse_from_rfc3339(date_str) {
-        Ok(datetime) => Ok(json!(datetime.timestamp())),
+        Ok(datetime) => Ok(Value::from(datetime.timestamp())),
         Err(_) => {
             // Try parsing as ISO 8601 with Z suffix
             match date_str.parse::<DateTime<Utc>>() {
-                Ok(datetime) => Ok(json!(datetime.timestamp())),
+                Ok(datetime) => Ok(Value::from(datetime.timestamp())),
                 Err(e) => Err(SusumuError::runtime_error(format!(
                     "Invalid date format '{}': {}",
                     date_str, e
@@ -1984,7 +2103,7 @@ fn builtin_core_add_time(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("addTime() expects a number of seconds"))?;
 
-    Ok(json!(timestamp + seconds))
+    Ok(Value::from(timestamp + seconds))
 }
 
 /// Calculate difference between two timestamps (in seconds)
@@ -2002,7 +2121,7 @@ fn builtin_core_time_diff(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("timeDiff() expects number timestamps"))?;
 
-    Ok(json!((timestamp2 - timestamp1).abs()))
+    Ok(Value::from((timestamp2 - timestamp1).abs()))
 }
 
 // === STDLIB IMPLEMENTATION FUNCTIONS ===
@@ -2030,7 +2149,7 @@ fn builtin_calculate_factorial(args: &[Value]) -> SusumuResult<Value> {
         result *= i;
     }
 
-    Ok(json!(result))
+    Ok(Value::from(result))
 }
 
 fn builtin_calculate_gcd(args: &[Value]) -> SusumuResult<Value> {
@@ -2067,7 +2186,7 @@ fn builtin_calculate_gcd(args: &[Value]) -> SusumuResult<Value> {
         }
     }
 
-    Ok(json!(gcd(a, b)))
+    Ok(Value::from(gcd(a, b)))
 }
 
 fn builtin_check_prime_factors(args: &[Value]) -> SusumuResult<Value> {
@@ -2083,16 +2202,16 @@ fn builtin_check_prime_factors(args: &[Value]) -> SusumuResult<Value> {
         as i64;
 
     if n <= 1 {
-        return Ok(json!(false));
+        return Ok(Value::from(false));
     }
 
     for i in 2..=((n as f64).sqrt() as i64) {
         if n % i == 0 {
-            return Ok(json!(false));
+            return Ok(Value::from(false));
         }
     }
 
-    Ok(json!(true))
+    Ok(Value::from(true))
 }
 
 fn builtin_calculate_fibonacci(args: &[Value]) -> SusumuResult<Value> {
@@ -2108,7 +2227,7 @@ fn builtin_calculate_fibonacci(args: &[Value]) -> SusumuResult<Value> {
         as u64;
 
     if n <= 1 {
-        return Ok(json!(n));
+        return Ok(Value::from(n));
     }
 
     let mut a = 0u64;
@@ -2119,7 +2238,7 @@ fn builtin_calculate_fibonacci(args: &[Value]) -> SusumuResult<Value> {
         b = temp;
     }
 
-    Ok(json!(b))
+    Ok(Value::from(b))
 }
 
 fn builtin_split_string(args: &[Value]) -> SusumuResult<Value> {
@@ -2136,12 +2255,13 @@ fn builtin_split_string(args: &[Value]) -> SusumuResult<Value> {
         SusumuError::runtime_error("split_string() expects a string delimiter as second argument")
     })?;
 
-    let parts: Vec<Value> = text.split(delimiter).map(|s| json!(s)).collect();
+    let parts: Vec<Value> = text.split(delimiter).map(Value::from).collect();
 
-    Ok(json!(parts))
+    Ok(Value::from(parts))
 }
 
-fn builtin_join_array(args: &[Value]) -> SusumuResult<Value> {
+/// Join strings with a delimiter, refusing results over the size limit before building them
+fn native_join_array(ctx: &mut CallContext, args: &[Value]) -> SusumuResult<Value> {
     if args.len() != 2 {
         return Err(SusumuError::runtime_error(
             "join_array() expects exactly 2 arguments (array, delimiter)",
@@ -2155,12 +2275,12 @@ fn builtin_join_array(args: &[Value]) -> SusumuResult<Value> {
         SusumuError::runtime_error("join_array() expects a string delimiter as second argument")
     })?;
 
-    let string_parts: Vec<String> = array
-        .iter()
-        .map(|v| v.as_str().unwrap_or("").to_string())
-        .collect();
+    let string_parts: Vec<&str> = array.iter().map(|v| v.as_str().unwrap_or("")).collect();
+    let len = string_parts.iter().map(|part| part.len()).sum::<usize>()
+        + delimiter.len() * string_parts.len().saturating_sub(1);
+    ctx.check_size(len)?;
 
-    Ok(json!(string_parts.join(delimiter)))
+    Ok(Value::from(string_parts.join(delimiter)))
 }
 
 fn builtin_to_title_case(args: &[Value]) -> SusumuResult<Value> {
@@ -2186,7 +2306,7 @@ fn builtin_to_title_case(args: &[Value]) -> SusumuResult<Value> {
         .collect::<Vec<_>>()
         .join(" ");
 
-    Ok(json!(title_case))
+    Ok(Value::from(title_case))
 }
 
 fn builtin_core_abs(args: &[Value]) -> SusumuResult<Value> {
@@ -2200,7 +2320,7 @@ fn builtin_core_abs(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("abs() expects a number"))?;
 
-    Ok(json!(n.abs()))
+    Ok(Value::from(n.abs()))
 }
 
 fn builtin_core_first(args: &[Value]) -> SusumuResult<Value> {
@@ -2215,7 +2335,7 @@ fn builtin_core_first(args: &[Value]) -> SusumuResult<Value> {
         .ok_or_else(|| SusumuError::runtime_error("first() expects an array"))?;
 
     if array.is_empty() {
-        Ok(json!(null))
+        Ok(Value::Null)
     } else {
         Ok(array[0].clone())
     }
@@ -2233,7 +2353,7 @@ fn builtin_core_last(args: &[Value]) -> SusumuResult<Value> {
         .ok_or_else(|| SusumuError::runtime_error("last() expects an array"))?;
 
     if array.is_empty() {
-        Ok(json!(null))
+        Ok(Value::Null)
     } else {
         Ok(array[array.len() - 1].clone())
     }
@@ -2252,7 +2372,7 @@ fn builtin_core_reverse(args: &[Value]) -> SusumuResult<Value> {
 
     let mut reversed = array.clone();
     reversed.reverse();
-    Ok(json!(reversed))
+    Ok(Value::from(reversed))
 }
 
 fn builtin_core_sum(args: &[Value]) -> SusumuResult<Value> {
@@ -2275,7 +2395,7 @@ fn builtin_core_sum(args: &[Value]) -> SusumuResult<Value> {
         }
     }
 
-    Ok(json!(total))
+    Ok(Value::from(total))
 }
 
 fn builtin_core_is_array(args: &[Value]) -> SusumuResult<Value> {
@@ -2285,7 +2405,7 @@ fn builtin_core_is_array(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    Ok(json!(args[0].is_array()))
+    Ok(Value::from(args[0].is_array()))
 }
 
 // =============================================================================
@@ -2294,7 +2414,10 @@ fn builtin_core_is_array(args: &[Value]) -> SusumuResult<Value> {
 
 /// Simple HTTP GET request with automatic connection management
 #[cfg(not(target_arch = "wasm32"))]
-fn builtin_core_http_get(args: &[Value]) -> SusumuResult<Value> {
+fn builtin_core_http_get(
+    client: &reqwest::blocking::Client,
+    args: &[Value],
+) -> SusumuResult<Value> {
     if args.len() != 1 {
         return Err(SusumuError::runtime_error(
             "httpGet() expects exactly 1 argument (URL)",
@@ -2305,7 +2428,7 @@ fn builtin_core_http_get(args: &[Value]) -> SusumuResult<Value> {
         .as_str()
         .ok_or_else(|| SusumuError::runtime_error("httpGet() expects a string URL"))?;
 
-    match reqwest::blocking::get(url) {
+    match client.get(url).send() {
         Ok(response) => {
             let status = response.status().as_u16();
             let headers = response
@@ -2315,11 +2438,11 @@ fn builtin_core_http_get(args: &[Value]) -> SusumuResult<Value> {
                 .collect::<std::collections::HashMap<String, String>>();
 
             match response.text() {
-                Ok(body) => Ok(json!({
+                Ok(body) => Ok(Value::from(json!({
                     "status": status,
                     "body": body,
                     "headers": headers
-                })),
+                }))),
                 Err(e) => Err(SusumuError::io_error(format!(
                     "Failed to read response body: {}",
                     e
@@ -2335,7 +2458,10 @@ fn builtin_core_http_get(args: &[Value]) -> SusumuResult<Value> {
 
 /// Simple HTTP POST request with JSON payload
 #[cfg(not(target_arch = "wasm32"))]
-fn builtin_core_http_post(args: &[Value]) -> SusumuResult<Value> {
+fn builtin_core_http_post(
+    client: &reqwest::blocking::Client,
+    args: &[Value],
+) -> SusumuResult<Value> {
     if args.len() != 2 {
         return Err(SusumuError::runtime_error(
             "httpPost() expects exactly 2 arguments (URL, data)",
@@ -2346,8 +2472,6 @@ fn builtin_core_http_post(args: &[Value]) -> SusumuResult<Value> {
         .as_str()
         .ok_or_else(|| SusumuError::runtime_error("httpPost() expects a string URL"))?;
 
-    let client = reqwest::blocking::Client::new();
-
     match client.post(url).json(&args[1]).send() {
         Ok(response) => {
             let status = response.status().as_u16();
@@ -2358,11 +2482,11 @@ fn builtin_core_http_post(args: &[Value]) -> SusumuResult<Value> {
                 .collect::<std::collections::HashMap<String, String>>();
 
             match response.text() {
-                Ok(body) => Ok(json!({
+                Ok(body) => Ok(Value::from(json!({
                     "status": status,
                     "body": body,
                     "headers": headers
-                })),
+                }))),
                 Err(e) => Err(SusumuError::io_error(format!(
                     "Failed to read response body: {}",
                     e
@@ -2378,7 +2502,10 @@ fn builtin_core_http_post(args: &[Value]) -> SusumuResult<Value> {
 
 /// HTTP request with full configuration
 #[cfg(not(target_arch = "wasm32"))]
-fn builtin_core_http_request(args: &[Value]) -> SusumuResult<Value> {
+fn builtin_core_http_request(
+    client: &reqwest::blocking::Client,
+    args: &[Value],
+) -> SusumuResult<Value> {
     if args.len() != 1 {
         return Err(SusumuError::runtime_error(
             "httpRequest() expects exactly 1 argument (config object)",
@@ -2398,7 +2525,6 @@ fn builtin_core_http_request(args: &[Value]) -> SusumuResult<Value> {
         .and_then(|v| v.as_str())
         .unwrap_or("GET");
 
-    let client = reqwest::blocking::Client::new();
     let mut request_builder = match method.to_uppercase().as_str() {
         "GET" => client.get(url),
         "POST" => client.post(url),
@@ -2443,11 +2569,11 @@ fn builtin_core_http_request(args: &[Value]) -> SusumuResult<Value> {
                 .collect::<std::collections::HashMap<String, String>>();
 
             match response.text() {
-                Ok(body) => Ok(json!({
+                Ok(body) => Ok(Value::from(json!({
                     "status": status,
                     "body": body,
                     "headers": headers
-                })),
+                }))),
                 Err(e) => Err(SusumuError::io_error(format!(
                     "Failed to read response body: {}",
                     e
@@ -2463,7 +2589,10 @@ fn builtin_core_http_request(args: &[Value]) -> SusumuResult<Value> {
 
 /// Parallel HTTP requests - takes array of URLs and fetches them concurrently
 #[cfg(not(target_arch = "wasm32"))]
-fn builtin_core_http_get_parallel(args: &[Value]) -> SusumuResult<Value> {
+fn builtin_core_http_get_parallel(
+    client: &reqwest::blocking::Client,
+    args: &[Value],
+) -> SusumuResult<Value> {
     if args.len() != 1 {
         return Err(SusumuError::runtime_error(
             "httpGetParallel() expects exactly 1 argument (array of URLs)",
@@ -2491,7 +2620,7 @@ fn builtin_core_http_get_parallel(args: &[Value]) -> SusumuResult<Value> {
 
         let results: Vec<Value> = url_strings
             .par_iter()
-            .map(|&url| match reqwest::blocking::get(url) {
+            .map(|&url| match client.get(url).send() {
                 Ok(response) => {
                     let status = response.status().as_u16();
                     let headers = response
@@ -2503,29 +2632,29 @@ fn builtin_core_http_get_parallel(args: &[Value]) -> SusumuResult<Value> {
                         .collect::<std::collections::HashMap<String, String>>();
 
                     match response.text() {
-                        Ok(body) => json!({
+                        Ok(body) => Value::from(json!({
                             "url": url,
                             "status": status,
                             "body": body,
                             "headers": headers,
                             "success": true
-                        }),
-                        Err(e) => json!({
+                        })),
+                        Err(e) => Value::from(json!({
                             "url": url,
                             "error": format!("Failed to read response: {}", e),
                             "success": false
-                        }),
+                        })),
                     }
                 }
-                Err(e) => json!({
+                Err(e) => Value::from(json!({
                     "url": url,
                     "error": format!("Request failed: {}", e),
                     "success": false
-                }),
+                })),
             })
             .collect();
 
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 
     #[cfg(not(feature = "parallel"))]
@@ -2533,7 +2662,7 @@ fn builtin_core_http_get_parallel(args: &[Value]) -> SusumuResult<Value> {
         // Fallback to sequential requests if parallel feature not enabled
         let mut results = Vec::new();
         for &url in &url_strings {
-            let result = match reqwest::blocking::get(url) {
+            let result = match client.get(url).send() {
                 Ok(response) => {
                     let status = response.status().as_u16();
                     let headers = response
@@ -2545,35 +2674,38 @@ fn builtin_core_http_get_parallel(args: &[Value]) -> SusumuResult<Value> {
                         .collect::<std::collections::HashMap<String, String>>();
 
                     match response.text() {
-                        Ok(body) => json!({
+                        Ok(body) => Value::from(json!({
                             "url": url,
                             "status": status,
                             "body": body,
                             "headers": headers,
                             "success": true
-                        }),
-                        Err(e) => json!({
+                        })),
+                        Err(e) => Value::from(json!({
                             "url": url,
                             "error": format!("Failed to read response: {}", e),
                             "success": false
-                        }),
+                        })),
                     }
                 }
-                Err(e) => json!({
+                Err(e) => Value::from(json!({
                     "url": url,
                     "error": format!("Request failed: {}", e),
                     "success": false
-                }),
+                })),
             };
             results.push(result);
         }
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 }
 
 /// Parallel HTTP POST requests - takes array of request configs
 #[cfg(not(target_arch = "wasm32"))]
-fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
+fn builtin_core_http_post_parallel(
+    client: &reqwest::blocking::Client,
+    args: &[Value],
+) -> SusumuResult<Value> {
     if args.len() != 1 {
         return Err(SusumuError::runtime_error(
             "httpPostParallel() expects exactly 1 argument (array of request configs)",
@@ -2594,27 +2726,26 @@ fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
                 let config_obj = match config.as_object() {
                     Some(obj) => obj,
                     None => {
-                        return json!({
+                        return Value::from(json!({
                             "error": "Each config must be an object with 'url' and 'data' fields",
                             "success": false
-                        })
+                        }))
                     }
                 };
 
                 let url = match config_obj.get("url").and_then(|v| v.as_str()) {
                     Some(url) => url,
                     None => {
-                        return json!({
+                        return Value::from(json!({
                             "error": "Config must include 'url' field",
                             "success": false
-                        })
+                        }))
                     }
                 };
 
-                let default_data = json!({});
+                let default_data = Value::Object(Map::new());
                 let data = config_obj.get("data").unwrap_or(&default_data);
 
-                let client = reqwest::blocking::Client::new();
                 match client.post(url).json(data).send() {
                     Ok(response) => {
                         let status = response.status().as_u16();
@@ -2627,30 +2758,30 @@ fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
                             .collect::<std::collections::HashMap<String, String>>();
 
                         match response.text() {
-                            Ok(body) => json!({
+                            Ok(body) => Value::from(json!({
                                 "url": url,
                                 "status": status,
                                 "body": body,
                                 "headers": headers,
                                 "success": true
-                            }),
-                            Err(e) => json!({
+                            })),
+                            Err(e) => Value::from(json!({
                                 "url": url,
                                 "error": format!("Failed to read response: {}", e),
                                 "success": false
-                            }),
+                            })),
                         }
                     }
-                    Err(e) => json!({
+                    Err(e) => Value::from(json!({
                         "url": url,
                         "error": format!("Request failed: {}", e),
                         "success": false
-                    }),
+                    })),
                 }
             })
             .collect();
 
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 
     #[cfg(not(feature = "parallel"))]
@@ -2661,10 +2792,10 @@ fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
             let config_obj = match config.as_object() {
                 Some(obj) => obj,
                 None => {
-                    results.push(json!({
+                    results.push(Value::from(json!({
                         "error": "Each config must be an object with 'url' and 'data' fields",
                         "success": false
-                    }));
+                    })));
                     continue;
                 }
             };
@@ -2672,18 +2803,17 @@ fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
             let url = match config_obj.get("url").and_then(|v| v.as_str()) {
                 Some(url) => url,
                 None => {
-                    results.push(json!({
+                    results.push(Value::from(json!({
                         "error": "Config must include 'url' field",
                         "success": false
-                    }));
+                    })));
                     continue;
                 }
             };
 
-            let default_data = json!({});
+            let default_data = Value::Object(Map::new());
             let data = config_obj.get("data").unwrap_or(&default_data);
 
-            let client = reqwest::blocking::Client::new();
             let result = match client.post(url).json(data).send() {
                 Ok(response) => {
                     let status = response.status().as_u16();
@@ -2696,29 +2826,29 @@ fn builtin_core_http_post_parallel(args: &[Value]) -> SusumuResult<Value> {
                         .collect::<std::collections::HashMap<String, String>>();
 
                     match response.text() {
-                        Ok(body) => json!({
+                        Ok(body) => Value::from(json!({
                             "url": url,
                             "status": status,
                             "body": body,
                             "headers": headers,
                             "success": true
-                        }),
-                        Err(e) => json!({
+                        })),
+                        Err(e) => Value::from(json!({
                             "url": url,
                             "error": format!("Failed to read response: {}", e),
                             "success": false
-                        }),
+                        })),
                     }
                 }
-                Err(e) => json!({
+                Err(e) => Value::from(json!({
                     "url": url,
                     "error": format!("Request failed: {}", e),
                     "success": false
-                }),
+                })),
             };
             results.push(result);
         }
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 }
 
@@ -2755,21 +2885,21 @@ fn builtin_core_read_files_parallel(args: &[Value]) -> SusumuResult<Value> {
         let results: Vec<Value> = path_strings
             .par_iter()
             .map(|&path| match std::fs::read_to_string(path) {
-                Ok(content) => json!({
+                Ok(content) => Value::from(json!({
                     "path": path,
                     "content": content,
                     "success": true,
                     "size": content.len()
-                }),
-                Err(e) => json!({
+                })),
+                Err(e) => Value::from(json!({
                     "path": path,
                     "error": format!("Failed to read file: {}", e),
                     "success": false
-                }),
+                })),
             })
             .collect();
 
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 
     #[cfg(not(feature = "parallel"))]
@@ -2777,21 +2907,21 @@ fn builtin_core_read_files_parallel(args: &[Value]) -> SusumuResult<Value> {
         let mut results = Vec::new();
         for &path in &path_strings {
             let result = match std::fs::read_to_string(path) {
-                Ok(content) => json!({
+                Ok(content) => Value::from(json!({
                     "path": path,
                     "content": content,
                     "success": true,
                     "size": content.len()
-                }),
-                Err(e) => json!({
+                })),
+                Err(e) => Value::from(json!({
                     "path": path,
                     "error": format!("Failed to read file: {}", e),
                     "success": false
-                }),
+                })),
             };
             results.push(result);
         }
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 }
 
@@ -2820,14 +2950,14 @@ fn builtin_core_map_parallel(args: &[Value]) -> SusumuResult<Value> {
             .map(|item| match operation {
                 "double" => {
                     if let Some(n) = item.as_f64() {
-                        json!(n * 2.0)
+                        Value::from(n * 2.0)
                     } else {
                         item.clone()
                     }
                 }
                 "square" => {
                     if let Some(n) = item.as_f64() {
-                        json!(n * n)
+                        Value::from(n * n)
                     } else {
                         item.clone()
                     }
@@ -2836,7 +2966,7 @@ fn builtin_core_map_parallel(args: &[Value]) -> SusumuResult<Value> {
             })
             .collect();
 
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 
     #[cfg(not(feature = "parallel"))]
@@ -2846,14 +2976,14 @@ fn builtin_core_map_parallel(args: &[Value]) -> SusumuResult<Value> {
             .map(|item| match operation {
                 "double" => {
                     if let Some(n) = item.as_f64() {
-                        json!(n * 2.0)
+                        Value::from(n * 2.0)
                     } else {
                         item.clone()
                     }
                 }
                 "square" => {
                     if let Some(n) = item.as_f64() {
-                        json!(n * n)
+                        Value::from(n * n)
                     } else {
                         item.clone()
                     }
@@ -2862,7 +2992,7 @@ fn builtin_core_map_parallel(args: &[Value]) -> SusumuResult<Value> {
             })
             .collect();
 
-        Ok(json!(results))
+        Ok(Value::from(results))
     }
 }
 
@@ -2882,7 +3012,7 @@ fn builtin_to_radians(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("toRadians() expects a number"))?;
 
-    Ok(json!(degrees * std::f64::consts::PI / 180.0))
+    Ok(Value::from(degrees * std::f64::consts::PI / 180.0))
 }
 
 /// Apply sine function
@@ -2897,7 +3027,7 @@ fn builtin_apply_sin(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("applySin() expects a number"))?;
 
-    Ok(json!(radians.sin()))
+    Ok(Value::from(radians.sin()))
 }
 
 /// Apply cosine function
@@ -2912,7 +3042,7 @@ fn builtin_apply_cos(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("applyCos() expects a number"))?;
 
-    Ok(json!(radians.cos()))
+    Ok(Value::from(radians.cos()))
 }
 
 /// Apply tangent function
@@ -2927,7 +3057,7 @@ fn builtin_apply_tan(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("applyTan() expects a number"))?;
 
-    Ok(json!(radians.tan()))
+    Ok(Value::from(radians.tan()))
 }
 
 /// Less than or equal comparison
@@ -2945,7 +3075,7 @@ fn builtin_less_than_or_equal(args: &[Value]) -> SusumuResult<Value> {
         .as_f64()
         .ok_or_else(|| SusumuError::runtime_error("lessThanOrEqual() expects numbers"))?;
 
-    Ok(json!(a <= b))
+    Ok(Value::from(a <= b))
 }
 
 /// Split string into parts (used by string module)
@@ -2963,9 +3093,9 @@ fn builtin_perform_split(args: &[Value]) -> SusumuResult<Value> {
         .as_str()
         .ok_or_else(|| SusumuError::runtime_error("performSplit() expects delimiter string"))?;
 
-    let parts: Vec<Value> = text.split(delimiter).map(|s| json!(s)).collect();
+    let parts: Vec<Value> = text.split(delimiter).map(Value::from).collect();
 
-    Ok(json!(parts))
+    Ok(Value::from(parts))
 }
 
 /// Split array into chunks (used by array module)
@@ -2990,7 +3120,10 @@ fn builtin_split_into_chunks(args: &[Value]) -> SusumuResult<Value> {
         ));
     }
 
-    let chunks: Vec<Value> = array.chunks(chunk_size).map(|chunk| json!(chunk)).collect();
+    let chunks: Vec<Value> = array
+        .chunks(chunk_size)
+        .map(|chunk| Value::Array(chunk.to_vec()))
+        .collect();
 
-    Ok(json!(chunks))
+    Ok(Value::from(chunks))
 }
@@ -0,0 +1,1265 @@
+//! Bytecode compiler for the Susumu virtual machine
+//!
+//! Lowers function bodies and the main expression to a flat instruction stream for the
+//! stack VM in [`crate::vm`]. Variables a function only uses itself are resolved to frame
+//! slots at compile time; variables that lambdas capture, and everything touched by the
+//! few constructs the VM hands back to the tree-walker, stay in runtime environments so
+//! both backends observe the same scopes.
+
+use crate::ast::*;
+use crate::error::Location;
+use crate::interpreter::LAMBDA_NAME;
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// A single VM instruction. Jump targets are instruction indices within the chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// Push `constants[index]`
+    Constant(u32),
+    Pop,
+    Dup,
+    Swap,
+    /// Push a local slot, falling back to shadowed slots and then the environment
+    LoadLocal(u32),
+    /// Pop into a local slot
+    StoreLocal(u32),
+    /// Look a name up in the environment (variables, then functions)
+    LoadName(u32),
+    /// Pop and define a variable in the current scope environment
+    DefineName {
+        name: u32,
+        mutable: bool,
+    },
+    /// Pop `count` values and join them as displayed into a string
+    Interpolate(u32),
+    /// Pop `count` values into an array
+    MakeArray(u32),
+    /// Pop one value per key of `shapes[index]` into an object
+    MakeObject(u32),
+    MakeSome,
+    MakeSuccess,
+    MakeError,
+    /// Create a function value for `lambdas[index]` closing over the current scope
+    MakeLambda(u32),
+    GetProperty(u32),
+    /// Pop an index and the value it indexes
+    GetIndex,
+    /// Pop the bounds that are present and the value they slice
+    GetSlice {
+        start: bool,
+        end: bool,
+    },
+    /// Replace a null or none value on top of the stack with null and jump
+    SkipIfAbsent(u32),
+    Binary(BinaryOperator),
+    Unary(UnaryOperator),
+    /// Call a function by name with `argc` arguments from the stack
+    CallName {
+        name: u32,
+        argc: u32,
+    },
+    /// Call the function held by a local slot, or the function of that name
+    CallLocal {
+        slot: u32,
+        name: u32,
+        argc: u32,
+    },
+    /// Call the function value below `argc` arguments
+    CallValue {
+        argc: u32,
+    },
+    /// With `[flow, target]` on the stack: continue if the target is a function,
+    /// otherwise replace the flow with the target and jump
+    FlowInto(u32),
+    /// Arrow chain with a `<-` that no function consumes
+    UnexpectedBackwardArrow,
+    Jump(u32),
+    /// Pop and jump if not truthy
+    JumpIfFalse(u32),
+    /// Push whether the value on top of the stack passes `conditions[index]`
+    TestCondition(u32),
+    /// Leave the function with the value on top of the stack
+    Return,
+    /// Raise the value on top of the stack as a control-flow error
+    Raise(RaiseKind),
+    /// Reset the slots of `scopes[index]` and push its environment if it has one
+    EnterScope(u32),
+    ExitScope(u32),
+    /// Pop an array and start a loop over it that `break` leaves at `exit`
+    ForEachStart {
+        label: Option<u32>,
+        exit: u32,
+    },
+    /// Pop the end and start of a range and start a loop counting through it
+    ForEachRange {
+        label: Option<u32>,
+        exit: u32,
+    },
+    /// Push the next item of the innermost loop, or jump when it is exhausted
+    ForEachNext(u32),
+    /// Pop an iteration result into the innermost loop's results
+    ForEachCollect,
+    /// Finish the innermost loop and push its results, or the value it broke with
+    ForEachEnd,
+    /// Start a while loop whose last result is on top of the stack
+    WhileStart {
+        label: Option<u32>,
+        exit: u32,
+    },
+    WhileEnd,
+    /// Leave the loop with this label, or the innermost loop, with the popped value
+    /// when there is one
+    Break {
+        label: Option<u32>,
+        value: bool,
+    },
+    /// Start the next pass of the loop with this label, or of the innermost loop
+    Continue {
+        label: Option<u32>,
+        value: bool,
+    },
+    /// Match the value on top of the stack against `patterns[pattern]`, keeping its
+    /// bindings for `Bind`, or jump to `fail`
+    MatchPattern {
+        pattern: u32,
+        fail: u32,
+    },
+    /// Match the value on top of the stack against `patterns[pattern]`, keeping its
+    /// bindings for `Bind`, or fail because it has another shape
+    Destructure(u32),
+    /// Store a binding of the last successful match
+    Bind {
+        name: u32,
+        target: Target,
+        mutable: bool,
+    },
+    NoMatch,
+    /// Catch errors raised before the matching `PopHandler` and continue at the target
+    PushHandler(u32),
+    PopHandler,
+    /// Evaluate `fallbacks[index]` with the tree-walker in the current environment
+    Eval(u32),
+    /// Match the value on top of the stack with the tree-walker using `fallbacks[index]`
+    EvalMatch(u32),
+}
+
+/// Control-flow statements that unwind as errors, as they do in the tree-walker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaiseKind {
+    Return,
+    Success,
+    Error,
+    ErrorReturn,
+}
+
+/// Where a variable lives at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Local(u32),
+    Name(u32),
+}
+
+/// Slots cleared when entering a scope, and whether it needs its own environment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeInfo {
+    pub first_slot: u32,
+    pub slot_count: u32,
+    pub has_env: bool,
+}
+
+/// Debug information for a local slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub name: u32,
+    /// Outer slot with the same name, read while this one is unset
+    pub shadows: Option<u32>,
+}
+
+/// A lambda literal compiled ahead of time
+#[derive(Debug, Clone, PartialEq)]
+pub struct LambdaProto {
+    pub def: FunctionDef,
+    pub chunk: Arc<Chunk>,
+}
+
+/// Compiled code for one function body or main expression
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub name: String,
+    pub code: Vec<Instruction>,
+    /// Source location each instruction was compiled from, for errors and stack frames
+    pub locations: Vec<Location>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub shapes: Vec<Vec<String>>,
+    pub conditions: Vec<ConditionType>,
+    pub patterns: Vec<Pattern>,
+    pub lambdas: Vec<Arc<LambdaProto>>,
+    pub fallbacks: Vec<Expression>,
+    pub scopes: Vec<ScopeInfo>,
+    pub slots: Vec<SlotInfo>,
+    /// Where each parameter is bound on entry
+    pub params: Vec<Target>,
+    /// Whether calls need a fresh environment for the function scope
+    pub function_env: bool,
+    /// Whether this chunk is the main expression, which runs in the global scope
+    pub is_main: bool,
+}
+
+impl Chunk {
+    /// Number of local slots a frame needs
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Human-readable listing of the instructions
+    pub fn disassemble(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "== {} ({} slots) ==", self.name, self.slots.len())?;
+        for (index, instruction) in self.code.iter().enumerate() {
+            write!(f, "{:04} {:?}", index, instruction)?;
+            match instruction {
+                Instruction::Constant(i) => write!(f, "  ; {}", self.constants[*i as usize])?,
+                Instruction::LoadName(i)
+                | Instruction::DefineName { name: i, .. }
+                | Instruction::GetProperty(i)
+                | Instruction::CallName { name: i, .. }
+                | Instruction::CallLocal { name: i, .. } => {
+                    write!(f, "  ; {}", self.names[*i as usize])?
+                }
+                Instruction::LoadLocal(slot) | Instruction::StoreLocal(slot) => {
+                    let name = self.slots[*slot as usize].name;
+                    write!(f, "  ; {}", self.names[name as usize])?
+                }
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        for lambda in &self.lambdas {
+            write!(f, "{}", lambda.chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// A lexical scope while compiling
+struct CompileScope {
+    names: HashMap<String, Target>,
+    info: u32,
+}
+
+/// Compiles AST to [`Chunk`]s
+pub struct Compiler {
+    chunk: Chunk,
+    scopes: Vec<CompileScope>,
+    /// Names used by lambdas or tree-walker fallbacks, which must stay in environments
+    captured: HashSet<String>,
+    /// Depth of `|` default handlers; returns inside them must unwind as errors
+    handler_depth: usize,
+    /// Source location recorded for emitted instructions
+    location: Location,
+}
+
+impl Compiler {
+    fn new(name: &str, body: &Expression, is_main: bool) -> Self {
+        let mut captured = HashSet::new();
+        collect_captured(body, &mut captured);
+        Self {
+            chunk: Chunk {
+                name: name.to_string(),
+                is_main,
+                ..Chunk::default()
+            },
+            scopes: Vec::new(),
+            captured,
+            handler_depth: 0,
+            location: Location::new(body.span, None),
+        }
+    }
+
+    /// Compile a named function or lambda body
+    pub fn compile_function(def: &FunctionDef) -> Chunk {
+        let mut compiler = Self::new(&def.name, &def.body, false);
+        let info = compiler.begin_scope();
+        for param in &def.params {
+            let target = compiler.declare(&param.name);
+            compiler.chunk.params.push(target);
+        }
+        // Arguments that do not fit their pattern are reported at the definition
+        compiler.location = Location::new(def.span, None);
+        for param in &def.params {
+            if let Some(pattern) = &param.pattern {
+                compiler.load(&param.name);
+                compiler.destructure(pattern, false);
+                compiler.emit(Instruction::Pop);
+            }
+        }
+        compiler.location = Location::new(def.body.span, None);
+        compiler.declare_assignments(&def.body);
+        compiler.expression(&def.body);
+        compiler.emit(Instruction::Return);
+        compiler.chunk.function_env = compiler.chunk.scopes[info as usize].has_env;
+        compiler.scopes.pop();
+        compiler.chunk
+    }
+
+    /// Compile the main expression of a program, whose variables live in the global scope
+    pub fn compile_main(expr: &Expression) -> Chunk {
+        let mut compiler = Self::new("<main>", expr, true);
+        compiler.begin_scope();
+        compiler.declare_assignments(expr);
+        compiler.expression(expr);
+        compiler.emit(Instruction::Return);
+        compiler.scopes.pop();
+        compiler.chunk
+    }
+
+    // === Scopes and variables ===
+
+    fn begin_scope(&mut self) -> u32 {
+        let info = self.chunk.scopes.len() as u32;
+        self.chunk.scopes.push(ScopeInfo {
+            first_slot: self.chunk.slots.len() as u32,
+            ..ScopeInfo::default()
+        });
+        self.scopes.push(CompileScope {
+            names: HashMap::new(),
+            info,
+        });
+        info
+    }
+
+    fn current_scope_info(&mut self) -> &mut ScopeInfo {
+        let info = self.scopes.last().expect("no scope").info;
+        &mut self.chunk.scopes[info as usize]
+    }
+
+    /// Declare a variable in the innermost scope, reusing an existing declaration
+    fn declare(&mut self, name: &str) -> Target {
+        if let Some(target) = self.scopes.last().and_then(|scope| scope.names.get(name)) {
+            return *target;
+        }
+
+        let name_index = self.name(name);
+        let global_scope = self.chunk.is_main && self.scopes.len() == 1;
+        let target = if global_scope || self.captured.contains(name) {
+            if !global_scope {
+                self.current_scope_info().has_env = true;
+            }
+            Target::Name(name_index)
+        } else {
+            let shadows = match self.resolve(name) {
+                Some(Target::Local(slot)) => Some(slot),
+                _ => None,
+            };
+            let slot = self.chunk.slots.len() as u32;
+            self.chunk.slots.push(SlotInfo {
+                name: name_index,
+                shadows,
+            });
+            self.current_scope_info().slot_count += 1;
+            Target::Local(slot)
+        };
+
+        self.scopes
+            .last_mut()
+            .expect("no scope")
+            .names
+            .insert(name.to_string(), target);
+        target
+    }
+
+    /// Declare every variable assigned directly in this scope before compiling it, so reads
+    /// that run before the assignment (in loops) see the same slot
+    fn declare_assignments(&mut self, expr: &Expression) {
+        let mut names = Vec::new();
+        collect_assignments(expr, &mut names);
+        for name in names {
+            self.declare(&name);
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<Target> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(name).copied())
+    }
+
+    fn load(&mut self, name: &str) {
+        match self.resolve(name) {
+            Some(Target::Local(slot)) => self.emit(Instruction::LoadLocal(slot)),
+            _ => {
+                let name = self.name(name);
+                self.emit(Instruction::LoadName(name))
+            }
+        };
+    }
+
+    fn store(&mut self, target: Target, mutable: bool) {
+        match target {
+            Target::Local(slot) => self.emit(Instruction::StoreLocal(slot)),
+            Target::Name(name) => self.emit(Instruction::DefineName { name, mutable }),
+        };
+    }
+
+    fn call(&mut self, name: &str, argc: usize) {
+        let argc = argc as u32;
+        let name_index = self.name(name);
+        match self.resolve(name) {
+            Some(Target::Local(slot)) => self.emit(Instruction::CallLocal {
+                slot,
+                name: name_index,
+                argc,
+            }),
+            _ => self.emit(Instruction::CallName {
+                name: name_index,
+                argc,
+            }),
+        };
+    }
+
+    // === Chunk tables ===
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.locations.push(self.location);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Point the jump at `index` to the next instruction
+    fn patch(&mut self, index: usize) {
+        let target = self.here();
+        match &mut self.chunk.code[index] {
+            Instruction::Jump(to)
+            | Instruction::JumpIfFalse(to)
+            | Instruction::SkipIfAbsent(to)
+            | Instruction::FlowInto(to)
+            | Instruction::ForEachNext(to)
+            | Instruction::ForEachStart { exit: to, .. }
+            | Instruction::ForEachRange { exit: to, .. }
+            | Instruction::WhileStart { exit: to, .. }
+            | Instruction::PushHandler(to)
+            | Instruction::MatchPattern { fail: to, .. } => *to = target,
+            other => unreachable!("cannot patch {:?}", other),
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        let index = self.chunk.constants.len() as u32;
+        self.chunk.constants.push(value);
+        self.emit(Instruction::Constant(index));
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        match self
+            .chunk
+            .names
+            .iter()
+            .position(|existing| existing == name)
+        {
+            Some(index) => index as u32,
+            None => {
+                self.chunk.names.push(name.to_string());
+                (self.chunk.names.len() - 1) as u32
+            }
+        }
+    }
+
+    fn fallback(&mut self, expr: &Expression) -> u32 {
+        // The tree-walker may define variables in whatever scope it runs in
+        self.current_scope_info().has_env = true;
+        self.chunk.fallbacks.push(expr.clone());
+        (self.chunk.fallbacks.len() - 1) as u32
+    }
+
+    // === Expressions ===
+
+    fn expression(&mut self, expr: &Expression) {
+        let outer = self.location;
+        if !expr.span.is_unknown() {
+            self.location = Location::new(expr.span, None);
+        }
+        self.compile_expression(expr);
+        self.location = outer;
+    }
+
+    fn compile_expression(&mut self, expr: &Expression) {
+        if needs_fallback(expr) {
+            let index = self.fallback(expr);
+            self.emit(Instruction::Eval(index));
+            return;
+        }
+
+        match &expr.kind {
+            ExpressionKind::Integer(n) => self.constant(Value::Int(*n)),
+            ExpressionKind::Number(n) => self.constant(Value::Float(*n)),
+            ExpressionKind::String(s) => self.constant(Value::String(s.clone())),
+            ExpressionKind::InterpolatedString(parts) => {
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => self.constant(Value::String(text.clone())),
+                        StringPart::Expression(expression) => self.expression(expression),
+                    }
+                }
+                self.emit(Instruction::Interpolate(parts.len() as u32));
+            }
+            ExpressionKind::Boolean(b) => self.constant(Value::Bool(*b)),
+            ExpressionKind::Null => self.constant(Value::Null),
+
+            ExpressionKind::Identifier(name) => self.load(name),
+
+            ExpressionKind::Tuple(elements) | ExpressionKind::Array(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+                self.emit(Instruction::MakeArray(elements.len() as u32));
+            }
+
+            ExpressionKind::Object(pairs) => {
+                for (_, value) in pairs {
+                    self.expression(value);
+                }
+                let keys = pairs.iter().map(|(key, _)| key.clone()).collect();
+                self.chunk.shapes.push(keys);
+                self.emit(Instruction::MakeObject(self.chunk.shapes.len() as u32 - 1));
+            }
+
+            ExpressionKind::ArrowChain {
+                expressions,
+                directions,
+            } => self.arrow_chain(expressions, directions),
+
+            ExpressionKind::FunctionCall { name, args } => {
+                for arg in args {
+                    self.expression(arg);
+                }
+                self.call(name, args.len());
+            }
+
+            ExpressionKind::Lambda { params, body } => {
+                let def = FunctionDef {
+                    name: LAMBDA_NAME.to_string(),
+                    params: params.clone(),
+                    return_type: None,
+                    body: (**body).clone(),
+                    span: expr.span,
+                };
+                let chunk = Arc::new(Compiler::compile_function(&def));
+                self.chunk
+                    .lambdas
+                    .push(Arc::new(LambdaProto { def, chunk }));
+                self.emit(Instruction::MakeLambda(self.chunk.lambdas.len() as u32 - 1));
+            }
+
+            ExpressionKind::Conditional {
+                condition_type,
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.conditional(condition_type, then_branch, else_if_branches, else_branch);
+            }
+
+            ExpressionKind::Return(value) => self.control_flow(value, RaiseKind::Return),
+            ExpressionKind::Success(value) => self.control_flow(value, RaiseKind::Success),
+            ExpressionKind::Error(value) => self.control_flow(value, RaiseKind::Error),
+            ExpressionKind::ErrorReturn(value) => self.control_flow(value, RaiseKind::ErrorReturn),
+
+            ExpressionKind::ForEach {
+                label,
+                pattern,
+                iterable,
+                body,
+            } => self.for_each(label, pattern, iterable, body),
+
+            ExpressionKind::While {
+                label,
+                condition,
+                body,
+            } => self.while_loop(label, condition, body),
+
+            ExpressionKind::Break { label, value } => self.loop_control(label, value, true),
+            ExpressionKind::Continue { label, value } => self.loop_control(label, value, false),
+
+            ExpressionKind::Block(expressions) => {
+                if expressions.is_empty() {
+                    self.constant(Value::Null);
+                }
+                for (i, expr) in expressions.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Instruction::Pop);
+                    }
+                    self.expression(expr);
+                }
+            }
+
+            ExpressionKind::Match { expr, cases } => {
+                match expr {
+                    Some(scrutinee) => self.expression(scrutinee),
+                    // A match outside an arrow chain has nothing to match against
+                    None => self.constant(Value::Null),
+                }
+                self.match_cases(cases);
+            }
+
+            ExpressionKind::Maybe { value } => match value {
+                Some(value) => {
+                    self.expression(value);
+                    self.emit(Instruction::MakeSome);
+                }
+                None => self.constant(Value::none()),
+            },
+
+            ExpressionKind::Result { is_success, value } => {
+                self.expression(value);
+                self.emit(if *is_success {
+                    Instruction::MakeSuccess
+                } else {
+                    Instruction::MakeError
+                });
+            }
+
+            ExpressionKind::Assignment {
+                target,
+                value,
+                mutable,
+            } => {
+                self.expression(value);
+                // Assignments evaluate to the assigned value
+                self.emit(Instruction::Dup);
+                let target = self.declare(target);
+                self.store(target, *mutable);
+            }
+            ExpressionKind::Destructure {
+                pattern,
+                value,
+                mutable,
+            } => {
+                self.expression(value);
+                self.emit(Instruction::Dup);
+                self.destructure(pattern, *mutable);
+                self.emit(Instruction::Pop);
+            }
+
+            ExpressionKind::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => {
+                self.expression(object);
+                let skip = self.skip_if_absent(*optional);
+                let name = self.name(property);
+                self.emit(Instruction::GetProperty(name));
+                if let Some(skip) = skip {
+                    self.patch(skip);
+                }
+            }
+
+            ExpressionKind::Index {
+                object,
+                index,
+                optional,
+            } => {
+                self.expression(object);
+                let skip = self.skip_if_absent(*optional);
+                self.expression(index);
+                self.emit(Instruction::GetIndex);
+                if let Some(skip) = skip {
+                    self.patch(skip);
+                }
+            }
+
+            ExpressionKind::Slice {
+                object,
+                start,
+                end,
+                optional,
+            } => {
+                self.expression(object);
+                let skip = self.skip_if_absent(*optional);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expression(bound);
+                }
+                self.emit(Instruction::GetSlice {
+                    start: start.is_some(),
+                    end: end.is_some(),
+                });
+                if let Some(skip) = skip {
+                    self.patch(skip);
+                }
+            }
+
+            ExpressionKind::BinaryOp {
+                left,
+                operator,
+                right,
+            } => match operator {
+                BinaryOperator::And | BinaryOperator::Or => self.logical(left, operator, right),
+                _ => {
+                    self.expression(left);
+                    self.expression(right);
+                    self.emit(Instruction::Binary(operator.clone()));
+                }
+            },
+
+            ExpressionKind::UnaryOp { operator, operand } => {
+                self.expression(operand);
+                self.emit(Instruction::Unary(operator.clone()));
+            }
+
+            ExpressionKind::ErrorPropagation { expression } => self.expression(expression),
+
+            ExpressionKind::DefaultValue {
+                expression,
+                default,
+            } => {
+                let handler = self.emit(Instruction::PushHandler(0));
+                self.handler_depth += 1;
+                self.expression(expression);
+                self.handler_depth -= 1;
+                self.emit(Instruction::PopHandler);
+                let done = self.emit(Instruction::Jump(0));
+                self.patch(handler);
+                self.expression(default);
+                self.patch(done);
+            }
+
+            ExpressionKind::ObjectMutation { .. } | ExpressionKind::Annotated { .. } => {
+                unreachable!("handled by the tree-walker fallback")
+            }
+        }
+    }
+
+    /// For `?.` and `?[`, jump over the access when the object is null or none
+    fn skip_if_absent(&mut self, optional: bool) -> Option<usize> {
+        optional.then(|| self.emit(Instruction::SkipIfAbsent(0)))
+    }
+
+    /// `&&` and `||` as jumps, so the right operand only runs when it decides the result
+    fn logical(&mut self, left: &Expression, operator: &BinaryOperator, right: &Expression) {
+        self.expression(left);
+        let left_false = self.emit(Instruction::JumpIfFalse(0));
+        let mut done = Vec::new();
+        if *operator == BinaryOperator::Or {
+            self.constant(Value::Bool(true));
+            done.push(self.emit(Instruction::Jump(0)));
+            self.patch(left_false);
+        }
+        self.expression(right);
+        let right_false = self.emit(Instruction::JumpIfFalse(0));
+        self.constant(Value::Bool(true));
+        done.push(self.emit(Instruction::Jump(0)));
+        if *operator == BinaryOperator::And {
+            self.patch(left_false);
+        }
+        self.patch(right_false);
+        self.constant(Value::Bool(false));
+        for jump in done {
+            self.patch(jump);
+        }
+    }
+
+    fn control_flow(&mut self, value: &Expression, kind: RaiseKind) {
+        self.expression(value);
+        // Plain returns leave the frame directly unless a `|` handler could observe them
+        let direct = !self.chunk.is_main
+            && self.handler_depth == 0
+            && matches!(kind, RaiseKind::Return | RaiseKind::Success);
+        if direct {
+            self.emit(Instruction::Return);
+        } else {
+            self.emit(Instruction::Raise(kind));
+        }
+    }
+
+    /// `x -> f <- a <- b` calls `f(x, a, b)`; mirrors the tree-walker's arrow chain rules
+    fn arrow_chain(&mut self, expressions: &[Expression], directions: &[ArrowDirection]) {
+        if expressions.is_empty() {
+            self.constant(Value::Null);
+            return;
+        }
+
+        self.expression(&expressions[0]);
+        let mut i = 0;
+        while i < directions.len() {
+            if directions[i] == ArrowDirection::Backward {
+                self.emit(Instruction::UnexpectedBackwardArrow);
+                return;
+            }
+
+            let current = &expressions[i + 1];
+            // Calls made by this step report its location; `expression` restores the outer one
+            if !current.span.is_unknown() {
+                self.location = Location::new(current.span, Some(i + 1));
+            }
+            match &current.kind {
+                ExpressionKind::Identifier(name) => {
+                    let end = self.convergent_args(expressions, directions, i + 1);
+                    self.call(name, end.1 + 1);
+                    i = end.0;
+                }
+                ExpressionKind::Match { expr: None, cases } => {
+                    if needs_fallback(current) {
+                        let index = self.fallback(current);
+                        self.emit(Instruction::EvalMatch(index));
+                    } else {
+                        self.match_cases(cases);
+                    }
+                    i += 1;
+                }
+                ExpressionKind::Conditional {
+                    condition_type,
+                    condition,
+                    then_branch,
+                    else_if_branches,
+                    else_branch,
+                } if condition.kind == ExpressionKind::Null => {
+                    // `value -> i cond { } e { }` tests the flowing value
+                    self.conditional(condition_type, then_branch, else_if_branches, else_branch);
+                    i += 1;
+                }
+                ExpressionKind::Match { .. } | ExpressionKind::Conditional { .. } => {
+                    self.emit(Instruction::Pop);
+                    self.expression(current);
+                    i += 1;
+                }
+                _ => {
+                    // Function values receive the flow; any other value replaces it
+                    self.expression(current);
+                    let not_function = self.emit(Instruction::FlowInto(0));
+                    self.emit(Instruction::Swap);
+                    let (end, argc) = self.convergent_args(expressions, directions, i + 1);
+                    self.emit(Instruction::CallValue {
+                        argc: argc as u32 + 1,
+                    });
+                    self.patch(not_function);
+                    i = end;
+                }
+            }
+        }
+    }
+
+    /// Compile the `<-` arguments after the function at `func_index`.
+    /// Returns the index of the last direction consumed and the argument count.
+    fn convergent_args(
+        &mut self,
+        expressions: &[Expression],
+        directions: &[ArrowDirection],
+        func_index: usize,
+    ) -> (usize, usize) {
+        let mut j = func_index;
+        let mut argc = 0;
+        while j < directions.len() && directions[j] == ArrowDirection::Backward {
+            j += 1;
+            if j < expressions.len() {
+                self.expression(&expressions[j]);
+                argc += 1;
+            }
+        }
+        (j, argc)
+    }
+
+    /// Branch on the condition value on top of the stack
+    fn conditional(
+        &mut self,
+        condition_type: &ConditionType,
+        then_branch: &Expression,
+        else_if_branches: &[ElseIfBranch],
+        else_branch: &Option<Box<Expression>>,
+    ) {
+        let branches = std::iter::once((condition_type, then_branch)).chain(
+            else_if_branches
+                .iter()
+                .map(|branch| (&branch.condition_type, &branch.then_branch)),
+        );
+
+        let mut exits = Vec::new();
+        for (condition_type, branch) in branches {
+            match condition_type {
+                ConditionType::Expression(test) => self.expression(test),
+                other => {
+                    self.chunk.conditions.push(other.clone());
+                    let index = self.chunk.conditions.len() as u32 - 1;
+                    self.emit(Instruction::TestCondition(index));
+                }
+            }
+            let skip = self.emit(Instruction::JumpIfFalse(0));
+            self.emit(Instruction::Pop);
+            self.expression(branch);
+            exits.push(self.emit(Instruction::Jump(0)));
+            self.patch(skip);
+        }
+
+        self.emit(Instruction::Pop);
+        match else_branch {
+            Some(else_branch) => self.expression(else_branch),
+            None => self.constant(Value::Null),
+        }
+        for exit in exits {
+            self.patch(exit);
+        }
+    }
+
+    /// Match the value on top of the stack against each case in order
+    fn match_cases(&mut self, cases: &[MatchCase]) {
+        let mut exits = Vec::new();
+        for case in cases {
+            self.chunk.patterns.push(case.pattern.clone());
+            let pattern = self.chunk.patterns.len() as u32 - 1;
+            let next = self.emit(Instruction::MatchPattern { pattern, fail: 0 });
+            self.emit(Instruction::Pop);
+
+            let scope = self.begin_scope();
+            let mut bindings = Vec::new();
+            collect_pattern_bindings(&case.pattern, &mut bindings);
+            let mut binds = Vec::new();
+            for binding in &bindings {
+                let target = self.declare(binding);
+                binds.push((self.name(binding), target));
+            }
+            self.declare_assignments(&case.body);
+
+            self.emit(Instruction::EnterScope(scope));
+            for (name, target) in binds {
+                self.emit(Instruction::Bind {
+                    name,
+                    target,
+                    mutable: false,
+                });
+            }
+            self.expression(&case.body);
+            self.emit(Instruction::ExitScope(scope));
+            self.scopes.pop();
+
+            exits.push(self.emit(Instruction::Jump(0)));
+            self.patch(next);
+        }
+        self.emit(Instruction::NoMatch);
+        for exit in exits {
+            self.patch(exit);
+        }
+    }
+
+    /// Bind the names of `pattern` to the parts of the value on top of the stack,
+    /// which are declared in the current scope
+    fn destructure(&mut self, pattern: &Pattern, mutable: bool) {
+        self.chunk.patterns.push(pattern.clone());
+        self.emit(Instruction::Destructure(
+            self.chunk.patterns.len() as u32 - 1,
+        ));
+        let mut bindings = Vec::new();
+        collect_pattern_bindings(pattern, &mut bindings);
+        for binding in &bindings {
+            let target = self.declare(binding);
+            let name = self.name(binding);
+            self.emit(Instruction::Bind {
+                name,
+                target,
+                mutable,
+            });
+        }
+    }
+
+    fn for_each(
+        &mut self,
+        label: &Option<String>,
+        pattern: &Pattern,
+        iterable: &Expression,
+        body: &Expression,
+    ) {
+        let label = label.as_deref().map(|label| self.name(label));
+        let start = match &iterable.kind {
+            // Ranges are counted through rather than built as arrays
+            ExpressionKind::BinaryOp {
+                left,
+                operator: BinaryOperator::Range,
+                right,
+            } => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::ForEachRange { label, exit: 0 })
+            }
+            _ => {
+                self.expression(iterable);
+                self.emit(Instruction::ForEachStart { label, exit: 0 })
+            }
+        };
+        let top = self.here();
+        let exit = self.emit(Instruction::ForEachNext(0));
+
+        // Each iteration runs in a fresh scope holding the loop variables
+        let scope = self.begin_scope();
+        match pattern {
+            Pattern::Identifier(variable) => {
+                let target = self.declare(variable);
+                self.declare_assignments(body);
+                self.emit(Instruction::EnterScope(scope));
+                self.store(target, false);
+            }
+            _ => {
+                let mut bindings = Vec::new();
+                collect_pattern_bindings(pattern, &mut bindings);
+                for binding in &bindings {
+                    self.declare(binding);
+                }
+                self.declare_assignments(body);
+                self.emit(Instruction::EnterScope(scope));
+                self.destructure(pattern, false);
+                self.emit(Instruction::Pop);
+            }
+        }
+        self.expression(body);
+        self.emit(Instruction::ForEachCollect);
+        self.emit(Instruction::ExitScope(scope));
+        self.scopes.pop();
+
+        self.emit(Instruction::Jump(top));
+        self.patch(exit);
+        self.patch(start);
+        self.emit(Instruction::ForEachEnd);
+    }
+
+    fn while_loop(&mut self, label: &Option<String>, condition: &Expression, body: &Expression) {
+        let label = label.as_deref().map(|label| self.name(label));
+        self.constant(Value::Null);
+        let start = self.emit(Instruction::WhileStart { label, exit: 0 });
+        let top = self.here();
+        self.expression(condition);
+        let exit = self.emit(Instruction::JumpIfFalse(0));
+        // The body runs in the enclosing scope so its assignments persist, and its
+        // result replaces the previous one
+        self.expression(body);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::Jump(top));
+        self.patch(exit);
+        self.patch(start);
+        self.emit(Instruction::WhileEnd);
+    }
+
+    /// `break` or `continue`, which unwind to their loop as errors so that they also
+    /// leave tree-walker fallbacks
+    fn loop_control(
+        &mut self,
+        label: &Option<String>,
+        value: &Option<Box<Expression>>,
+        is_break: bool,
+    ) {
+        if let Some(value) = value {
+            self.expression(value);
+        }
+        let label = label.as_deref().map(|label| self.name(label));
+        let value = value.is_some();
+        self.emit(if is_break {
+            Instruction::Break { label, value }
+        } else {
+            Instruction::Continue { label, value }
+        });
+    }
+}
+
+/// Constructs the VM leaves to the tree-walker
+fn needs_fallback(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::ObjectMutation { .. } | ExpressionKind::Annotated { .. } => true,
+        // Guards see pattern bindings through the tree-walker's scoping rules
+        ExpressionKind::Match { cases, .. } => cases.iter().any(|case| case.guard.is_some()),
+        _ => false,
+    }
+}
+
+/// Subexpressions evaluated in the same function
+fn children(expr: &Expression) -> Vec<&Expression> {
+    match &expr.kind {
+        ExpressionKind::Integer(_)
+        | ExpressionKind::Number(_)
+        | ExpressionKind::String(_)
+        | ExpressionKind::Boolean(_)
+        | ExpressionKind::Null
+        | ExpressionKind::Identifier(_) => vec![],
+        ExpressionKind::Tuple(items)
+        | ExpressionKind::Array(items)
+        | ExpressionKind::Block(items) => items.iter().collect(),
+        ExpressionKind::InterpolatedString(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                StringPart::Expression(expression) => Some(expression),
+                StringPart::Text(_) => None,
+            })
+            .collect(),
+        ExpressionKind::Object(pairs) => pairs.iter().map(|(_, value)| value).collect(),
+        ExpressionKind::ObjectMutation { target, mutations } => std::iter::once(&**target)
+            .chain(mutations.iter().map(|(_, value)| value))
+            .collect(),
+        ExpressionKind::ArrowChain { expressions, .. } => expressions.iter().collect(),
+        ExpressionKind::FunctionCall { args, .. } => args.iter().collect(),
+        ExpressionKind::Lambda { body, .. } => vec![body],
+        ExpressionKind::Conditional {
+            condition_type,
+            condition,
+            then_branch,
+            else_if_branches,
+            else_branch,
+        } => {
+            let mut result = vec![&**condition, &**then_branch];
+            if let ConditionType::Expression(test) = condition_type {
+                result.push(test);
+            }
+            for branch in else_if_branches {
+                if let ConditionType::Expression(test) = &branch.condition_type {
+                    result.push(test);
+                }
+                result.push(&branch.condition);
+                result.push(&branch.then_branch);
+            }
+            result.extend(else_branch.as_deref());
+            result
+        }
+        ExpressionKind::Return(value)
+        | ExpressionKind::Error(value)
+        | ExpressionKind::Success(value)
+        | ExpressionKind::ErrorReturn(value)
+        | ExpressionKind::Result { value, .. }
+        | ExpressionKind::Assignment { value, .. }
+        | ExpressionKind::Destructure { value, .. } => vec![value],
+        ExpressionKind::ForEach { iterable, body, .. } => vec![iterable, body],
+        ExpressionKind::While {
+            condition, body, ..
+        } => vec![condition, body],
+        ExpressionKind::Break { value, .. } | ExpressionKind::Continue { value, .. } => {
+            value.as_deref().into_iter().collect()
+        }
+        ExpressionKind::Match { expr, cases } => {
+            let mut result: Vec<&Expression> = expr.as_deref().into_iter().collect();
+            for case in cases {
+                result.extend(case.guard.as_ref());
+                result.push(&case.body);
+            }
+            result
+        }
+        ExpressionKind::Maybe { value } => value.as_deref().into_iter().collect(),
+        ExpressionKind::PropertyAccess { object, .. } => vec![object],
+        ExpressionKind::Index { object, index, .. } => vec![object, index],
+        ExpressionKind::Slice {
+            object, start, end, ..
+        } => std::iter::once(&**object)
+            .chain(start.as_deref())
+            .chain(end.as_deref())
+            .collect(),
+        ExpressionKind::BinaryOp { left, right, .. } => vec![left, right],
+        ExpressionKind::UnaryOp { operand, .. } => vec![operand],
+        ExpressionKind::Annotated { expression, .. }
+        | ExpressionKind::ErrorPropagation { expression } => {
+            vec![expression]
+        }
+        ExpressionKind::DefaultValue {
+            expression,
+            default,
+        } => vec![expression, default],
+    }
+}
+
+/// Names referenced by lambdas and fallbacks anywhere in a function body
+fn collect_captured(expr: &Expression, captured: &mut HashSet<String>) {
+    if matches!(&expr.kind, ExpressionKind::Lambda { .. }) || needs_fallback(expr) {
+        collect_names(expr, captured);
+        return;
+    }
+    for child in children(expr) {
+        collect_captured(child, captured);
+    }
+}
+
+/// Every variable or function name mentioned in an expression
+fn collect_names(expr: &Expression, names: &mut HashSet<String>) {
+    match &expr.kind {
+        ExpressionKind::Identifier(name) | ExpressionKind::FunctionCall { name, .. } => {
+            names.insert(name.clone());
+        }
+        ExpressionKind::Assignment { target, .. } => {
+            names.insert(target.clone());
+        }
+        ExpressionKind::ForEach { pattern, .. } | ExpressionKind::Destructure { pattern, .. } => {
+            let mut bindings = Vec::new();
+            collect_pattern_bindings(pattern, &mut bindings);
+            names.extend(bindings);
+        }
+        ExpressionKind::Match { cases, .. } => {
+            for case in cases {
+                let mut bindings = Vec::new();
+                collect_pattern_bindings(&case.pattern, &mut bindings);
+                names.extend(bindings);
+            }
+        }
+        _ => {}
+    }
+    for child in children(expr) {
+        collect_names(child, names);
+    }
+}
+
+/// Assignment targets that define variables in the current scope, skipping nested scopes
+fn collect_assignments(expr: &Expression, names: &mut Vec<String>) {
+    match &expr.kind {
+        ExpressionKind::Assignment { target, value, .. } => {
+            collect_assignments(value, names);
+            if !names.contains(target) {
+                names.push(target.clone());
+            }
+        }
+        ExpressionKind::Destructure { pattern, value, .. } => {
+            collect_assignments(value, names);
+            collect_pattern_bindings(pattern, names);
+        }
+        ExpressionKind::Lambda { .. } => {}
+        _ if needs_fallback(expr) => {}
+        ExpressionKind::ForEach { iterable, .. } => collect_assignments(iterable, names),
+        ExpressionKind::Match {
+            expr: Some(scrutinee),
+            ..
+        } => collect_assignments(scrutinee, names),
+        ExpressionKind::Match { .. } => {}
+        _ => {
+            for child in children(expr) {
+                collect_assignments(child, names);
+            }
+        }
+    }
+}
+
+fn collect_pattern_bindings(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Identifier(name) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                collect_pattern_bindings(pattern, names);
+            }
+        }
+        Pattern::Object(fields) => {
+            for (_, pattern) in fields {
+                collect_pattern_bindings(pattern, names);
+            }
+        }
+        Pattern::ArrowPattern { arg, .. } => collect_pattern_bindings(arg, names),
+        Pattern::Literal(_) | Pattern::Wildcard | Pattern::Comparison { .. } => {}
+    }
+}
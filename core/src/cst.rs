@@ -0,0 +1,292 @@
+//! Lossless concrete syntax tree for Susumu source
+//!
+//! Every significant token carries the whitespace, newlines and comments around it as
+//! trivia, so the original text can be reproduced byte-for-byte. A comment or space
+//! after a token on the same line is that token's trailing trivia; everything else,
+//! including newlines and whole-line comments, leads the next token. The tree lines up
+//! with the AST through spans: the tokens of an `Expression` are those inside its span.
+
+use crate::ast::Span;
+use crate::error::SusumuResult;
+use crate::lexer::{Lexer, Token, TokenType};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+/// Source text with no meaning to the parser beyond separating statements
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A significant token with its exact source text and surrounding trivia
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyntaxToken {
+    pub kind: TokenType,
+    /// Source text of the token itself, e.g. a string literal with its quotes
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+/// Token stream with attached trivia. The last token is always `EOF`, whose leading
+/// trivia holds whatever follows the final significant token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyntaxTree {
+    tokens: Vec<SyntaxToken>,
+}
+
+impl SyntaxTree {
+    /// Lex `source` into a syntax tree
+    pub fn new(source: &str) -> SusumuResult<Self> {
+        let tokens = Lexer::new(source).tokenize()?;
+        Ok(Self::from_tokens(source, &tokens))
+    }
+
+    /// Build the tree from tokens already lexed from `source`
+    pub fn from_tokens(source: &str, tokens: &[Token]) -> Self {
+        let index = LineIndex::new(source);
+        let mut result: Vec<SyntaxToken> = Vec::new();
+        let mut leading = Vec::new();
+        // Trivia joins the previous token until the line ends
+        let mut line_open = false;
+        let mut cursor = (1, 1);
+
+        for token in tokens {
+            let start = (token.line, token.column);
+            let end = (token.end_line, token.end_column);
+            if start > cursor {
+                let whitespace = Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: index.slice(cursor, start).to_string(),
+                    span: Span::new(cursor.0, cursor.1, start.0, start.1),
+                };
+                match result.last_mut() {
+                    Some(previous) if line_open => previous.trailing.push(whitespace),
+                    _ => leading.push(whitespace),
+                }
+            }
+            cursor = cursor.max(end);
+
+            let text = index.slice(start, end).to_string();
+            let kind = match token.token_type {
+                TokenType::Newline => Some(TriviaKind::Newline),
+                TokenType::Comment => Some(TriviaKind::Comment),
+                _ => None,
+            };
+            match kind {
+                Some(kind) => {
+                    let trivia = Trivia {
+                        kind,
+                        text,
+                        span: token.span(),
+                    };
+                    match result.last_mut() {
+                        Some(previous) if line_open && kind == TriviaKind::Comment => {
+                            previous.trailing.push(trivia)
+                        }
+                        _ => {
+                            line_open = false;
+                            leading.push(trivia)
+                        }
+                    }
+                }
+                None => {
+                    result.push(SyntaxToken {
+                        kind: token.token_type.clone(),
+                        text,
+                        span: token.span(),
+                        leading: std::mem::take(&mut leading),
+                        trailing: Vec::new(),
+                    });
+                    line_open = true;
+                }
+            }
+        }
+
+        Self { tokens: result }
+    }
+
+    pub fn tokens(&self) -> &[SyntaxToken] {
+        &self.tokens
+    }
+
+    /// Every comment in source order
+    pub fn comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.tokens
+            .iter()
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .filter(|trivia| trivia.kind == TriviaKind::Comment)
+    }
+
+    /// The tokens making up a node with the given span, e.g. an `Expression`'s
+    pub fn tokens_in(&self, span: Span) -> &[SyntaxToken] {
+        let start = self
+            .tokens
+            .partition_point(|t| (t.span.line, t.span.column) < (span.line, span.column));
+        let end = self.tokens.partition_point(|t| {
+            (t.span.end_line, t.span.end_column) <= (span.end_line, span.end_column)
+        });
+        &self.tokens[start..end.max(start)]
+    }
+
+    /// Exact source text of a node, including comments inside it but not around it
+    pub fn text(&self, span: Span) -> String {
+        let tokens = self.tokens_in(span);
+        let mut text = String::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if i > 0 {
+                token.leading.iter().for_each(|t| text.push_str(&t.text));
+            }
+            text.push_str(&token.text);
+            if i + 1 < tokens.len() {
+                token.trailing.iter().for_each(|t| text.push_str(&t.text));
+            }
+        }
+        text
+    }
+
+    /// Comments on the lines directly above a node, such as a function's documentation.
+    /// A blank line ends the run.
+    pub fn leading_comments(&self, span: Span) -> Vec<&Trivia> {
+        let Some(first) = self.tokens_in(span).first() else {
+            return Vec::new();
+        };
+
+        let mut comments = Vec::new();
+        let mut newlines = 0;
+        for trivia in first.leading.iter().rev() {
+            match trivia.kind {
+                TriviaKind::Comment => {
+                    comments.push(trivia);
+                    newlines = 0;
+                }
+                TriviaKind::Newline => {
+                    newlines += 1;
+                    if newlines > 1 {
+                        break;
+                    }
+                }
+                TriviaKind::Whitespace => {}
+            }
+        }
+        comments.reverse();
+        comments
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    /// Writes the original source back out
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(&token.text)?;
+            for trivia in &token.trailing {
+                f.write_str(&trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts the lexer's 1-based character positions into byte offsets
+struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    fn offset(&self, (line, column): (usize, usize)) -> usize {
+        let Some(&line_start) = self.line_starts.get(line.saturating_sub(1)) else {
+            return self.source.len();
+        };
+        self.source[line_start..]
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(self.source.len(), |(i, _)| line_start + i)
+    }
+
+    fn slice(&self, start: (usize, usize), end: (usize, usize)) -> &'a str {
+        &self.source[self.offset(start)..self.offset(end)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_round_trips_source_exactly() {
+        let source =
+            "// Doc ✓\r\nmain() {\r\n\tx = \"multi\nline\"  // note\n    x -> print\n}\n\n// end";
+        let tree = SyntaxTree::new(source).unwrap();
+        assert_eq!(tree.to_string(), source);
+
+        let comments: Vec<&str> = tree.comments().map(|c| c.text.as_str()).collect();
+        assert_eq!(comments, vec!["// Doc ✓\r", "// note", "// end"]);
+
+        // The same-line comment trails the string; the others lead the next token
+        let string = tree
+            .tokens()
+            .iter()
+            .find(|t| t.kind == TokenType::String)
+            .unwrap();
+        assert_eq!(string.text, "\"multi\nline\"");
+        assert!(string.trailing.iter().any(|t| t.text == "// note"));
+        assert_eq!(tree.tokens().last().unwrap().kind, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_maps_ast_nodes_to_source() {
+        let source = "\
+// Unrelated
+
+// Doubles a number
+// twice over
+double(x) {
+    x -> multiply <- 2 // inline
+    x -> add   <- x
+}";
+        let tree = SyntaxTree::new(source).unwrap();
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let function = &program.functions[0];
+
+        let docs: Vec<&str> = tree
+            .leading_comments(function.span)
+            .iter()
+            .map(|c| c.text.as_str())
+            .collect();
+        assert_eq!(docs, vec!["// Doubles a number", "// twice over"]);
+
+        let crate::ast::ExpressionKind::Block(statements) = &function.body.kind else {
+            panic!("expected a block body");
+        };
+        assert_eq!(tree.text(statements[1].span), "x -> add   <- x");
+        assert_eq!(
+            tree.text(function.body.span),
+            "{\n    x -> multiply <- 2 // inline\n    x -> add   <- x\n}"
+        );
+    }
+}
@@ -0,0 +1,947 @@
+//! Debug Adapter Protocol server for Susumu
+//!
+//! `susumu-dap` speaks DAP over stdio so editors can debug Susumu programs: line
+//! breakpoints with conditions, stepping through statements and individual arrow
+//! steps, call stack and variable inspection, and evaluating expressions in a stopped
+//! frame.
+//!
+//! The adapter reads requests on the calling thread and runs the program on its own
+//! thread with the tree-walking backend. The debuggee observes the interpreter, and
+//! before every statement and arrow step decides whether to stop there; when it
+//! stops, it serves stack, variable and evaluate requests from the paused thread until
+//! a continue or step request resumes the program. Requests that need a stopped
+//! program are queued while it runs. Output from `print`, `println` and `debug` is
+//! sent to the client as output events, since stdout carries the protocol. The
+//! program runs with all permissions, like the interpreter embedded in any host.
+
+use crate::ast::{Expression, Span};
+use crate::builtins::value_to_display_string;
+use crate::environment::Environment;
+use crate::error::{SusumuError, SusumuResult};
+use crate::interpreter::Interpreter;
+use crate::observer::ExecutionObserver;
+use crate::visual_debug::{Breakpoint, StepMode};
+use crate::{Lexer, Parser, Program, Value};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Susumu programs run on a single thread
+const THREAD_ID: i64 = 1;
+
+/// Serve DAP on stdin and stdout until the client disconnects
+pub fn run_dap_server() -> io::Result<()> {
+    serve(io::stdin().lock(), io::stdout())
+}
+
+/// Serve DAP requests read from `input`, writing responses and events to `output`.
+/// Returns once the client disconnects or `input` ends, after the program has stopped.
+pub fn serve<R, W>(mut input: R, output: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write + Send + 'static,
+{
+    let mut adapter = Adapter::new(Client::new(output));
+    let result = loop {
+        match read_message(&mut input) {
+            Ok(Some(message)) => {
+                if !adapter.handle(message) {
+                    break Ok(());
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    adapter.stop_program();
+    result
+}
+
+/// Read one `Content-Length` framed message, or `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes responses and events, shared by the request loop and the program thread
+#[derive(Clone)]
+struct Client {
+    inner: Arc<Mutex<ClientInner>>,
+}
+
+struct ClientInner {
+    output: Box<dyn Write + Send>,
+    seq: i64,
+}
+
+impl Client {
+    fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ClientInner {
+                output: Box::new(output),
+                seq: 0,
+            })),
+        }
+    }
+
+    fn send(&self, mut message: Json) {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        inner.seq += 1;
+        message["seq"] = json!(inner.seq);
+        let body = message.to_string();
+        // A client that went away cannot be told about it
+        let _ = write!(
+            inner.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let _ = inner.output.flush();
+    }
+
+    fn respond(&self, request: &Json, result: Result<Json, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({"type": "event", "event": event, "body": body}));
+    }
+
+    fn output(&self, category: &str, output: String) {
+        self.event("output", json!({"category": category, "output": output}));
+    }
+}
+
+/// State shared between the request loop and the paused program
+#[derive(Default)]
+struct Shared {
+    /// Line breakpoints by canonical source path
+    breakpoints: Mutex<HashMap<PathBuf, Vec<Breakpoint>>>,
+    pause_requested: AtomicBool,
+    terminate: AtomicBool,
+}
+
+/// A program waiting for `configurationDone` to start
+struct Launch {
+    path: PathBuf,
+    program: Program,
+    args: Vec<String>,
+    stop_on_entry: bool,
+}
+
+/// Handles requests on the thread reading them
+struct Adapter {
+    client: Client,
+    shared: Arc<Shared>,
+    launch: Option<Launch>,
+    configured: bool,
+    /// Requests for the running program, served when it stops
+    program_requests: Option<Sender<Json>>,
+    program_thread: Option<JoinHandle<()>>,
+}
+
+impl Adapter {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            shared: Arc::default(),
+            launch: None,
+            configured: false,
+            program_requests: None,
+            program_thread: None,
+        }
+    }
+
+    /// Handle one request; returns `false` when the session is over
+    fn handle(&mut self, request: Json) -> bool {
+        if request["type"] != "request" {
+            return true;
+        }
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.client.respond(
+                    &request,
+                    Ok(json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsConditionalBreakpoints": true,
+                        "supportsEvaluateForHovers": true,
+                        "supportsTerminateRequest": true,
+                    })),
+                );
+                self.client.event("initialized", json!({}));
+            }
+            "launch" => match prepare_launch(arguments) {
+                Ok(launch) => {
+                    self.launch = Some(launch);
+                    self.client.respond(&request, Ok(json!({})));
+                    self.start_when_ready();
+                }
+                Err(message) => self.client.respond(&request, Err(message)),
+            },
+            "setBreakpoints" => {
+                let body = self.set_breakpoints(arguments);
+                self.client.respond(&request, Ok(body));
+            }
+            "setExceptionBreakpoints" => self.client.respond(&request, Ok(json!({}))),
+            "configurationDone" => {
+                self.configured = true;
+                self.client.respond(&request, Ok(json!({})));
+                self.start_when_ready();
+            }
+            "threads" => self.client.respond(
+                &request,
+                Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+            ),
+            "pause" => {
+                self.shared.pause_requested.store(true, Ordering::SeqCst);
+                self.client.respond(&request, Ok(json!({})));
+            }
+            "stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn"
+            | "stepOut" => {
+                let sent = match &self.program_requests {
+                    Some(requests) => requests.send(request.clone()).is_ok(),
+                    None => false,
+                };
+                if !sent {
+                    self.client
+                        .respond(&request, Err("The program is not running".to_string()));
+                }
+            }
+            "terminate" => {
+                self.terminate_program();
+                self.client.respond(&request, Ok(json!({})));
+            }
+            "disconnect" => {
+                self.terminate_program();
+                self.client.respond(&request, Ok(json!({})));
+                return false;
+            }
+            command => self
+                .client
+                .respond(&request, Err(format!("Unsupported request '{}'", command))),
+        }
+        true
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let path = arguments["source"]["path"].as_str().unwrap_or_default();
+        let breakpoints: Vec<Breakpoint> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| {
+                Some(Breakpoint {
+                    line: breakpoint["line"].as_u64()? as usize,
+                    column: None,
+                    condition: breakpoint["condition"]
+                        .as_str()
+                        .filter(|condition| !condition.trim().is_empty())
+                        .map(str::to_string),
+                    enabled: true,
+                    hit_count: 0,
+                })
+            })
+            .collect();
+
+        let body = json!({
+            "breakpoints": breakpoints
+                .iter()
+                .map(|breakpoint| json!({"verified": true, "line": breakpoint.line}))
+                .collect::<Vec<_>>()
+        });
+        self.shared
+            .breakpoints
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(canonical_path(Path::new(path)), breakpoints);
+        body
+    }
+
+    /// Start the program once it is launched and the client has set its breakpoints
+    fn start_when_ready(&mut self) {
+        if !self.configured || self.program_thread.is_some() {
+            return;
+        }
+        let Some(launch) = self.launch.take() else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let client = self.client.clone();
+        let shared = self.shared.clone();
+        self.program_requests = Some(sender);
+        self.program_thread = Some(thread::spawn(move || {
+            run_program(launch, client, shared, receiver)
+        }));
+    }
+
+    /// Make the program stop at its next pause point, or now if it is paused
+    fn terminate_program(&mut self) {
+        self.shared.terminate.store(true, Ordering::SeqCst);
+        self.program_requests = None;
+    }
+
+    fn stop_program(&mut self) {
+        self.terminate_program();
+        if let Some(thread) = self.program_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn prepare_launch(arguments: &Json) -> Result<Launch, String> {
+    let path = arguments["program"]
+        .as_str()
+        .ok_or("The launch configuration needs a 'program' to debug")?;
+    if let Some(cwd) = arguments["cwd"].as_str() {
+        std::env::set_current_dir(cwd)
+            .map_err(|err| format!("Cannot change to directory '{}': {}", cwd, err))?;
+    }
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| format!("Error reading file '{}': {}", path, err))?;
+    let program = Lexer::new(&source)
+        .tokenize()
+        .and_then(|tokens| Parser::new(tokens).parse())
+        .map_err(|err| format!("{}: {}", path, err))?;
+    let args = arguments["args"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|arg| arg.as_str().map_or_else(|| arg.to_string(), str::to_string))
+        .collect();
+
+    Ok(Launch {
+        path: canonical_path(Path::new(path)),
+        program,
+        args,
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+    })
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Run a launched program to completion on the current thread
+fn run_program(launch: Launch, client: Client, shared: Arc<Shared>, requests: Receiver<Json>) {
+    let mut interpreter = Interpreter::new();
+    for name in ["print", "println", "debug"] {
+        let client = client.clone();
+        interpreter.register_native_function(name, move |_ctx, args| {
+            let text: Vec<String> = args
+                .iter()
+                .map(|arg| match name {
+                    "debug" => format!("{:?}", arg),
+                    _ => value_to_display_string(arg),
+                })
+                .collect();
+            client.output("stdout", format!("{}\n", text.join(" ")));
+            Ok(Value::Null)
+        });
+    }
+    interpreter
+        .add_observer(Debuggee {
+            client: client.clone(),
+            shared,
+            requests,
+            source: json!({
+                "name": launch.path.file_name().map(|name| name.to_string_lossy()),
+                "path": launch.path,
+            }),
+            path: launch.path.clone(),
+            step: match launch.stop_on_entry {
+                true => StepMode::StepInto,
+                false => StepMode::Continue,
+            },
+            step_depth: 0,
+            reason: "entry",
+            frames: Vec::new(),
+            handles: Vec::new(),
+        })
+        .expect("Interpreter::new uses the tree-walking backend");
+
+    let exit_code = match interpreter.execute_with_args(&launch.program, &launch.args) {
+        Ok(_) => 0,
+        Err(err) => {
+            if !matches!(err, SusumuError::Exit { .. }) {
+                client.output("stderr", format!("Error: {}\n", err.report()));
+            }
+            err.exit_code()
+        }
+    };
+    client.event("exited", json!({"exitCode": exit_code}));
+    client.event("terminated", json!({}));
+}
+
+/// A stack frame of the paused program, innermost first
+struct Frame {
+    name: String,
+    span: Span,
+    env: Arc<Environment>,
+}
+
+/// Variables the client can expand, numbered by their `variablesReference` from 1
+enum Handle {
+    Scope(Vec<(String, Value)>),
+    Value(Value),
+}
+
+/// Pause hook that decides where to stop and serves requests while stopped
+struct Debuggee {
+    client: Client,
+    shared: Arc<Shared>,
+    requests: Receiver<Json>,
+    /// DAP source of the program, reported in stack frames
+    source: Json,
+    path: PathBuf,
+    step: StepMode,
+    /// Call depth when the last step request was made
+    step_depth: usize,
+    /// Reason reported when stepping stops
+    reason: &'static str,
+    frames: Vec<Frame>,
+    handles: Vec<Handle>,
+}
+
+impl ExecutionObserver for Debuggee {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Expression,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        self.pause(interpreter, true, statement.span, env)
+    }
+
+    fn before_arrow_step(
+        &mut self,
+        interpreter: &mut Interpreter,
+        _index: usize,
+        step: &Expression,
+        _input: &Value,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        self.pause(interpreter, false, step.span, env)
+    }
+}
+
+impl Debuggee {
+    /// Stop before a statement or arrow step if a pause was requested, a step has
+    /// finished or, for statements, a breakpoint is hit
+    fn pause(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: bool,
+        span: Span,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        if self.shared.terminate.load(Ordering::SeqCst) {
+            return Err(SusumuError::aborted("the debug session ended"));
+        }
+
+        let depth = interpreter.call_frames().len();
+        let stepped = match self.step {
+            StepMode::StepInto => true,
+            StepMode::StepOver => depth <= self.step_depth,
+            StepMode::StepOut => depth < self.step_depth,
+            StepMode::None | StepMode::Continue => false,
+        };
+        let reason = if self.shared.pause_requested.swap(false, Ordering::SeqCst) {
+            "pause"
+        } else if stepped {
+            self.reason
+        } else if statement && self.hits_breakpoint(interpreter, span, env) {
+            "breakpoint"
+        } else {
+            return Ok(());
+        };
+
+        self.stop(interpreter, reason, span, env)
+    }
+
+    /// Whether an enabled breakpoint on this line has a true condition, or none.
+    /// A condition that fails to evaluate stops too, so the mistake is noticed.
+    fn hits_breakpoint(
+        &mut self,
+        interpreter: &mut Interpreter,
+        span: Span,
+        env: &Arc<Environment>,
+    ) -> bool {
+        let condition = {
+            let mut breakpoints = self
+                .shared
+                .breakpoints
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            let Some(breakpoint) = breakpoints.get_mut(&self.path).and_then(|lines| {
+                lines
+                    .iter_mut()
+                    .find(|bp| bp.enabled && bp.line == span.line)
+            }) else {
+                return false;
+            };
+            breakpoint.hit_count += 1;
+            breakpoint.condition.clone()
+        };
+
+        match condition {
+            Some(condition) => interpreter
+                .evaluate_source(&condition, env)
+                .map_or(true, |value| value.is_truthy()),
+            None => true,
+        }
+    }
+
+    /// Report the stop and serve requests until one resumes the program
+    fn stop(
+        &mut self,
+        interpreter: &mut Interpreter,
+        reason: &str,
+        span: Span,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        self.frames = collect_frames(interpreter, span, env);
+        self.handles.clear();
+        self.client.event(
+            "stopped",
+            json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}),
+        );
+
+        loop {
+            let Ok(request) = self.requests.recv() else {
+                return Err(SusumuError::aborted("the debug session ended"));
+            };
+            let arguments = &request["arguments"];
+            let step = match request["command"].as_str().unwrap_or_default() {
+                "continue" => StepMode::Continue,
+                "next" => StepMode::StepOver,
+                "stepIn" => StepMode::StepInto,
+                "stepOut" => StepMode::StepOut,
+                command => {
+                    let result = match command {
+                        "stackTrace" => Ok(self.stack_trace(arguments)),
+                        "scopes" => self.scopes(interpreter, arguments),
+                        "variables" => self.variables(arguments),
+                        "evaluate" => self.evaluate(interpreter, arguments),
+                        _ => Err(format!("Unsupported request '{}'", command)),
+                    };
+                    self.client.respond(&request, result);
+                    continue;
+                }
+            };
+
+            self.step = step;
+            self.step_depth = interpreter.call_frames().len();
+            self.reason = "step";
+            self.client
+                .respond(&request, Ok(json!({"allThreadsContinued": true})));
+            return Ok(());
+        }
+    }
+
+    fn stack_trace(&self, arguments: &Json) -> Json {
+        let start = arguments["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match arguments["levels"].as_u64().unwrap_or(0) as usize {
+            0 => self.frames.len(),
+            levels => levels,
+        };
+        let frames: Vec<Json> = self
+            .frames
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(levels)
+            .map(|(index, frame)| {
+                json!({
+                    "id": index + 1,
+                    "name": frame.name,
+                    "source": self.source,
+                    "line": frame.span.line,
+                    "column": frame.span.column,
+                    "endLine": frame.span.end_line,
+                    "endColumn": frame.span.end_column,
+                })
+            })
+            .collect();
+        json!({"stackFrames": frames, "totalFrames": self.frames.len()})
+    }
+
+    fn scopes(&mut self, interpreter: &Interpreter, arguments: &Json) -> Result<Json, String> {
+        let frame = self.frame(arguments)?;
+        let global = interpreter.global_env();
+        let mut locals = Vec::new();
+        let mut scope = Some(&frame.env);
+        while let Some(env) = scope.filter(|env| !Arc::ptr_eq(env, &global)) {
+            let mut names = env.variable_names();
+            names.sort();
+            for name in names {
+                if locals.iter().all(|(local, _)| *local != name) {
+                    if let Ok(value) = env.get(&name) {
+                        locals.push((name, value));
+                    }
+                }
+            }
+            scope = env.parent();
+        }
+
+        let mut scopes = Vec::new();
+        if !Arc::ptr_eq(&frame.env, &global) {
+            let reference = self.handle(Handle::Scope(locals));
+            scopes.push(
+                json!({"name": "Locals", "variablesReference": reference, "expensive": false}),
+            );
+        }
+        let reference = self.handle(Handle::Scope(interpreter.global_variables()));
+        scopes
+            .push(json!({"name": "Globals", "variablesReference": reference, "expensive": false}));
+        Ok(json!({"scopes": scopes}))
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let index = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let variables = match self.handles.get(index.wrapping_sub(1)) {
+            Some(Handle::Scope(variables)) => variables.clone(),
+            Some(Handle::Value(value)) => children(value),
+            None => return Err("Unknown variables reference".to_string()),
+        };
+        let variables: Vec<Json> = variables
+            .into_iter()
+            .map(|(name, value)| {
+                let mut variable = self.describe(value);
+                variable["name"] = json!(name);
+                variable
+            })
+            .collect();
+        Ok(json!({"variables": variables}))
+    }
+
+    fn evaluate(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: &Json,
+    ) -> Result<Json, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        let env = match arguments["frameId"].is_null() {
+            true => interpreter.global_env(),
+            false => self.frame(arguments)?.env.clone(),
+        };
+        let value = interpreter
+            .evaluate_source(expression, &env)
+            .map_err(|err| err.to_string())?;
+        let mut result = self.describe(value);
+        result["result"] = result["value"].take();
+        Ok(result)
+    }
+
+    fn frame(&self, arguments: &Json) -> Result<&Frame, String> {
+        let id = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        self.frames
+            .get(id.wrapping_sub(1))
+            .ok_or_else(|| format!("Unknown stack frame {}", id))
+    }
+
+    /// A DAP variable for `value` without its name, expandable when it has children
+    fn describe(&mut self, value: Value) -> Json {
+        let text = match &value {
+            Value::String(string) => format!("{:?}", string),
+            other => value_to_display_string(other),
+        };
+        let kind = value.type_name();
+        let reference = match children(&value).is_empty() {
+            true => 0,
+            false => self.handle(Handle::Value(value)),
+        };
+        json!({"value": text, "type": kind, "variablesReference": reference})
+    }
+
+    fn handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+}
+
+/// Frames of the paused program, innermost first. The top level is left out when
+/// `main` was called by the interpreter rather than from the program.
+fn collect_frames(interpreter: &Interpreter, span: Span, env: &Arc<Environment>) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut span = span;
+    let mut env = env.clone();
+    for call in interpreter.call_frames().iter().rev() {
+        frames.push(Frame {
+            name: call.function.clone(),
+            span,
+            env,
+        });
+        span = call.call_site.span;
+        env = call.caller_env.clone();
+    }
+    if !span.is_unknown() {
+        frames.push(Frame {
+            name: "<top level>".to_string(),
+            span,
+            env,
+        });
+    }
+    frames
+}
+
+/// Elements, fields or wrapped value that a variable expands to
+fn children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (index.to_string(), item.clone()))
+            .collect(),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        Value::Maybe(Some(inner)) => vec![("value".to_string(), (**inner).clone())],
+        Value::Result(Ok(inner)) => vec![("success".to_string(), (**inner).clone())],
+        Value::Result(Err(inner)) => vec![("error".to_string(), (**inner).clone())],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    /// Input the adapter reads as the test writes it
+    struct Pipe {
+        chunks: Receiver<Vec<u8>>,
+        buffer: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.buffer.position() as usize == self.buffer.get_ref().len() {
+                match self.chunks.recv() {
+                    Ok(chunk) => self.buffer = io::Cursor::new(chunk),
+                    Err(_) => return Ok(0),
+                }
+            }
+            self.buffer.read(buf)
+        }
+    }
+
+    /// Output the adapter has written so far
+    #[derive(Clone, Default)]
+    struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Session {
+        input: Option<Sender<Vec<u8>>>,
+        transcript: Transcript,
+        seq: i64,
+        server: Option<JoinHandle<io::Result<()>>>,
+    }
+
+    impl Session {
+        fn start() -> Self {
+            let (input, chunks) = mpsc::channel();
+            let transcript = Transcript::default();
+            let output = transcript.clone();
+            let server = thread::spawn(move || {
+                let pipe = Pipe {
+                    chunks,
+                    buffer: io::Cursor::new(Vec::new()),
+                };
+                serve(io::BufReader::new(pipe), output)
+            });
+            Self {
+                input: Some(input),
+                transcript,
+                seq: 0,
+                server: Some(server),
+            }
+        }
+
+        fn messages(&self) -> Vec<Json> {
+            let mut output = io::Cursor::new(self.transcript.0.lock().unwrap().clone());
+            std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+        }
+
+        /// Wait for the first message after the `skip` matching ones
+        fn wait_for(&self, skip: usize, matches: impl Fn(&Json) -> bool) -> Json {
+            let started = Instant::now();
+            loop {
+                if let Some(message) = self.messages().into_iter().filter(&matches).nth(skip) {
+                    return message;
+                }
+                assert!(
+                    started.elapsed() < Duration::from_secs(10),
+                    "timed out: {:#?}",
+                    self.messages()
+                );
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        fn request(&mut self, command: &str, arguments: Json) -> Json {
+            self.seq += 1;
+            let body = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            let message = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+            self.input
+                .as_ref()
+                .unwrap()
+                .send(message.into_bytes())
+                .unwrap();
+            let seq = self.seq;
+            self.wait_for(0, |m| m["type"] == "response" && m["request_seq"] == seq)
+        }
+
+        /// Reason for the `count`th stop
+        fn stopped(&self, count: usize) -> Json {
+            self.wait_for(count - 1, |m| m["event"] == "stopped")["body"]["reason"].clone()
+        }
+
+        fn finish(mut self) -> Vec<Json> {
+            self.input = None;
+            self.server.take().unwrap().join().unwrap().unwrap();
+            self.messages()
+        }
+    }
+
+    #[test]
+    fn test_breakpoints_stepping_and_inspection() {
+        let path = std::env::temp_dir().join(format!("susumu_dap_{}.susu", std::process::id()));
+        let source = "add = (a, b) => {\n  total = a + b\n  return <- total\n}\n\
+                      x = 1\ny = x -> add <- 2 -> add <- 10\nprint(y)\n";
+        std::fs::write(&path, source).unwrap();
+
+        let mut session = Session::start();
+        let capabilities = session.request("initialize", json!({"adapterID": "susumu"}));
+        assert_eq!(capabilities["body"]["supportsConditionalBreakpoints"], true);
+        session.request("launch", json!({"program": path}));
+        let breakpoints = session.request(
+            "setBreakpoints",
+            json!({
+                "source": {"path": path},
+                "breakpoints": [{"line": 2, "condition": "a > 2"}, {"line": 6}]
+            }),
+        );
+        assert_eq!(breakpoints["body"]["breakpoints"][1]["verified"], true);
+        session.request("configurationDone", json!({}));
+
+        // Stepping over a statement stops at each arrow step of its chain
+        assert_eq!(session.stopped(1), "breakpoint");
+        session.request("next", json!({"threadId": 1}));
+        assert_eq!(session.stopped(2), "step");
+        let frames = session.request("stackTrace", json!({"threadId": 1}));
+        assert_eq!(frames["body"]["stackFrames"][0]["column"], 10);
+        session.request("stepIn", json!({"threadId": 1}));
+        assert_eq!(session.stopped(3), "step");
+        let frames = session.request("stackTrace", json!({"threadId": 1}));
+        assert_eq!(frames["body"]["stackFrames"][0]["line"], 2);
+
+        // The conditional breakpoint skips the first call, add(1, 2)
+        session.request("continue", json!({"threadId": 1}));
+        assert_eq!(session.stopped(4), "breakpoint");
+        let frames = session.request("stackTrace", json!({"threadId": 1}));
+        let frames = &frames["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "<lambda>");
+        assert_eq!(frames[0]["line"], 2);
+        assert_eq!(frames[1]["name"], "<top level>");
+        assert_eq!(frames[1]["line"], 6);
+
+        let scopes = session.request("scopes", json!({"frameId": 1}));
+        let scopes = &scopes["body"]["scopes"];
+        assert_eq!(scopes[0]["name"], "Locals");
+        let reference = scopes[0]["variablesReference"].clone();
+        let locals = session.request("variables", json!({"variablesReference": reference}));
+        let locals = &locals["body"]["variables"];
+        assert_eq!(
+            (&locals[0]["name"], &locals[0]["value"]),
+            (&json!("a"), &json!("3"))
+        );
+        assert_eq!(
+            (&locals[1]["name"], &locals[1]["value"]),
+            (&json!("b"), &json!("10"))
+        );
+        let evaluated = session.request("evaluate", json!({"expression": "a * b", "frameId": 1}));
+        assert_eq!(evaluated["body"]["result"], "30");
+        let evaluated = session.request("evaluate", json!({"expression": "x", "frameId": 2}));
+        assert_eq!(evaluated["body"]["result"], "1");
+
+        session.request("stepOut", json!({"threadId": 1}));
+        assert_eq!(session.stopped(5), "step");
+        let frames = session.request("stackTrace", json!({"threadId": 1}));
+        assert_eq!(frames["body"]["stackFrames"][0]["line"], 7);
+        session.request("continue", json!({"threadId": 1}));
+        session.wait_for(0, |m| m["event"] == "terminated");
+        let not_running = session.request("evaluate", json!({"expression": "x"}));
+        assert_eq!(not_running["success"], false);
+        session.request("disconnect", json!({}));
+
+        let messages = session.finish();
+        std::fs::remove_file(&path).unwrap();
+        let output: String = messages
+            .iter()
+            .filter(|m| m["event"] == "output")
+            .filter_map(|m| m["body"]["output"].as_str())
+            .collect();
+        assert_eq!(output, "13\n");
+        assert!(messages
+            .iter()
+            .any(|m| m["event"] == "exited" && m["body"]["exitCode"] == 0));
+    }
+}
@@ -1,24 +1,84 @@
 //! Environment for variable and function scoping in Susumu
 
 use crate::ast::FunctionDef;
+use crate::bytecode::Chunk;
 use crate::error::{SusumuError, SusumuResult};
+use crate::value::Value;
 use dashmap::DashMap;
 use parking_lot::RwLock;
-use serde_json::Value;
-use std::sync::Arc;
+use std::fmt;
+use std::sync::{Arc, Weak};
 
 /// Variable entry tracking value and mutability
 #[derive(Debug, Clone)]
 pub struct VariableEntry {
     pub value: Value,
     pub is_mutable: bool,
+    /// Set when `value` is a lambda that captured the scope holding this entry. The
+    /// stored lambda drops its own reference to the scope so the two do not keep each
+    /// other alive, and gets it back from this one whenever the variable is read.
+    captured_scope: Option<Weak<Environment>>,
+}
+
+impl VariableEntry {
+    fn new(scope: &Environment, value: Value, is_mutable: bool) -> Self {
+        let captured_scope = match &value {
+            Value::Lambda(closure) => closure
+                .env
+                .as_ref()
+                .filter(|env| std::ptr::eq(Arc::as_ptr(env), scope))
+                .map(Arc::downgrade),
+            _ => None,
+        };
+        let value = match (&value, &captured_scope) {
+            (Value::Lambda(closure), Some(_)) => Value::Lambda(Arc::new(Closure {
+                env: None,
+                ..Closure::clone(closure)
+            })),
+            _ => value,
+        };
+        Self {
+            value,
+            is_mutable,
+            captured_scope,
+        }
+    }
+
+    fn read(&self) -> Value {
+        match (&self.value, &self.captured_scope) {
+            (Value::Lambda(closure), Some(scope)) => Value::Lambda(Arc::new(Closure {
+                env: scope.upgrade(),
+                ..Closure::clone(closure)
+            })),
+            _ => self.value.clone(),
+        }
+    }
+}
+
+/// A user function together with the scope it was defined in
+#[derive(Clone)]
+pub struct Closure {
+    pub def: FunctionDef,
+    /// Captured defining scope; `None` for top-level functions, which see the global scope
+    pub env: Option<Arc<Environment>>,
+    /// Bytecode of a lambda created by the bytecode backend
+    pub chunk: Option<Arc<Chunk>>,
+}
+
+/// The captured scope is left out: it can be large and may hold the closure itself
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("def", &self.def)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Thread-safe environment for concurrent arrow processing
 #[derive(Debug, Clone)]
 pub struct Environment {
     variables: Arc<DashMap<String, VariableEntry>>,
-    functions: Arc<DashMap<String, FunctionDef>>,
+    functions: Arc<DashMap<String, Closure>>,
     parent: Option<Arc<Environment>>,
 }
 
@@ -48,19 +108,36 @@ impl Environment {
 
     /// Define a variable with explicit mutability
     pub fn define_with_mutability(&self, name: String, value: Value, is_mutable: bool) {
-        let entry = VariableEntry { value, is_mutable };
+        let entry = VariableEntry::new(self, value, is_mutable);
         self.variables.insert(name, entry);
     }
 
-    /// Define a function in this environment
+    /// Define a top-level function in this environment
     pub fn define_function(&self, name: String, func: FunctionDef) {
-        self.functions.insert(name, func);
+        self.functions.insert(
+            name,
+            Closure {
+                def: func,
+                env: None,
+                chunk: None,
+            },
+        );
+    }
+
+    /// Define a function that closes over the given scope
+    pub fn define_closure(&self, name: String, func: FunctionDef, env: Arc<Environment>) {
+        let closure = Closure {
+            def: func,
+            env: Some(env),
+            chunk: None,
+        };
+        self.functions.insert(name, closure);
     }
 
     /// Get a variable value, checking parent scopes if needed
     pub fn get(&self, name: &str) -> SusumuResult<Value> {
         if let Some(entry) = self.variables.get(name) {
-            return Ok(entry.value.clone());
+            return Ok(entry.read());
         }
 
         if let Some(parent) = &self.parent {
@@ -87,7 +164,7 @@ impl Environment {
     pub fn update_mutable(&self, name: &str, new_value: Value) -> SusumuResult<()> {
         if let Some(mut entry) = self.variables.get_mut(name) {
             if entry.is_mutable {
-                entry.value = new_value;
+                *entry = VariableEntry::new(self, new_value, true);
                 return Ok(());
             } else {
                 return Err(SusumuError::runtime_error(&format!(
@@ -106,12 +183,17 @@ impl Environment {
 
     /// Get a function definition, checking parent scopes if needed
     pub fn get_function(&self, name: &str) -> SusumuResult<FunctionDef> {
-        if let Some(func) = self.functions.get(name) {
-            return Ok(func.clone());
+        self.get_closure(name).map(|closure| closure.def)
+    }
+
+    /// Get a function with its captured scope, checking parent scopes if needed
+    pub fn get_closure(&self, name: &str) -> SusumuResult<Closure> {
+        if let Some(closure) = self.functions.get(name) {
+            return Ok(closure.clone());
         }
 
         if let Some(parent) = &self.parent {
-            return parent.get_function(name);
+            return parent.get_closure(name);
         }
 
         Err(SusumuError::undefined_function(name))
@@ -141,6 +223,11 @@ impl Environment {
                 .map_or(false, |p| p.contains_function(name))
     }
 
+    /// Enclosing scope, `None` for the global environment
+    pub fn parent(&self) -> Option<&Arc<Environment>> {
+        self.parent.as_ref()
+    }
+
     /// Get all variable names in this environment (for debugging)
     pub fn variable_names(&self) -> Vec<String> {
         self.variables
@@ -244,20 +331,52 @@ impl Default for EnvironmentManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
+    use crate::ast::{Expression, ExpressionKind, Span};
 
     #[test]
     fn test_environment_variable_scoping() {
         let parent = Arc::new(Environment::new());
-        parent.define("x".to_string(), json!(42));
+        parent.define("x".to_string(), Value::from(42));
 
         let child = Environment::with_parent(parent);
-        child.define("y".to_string(), json!(24));
+        child.define("y".to_string(), Value::from(24));
 
         // Child can access parent variables
-        assert_eq!(child.get("x").unwrap(), json!(42));
+        assert_eq!(child.get("x").unwrap(), Value::from(42));
         // Child can access own variables
-        assert_eq!(child.get("y").unwrap(), json!(24));
+        assert_eq!(child.get("y").unwrap(), Value::from(24));
+    }
+
+    #[test]
+    fn test_lambda_bound_in_its_own_scope_does_not_keep_it_alive() {
+        let scope = Arc::new(Environment::with_parent(Arc::new(Environment::new())));
+        let def = FunctionDef {
+            name: "<lambda>".to_string(),
+            params: Vec::new(),
+            return_type: None,
+            body: Expression::new(ExpressionKind::Null, Span::default()),
+            span: Span::default(),
+        };
+        let lambda = Value::Lambda(Arc::new(Closure {
+            def,
+            env: Some(scope.clone()),
+            chunk: None,
+        }));
+        scope.define("f".to_string(), lambda);
+        assert_eq!(Arc::strong_count(&scope), 1);
+
+        // Reading the variable hands out a lambda that holds the scope again
+        match scope.get("f").unwrap() {
+            Value::Lambda(closure) => {
+                assert!(Arc::ptr_eq(closure.env.as_ref().unwrap(), &scope));
+                assert_eq!(Arc::strong_count(&scope), 2);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let weak = Arc::downgrade(&scope);
+        drop(scope);
+        assert!(weak.upgrade().is_none());
     }
 
     #[test]
@@ -267,28 +386,28 @@ mod tests {
         // Define in global scope
         manager
             .global()
-            .define("global_var".to_string(), json!("global"));
+            .define("global_var".to_string(), Value::from("global"));
 
         // Test with new scope
         let result = manager
             .with_new_scope(|env| {
-                env.define("local_var".to_string(), json!("local"));
+                env.define("local_var".to_string(), Value::from("local"));
 
                 // Can access both global and local
-                assert_eq!(env.get("global_var").unwrap(), json!("global"));
-                assert_eq!(env.get("local_var").unwrap(), json!("local"));
+                assert_eq!(env.get("global_var").unwrap(), Value::from("global"));
+                assert_eq!(env.get("local_var").unwrap(), Value::from("local"));
 
-                Ok(json!("success"))
+                Ok(Value::from("success"))
             })
             .unwrap();
 
-        assert_eq!(result, json!("success"));
+        assert_eq!(result, Value::from("success"));
 
         // Local variable should not be accessible in global scope
         assert!(manager.current().get("local_var").is_err());
         assert_eq!(
             manager.current().get("global_var").unwrap(),
-            json!("global")
+            Value::from("global")
         );
     }
 }
@@ -1,8 +1,16 @@
 //! Error handling for Susumu language
 
+use crate::ast::Span;
+use crate::limits::Limit;
+use crate::permissions::Capability;
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use thiserror::Error;
 
+/// Stack frames kept at each end of a long trace in [`SusumuError::report`]
+const REPORT_EDGE_FRAMES: usize = 10;
+
 pub type SusumuResult<T> = Result<T, SusumuError>;
 
 #[derive(Error, Debug, Clone, PartialEq)]
@@ -14,41 +22,129 @@ pub enum SusumuError {
         message: String,
     },
 
-    #[error("Parser error at line {line}: {message}")]
-    ParserError { line: usize, message: String },
+    #[error("Parser error at line {line}, column {column}: {message}")]
+    ParserError {
+        line: usize,
+        column: usize,
+        /// Position just past the offending token
+        end_line: usize,
+        end_column: usize,
+        message: String,
+    },
+
+    #[error("Runtime error: {message}{}", located(.span))]
+    RuntimeError {
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
+
+    #[error("Type error: expected {expected}, found {found}{}", located(.span))]
+    TypeError {
+        expected: String,
+        found: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
+
+    #[error("Undefined variable: {name}{}", located(.span))]
+    UndefinedVariable {
+        name: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
+
+    #[error("Undefined function: {name}{}", located(.span))]
+    UndefinedFunction {
+        name: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
+
+    #[error("Function call error: {message}{}", located(.span))]
+    FunctionCallError {
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Runtime error: {message}")]
-    RuntimeError { message: String },
+    #[error("Arrow chain error: {message}{}", located(.span))]
+    ArrowChainError {
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Type error: expected {expected}, found {found}")]
-    TypeError { expected: String, found: String },
+    #[error("User-defined error: {value:?}{}", located(.span))]
+    UserError {
+        value: Value,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Undefined variable: {name}")]
-    UndefinedVariable { name: String },
+    #[error("Assertion failed: {message}{}", located(.span))]
+    AssertionError {
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Undefined function: {name}")]
-    UndefinedFunction { name: String },
+    #[error("Execution limit exceeded: {limit}{}", located(.span))]
+    LimitExceeded {
+        limit: Limit,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Function call error: {message}")]
-    FunctionCallError { message: String },
+    #[error("Permission denied: {message}{}", located(.span))]
+    PermissionDenied {
+        capability: Capability,
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
-    #[error("Arrow chain error: {message}")]
-    ArrowChainError { message: String },
+    #[error("Program exited with status {code}")]
+    Exit { code: i32 },
 
-    #[error("User-defined error: {value:?}")]
-    UserError { value: serde_json::Value },
+    #[error("Execution aborted: {reason}{}", located(.span))]
+    Aborted {
+        reason: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
 
     #[error("Return value: {value:?}")]
-    ReturnValue { value: serde_json::Value },
+    ReturnValue { value: Value },
 
     #[error("Success return: {value:?}")]
-    SuccessReturn { value: serde_json::Value },
+    SuccessReturn { value: Value },
 
     #[error("Error return: {value:?}")]
-    ErrorReturn { value: serde_json::Value },
+    ErrorReturn { value: Value },
+
+    #[error("Break out of loop")]
+    Break {
+        label: Option<String>,
+        value: Option<Value>,
+    },
 
-    #[error("IO error: {message}")]
-    IoError { message: String },
+    #[error("Continue loop")]
+    Continue {
+        label: Option<String>,
+        value: Option<Value>,
+    },
+
+    #[error("IO error: {message}{}", located(.span))]
+    IoError {
+        message: String,
+        span: Option<Span>,
+        stack: Vec<StackFrame>,
+    },
+}
+
+fn located(span: &Option<Span>) -> String {
+    span.map(|span| format!(" at {}", span)).unwrap_or_default()
 }
 
 impl SusumuError {
@@ -60,9 +156,12 @@ impl SusumuError {
         }
     }
 
-    pub fn parser_error(line: usize, message: impl Into<String>) -> Self {
+    pub fn parser_error(span: Span, message: impl Into<String>) -> Self {
         Self::ParserError {
-            line,
+            line: span.line,
+            column: span.column,
+            end_line: span.end_line,
+            end_column: span.end_column,
             message: message.into(),
         }
     }
@@ -70,6 +169,8 @@ impl SusumuError {
     pub fn runtime_error(message: impl Into<String>) -> Self {
         Self::RuntimeError {
             message: message.into(),
+            span: None,
+            stack: Vec::new(),
         }
     }
 
@@ -77,48 +178,312 @@ impl SusumuError {
         Self::TypeError {
             expected: expected.into(),
             found: found.into(),
+            span: None,
+            stack: Vec::new(),
         }
     }
 
     pub fn undefined_variable(name: impl Into<String>) -> Self {
-        Self::UndefinedVariable { name: name.into() }
+        Self::UndefinedVariable {
+            name: name.into(),
+            span: None,
+            stack: Vec::new(),
+        }
     }
 
     pub fn undefined_function(name: impl Into<String>) -> Self {
-        Self::UndefinedFunction { name: name.into() }
+        Self::UndefinedFunction {
+            name: name.into(),
+            span: None,
+            stack: Vec::new(),
+        }
     }
 
     pub fn function_call_error(message: impl Into<String>) -> Self {
         Self::FunctionCallError {
             message: message.into(),
+            span: None,
+            stack: Vec::new(),
         }
     }
 
     pub fn arrow_chain_error(message: impl Into<String>) -> Self {
         Self::ArrowChainError {
             message: message.into(),
+            span: None,
+            stack: Vec::new(),
         }
     }
 
-    pub fn user_error(value: serde_json::Value) -> Self {
-        Self::UserError { value }
+    pub fn user_error(value: Value) -> Self {
+        Self::UserError {
+            value,
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn assertion_error(message: impl Into<String>) -> Self {
+        Self::AssertionError {
+            message: message.into(),
+            span: None,
+            stack: Vec::new(),
+        }
     }
 
-    pub fn return_value(value: serde_json::Value) -> Self {
+    pub fn limit_exceeded(limit: Limit) -> Self {
+        Self::LimitExceeded {
+            limit,
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn permission_denied(capability: Capability, message: impl Into<String>) -> Self {
+        Self::PermissionDenied {
+            capability,
+            message: message.into(),
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn exit(code: i32) -> Self {
+        Self::Exit { code }
+    }
+
+    /// Stop a program from outside it, e.g. from an [`ExecutionObserver`](crate::ExecutionObserver)
+    pub fn aborted(reason: impl Into<String>) -> Self {
+        Self::Aborted {
+            reason: reason.into(),
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn return_value(value: Value) -> Self {
         Self::ReturnValue { value }
     }
 
-    pub fn success_return(value: serde_json::Value) -> Self {
+    pub fn success_return(value: Value) -> Self {
         Self::SuccessReturn { value }
     }
 
-    pub fn error_return(value: serde_json::Value) -> Self {
+    pub fn error_return(value: Value) -> Self {
         Self::ErrorReturn { value }
     }
 
+    /// `break`, leaving the innermost loop or the one labelled `label`
+    pub fn loop_break(label: Option<String>, value: Option<Value>) -> Self {
+        Self::Break { label, value }
+    }
+
+    pub fn loop_continue(label: Option<String>, value: Option<Value>) -> Self {
+        Self::Continue { label, value }
+    }
+
+    /// Whether the error is a `break` or `continue` on its way to its loop
+    pub fn is_loop_control(&self) -> bool {
+        matches!(self, Self::Break { .. } | Self::Continue { .. })
+    }
+
     pub fn io_error(message: impl Into<String>) -> Self {
         Self::IoError {
             message: message.into(),
+            span: None,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Whether a running program must not handle the error: `| default` and
+    /// `assertError` let `exit`, aborts, exhausted limits and denied permissions through
+    pub fn is_uncatchable(&self) -> bool {
+        matches!(
+            self,
+            Self::Exit { .. }
+                | Self::Aborted { .. }
+                | Self::LimitExceeded { .. }
+                | Self::PermissionDenied { .. }
+        )
+    }
+
+    /// Process exit status for a program that stopped with this error: the status
+    /// passed to `exit`, the value of an uncaught `error <- value` when it is a status
+    /// from 1 to 255 or an object with such a `code`, and 1 for anything else
+    pub fn exit_code(&self) -> i32 {
+        let value = match self {
+            Self::Exit { code } => return *code,
+            Self::UserError { value, .. } | Self::ErrorReturn { value } => value,
+            _ => return 1,
+        };
+        let code = match value {
+            Value::Object(fields) => fields.get("code"),
+            other => Some(other),
+        };
+        match code {
+            Some(Value::Int(code @ 1..=255)) => *code as i32,
+            _ => 1,
+        }
+    }
+
+    /// Source location the error was raised at, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::LexerError { line, column, .. } => {
+                Some(Span::new(*line, *column, *line, *column + 1))
+            }
+            Self::ParserError {
+                line,
+                column,
+                end_line,
+                end_column,
+                ..
+            } => Some(Span::new(*line, *column, *end_line, *end_column)),
+            _ => self.context().and_then(|(span, _)| *span),
+        }
+    }
+
+    /// Susumu calls that were active when the error was raised, innermost first
+    pub fn stack_trace(&self) -> &[StackFrame] {
+        self.context().map_or(&[], |(_, stack)| stack.as_slice())
+    }
+
+    /// Record where a runtime error was raised. The innermost location wins, so
+    /// wrapping expressions do not overwrite the span of the node that failed.
+    pub fn with_span(mut self, location: Span) -> Self {
+        if location.is_unknown() {
+            return self;
+        }
+        if let Some((span, _)) = self.context_mut() {
+            span.get_or_insert(location);
+        }
+        self
+    }
+
+    /// Record that the error unwound out of a call to `function`
+    pub fn with_frame(mut self, function: impl Into<String>, call_site: Location) -> Self {
+        if let Some((_, stack)) = self.context_mut() {
+            stack.push(StackFrame {
+                function: function.into(),
+                call_site,
+            });
+        }
+        self
+    }
+
+    /// The error message followed by its stack trace, one frame per line. Consecutive
+    /// identical frames, as left by direct recursion, are printed once with a repeat
+    /// count, and only the innermost and outermost frames of a deep trace are kept.
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        if self.stack_trace().is_empty() {
+            return report;
+        }
+
+        let mut runs: Vec<(&StackFrame, usize)> = Vec::new();
+        for frame in self.stack_trace() {
+            match runs.last_mut() {
+                Some((last, count)) if *last == frame => *count += 1,
+                _ => runs.push((frame, 1)),
+            }
+        }
+        let hidden = runs.len().saturating_sub(2 * REPORT_EDGE_FRAMES);
+
+        report.push_str("\nStack trace (most recent call first):");
+        for (index, (frame, count)) in runs.iter().enumerate() {
+            if index == REPORT_EDGE_FRAMES && hidden > 0 {
+                let frames: usize = runs[index..index + hidden].iter().map(|run| run.1).sum();
+                report.push_str(&format!("\n    ... {} more frames", frames));
+            }
+            if (REPORT_EDGE_FRAMES..REPORT_EDGE_FRAMES + hidden).contains(&index) {
+                continue;
+            }
+            report.push_str(&format!("\n    {}", frame));
+            if *count > 1 {
+                report.push_str(&format!(
+                    "\n    ... previous frame repeated {} times",
+                    count - 1
+                ));
+            }
+        }
+        report
+    }
+
+    fn context(&self) -> Option<(&Option<Span>, &Vec<StackFrame>)> {
+        match self {
+            Self::RuntimeError { span, stack, .. }
+            | Self::TypeError { span, stack, .. }
+            | Self::UndefinedVariable { span, stack, .. }
+            | Self::UndefinedFunction { span, stack, .. }
+            | Self::FunctionCallError { span, stack, .. }
+            | Self::ArrowChainError { span, stack, .. }
+            | Self::UserError { span, stack, .. }
+            | Self::AssertionError { span, stack, .. }
+            | Self::Aborted { span, stack, .. }
+            | Self::LimitExceeded { span, stack, .. }
+            | Self::PermissionDenied { span, stack, .. }
+            | Self::IoError { span, stack, .. } => Some((span, stack)),
+            _ => None,
+        }
+    }
+
+    fn context_mut(&mut self) -> Option<(&mut Option<Span>, &mut Vec<StackFrame>)> {
+        match self {
+            Self::RuntimeError { span, stack, .. }
+            | Self::TypeError { span, stack, .. }
+            | Self::UndefinedVariable { span, stack, .. }
+            | Self::UndefinedFunction { span, stack, .. }
+            | Self::FunctionCallError { span, stack, .. }
+            | Self::ArrowChainError { span, stack, .. }
+            | Self::UserError { span, stack, .. }
+            | Self::AssertionError { span, stack, .. }
+            | Self::Aborted { span, stack, .. }
+            | Self::LimitExceeded { span, stack, .. }
+            | Self::PermissionDenied { span, stack, .. }
+            | Self::IoError { span, stack, .. } => Some((span, stack)),
+            _ => None,
+        }
+    }
+}
+
+/// Where a call was made: its source range and, when the call is a step of an arrow
+/// chain, the step's 1-based position in that chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Location {
+    pub span: Span,
+    pub arrow_step: Option<usize>,
+}
+
+impl Location {
+    pub fn new(span: Span, arrow_step: Option<usize>) -> Self {
+        Self { span, arrow_step }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.span)?;
+        if let Some(step) = self.arrow_step {
+            write!(f, ", arrow step {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// One Susumu function call that an error unwound through
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackFrame {
+    pub function: String,
+    pub call_site: Location,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Entry points such as `main` are called by the runtime, not from source
+        if self.call_site.span.is_unknown() {
+            write!(f, "in {}", self.function)
+        } else {
+            write!(f, "in {} called at {}", self.function, self.call_site)
         }
     }
 }
@@ -126,8 +491,8 @@ impl SusumuError {
 // Custom flow control errors for return and user-defined errors
 #[derive(Debug, Clone, PartialEq)]
 pub enum FlowControl {
-    Return(serde_json::Value),
-    Error(serde_json::Value),
+    Return(Value),
+    Error(Value),
 }
 
 impl fmt::Display for FlowControl {
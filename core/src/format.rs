@@ -0,0 +1,1438 @@
+//! Canonical source formatter for Susumu
+//!
+//! Prints a parsed program back as source with four-space indentation, normalised
+//! `i/ei/e` blocks and object literals, and multi-line arrow chains laid out one
+//! forward step per line with their convergence inputs (`-> f <- a <- b`) beside the
+//! function they feed. Comments are re-attached to the code they precede or follow.
+
+use crate::ast::*;
+use crate::cst::{SyntaxTree, TriviaKind};
+use crate::error::{SusumuError, SusumuResult};
+use crate::lexer::{Lexer, TokenType};
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+const MAX_WIDTH: usize = 100;
+
+/// Source position as (line, column)
+type Pos = (usize, usize);
+
+/// Format Susumu source code. Fails on syntax errors, and refuses to return output that
+/// would parse to a different program or drop a comment.
+pub fn format_source(source: &str) -> SusumuResult<String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let tree = SyntaxTree::from_tokens(source, &tokens);
+    let program = Parser::new(tokens).parse()?;
+    let formatted = Formatter::new(source, &tree).program(&program);
+    verify(&program, &tree, &formatted)?;
+    Ok(formatted)
+}
+
+/// Check that `formatted` means the same as the original program and kept its comments
+fn verify(program: &Program, tree: &SyntaxTree, formatted: &str) -> SusumuResult<()> {
+    let unchanged = || {
+        SusumuError::runtime_error(
+            "Formatting would change the meaning of this program; leaving it unformatted",
+        )
+    };
+    let new_tokens = Lexer::new(formatted).tokenize().map_err(|_| unchanged())?;
+    let new_tree = SyntaxTree::from_tokens(formatted, &new_tokens);
+    let reparsed = Parser::new(new_tokens).parse().map_err(|_| unchanged())?;
+
+    if without_spans(program) != without_spans(&reparsed)
+        || comment_texts(tree) != comment_texts(&new_tree)
+    {
+        return Err(unchanged());
+    }
+    Ok(())
+}
+
+/// Serialized AST with source locations removed, so layout changes compare equal
+fn without_spans(program: &Program) -> serde_json::Value {
+    fn strip(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.remove("span");
+                map.values_mut().for_each(strip);
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(program).unwrap_or_default();
+    strip(&mut value);
+    value
+}
+
+fn comment_texts(tree: &SyntaxTree) -> Vec<&str> {
+    tree.comments().map(|c| c.text.trim_end()).collect()
+}
+
+/// How tightly a construct binds, following the parser's descent from `expression`
+/// down to `primary`. A child printed below the level its position requires gets
+/// parenthesised.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Prec {
+    Expr,
+    Chain,
+    Postfix,
+    Binary,
+    Unary,
+    Power,
+    /// Loops, `match` and the flow-control keywords
+    Flow,
+    /// `.name`, `[index]` and `[start:end]`
+    Access,
+    Primary,
+}
+
+/// What the parser will see right after an expression, which decides whether an
+/// open-ended construct such as `return <- x` or `(x) => x + 1` would swallow it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Follow {
+    Nothing,
+    Arrow,
+    Operator,
+}
+
+struct Comment {
+    pos: Pos,
+    text: String,
+    /// Code precedes the comment on its line
+    trailing: bool,
+}
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    tree: &'a SyntaxTree,
+    comments: Vec<Comment>,
+    /// Index of the first comment not yet written
+    next: usize,
+    /// Width already taken on the line where the next expression starts, e.g. by `x = `
+    lead: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, tree: &'a SyntaxTree) -> Self {
+        let mut comments = Vec::new();
+        for token in tree.tokens() {
+            let leading = token.leading.iter().map(|trivia| (trivia, false));
+            let trailing = token.trailing.iter().map(|trivia| (trivia, true));
+            for (trivia, trailing) in leading.chain(trailing) {
+                if trivia.kind == TriviaKind::Comment {
+                    comments.push(Comment {
+                        pos: start(trivia.span),
+                        text: trivia.text.trim_end().to_string(),
+                        trailing,
+                    });
+                }
+            }
+        }
+
+        Self {
+            lines: source.lines().collect(),
+            tree,
+            comments,
+            next: 0,
+            lead: 0,
+        }
+    }
+
+    fn program(&mut self, program: &Program) -> String {
+        // Functions and top-level statements interleave in the source; keep that order
+        let mut items: Vec<(Pos, Option<&FunctionDef>, Option<&Expression>)> = program
+            .functions
+            .iter()
+            .map(|f| (start(f.span), Some(f), None))
+            .collect();
+        if let Some(main) = &program.main_expression {
+            items.extend(
+                statements_of(main)
+                    .iter()
+                    .map(|s| (start(s.span), None, Some(s))),
+            );
+        }
+        items.sort_by_key(|(pos, _, _)| *pos);
+
+        let mut out = String::new();
+        let mut prev_line = None;
+        let mut prev_function = false;
+        for (pos, function, statement) in items {
+            // Functions are always set apart from their neighbours by a blank line
+            if prev_line.is_some() && (prev_function || function.is_some()) {
+                blank_line(&mut out);
+            }
+            self.leading(&mut out, pos, 0, &mut prev_line);
+            self.separate(&mut out, prev_line, pos.0);
+
+            let end_line = match (function, statement) {
+                (Some(function), _) => {
+                    out.push_str(&self.function(function));
+                    function.span.end_line
+                }
+                (_, Some(statement)) => {
+                    out.push_str(&self.statement(statement, 0));
+                    statement.span.end_line
+                }
+                _ => continue,
+            };
+            self.trailing(&mut out, end_line);
+            out.push('\n');
+            prev_line = Some(end_line);
+            prev_function = function.is_some();
+        }
+        self.leading(&mut out, (usize::MAX, 0), 0, &mut prev_line);
+        out
+    }
+
+    fn function(&mut self, function: &FunctionDef) -> String {
+        let mut out = String::new();
+        let mut body = &function.body;
+
+        // `@trace <- "x"` style annotations go above the definition, as written; the
+        // parser only recognises single-token arguments there
+        if let ExpressionKind::Annotated {
+            annotation,
+            expression,
+        } = &body.kind
+        {
+            if !matches!(annotation, Annotation::Monitor(_) | Annotation::Config(_)) {
+                out.push_str(&self.annotation(annotation));
+                out.push('\n');
+                body = expression;
+            }
+        }
+
+        out.push_str(&function.name);
+        out.push_str(&self.params(&function.params));
+        if let Some(return_type) = &function.return_type {
+            out.push_str(" -> ");
+            out.push_str(&return_type_source(return_type));
+        }
+        out.push(' ');
+        let close = closing(function.span);
+        out.push_str(&self.block(body, 0, Some(close)));
+        out
+    }
+
+    fn params(&mut self, params: &[FunctionParam]) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .map(|param| {
+                let name = match &param.pattern {
+                    Some(pattern) => self.pattern(pattern, 0),
+                    None => param.name.clone(),
+                };
+                match &param.type_annotation {
+                    Some(annotation) => format!("{}: {}", name, type_source(annotation)),
+                    None => name,
+                }
+            })
+            .collect();
+        format!("({})", params.join(", "))
+    }
+
+    /// A braced body, one statement per line. `close` is where its `}` was in the
+    /// source, when known, so comments before it stay inside the block.
+    fn block(&mut self, body: &Expression, indent: usize, close: Option<Pos>) -> String {
+        let statements = statements_of(body);
+        let Some(last) = statements.last() else {
+            // An empty body's span ends at its closing brace
+            let mut out = String::from("{\n");
+            self.leading(&mut out, closing(body.span), indent + 1, &mut None);
+            if out.len() == 2 {
+                return "{}".to_string();
+            }
+            out.push_str(&pad(indent));
+            out.push('}');
+            return out;
+        };
+
+        let mut out = String::from("{");
+        self.trailing(&mut out, statements[0].span.line.saturating_sub(1));
+        out.push('\n');
+        let mut prev_line = self.statements(&mut out, statements, indent + 1);
+        let close = close.unwrap_or_else(|| self.closing_brace(end(last.span)));
+        self.leading(&mut out, close, indent + 1, &mut prev_line);
+        out.push_str(&pad(indent));
+        out.push('}');
+        out
+    }
+
+    /// Write statements one per line, keeping single blank lines from the source.
+    /// Returns the source line the last statement ended on.
+    fn statements(
+        &mut self,
+        out: &mut String,
+        statements: &[Expression],
+        indent: usize,
+    ) -> Option<usize> {
+        let mut prev_line = None;
+        for statement in statements {
+            self.leading(out, start(statement.span), indent, &mut prev_line);
+            self.separate(out, prev_line, statement.span.line);
+            out.push_str(&pad(indent));
+            out.push_str(&self.statement(statement, indent));
+            self.trailing(out, statement.span.end_line);
+            out.push('\n');
+            prev_line = Some(statement.span.end_line);
+        }
+        prev_line
+    }
+
+    fn statement(&mut self, statement: &Expression, indent: usize) -> String {
+        match &statement.kind {
+            ExpressionKind::Assignment {
+                target,
+                value,
+                mutable,
+            } => {
+                let target = format!("{}{} = ", if *mutable { "mut " } else { "" }, target);
+                self.lead = target.len();
+                target + &self.expr(value, Prec::Expr, Follow::Nothing, indent)
+            }
+            ExpressionKind::Destructure {
+                pattern,
+                value,
+                mutable,
+            } => {
+                let target = format!(
+                    "{}{} = ",
+                    if *mutable { "mut " } else { "" },
+                    self.pattern(pattern, indent)
+                );
+                self.lead = target.len();
+                target + &self.expr(value, Prec::Expr, Follow::Nothing, indent)
+            }
+            _ => self.expr(statement, Prec::Expr, Follow::Nothing, indent),
+        }
+    }
+
+    fn expr(&mut self, e: &Expression, prec: Prec, follow: Follow, indent: usize) -> String {
+        let lead = std::mem::take(&mut self.lead);
+        if precedence(e) < prec || swallows(e, follow) {
+            return format!("({})", self.expr(e, Prec::Expr, Follow::Nothing, indent));
+        }
+
+        match &e.kind {
+            ExpressionKind::Integer(n) => n.to_string(),
+            ExpressionKind::Number(n) => number_source(*n),
+            // Literals keep the quoting and escapes they were written with
+            ExpressionKind::String(_) | ExpressionKind::InterpolatedString(_) => {
+                self.tree.text(e.span)
+            }
+            ExpressionKind::Boolean(b) => b.to_string(),
+            ExpressionKind::Null => "null".to_string(),
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::Tuple(items) => {
+                let items = self.comma_separated(items, indent);
+                if items.len() == 1 {
+                    format!("({},)", items[0])
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            }
+            ExpressionKind::Array(items) => {
+                let entries: Vec<_> = items.iter().map(|item| (String::new(), item)).collect();
+                self.sequence("[", "]", &entries, e.span, indent, lead)
+            }
+            ExpressionKind::Object(pairs) => {
+                let entries: Vec<_> = pairs
+                    .iter()
+                    .map(|(key, value)| (format!("{}: ", key_source(key)), value))
+                    .collect();
+                self.sequence("{", "}", &entries, e.span, indent, lead)
+            }
+            ExpressionKind::ObjectMutation { target, mutations } => {
+                self.object_mutation(e, target, mutations, indent, lead)
+            }
+            ExpressionKind::ArrowChain {
+                expressions,
+                directions,
+            } => self.arrow_chain(expressions, directions, follow, indent, lead),
+            ExpressionKind::FunctionCall { name, args } => {
+                format!(
+                    "{}({})",
+                    name,
+                    self.comma_separated(args, indent).join(", ")
+                )
+            }
+            ExpressionKind::Lambda { params, body } => {
+                let params = self.params(params);
+                if lambda_needs_braces(body) {
+                    format!("{} => {}", params, self.block(body, indent, None))
+                } else {
+                    let body = self.expr(body, Prec::Postfix, follow, indent);
+                    format!("{} => {}", params, body)
+                }
+            }
+            ExpressionKind::Conditional {
+                condition_type,
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+            } => {
+                // A null condition is what the parser leaves for `i cond { ... }` standing
+                // on its own, which cannot spell `allValid`
+                let mut out = if is_placeholder(condition)
+                    && !matches!(condition_type, ConditionType::AllValid)
+                {
+                    String::from("i ")
+                } else {
+                    let condition = self.expr(condition, Prec::Chain, Follow::Arrow, indent);
+                    format!("{} i ", condition)
+                };
+                out.push_str(&self.condition(condition_type, indent));
+                out.push(' ');
+                out.push_str(&self.block(then_branch, indent, None));
+                for branch in else_if_branches {
+                    out.push_str(" ei ");
+                    out.push_str(&self.condition(&branch.condition_type, indent));
+                    out.push(' ');
+                    out.push_str(&self.block(&branch.then_branch, indent, None));
+                }
+                if let Some(else_branch) = else_branch {
+                    out.push_str(" e ");
+                    out.push_str(&self.block(else_branch, indent, None));
+                }
+                out
+            }
+            ExpressionKind::Return(value) => {
+                format!("return <- {}", self.expr(value, Prec::Expr, follow, indent))
+            }
+            ExpressionKind::Success(value) => {
+                format!(
+                    "success <- {}",
+                    self.expr(value, Prec::Expr, follow, indent)
+                )
+            }
+            ExpressionKind::Error(value) | ExpressionKind::ErrorReturn(value) => {
+                format!("error <- {}", self.expr(value, Prec::Expr, follow, indent))
+            }
+            ExpressionKind::ForEach {
+                label,
+                pattern,
+                iterable,
+                body,
+            } => format!(
+                "{}fe {} in {} {}",
+                loop_label(label),
+                self.pattern(pattern, indent),
+                self.expr(iterable, Prec::Binary, Follow::Nothing, indent),
+                self.block(body, indent, None)
+            ),
+            ExpressionKind::While {
+                label,
+                condition,
+                body,
+            } => format!(
+                "{}w {} {}",
+                loop_label(label),
+                self.expr(condition, Prec::Expr, Follow::Nothing, indent),
+                self.block(body, indent, None)
+            ),
+            ExpressionKind::Break { label, value } => {
+                self.loop_control("break", label, value, follow, indent)
+            }
+            ExpressionKind::Continue { label, value } => {
+                self.loop_control("continue", label, value, follow, indent)
+            }
+            ExpressionKind::Block(_) => self.block(e, indent, None),
+            ExpressionKind::Match { expr, cases } => self.match_expression(e, expr, cases, indent),
+            ExpressionKind::Maybe { value } => match value {
+                Some(value) => format!("some({})", self.expr(value, Prec::Expr, follow, indent)),
+                None => "none()".to_string(),
+            },
+            ExpressionKind::Result { is_success, value } => format!(
+                "{} <- {}",
+                if *is_success { "success" } else { "error" },
+                self.expr(value, Prec::Expr, follow, indent)
+            ),
+            ExpressionKind::Assignment { .. } | ExpressionKind::Destructure { .. } => {
+                self.statement(e, indent)
+            }
+            ExpressionKind::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => format!(
+                "{}{}.{}",
+                self.expr(object, Prec::Access, Follow::Operator, indent),
+                optional_mark(*optional),
+                property
+            ),
+            ExpressionKind::Index {
+                object,
+                index,
+                optional,
+            } => format!(
+                "{}{}[{}]",
+                self.expr(object, Prec::Access, Follow::Operator, indent),
+                optional_mark(*optional),
+                self.expr(index, Prec::Expr, Follow::Nothing, indent)
+            ),
+            ExpressionKind::Slice {
+                object,
+                start,
+                end,
+                optional,
+            } => {
+                let object = self.expr(object, Prec::Access, Follow::Operator, indent);
+                let mut bound = |bound: &Option<Box<Expression>>| {
+                    bound.as_ref().map_or(String::new(), |bound| {
+                        self.expr(bound, Prec::Expr, Follow::Nothing, indent)
+                    })
+                };
+                let (start, end) = (bound(start), bound(end));
+                format!("{}{}[{}:{}]", object, optional_mark(*optional), start, end)
+            }
+            ExpressionKind::ErrorPropagation { expression } => {
+                format!("{}?", self.postfix_operand(expression, indent))
+            }
+            ExpressionKind::DefaultValue {
+                expression,
+                default,
+            } => format!(
+                "{} | {}",
+                self.expr(expression, Prec::Postfix, Follow::Operator, indent),
+                self.expr(default, Prec::Binary, follow, indent)
+            ),
+            ExpressionKind::BinaryOp {
+                left,
+                operator: BinaryOperator::Power,
+                right,
+            } => format!(
+                "{} ** {}",
+                self.expr(left, Prec::Flow, Follow::Operator, indent),
+                self.expr(right, Prec::Unary, follow, indent)
+            ),
+            ExpressionKind::BinaryOp {
+                left,
+                operator,
+                right,
+            } => {
+                // Operators of a level group to the left, so an equal one on the right
+                // needs parentheses as well as a looser one on either side
+                let rank = operator_rank(operator);
+                let left_source =
+                    self.binary_operand(left, |inner| inner < rank, Follow::Operator, indent);
+                let right_source =
+                    self.binary_operand(right, |inner| inner <= rank, follow, indent);
+                // Ranges read as one unit: `0..n`
+                let separator = match operator {
+                    BinaryOperator::Range => "",
+                    _ => " ",
+                };
+                format!(
+                    "{}{}{}{}{}",
+                    left_source,
+                    separator,
+                    operator_source(operator),
+                    separator,
+                    right_source
+                )
+            }
+            ExpressionKind::UnaryOp { operator, operand } => format!(
+                "{}{}",
+                match operator {
+                    UnaryOperator::Negate => "-",
+                    UnaryOperator::Not => "!",
+                },
+                self.expr(operand, Prec::Unary, follow, indent)
+            ),
+            ExpressionKind::Annotated {
+                annotation,
+                expression,
+            } => format!(
+                "{}\n{}{}",
+                self.annotation(annotation),
+                pad(indent),
+                self.expr(expression, Prec::Expr, follow, indent)
+            ),
+        }
+    }
+
+    /// An operand of a binary operator other than `**`, wrapped when `looser` says its
+    /// own operator's rank would let it bind the wrong way
+    fn binary_operand(
+        &mut self,
+        operand: &Expression,
+        looser: impl Fn(u8) -> bool,
+        follow: Follow,
+        indent: usize,
+    ) -> String {
+        let source = self.expr(operand, Prec::Binary, follow, indent);
+        match &operand.kind {
+            ExpressionKind::BinaryOp { operator, .. } if looser(operator_rank(operator)) => {
+                format!("({})", source)
+            }
+            _ => source,
+        }
+    }
+
+    /// The operand of `?`. Anything looser than an access is wrapped, since `a + b?`
+    /// would read as if the `?` bound tighter.
+    fn postfix_operand(&mut self, operand: &Expression, indent: usize) -> String {
+        let prec = match operand.kind {
+            ExpressionKind::ErrorPropagation { .. } => Prec::Postfix,
+            _ => Prec::Access,
+        };
+        self.expr(operand, prec, Follow::Operator, indent)
+    }
+
+    fn comma_separated(&mut self, items: &[Expression], indent: usize) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| self.expr(item, Prec::Expr, Follow::Nothing, indent))
+            .collect()
+    }
+
+    /// An array or object literal: on one line when it fits, otherwise one entry per line
+    fn sequence(
+        &mut self,
+        open: &str,
+        close: &str,
+        entries: &[(String, &Expression)],
+        span: Span,
+        indent: usize,
+        lead: usize,
+    ) -> String {
+        if entries.is_empty() {
+            return format!("{}{}", open, close);
+        }
+
+        if !self.has_comments(span) {
+            let saved = self.next;
+            let items: Vec<String> = entries
+                .iter()
+                .map(|(prefix, value)| {
+                    let value = self.expr(value, Prec::Expr, Follow::Nothing, indent);
+                    format!("{}{}", prefix, value)
+                })
+                .collect();
+            let flat = format!("{}{}{}", open, items.join(", "), close);
+            if !flat.contains('\n') && fits(indent, lead, &flat) {
+                return flat;
+            }
+            self.next = saved;
+        }
+
+        let mut out = open.to_string();
+        out.push('\n');
+        for (i, (prefix, value)) in entries.iter().enumerate() {
+            self.leading(&mut out, start(value.span), indent + 1, &mut None);
+            out.push_str(&pad(indent + 1));
+            out.push_str(prefix);
+            out.push_str(&self.expr(value, Prec::Expr, Follow::Nothing, indent + 1));
+            if i + 1 < entries.len() {
+                out.push(',');
+            }
+            self.trailing(&mut out, value.span.end_line);
+            out.push('\n');
+        }
+        self.leading(&mut out, closing(span), indent + 1, &mut None);
+        out.push_str(&pad(indent));
+        out.push_str(close);
+        out
+    }
+
+    /// An arrow chain stays on one line when it was written on one and fits. Otherwise
+    /// every forward step starts a new line, indented under the chain, with the
+    /// backward inputs converging on it kept beside it; comments between steps put
+    /// each step on its own line so they stay next to their step.
+    fn arrow_chain(
+        &mut self,
+        expressions: &[Expression],
+        directions: &[ArrowDirection],
+        follow: Follow,
+        indent: usize,
+        lead: usize,
+    ) -> String {
+        let last = expressions.len() - 1;
+        let step_follow = |i: usize| if i == last { follow } else { Follow::Arrow };
+        let written_broken = expressions
+            .windows(2)
+            .any(|pair| pair[1].span.line > pair[0].span.end_line);
+        // Comments inside a step, e.g. in a conditional's block, are that step's business
+        let has_comments = expressions.windows(2).any(|pair| {
+            self.has_comments(Span::new(
+                pair[0].span.end_line,
+                pair[0].span.end_column,
+                pair[1].span.line,
+                pair[1].span.column,
+            ))
+        });
+
+        if !written_broken && !has_comments {
+            let saved = self.next;
+            let mut flat = self.expr(&expressions[0], Prec::Postfix, step_follow(0), indent);
+            for (i, (expression, direction)) in expressions[1..].iter().zip(directions).enumerate()
+            {
+                flat.push_str(arrow_source(direction));
+                flat.push_str(&self.expr(expression, Prec::Postfix, step_follow(i + 1), indent));
+            }
+            if fits(indent, lead, &flat) {
+                return flat;
+            }
+            self.next = saved;
+        }
+
+        let mut out = self.expr(&expressions[0], Prec::Postfix, step_follow(0), indent);
+        for (i, (expression, direction)) in expressions[1..].iter().zip(directions).enumerate() {
+            if has_comments || *direction == ArrowDirection::Forward {
+                out.push_str(arrow_source(direction).trim_end());
+                // Only comments written before the next step belong on this line
+                if self
+                    .comments
+                    .get(self.next)
+                    .is_some_and(|c| c.pos < start(expression.span))
+                {
+                    self.trailing(&mut out, expressions[i].span.end_line);
+                }
+                out.push('\n');
+                self.leading(&mut out, start(expression.span), indent + 1, &mut None);
+                out.push_str(&pad(indent + 1));
+            } else {
+                out.push_str(arrow_source(direction));
+            }
+            let step = self.expr(expression, Prec::Postfix, step_follow(i + 1), indent + 1);
+            out.push_str(&step);
+        }
+        out
+    }
+
+    fn object_mutation(
+        &mut self,
+        e: &Expression,
+        target: &Expression,
+        mutations: &[(String, Expression)],
+        indent: usize,
+        lead: usize,
+    ) -> String {
+        let target = self.expr(target, Prec::Postfix, Follow::Arrow, indent);
+        let has_comments = self.has_comments(e.span);
+
+        if !has_comments {
+            let saved = self.next;
+            let changes: Vec<String> = mutations
+                .iter()
+                .map(|(path, value)| {
+                    let value = self.expr(value, Prec::Chain, Follow::Nothing, indent);
+                    format!("({} <- {})", path, value)
+                })
+                .collect();
+            let flat = format!("{} <~ {}", target, changes.join(" <- "));
+            if fits(indent, lead, &flat) {
+                return flat;
+            }
+            self.next = saved;
+        }
+
+        let mut out = format!("{} <~", target);
+        for (i, (path, value)) in mutations.iter().enumerate() {
+            if i > 0 {
+                out.push_str(" <-");
+                self.trailing(&mut out, mutations[i - 1].1.span.end_line);
+            }
+            out.push('\n');
+            self.leading(&mut out, start(value.span), indent + 1, &mut None);
+            out.push_str(&pad(indent + 1));
+            let value = self.expr(value, Prec::Chain, Follow::Nothing, indent + 1);
+            out.push_str(&format!("({} <- {})", path, value));
+        }
+        out
+    }
+
+    fn match_expression(
+        &mut self,
+        e: &Expression,
+        subject: &Option<Box<Expression>>,
+        cases: &[MatchCase],
+        indent: usize,
+    ) -> String {
+        let mut out = String::from("match ");
+        if let Some(subject) = subject {
+            let mut subject = self.expr(subject, Prec::Primary, Follow::Nothing, indent);
+            // A leading brace would be read as the start of the cases
+            if subject.starts_with('{') {
+                subject = format!("({})", subject);
+            }
+            out.push_str(&subject);
+            out.push(' ');
+        }
+        out.push_str("{\n");
+
+        let case_indent = indent + 1;
+        for case in cases {
+            self.leading(&mut out, start(case.body.span), case_indent, &mut None);
+            out.push_str(&pad(case_indent));
+            out.push_str(&self.pattern(&case.pattern, case_indent));
+            if let Some(guard) = &case.guard {
+                // A guard is a full expression, so its `->` has to start the next line
+                let guard = self.expr(guard, Prec::Expr, Follow::Nothing, case_indent);
+                out.push_str(&format!(" when {}\n{}->", guard, pad(case_indent + 1)));
+            } else {
+                out.push_str(" ->");
+            }
+            out.push(' ');
+            out.push_str(&self.case_body(&case.body, case_indent));
+            self.trailing(&mut out, case.body.span.end_line);
+            out.push('\n');
+        }
+
+        self.leading(&mut out, closing(e.span), case_indent, &mut None);
+        out.push_str(&pad(indent));
+        out.push('}');
+        out
+    }
+
+    /// Short case bodies sit after the arrow; anything longer gets a block
+    fn case_body(&mut self, body: &Expression, indent: usize) -> String {
+        if !matches!(
+            body.kind,
+            ExpressionKind::Block(_)
+                | ExpressionKind::Assignment { .. }
+                | ExpressionKind::Destructure { .. }
+        ) {
+            let saved = self.next;
+            let inline = self.expr(body, Prec::Expr, Follow::Nothing, indent);
+            if !inline.contains('\n') {
+                return if inline.starts_with('{') {
+                    format!("({})", inline)
+                } else {
+                    inline
+                };
+            }
+            self.next = saved;
+        }
+        self.block(body, indent, None)
+    }
+
+    /// `break` or `continue`, with its label and value
+    fn loop_control(
+        &mut self,
+        keyword: &str,
+        label: &Option<String>,
+        value: &Option<Box<Expression>>,
+        follow: Follow,
+        indent: usize,
+    ) -> String {
+        let mut out = keyword.to_string();
+        if let Some(label) = label {
+            out.push(' ');
+            out.push_str(label);
+        }
+        if let Some(value) = value {
+            out.push_str(" <- ");
+            out.push_str(&self.expr(value, Prec::Expr, follow, indent));
+        }
+        out
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, indent: usize) -> String {
+        match pattern {
+            Pattern::Literal(literal) => match literal {
+                LiteralValue::Number(n) => n.to_string(),
+                LiteralValue::String(s) => quote(s),
+                LiteralValue::Boolean(b) => b.to_string(),
+                LiteralValue::Null => "null".to_string(),
+            },
+            Pattern::Identifier(name) => name.clone(),
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Tuple(patterns) => {
+                let patterns: Vec<String> =
+                    patterns.iter().map(|p| self.pattern(p, indent)).collect();
+                format!("({})", patterns.join(", "))
+            }
+            Pattern::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, p)| match p {
+                        Pattern::Identifier(name) if name == key => key.clone(),
+                        _ => format!("{}: {}", key, self.pattern(p, indent)),
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            Pattern::ArrowPattern { constructor, arg } => {
+                if constructor == "none" {
+                    constructor.clone()
+                } else {
+                    format!("{} <- {}", constructor, self.pattern(arg, indent))
+                }
+            }
+            Pattern::Comparison { operator, value } => format!(
+                "{} {}",
+                operator,
+                self.expr(value, Prec::Primary, Follow::Nothing, indent)
+            ),
+        }
+    }
+
+    fn condition(&mut self, condition_type: &ConditionType, indent: usize) -> String {
+        match condition_type {
+            ConditionType::Success => "success".to_string(),
+            ConditionType::AllValid => "allValid".to_string(),
+            ConditionType::Custom(name) => name.clone(),
+            // Not produced by the parser; `valid` is the custom condition testing the
+            // same truthiness
+            ConditionType::If => "valid".to_string(),
+            ConditionType::Expression(expression) => {
+                let source = self.expr(expression, Prec::Expr, Follow::Nothing, indent);
+                // A condition starting with one of these words is read as that keyword
+                let first_word = source
+                    .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .next()
+                    .unwrap_or_default();
+                if matches!(
+                    first_word,
+                    "success" | "allValid" | "true" | "false" | "valid"
+                ) {
+                    format!("({})", source)
+                } else {
+                    source
+                }
+            }
+        }
+    }
+
+    fn annotation(&self, annotation: &Annotation) -> String {
+        match annotation {
+            Annotation::Trace(name) => format!("@trace <- {}", quote(name)),
+            Annotation::Monitor(metrics) => {
+                let metrics: Vec<String> = metrics.iter().map(|m| quote(m)).collect();
+                format!("@monitor <- [{}]", metrics.join(", "))
+            }
+            Annotation::Config(config) => format!("@config <- {}", json_source(config)),
+            Annotation::Parallel(None) => "@parallel".to_string(),
+            Annotation::Parallel(Some(cores)) => format!("@parallel <- {}", cores),
+            Annotation::Debug(None) => "@debug".to_string(),
+            Annotation::Debug(Some(label)) => format!("@debug <- {}", quote(label)),
+            Annotation::Test(None) => "@test".to_string(),
+            Annotation::Test(Some(description)) => format!("@test <- {}", quote(description)),
+        }
+    }
+
+    /// Write the comments that come before `pos` on lines of their own, keeping single
+    /// blank lines from the source between them
+    fn leading(
+        &mut self,
+        out: &mut String,
+        pos: Pos,
+        indent: usize,
+        prev_line: &mut Option<usize>,
+    ) {
+        while let Some(comment) = self.comments.get(self.next) {
+            if comment.pos >= pos {
+                break;
+            }
+            let line = comment.pos.0;
+            self.separate(out, *prev_line, line);
+            out.push_str(&pad(indent));
+            out.push_str(&comment.text);
+            out.push('\n');
+            *prev_line = Some(line);
+            self.next += 1;
+        }
+    }
+
+    /// Append the next comment to the current line if it trailed code on `line` or
+    /// earlier. The caller ends the line straight after.
+    fn trailing(&mut self, out: &mut String, line: usize) {
+        if let Some(comment) = self.comments.get(self.next) {
+            if comment.trailing && comment.pos.0 <= line {
+                out.push(' ');
+                out.push_str(&comment.text);
+                self.next += 1;
+            }
+        }
+    }
+
+    /// Keep a blank line the source had between `prev_line` and `line`
+    fn separate(&self, out: &mut String, prev_line: Option<usize>, line: usize) {
+        if let Some(prev_line) = prev_line {
+            let blank = (prev_line + 1..line).any(|l| {
+                self.lines
+                    .get(l - 1)
+                    .is_some_and(|text| text.trim().is_empty())
+            });
+            if blank {
+                blank_line(out);
+            }
+        }
+    }
+
+    fn has_comments(&self, span: Span) -> bool {
+        self.comments
+            .iter()
+            .any(|c| c.pos >= start(span) && c.pos < end(span))
+    }
+
+    /// Position of the `}` closing the block whose last statement ends at `after`
+    fn closing_brace(&self, after: Pos) -> Pos {
+        let tokens = self.tree.tokens();
+        let first = tokens.partition_point(|t| start(t.span) < after);
+        let mut depth = 0usize;
+        for token in &tokens[first..] {
+            match token.kind {
+                TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                TokenType::RightBrace if depth == 0 => return start(token.span),
+                TokenType::RightBrace | TokenType::RightParen | TokenType::RightBracket => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        (usize::MAX, 0)
+    }
+}
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+/// End the current paragraph with an empty line, unless it already is or a block has
+/// only just opened
+fn blank_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with("\n\n") && !out.ends_with("{\n") {
+        out.push('\n');
+    }
+}
+
+/// Whether every line stays within the width limit, given that the first one starts
+/// after the indentation and `lead` further columns
+fn fits(indent: usize, lead: usize, source: &str) -> bool {
+    source.lines().enumerate().all(|(i, line)| {
+        let start = if i == 0 {
+            indent * INDENT.len() + lead
+        } else {
+            0
+        };
+        let width = start + line.chars().count();
+        width <= MAX_WIDTH
+    })
+}
+
+fn start(span: Span) -> Pos {
+    (span.line, span.column)
+}
+
+fn end(span: Span) -> Pos {
+    (span.end_line, span.end_column)
+}
+
+/// Position of the last character of a span, e.g. a closing bracket
+fn closing(span: Span) -> Pos {
+    (span.end_line, span.end_column.saturating_sub(1))
+}
+
+fn statements_of(body: &Expression) -> &[Expression] {
+    match &body.kind {
+        ExpressionKind::Block(statements) => statements,
+        _ => std::slice::from_ref(body),
+    }
+}
+
+fn precedence(e: &Expression) -> Prec {
+    match &e.kind {
+        ExpressionKind::Annotated { .. }
+        | ExpressionKind::Assignment { .. }
+        | ExpressionKind::Destructure { .. } => Prec::Expr,
+        ExpressionKind::Conditional { condition, .. } => {
+            if is_placeholder(condition) {
+                Prec::Primary
+            } else {
+                Prec::Expr
+            }
+        }
+        ExpressionKind::ArrowChain { .. } | ExpressionKind::ObjectMutation { .. } => Prec::Chain,
+        ExpressionKind::ErrorPropagation { .. } | ExpressionKind::DefaultValue { .. } => {
+            Prec::Postfix
+        }
+        ExpressionKind::PropertyAccess { .. }
+        | ExpressionKind::Index { .. }
+        | ExpressionKind::Slice { .. } => Prec::Access,
+        ExpressionKind::BinaryOp {
+            operator: BinaryOperator::Power,
+            ..
+        } => Prec::Power,
+        ExpressionKind::BinaryOp { .. } => Prec::Binary,
+        ExpressionKind::UnaryOp { .. } => Prec::Unary,
+        ExpressionKind::Match { .. }
+        | ExpressionKind::ForEach { .. }
+        | ExpressionKind::While { .. }
+        | ExpressionKind::Return(_)
+        | ExpressionKind::Success(_)
+        | ExpressionKind::Error(_)
+        | ExpressionKind::ErrorReturn(_)
+        | ExpressionKind::Break { .. }
+        | ExpressionKind::Continue { .. }
+        | ExpressionKind::Result { .. } => Prec::Flow,
+        _ => Prec::Primary,
+    }
+}
+
+/// Whether an open-ended construct would absorb what follows it
+fn swallows(e: &Expression, follow: Follow) -> bool {
+    match &e.kind {
+        ExpressionKind::Return(_)
+        | ExpressionKind::Success(_)
+        | ExpressionKind::Error(_)
+        | ExpressionKind::ErrorReturn(_)
+        | ExpressionKind::Result { .. }
+        | ExpressionKind::Annotated { .. } => follow != Follow::Nothing,
+        ExpressionKind::Break { value, .. } | ExpressionKind::Continue { value, .. } => {
+            value.is_some() && follow != Follow::Nothing
+        }
+        ExpressionKind::Lambda { body, .. } => {
+            follow == Follow::Operator && !lambda_needs_braces(body)
+        }
+        _ => false,
+    }
+}
+
+/// Bare lambda bodies stop at the next arrow, so anything looser needs a block
+fn lambda_needs_braces(body: &Expression) -> bool {
+    precedence(body) < Prec::Postfix
+        || matches!(
+            body.kind,
+            ExpressionKind::Block(_) | ExpressionKind::Object(_)
+        )
+}
+
+/// `label: ` before a labelled loop
+fn loop_label(label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{}: ", label),
+        None => String::new(),
+    }
+}
+
+fn optional_mark(optional: bool) -> &'static str {
+    if optional {
+        "?"
+    } else {
+        ""
+    }
+}
+
+/// The null condition the parser gives a standalone `i cond { ... }`
+fn is_placeholder(condition: &Expression) -> bool {
+    matches!(condition.kind, ExpressionKind::Null)
+}
+
+/// Binding strength of the operators the parser reads in `binary_op`, loosest first
+fn operator_rank(operator: &BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Or => 0,
+        BinaryOperator::And => 1,
+        BinaryOperator::Equal | BinaryOperator::NotEqual => 2,
+        BinaryOperator::Less
+        | BinaryOperator::Greater
+        | BinaryOperator::LessEq
+        | BinaryOperator::GreaterEq => 3,
+        BinaryOperator::Range => 4,
+        BinaryOperator::Add | BinaryOperator::Subtract => 5,
+        BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 6,
+        BinaryOperator::Power => 7,
+    }
+}
+
+fn operator_source(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Modulo => "%",
+        BinaryOperator::Power => "**",
+        BinaryOperator::Range => "..",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::LessEq => "<=",
+        BinaryOperator::GreaterEq => ">=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+    }
+}
+
+fn arrow_source(direction: &ArrowDirection) -> &'static str {
+    match direction {
+        ArrowDirection::Forward => " -> ",
+        ArrowDirection::Backward => " <- ",
+    }
+}
+
+/// Fractional literals keep their decimal point so they stay floats
+fn number_source(n: f64) -> String {
+    let source = n.to_string();
+    if source.contains('.') {
+        source
+    } else {
+        format!("{}.0", source)
+    }
+}
+
+/// Object keys are written bare unless they would not lex as an identifier
+fn key_source(key: &str) -> String {
+    let is_identifier = Lexer::new(key).tokenize().is_ok_and(|tokens| {
+        tokens.len() == 2
+            && tokens[0].token_type == TokenType::Identifier
+            && tokens[0].lexeme == key
+    });
+    if is_identifier {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+/// A double-quoted literal for `text`
+fn quote(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_source(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "null".to_string(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => quote(s),
+        serde_json::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(json_source).collect();
+            format!("[{}]", items.join(", "))
+        }
+        serde_json::Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key_source(key), json_source(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+fn type_source(annotation: &TypeAnnotation) -> String {
+    match annotation {
+        TypeAnnotation::Simple(name) => name.clone(),
+        TypeAnnotation::Generic(name, args) => {
+            let args: Vec<String> = args.iter().map(type_source).collect();
+            format!("{}<{}>", name, args.join(", "))
+        }
+        TypeAnnotation::Union(types) => {
+            let types: Vec<String> = types.iter().map(type_source).collect();
+            types.join(" | ")
+        }
+    }
+}
+
+fn return_type_source(return_type: &ReturnType) -> String {
+    let errors: Vec<String> = return_type.error_types.iter().map(type_source).collect();
+    match (&return_type.success_type, errors.is_empty()) {
+        (Some(success), true) => type_source(success),
+        (Some(success), false) => format!(
+            "success: {}, error: {}",
+            type_source(success),
+            errors.join(" | ")
+        ),
+        (None, _) => format!("error: {}", errors.join(" | ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_to_canonical_layout() {
+        let source =
+            "total(order){\n  order->\n  combine<-order.tax<-order.shipping->\n  print\n  \
+                      config={host:\"localhost\",\"max size\":5}\n  order->validate i success{ok} \
+                      ei (order.total)>100{error<-\"too big\"} e{-1}\n}";
+        let expected = "\
+total(order) {
+    order ->
+        combine <- order.tax <- order.shipping ->
+        print
+    config = {host: \"localhost\", \"max size\": 5}
+    order -> validate i success {
+        ok
+    } ei order.total > 100 {
+        error <- \"too big\"
+    } e {
+        -1
+    }
+}
+";
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keeps_comments_and_meaning() {
+        let source = "\
+// Totals
+main() {
+    x = (1 + 2) * 3 // nine
+
+
+    // Convergence
+    5 ->
+    add <- 10 <- // ten
+    15
+    [1, 2] -> map <- (n) => n * 2
+    i valid { x } e {
+        // nothing to do
+    }
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "\
+// Totals
+main() {
+    x = (1 + 2) * 3 // nine
+
+    // Convergence
+    5 ->
+        add <-
+        10 <- // ten
+        15
+    [1, 2] -> map <- (n) => n * 2
+    i valid {
+        x
+    } e {
+        // nothing to do
+    }
+}
+"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_parenthesises_by_operator_precedence() {
+        let cases = [
+            ("x = ((a + (b * c)))", "x = a + b * c\n"),
+            ("x = (a + b) * c - (d - f)", "x = (a + b) * c - (d - f)\n"),
+            (
+                "x = !(a && b) || (c || d) && f",
+                "x = !(a && b) || (c || d) && f\n",
+            ),
+            (
+                "x = (-2) ** 2 + -(2 ** 2) + 2 ** (3 ** 2)",
+                "x = (-2) ** 2 + -2 ** 2 + 2 ** 3 ** 2\n",
+            ),
+            ("x = (a < b) == (n % 2 != 0)", "x = a < b == (n % 2 != 0)\n"),
+            (
+                "x = (xs[0]) + (a?)[1] + (o.p)?.q[:2] + (a + b).c",
+                "x = xs[0] + (a?)[1] + o.p?.q[:2] + (a + b).c\n",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(format_source(source).unwrap(), expected, "{}", source);
+            assert_eq!(format_source(expected).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_keeps_string_literals_as_written() {
+        let source = "\
+main() {
+  note = \"\"\"
+      Dear {name},
+        thanks
+      \"\"\"
+  [r\"C:\\dir\", \"tab\\there {n+1} \\{x\\}\"]   -> print
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "\
+main() {
+    note = \"\"\"
+      Dear {name},
+        thanks
+      \"\"\"
+    [r\"C:\\dir\", \"tab\\there {n+1} \\{x\\}\"] -> print
+}
+"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_destructuring() {
+        let source = "\
+describe( {name:name, age : years} , (x,_)) {
+  mut ( a,b ) = [x, years]
+  fe (k,v) in entries({a:a}) { k }
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "\
+describe({name, age: years}, (x, _)) {
+    mut (a, b) = [x, years]
+    fe (k, v) in entries({a: a}) {
+        k
+    }
+}
+"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_loop_control() {
+        let source = "\
+outer:fe a in 0 .. n+1 {
+  w true { break outer<-a }
+  fe b in ((0 .. 3) -> reverse) { continue }
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "\
+outer: fe a in 0..n + 1 {
+    w true {
+        break outer <- a
+    }
+    fe b in (0..3 -> reverse) {
+        continue
+    }
+}
+"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_rejects_syntax_errors() {
+        assert!(format_source("main() { 5 -> }").is_err());
+    }
+}
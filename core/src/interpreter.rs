@@ -1,12 +1,22 @@
 //! Interpreter for Susumu with visual debugging and type safety
 
 use crate::ast::*;
-use crate::builtins::{value_to_display_string, BuiltinRegistry};
-use crate::environment::{Environment, EnvironmentManager};
-use crate::error::{SusumuError, SusumuResult};
-use serde_json::Value;
+use crate::builtins::{value_to_display_string, Builtin, BuiltinRegistry};
+use crate::bytecode::{Chunk, Compiler, LambdaProto};
+use crate::environment::{Closure, Environment, EnvironmentManager};
+use crate::error::{Location, SusumuError, SusumuResult};
+use crate::lexer::Lexer;
+use crate::limits::{ExecutionLimits, Limit};
+use crate::native::CallContext;
+use crate::observer::{Branch, ExecutionObserver};
+use crate::parser::Parser;
+use crate::permissions::{Capability, Permissions};
+use crate::value::{Map, NumericOp, Value};
+use crate::vm::{self, CompiledFunction};
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 #[cfg(feature = "parallel")]
@@ -19,8 +29,77 @@ use std::time::Instant;
 #[cfg(target_arch = "wasm32")]
 use js_sys::Date;
 
+#[cfg(not(target_arch = "wasm32"))]
+type Timestamp = Instant;
+
+#[cfg(target_arch = "wasm32")]
+type Timestamp = f64;
+
+/// Steps between checks of the clock against the timeout
+const CLOCK_CHECK_INTERVAL: u64 = 256;
+
+/// Native stack a Susumu call needs to be left before it starts. A debug build uses
+/// tens of kilobytes per call, so this leaves room for deeply nested expressions too.
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 1024 * 1024;
+
+/// Size of each extra stack segment a deeply nested call moves onto
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 16 * 1024 * 1024;
+
+/// Extra segments one thread may use, 256 MiB of native stack in all. Calls nested
+/// deeper than that fail like ones past `max_call_depth` instead of overflowing.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_STACK_SEGMENTS: usize = 16;
+
+/// Execution engine used to run programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Evaluate the AST directly, recording detailed execution traces
+    #[default]
+    TreeWalker,
+    /// Compile to bytecode and run it on the stack VM. Function locals live in VM slots,
+    /// so only the top-level execution trace is recorded, native functions see global
+    /// and captured variables through `CallContext::get_variable`, and execution
+    /// observers cannot be added.
+    Bytecode,
+}
+
+/// Configuration for an [`Interpreter`]
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterOptions {
+    pub backend: Backend,
+    /// Resource limits for sandboxed execution; unlimited by default
+    pub limits: ExecutionLimits,
+    /// Files, hosts, clock and environment the program may access; everything by default
+    pub permissions: Permissions,
+}
+
+/// Resources used so far by the current run, checked against [`ExecutionLimits`]
+#[derive(Debug, Clone, Default)]
+struct Budget {
+    /// Shared with the workers that evaluate convergent arguments in parallel
+    steps: Arc<AtomicU64>,
+    started: Option<Timestamp>,
+    depth: usize,
+    /// Extra native stack segments in use by calls on this thread
+    stack_segments: usize,
+}
+
+/// A call to a user-defined function or lambda that has not returned yet
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    /// Name of the function, `<lambda>` for anonymous ones
+    pub function: String,
+    /// Where the caller made the call
+    pub call_site: Location,
+    /// Scope the caller was evaluating in
+    pub caller_env: Arc<Environment>,
+}
+
 /// Enhanced interpreter with visual debugging and performance optimization
 pub struct Interpreter {
+    options: InterpreterOptions,
     env_manager: EnvironmentManager,
     builtins: BuiltinRegistry,
     // type_checker: TypeChecker,
@@ -30,6 +109,14 @@ pub struct Interpreter {
     performance_stats: PerformanceStats,
     /// Module loader for import/export resolution
     module_loader: ModuleLoader,
+    /// Bytecode for named user functions, compiled on first call
+    compiled: HashMap<String, Arc<CompiledFunction>>,
+    /// Expression or arrow step being evaluated
+    location: Location,
+    budget: Budget,
+    /// Susumu function calls in progress, outermost first
+    frames: Vec<CallFrame>,
+    observers: Vec<Box<dyn ExecutionObserver>>,
 }
 
 /// Module loader handles module resolution and caching
@@ -103,13 +190,24 @@ pub struct PerformanceStats {
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_options(InterpreterOptions::default())
+    }
+
+    /// Create an interpreter with explicit options, e.g. to select the bytecode backend
+    pub fn with_options(options: InterpreterOptions) -> Self {
         let mut interpreter = Self {
+            options,
             env_manager: EnvironmentManager::new(),
             builtins: BuiltinRegistry::new(),
             // type_checker: TypeChecker::new(),
             execution_traces: Vec::new(),
             performance_stats: PerformanceStats::default(),
             module_loader: ModuleLoader::new(),
+            compiled: HashMap::new(),
+            location: Location::default(),
+            budget: Budget::default(),
+            frames: Vec::new(),
+            observers: Vec::new(),
         };
 
         interpreter.setup_global_environment();
@@ -118,22 +216,30 @@ impl Interpreter {
 
     /// Execute a program and return the result
     pub fn execute(&mut self, program: &Program) -> SusumuResult<Value> {
+        self.execute_with_args(program, &[])
+    }
+
+    /// Execute a program, passing command-line arguments to `main(args)` as an array of
+    /// strings. A `main` without parameters is called without them.
+    pub fn execute_with_args(&mut self, program: &Program, args: &[String]) -> SusumuResult<Value> {
         let start_time = self.get_current_time();
-        // Register all functions
-        for func_def in &program.functions {
-            self.register_user_function(func_def)?;
-        }
 
         // Execute main expression if present, otherwise try to call main() function
-        let result = if let Some(main_expr) = &program.main_expression {
-            self.evaluate_with_debugging(main_expr)
+        let result = if program.main_expression.is_some() {
+            self.load(program)
         } else {
+            self.load(program)?;
             // Check if there's a main function and call it automatically
             let global_env = self.env_manager.global();
-            if global_env.get_function("main").is_ok() {
-                self.call_function_with_args("main", &[], &global_env)
-            } else {
-                Ok(Value::Null)
+            match global_env.get_function("main") {
+                Ok(main) => {
+                    let args = match main.params.is_empty() {
+                        true => Vec::new(),
+                        false => vec![Value::from(args.to_vec())],
+                    };
+                    self.call_function_with_args("main", &args, &global_env)
+                }
+                Err(_) => Ok(Value::Null),
             }
         };
 
@@ -142,6 +248,35 @@ impl Interpreter {
         result
     }
 
+    /// Define a program's functions and run its top-level statements without calling
+    /// `main`, so the host can call individual functions afterwards
+    pub fn load(&mut self, program: &Program) -> SusumuResult<Value> {
+        self.start_run();
+        for func_def in &program.functions {
+            self.register_user_function(func_def)?;
+        }
+
+        match &program.main_expression {
+            Some(main_expr) => self.evaluate_with_debugging(main_expr),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Options this interpreter was created with
+    pub fn options(&self) -> &InterpreterOptions {
+        &self.options
+    }
+
+    /// Change the limits applied to later runs
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.options.limits = limits;
+    }
+
+    /// Change what later calls may access
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.options.permissions = permissions;
+    }
+
     /// Get execution traces for visual debugging
     pub fn get_execution_traces(&self) -> &[ExecutionTrace] {
         &self.execution_traces
@@ -152,6 +287,234 @@ impl Interpreter {
         &self.performance_stats
     }
 
+    /// Variables defined in the global scope, sorted by name. Builtins, which are
+    /// global function values too, are left out.
+    pub fn global_variables(&self) -> Vec<(String, Value)> {
+        let global = self.env_manager.global();
+        let mut variables: Vec<(String, Value)> = global
+            .variable_names()
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), global.get(&name).ok()?)))
+            .filter(|(name, value)| {
+                !(self.builtins.contains(name) && *value == Value::function(name.as_str()))
+            })
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        variables
+    }
+
+    /// Functions defined by the programs run so far, sorted by name
+    pub fn user_functions(&self) -> Vec<FunctionDef> {
+        let global = self.env_manager.global();
+        let mut functions: Vec<FunctionDef> = global
+            .function_names()
+            .into_iter()
+            .filter_map(|name| global.get_function(&name).ok())
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        functions
+    }
+
+    /// Names of the builtin and native functions
+    pub fn builtin_names(&self) -> Vec<String> {
+        self.builtins.function_names()
+    }
+
+    /// Register a host function that can call back into the interpreter.
+    /// It replaces any builtin with the same name and is available as a function value.
+    pub fn register_native_function<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&mut CallContext<'_>, &[Value]) -> SusumuResult<Value> + Send + Sync + 'static,
+    {
+        self.builtins.register_native(name, func);
+        self.env_manager
+            .global()
+            .define(name.to_string(), Value::function(name));
+    }
+
+    /// Call a Susumu function (user-defined, builtin or native) from the host
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> SusumuResult<Value> {
+        self.start_run();
+        let global_env = self.env_manager.global();
+        self.call_function_with_args(name, args, &global_env)
+    }
+
+    /// Reset the step count and clock that limits are measured against
+    fn start_run(&mut self) {
+        self.budget.steps.store(0, Ordering::Relaxed);
+        self.budget.started = Some(self.get_current_time());
+    }
+
+    /// Count one evaluation step against the step and time limits
+    pub(crate) fn tick(&mut self) -> SusumuResult<()> {
+        let limits = self.options.limits;
+        if limits.max_steps.is_none() && limits.timeout.is_none() {
+            return Ok(());
+        }
+
+        let steps = self.budget.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max) = limits.max_steps {
+            if steps > max {
+                return Err(SusumuError::limit_exceeded(Limit::Steps(max)));
+            }
+        }
+        if let (Some(timeout), Some(started)) = (limits.timeout, self.budget.started) {
+            if steps.is_multiple_of(CLOCK_CHECK_INTERVAL)
+                && u128::from(self.elapsed_time_ns(started)) > timeout.as_nanos()
+            {
+                return Err(SusumuError::limit_exceeded(Limit::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail when a value is larger than the collection size limit allows
+    fn check_size(&self, value: Value) -> SusumuResult<Value> {
+        match self.options.limits.check_size(&value) {
+            Some(limit) => Err(SusumuError::limit_exceeded(limit)),
+            None => Ok(value),
+        }
+    }
+
+    /// Fail when a collection being built has grown past the collection size limit
+    pub(crate) fn check_len(&self, len: usize) -> SusumuResult<()> {
+        match self.options.limits.check_len(len) {
+            Some(limit) => Err(SusumuError::limit_exceeded(limit)),
+            None => Ok(()),
+        }
+    }
+
+    /// Make a Susumu function call one level deeper, enforcing the call depth limit.
+    /// Without one, calls still stop once the native stack budget is spent.
+    fn nested_call(
+        &mut self,
+        call: impl FnOnce(&mut Self) -> SusumuResult<Value>,
+    ) -> SusumuResult<Value> {
+        if let Some(max) = self.options.limits.max_call_depth {
+            if self.budget.depth >= max {
+                return Err(SusumuError::limit_exceeded(Limit::CallDepth(max)));
+            }
+        }
+        self.budget.depth += 1;
+        let result = self.with_stack(call);
+        self.budget.depth -= 1;
+        result
+    }
+
+    /// Run `call` with at least [`STACK_RED_ZONE`] of native stack, moving to a new
+    /// segment when the current one is nearly used up
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_stack(
+        &mut self,
+        call: impl FnOnce(&mut Self) -> SusumuResult<Value>,
+    ) -> SusumuResult<Value> {
+        if stacker::remaining_stack().is_none_or(|remaining| remaining >= STACK_RED_ZONE) {
+            return call(self);
+        }
+        if self.budget.stack_segments >= MAX_STACK_SEGMENTS {
+            return Err(SusumuError::limit_exceeded(Limit::CallDepth(
+                self.budget.depth - 1,
+            )));
+        }
+        self.budget.stack_segments += 1;
+        let result = stacker::grow(STACK_SEGMENT, || call(self));
+        self.budget.stack_segments -= 1;
+        result
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn with_stack(
+        &mut self,
+        call: impl FnOnce(&mut Self) -> SusumuResult<Value>,
+    ) -> SusumuResult<Value> {
+        call(self)
+    }
+
+    pub(crate) fn record_trace(&mut self, trace: ExecutionTrace) {
+        self.execution_traces.push(trace);
+    }
+
+    /// Source range of the expression currently being evaluated
+    pub(crate) fn current_span(&self) -> Span {
+        self.location.span
+    }
+
+    /// Point traces, errors and stack frames at `location` until the caller restores the
+    /// previous one, which is returned
+    pub(crate) fn enter_location(&mut self, location: Location) -> Location {
+        if location.span.is_unknown() {
+            self.location
+        } else {
+            std::mem::replace(&mut self.location, location)
+        }
+    }
+
+    pub(crate) fn restore_location(&mut self, location: Location) {
+        self.location = location;
+    }
+
+    /// Where the call currently being made was written
+    pub(crate) fn current_location(&self) -> Location {
+        self.location
+    }
+
+    /// The global scope, which holds top-level variables, functions and builtins
+    pub fn global_env(&self) -> Arc<Environment> {
+        self.env_manager.global()
+    }
+
+    /// Watch evaluation with `observer`, after any observers added before it. Only the
+    /// tree-walking backend reports statements, arrow steps and branches, so observers
+    /// are refused when the bytecode backend is selected.
+    pub fn add_observer(&mut self, observer: impl ExecutionObserver + 'static) -> SusumuResult<()> {
+        if self.options.backend == Backend::Bytecode {
+            return Err(SusumuError::runtime_error(
+                "Execution observers need the tree-walking backend, not the bytecode backend",
+            ));
+        }
+        self.observers.push(Box::new(observer));
+        Ok(())
+    }
+
+    /// Remove all observers
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// User-defined functions and lambdas currently being called, outermost first
+    pub fn call_frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    /// Parse `source` and evaluate it in `env`, as a debugger does for expressions
+    /// typed while the program is paused
+    pub fn evaluate_source(&mut self, source: &str, env: &Arc<Environment>) -> SusumuResult<Value> {
+        let program = Parser::new(Lexer::new(source).tokenize()?).parse()?;
+        match &program.main_expression {
+            Some(expr) => self.evaluate(expr, env),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Call `event` on each observer until one fails. Observers are detached while they
+    /// run, so code they evaluate is not observed.
+    fn notify(
+        &mut self,
+        mut event: impl FnMut(&mut dyn ExecutionObserver, &mut Self) -> SusumuResult<()>,
+    ) -> SusumuResult<()> {
+        if self.observers.is_empty() {
+            return Ok(());
+        }
+        let mut observers = std::mem::take(&mut self.observers);
+        let result = observers
+            .iter_mut()
+            .try_for_each(|observer| event(observer.as_mut(), self));
+        // Keep observers added by the callbacks after the existing ones
+        observers.append(&mut self.observers);
+        self.observers = observers;
+        result
+    }
+
     /// Generate visual execution flow diagram
     pub fn generate_execution_diagram(&self) -> String {
         let mut diagram = String::new();
@@ -242,7 +605,8 @@ impl Interpreter {
 
         // Register built-in functions as callable values
         for func_name in self.builtins.function_names() {
-            global.define(func_name, Value::String("builtin_function".to_string()));
+            let value = Value::function(func_name.as_str());
+            global.define(func_name, value);
         }
     }
 
@@ -250,6 +614,7 @@ impl Interpreter {
         // Store the function definition in the global environment
         let global = self.env_manager.global();
         global.define_function(func_def.name.clone(), func_def.clone());
+        self.compiled.remove(&func_def.name);
         Ok(())
     }
 
@@ -257,7 +622,14 @@ impl Interpreter {
         let start_time = self.get_current_time();
         self.performance_stats.total_expressions_evaluated += 1;
 
-        let result = self.evaluate(expr, &self.env_manager.current());
+        let env = self.env_manager.current();
+        let result = match self.options.backend {
+            Backend::TreeWalker => self.evaluate_body(expr, &env),
+            Backend::Bytecode => {
+                let chunk = Compiler::compile_main(expr);
+                vm::run_main(self, &chunk, env)
+            }
+        };
 
         let execution_time = self.elapsed_time_ns(start_time);
 
@@ -267,8 +639,8 @@ impl Interpreter {
             input_value: Value::Null, // Would track actual input
             output_value: result.as_ref().unwrap_or(&Value::Null).clone(),
             execution_time_ns: execution_time,
-            line: 1, // Would come from AST metadata
-            column: 1,
+            line: expr.span.line,
+            column: expr.span.column,
             step_type: ExecutionStepType::FunctionCall {
                 name: "evaluate".to_string(),
                 args: vec![],
@@ -279,41 +651,85 @@ impl Interpreter {
         result
     }
 
-    fn evaluate(&mut self, expr: &Expression, env: &Arc<Environment>) -> SusumuResult<Value> {
-        match expr {
-            Expression::Number(n) => Ok(self.create_number_value(*n)),
-            Expression::String(s) => Ok(Value::String(s.clone())),
-            Expression::Boolean(b) => Ok(Value::Bool(*b)),
-            Expression::Null => Ok(Value::Null),
+    /// Evaluate a program or function body, which is a block of statements or a single
+    /// expression
+    fn evaluate_body(&mut self, body: &Expression, env: &Arc<Environment>) -> SusumuResult<Value> {
+        match body.kind {
+            ExpressionKind::Block(_) => self.evaluate(body, env),
+            _ => self.evaluate_statement(body, env),
+        }
+    }
 
-            Expression::Identifier(name) => {
-                // First try to look up as a variable
-                match env.get(name) {
-                    Ok(value) => Ok(value),
-                    Err(_) => {
-                        // If not found as a variable, try as a zero-argument function call
-                        match env.get_function(name) {
-                            Ok(_) => self.call_function_with_args(name, &[], env),
-                            Err(_) => Err(SusumuError::undefined_variable(name)),
+    fn evaluate_statement(
+        &mut self,
+        expr: &Expression,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        self.notify(|observer, this| observer.before_statement(this, expr, env))?;
+        self.evaluate(expr, env)
+    }
+
+    pub(crate) fn evaluate(
+        &mut self,
+        expr: &Expression,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        let outer = self.enter_location(Location::new(expr.span, None));
+        let mut result = self
+            .notify(|observer, this| observer.before_expression(this, expr, env))
+            .and_then(|()| self.tick())
+            .and_then(|()| self.evaluate_expression(expr, env))
+            .and_then(|value| self.check_size(value));
+        if let Err(error) =
+            self.notify(|observer, this| observer.after_expression(this, expr, &result))
+        {
+            result = Err(error);
+        }
+        let result = result.map_err(|error| error.with_span(self.location.span));
+        self.restore_location(outer);
+        result
+    }
+
+    fn evaluate_expression(
+        &mut self,
+        expr: &Expression,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        match &expr.kind {
+            ExpressionKind::Integer(n) => Ok(Value::Int(*n)),
+            ExpressionKind::Number(n) => Ok(Value::Float(*n)),
+            ExpressionKind::String(s) => Ok(Value::String(s.clone())),
+            ExpressionKind::InterpolatedString(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => result.push_str(text),
+                        StringPart::Expression(expression) => {
+                            result.push_str(&self.evaluate(expression, env)?.to_string())
                         }
                     }
                 }
+                Ok(Value::String(result))
             }
+            ExpressionKind::Boolean(b) => Ok(Value::Bool(*b)),
+            ExpressionKind::Null => Ok(Value::Null),
+
+            ExpressionKind::Identifier(name) => self.lookup_identifier(name, env),
 
-            Expression::Tuple(elements) => {
+            ExpressionKind::Tuple(elements) => {
                 let values: Result<Vec<_>, _> =
                     elements.iter().map(|e| self.evaluate(e, env)).collect();
                 Ok(Value::Array(values?))
             }
 
-            Expression::Array(elements) => {
+            ExpressionKind::Array(elements) => {
                 let values: Result<Vec<_>, _> =
                     elements.iter().map(|e| self.evaluate(e, env)).collect();
                 Ok(Value::Array(values?))
             }
 
-            Expression::Object(pairs) => {
-                let mut object = serde_json::Map::new();
+            ExpressionKind::Object(pairs) => {
+                let mut object = Map::new();
                 for (key, value_expr) in pairs {
                     let value = self.evaluate(value_expr, env)?;
                     object.insert(key.clone(), value);
@@ -321,20 +737,32 @@ impl Interpreter {
                 Ok(Value::Object(object))
             }
 
-            Expression::ObjectMutation { target, mutations } => {
+            ExpressionKind::ObjectMutation { target, mutations } => {
                 self.evaluate_object_mutation(target, mutations, env)
             }
 
-            Expression::ArrowChain {
+            ExpressionKind::ArrowChain {
                 expressions,
                 directions,
             } => self.evaluate_arrow_chain_with_debugging(expressions, directions, env),
 
-            Expression::FunctionCall { name, args } => {
+            ExpressionKind::FunctionCall { name, args } => {
                 self.evaluate_function_call_with_debugging(name, args, env)
             }
 
-            Expression::Conditional {
+            ExpressionKind::Lambda { params, body } => {
+                // Anonymous functions close over the scope they are created in
+                let def = FunctionDef {
+                    name: LAMBDA_NAME.to_string(),
+                    params: params.clone(),
+                    return_type: None,
+                    body: (**body).clone(),
+                    span: expr.span,
+                };
+                Ok(self.lambda(def, env, None))
+            }
+
+            ExpressionKind::Conditional {
                 condition_type,
                 condition,
                 then_branch,
@@ -349,78 +777,79 @@ impl Interpreter {
                 env,
             ),
 
-            Expression::Return(value) => {
+            ExpressionKind::Return(value) => {
                 let val = self.evaluate(value, env)?;
                 Err(SusumuError::return_value(val))
             }
 
-            Expression::Error(value) => {
+            ExpressionKind::Error(value) => {
                 let val = self.evaluate(value, env)?;
                 Err(SusumuError::user_error(val))
             }
 
-            Expression::Success(value) => {
+            ExpressionKind::Success(value) => {
                 let val = self.evaluate(value, env)?;
                 Err(SusumuError::success_return(val))
             }
 
-            Expression::ErrorReturn(value) => {
+            ExpressionKind::ErrorReturn(value) => {
                 let val = self.evaluate(value, env)?;
                 Err(SusumuError::error_return(val))
             }
 
-            Expression::ForEach {
-                variable,
+            ExpressionKind::ForEach {
+                label,
+                pattern,
                 iterable,
                 body,
-            } => self.evaluate_foreach_with_debugging(variable, iterable, body, env),
-            Expression::While { condition, body } => {
-                self.evaluate_while_with_debugging(condition, body, env)
+            } => self.evaluate_foreach_with_debugging(label, pattern, iterable, body, env),
+            ExpressionKind::While {
+                label,
+                condition,
+                body,
+            } => self.evaluate_while_with_debugging(label, condition, body, env),
+            ExpressionKind::Break { label, value } => {
+                let value = match value {
+                    Some(value) => Some(self.evaluate(value, env)?),
+                    None => None,
+                };
+                Err(SusumuError::loop_break(label.clone(), value))
+            }
+            ExpressionKind::Continue { label, value } => {
+                let value = match value {
+                    Some(value) => Some(self.evaluate(value, env)?),
+                    None => None,
+                };
+                Err(SusumuError::loop_continue(label.clone(), value))
             }
 
-            Expression::Block(expressions) => {
+            ExpressionKind::Block(expressions) => {
                 let mut result = Value::Null;
                 for expr in expressions {
-                    result = self.evaluate(expr, env)?;
+                    result = self.evaluate_statement(expr, env)?;
                 }
                 Ok(result)
             }
 
-            Expression::Match { expr, cases } => {
+            ExpressionKind::Match { expr, cases } => {
                 self.evaluate_match_with_debugging(expr, cases, env)
             }
 
-            Expression::Maybe { value } => match value {
-                Some(v) => {
-                    let val = self.evaluate(v, env)?;
-                    Ok(Value::Object({
-                        let mut map = serde_json::Map::new();
-                        map.insert("type".to_string(), Value::String("some".to_string()));
-                        map.insert("value".to_string(), val);
-                        map
-                    }))
-                }
-                None => Ok(Value::Object({
-                    let mut map = serde_json::Map::new();
-                    map.insert("type".to_string(), Value::String("none".to_string()));
-                    map
-                })),
+            ExpressionKind::Maybe { value } => match value {
+                Some(v) => Ok(Value::some(self.evaluate(v, env)?)),
+                None => Ok(Value::none()),
             },
 
-            Expression::Result { is_success, value } => {
+            ExpressionKind::Result { is_success, value } => {
                 let val = self.evaluate(value, env)?;
-                Ok(Value::Object({
-                    let mut map = serde_json::Map::new();
-                    map.insert(
-                        "type".to_string(),
-                        Value::String(if *is_success { "success" } else { "error" }.to_string()),
-                    );
-                    map.insert("value".to_string(), val);
-                    map
-                }))
-            }
-
-            Expression::Assignment {
+                Ok(if *is_success {
+                    Value::success(val)
+                } else {
+                    Value::error(val)
+                })
+            }
+
+            ExpressionKind::Assignment {
                 target,
                 value,
                 mutable,
@@ -430,26 +859,82 @@ impl Interpreter {
                 env.define_with_mutability(target.clone(), val.clone(), *mutable);
                 Ok(val)
             }
+            ExpressionKind::Destructure {
+                pattern,
+                value,
+                mutable,
+            } => {
+                let val = self.evaluate(value, env)?;
+                for (name, part) in self.destructure(pattern, &val)? {
+                    env.define_with_mutability(name, part, *mutable);
+                }
+                Ok(val)
+            }
 
-            Expression::PropertyAccess { object, property } => {
+            ExpressionKind::PropertyAccess {
+                object,
+                property,
+                optional,
+            } => {
                 let obj = self.evaluate(object, env)?;
-                Ok(match obj {
-                    Value::Object(map) => map.get(property).cloned().unwrap_or(Value::Null),
-                    _ => Value::Null,
-                })
+                if *optional && obj.is_absent() {
+                    return Ok(Value::Null);
+                }
+                obj.property(property)
+            }
+
+            ExpressionKind::Index {
+                object,
+                index,
+                optional,
+            } => {
+                let obj = self.evaluate(object, env)?;
+                if *optional && obj.is_absent() {
+                    return Ok(Value::Null);
+                }
+                let index = self.evaluate(index, env)?;
+                obj.index(&index)
+            }
+
+            ExpressionKind::Slice {
+                object,
+                start,
+                end,
+                optional,
+            } => {
+                let obj = self.evaluate(object, env)?;
+                if *optional && obj.is_absent() {
+                    return Ok(Value::Null);
+                }
+                let mut bound = |bound: &Option<Box<Expression>>| {
+                    bound.as_ref().map(|b| self.evaluate(b, env)).transpose()
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                obj.slice(start.as_ref(), end.as_ref())
             }
 
-            Expression::BinaryOp {
+            ExpressionKind::BinaryOp {
                 left,
                 operator,
                 right,
             } => {
                 let left_val = self.evaluate(left, env)?;
+                // `&&` and `||` skip the right operand once the left one decides
+                match operator {
+                    BinaryOperator::And if !left_val.is_truthy() => return Ok(Value::Bool(false)),
+                    BinaryOperator::Or if left_val.is_truthy() => return Ok(Value::Bool(true)),
+                    _ => {}
+                }
                 let right_val = self.evaluate(right, env)?;
                 self.evaluate_binary_op(&left_val, operator, &right_val)
             }
 
-            Expression::ErrorPropagation { expression } => {
+            ExpressionKind::UnaryOp { operator, operand } => {
+                let value = self.evaluate(operand, env)?;
+                self.evaluate_unary_op(operator, &value)
+            }
+
+            ExpressionKind::ErrorPropagation { expression } => {
                 // Evaluate the expression and propagate errors
                 match self.evaluate(expression, env) {
                     Ok(value) => Ok(value),
@@ -463,24 +948,78 @@ impl Interpreter {
                 }
             }
 
-            Expression::DefaultValue {
+            ExpressionKind::DefaultValue {
                 expression,
                 default,
             } => {
                 // Try to evaluate the expression, use default if it fails
                 match self.evaluate(expression, env) {
                     Ok(value) => Ok(value),
+                    // Running out of a resource or permission is not a failure the
+                    // program can handle, and `break` and `continue` are not failures
+                    Err(error) if error.is_uncatchable() || error.is_loop_control() => Err(error),
                     Err(_) => self.evaluate(default, env), // Use default on any error
                 }
             }
 
-            Expression::Annotated {
+            ExpressionKind::Annotated {
                 annotation,
                 expression,
             } => self.evaluate_annotated_expression(annotation, expression, env),
         }
     }
 
+    /// Resolve an identifier: variables first, then zero-argument functions are called
+    /// and functions taking parameters become function values
+    pub(crate) fn lookup_identifier(
+        &mut self,
+        name: &str,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        match env.get(name) {
+            Ok(value) => Ok(value),
+            Err(_) => match env.get_function(name) {
+                Ok(func_def) if func_def.params.is_empty() => {
+                    self.call_function_with_args(name, &[], env)
+                }
+                Ok(_) => Ok(Value::function(name)),
+                Err(_) => Err(SusumuError::undefined_variable(name)),
+            },
+        }
+    }
+
+    /// Create a lambda compiled by the bytecode backend as a closure over `env`
+    pub(crate) fn define_compiled_lambda(
+        &mut self,
+        lambda: &LambdaProto,
+        env: Arc<Environment>,
+    ) -> Value {
+        self.lambda(lambda.def.clone(), &env, Some(lambda.chunk.clone()))
+    }
+
+    /// A function value owning its closure over `env`. Lambdas created at the top level
+    /// see the global scope like named functions, rather than holding on to it.
+    fn lambda(&self, def: FunctionDef, env: &Arc<Environment>, chunk: Option<Arc<Chunk>>) -> Value {
+        let global = self.env_manager.global();
+        let env = (!Arc::ptr_eq(env, &global)).then(|| env.clone());
+        Value::Lambda(Arc::new(Closure { def, env, chunk }))
+    }
+
+    /// Compiled code for a user function, compiling it on first use
+    fn compiled_function(&mut self, name: &str) -> Option<Arc<CompiledFunction>> {
+        if let Some(function) = self.compiled.get(name) {
+            return Some(function.clone());
+        }
+
+        let closure = self.env_manager.global().get_closure(name).ok()?;
+        let function = Arc::new(CompiledFunction {
+            chunk: Arc::new(Compiler::compile_function(&closure.def)),
+            env: closure.env,
+        });
+        self.compiled.insert(name.to_string(), function.clone());
+        Some(function)
+    }
+
     fn evaluate_annotated_expression(
         &mut self,
         annotation: &Annotation,
@@ -592,6 +1131,8 @@ impl Interpreter {
                 }
                 result
             }
+            // Test functions are collected by `susumu test` and otherwise run as written
+            Annotation::Test(_) => self.evaluate(expression, env),
         }
     }
 
@@ -617,69 +1158,22 @@ impl Interpreter {
 
         while i < directions.len() {
             let direction = &directions[i];
-            let current_expr = &expressions[i + 1];
+            let step = i + 1;
+            let current_expr = &expressions[step];
+            // Calls made by this step report its location; `evaluate` restores the outer one
+            self.enter_location(Location::new(current_expr.span, Some(step)));
+            self.notify(|observer, this| {
+                observer.before_arrow_step(this, step, current_expr, &result, env)
+            })?;
 
             match direction {
                 ArrowDirection::Forward => {
-                    if let Expression::Identifier(func_name) = current_expr {
+                    if let ExpressionKind::Identifier(func_name) = &current_expr.kind {
                         // Check for convergent arguments
                         let mut args = vec![result.clone()];
-                        let mut j = i + 1;
-
-                        // Collect convergent expressions for parallel evaluation
-                        let mut convergent_expressions = Vec::new();
-                        while j < directions.len() && directions[j] == ArrowDirection::Backward {
-                            j += 1;
-                            if j < expressions.len() {
-                                convergent_expressions.push(&expressions[j]);
-                            }
-                        }
-
-                        // Evaluate convergent arguments - use parallel processing if available
-                        #[cfg(feature = "parallel")]
-                        {
-                            if convergent_expressions.len() > 1 {
-                                // Parallel evaluation using rayon - significant performance boost for convergent operations
-                                let env_clone = env.clone();
-                                let env_manager_clone = self.env_manager.clone();
-                                let builtins_clone = self.builtins.clone();
-
-                                let convergent_results: Result<Vec<Value>, SusumuError> =
-                                    convergent_expressions
-                                        .par_iter()
-                                        .map(|expr| {
-                                            // Each thread gets its own interpreter instance with shared state
-                                            let mut temp_interpreter = Interpreter::new();
-                                            temp_interpreter.env_manager =
-                                                env_manager_clone.clone();
-                                            temp_interpreter.builtins = builtins_clone.clone();
-                                            temp_interpreter.evaluate(expr, &env_clone)
-                                        })
-                                        .collect();
-
-                                match convergent_results {
-                                    Ok(results) => args.extend(results),
-                                    Err(e) => return Err(e),
-                                }
-
-                                self.performance_stats.parallel_operations += 1;
-                            } else {
-                                // Single or no convergent arguments
-                                for expr in convergent_expressions {
-                                    let converging_value = self.evaluate(expr, env)?;
-                                    args.push(converging_value);
-                                }
-                            }
-                        }
-
-                        #[cfg(not(feature = "parallel"))]
-                        {
-                            // Sequential evaluation fallback
-                            for expr in convergent_expressions {
-                                let converging_value = self.evaluate(expr, env)?;
-                                args.push(converging_value);
-                            }
-                        }
+                        let (convergent_args, j) =
+                            self.evaluate_convergent_args(expressions, directions, i + 1, env)?;
+                        args.extend(convergent_args);
 
                         self.performance_stats.convergence_operations += 1;
 
@@ -689,8 +1183,8 @@ impl Interpreter {
                             input_value: Value::Array(args.clone()),
                             output_value: Value::Null, // Will be updated
                             execution_time_ns: 0,
-                            line: 1,
-                            column: 1,
+                            line: current_expr.span.line,
+                            column: current_expr.span.column,
                             step_type: ExecutionStepType::ArrowForward {
                                 from: self.value_to_string(&result),
                                 to: func_name.clone(),
@@ -705,7 +1199,7 @@ impl Interpreter {
                         }
 
                         i = j; // Skip past convergence
-                    } else if let Expression::Match { expr, cases } = current_expr {
+                    } else if let ExpressionKind::Match { expr, cases } = &current_expr.kind {
                         // Special handling for match expressions in arrow chains
                         // Pass the current result as the value to match against
                         match expr {
@@ -719,17 +1213,17 @@ impl Interpreter {
                             }
                         }
                         i += 1;
-                    } else if let Expression::Conditional {
+                    } else if let ExpressionKind::Conditional {
                         condition_type,
                         condition,
                         then_branch,
                         else_if_branches,
                         else_branch,
-                    } = current_expr
+                    } = &current_expr.kind
                     {
                         // Special handling for conditional expressions in arrow chains
                         // Use the current arrow chain result as the condition
-                        if let Expression::Null = **condition {
+                        if let ExpressionKind::Null = condition.kind {
                             // This is a conditional from an arrow chain (placeholder condition)
                             // Use the current result as the condition
                             result = self.evaluate_conditional_with_arrow_result(
@@ -747,8 +1241,19 @@ impl Interpreter {
                         i += 1;
                     } else {
                         // Direct function call or other expression
-                        result = self.evaluate(current_expr, env)?;
-                        i += 1;
+                        let target = self.evaluate(current_expr, env)?;
+                        if target.is_function() {
+                            // Lambdas and other function values receive the flow like named functions
+                            let mut args = vec![result.clone()];
+                            let (convergent_args, j) =
+                                self.evaluate_convergent_args(expressions, directions, i + 1, env)?;
+                            args.extend(convergent_args);
+                            result = self.call_callable(&target, &args, env)?;
+                            i = j;
+                        } else {
+                            result = target;
+                            i += 1;
+                        }
                     }
                 }
                 ArrowDirection::Backward => {
@@ -758,88 +1263,111 @@ impl Interpreter {
                     ));
                 }
             }
+            self.notify(|observer, this| {
+                observer.after_arrow_step(this, step, current_expr, &result)
+            })?;
         }
 
         Ok(result)
     }
 
-    fn evaluate_conditional_with_arrow_result(
+    /// Evaluate the `<-` arguments converging on the function at `func_index`.
+    /// Returns the argument values and the index of the last direction consumed.
+    fn evaluate_convergent_args(
         &mut self,
-        condition_type: &ConditionType,
-        arrow_result: &Value,
-        then_branch: &Expression,
-        else_if_branches: &Vec<ElseIfBranch>,
-        else_branch: &Option<Box<Expression>>,
+        expressions: &[Expression],
+        directions: &[ArrowDirection],
+        func_index: usize,
         env: &Arc<Environment>,
-    ) -> SusumuResult<Value> {
-        let mut branch_name = "none";
-        let result;
+    ) -> SusumuResult<(Vec<Value>, usize)> {
+        let mut args = Vec::new();
+        let mut j = func_index;
 
-        // Check main condition using arrow result
-        let branch_taken = match condition_type {
-            ConditionType::Success => !matches!(arrow_result, Value::Null),
-            ConditionType::AllValid => self.evaluate_all_valid_condition(arrow_result)?,
-            ConditionType::Custom(condition_name) => {
-                self.evaluate_custom_condition(condition_name, arrow_result)?
-            }
-            ConditionType::If => self.is_truthy(arrow_result),
-            ConditionType::Expression(expr) => {
-                let condition_result = self.evaluate(expr, env)?;
-                self.is_truthy(&condition_result)
+        // Collect convergent expressions for parallel evaluation
+        let mut convergent_expressions = Vec::new();
+        while j < directions.len() && directions[j] == ArrowDirection::Backward {
+            j += 1;
+            if j < expressions.len() {
+                convergent_expressions.push(&expressions[j]);
             }
-        };
-
-        if branch_taken {
-            result = self.evaluate(then_branch, env)?;
-            branch_name = "then";
-        } else {
-            // Check else-if branches
-            let mut else_if_taken = false;
-            let mut else_if_result = Value::Null;
-
-            for else_if_branch in else_if_branches {
-                let else_if_condition_result = match &else_if_branch.condition_type {
-                    ConditionType::Success => !matches!(arrow_result, Value::Null),
-                    ConditionType::AllValid => self.evaluate_all_valid_condition(arrow_result)?,
-                    ConditionType::Custom(condition_name) => {
-                        self.evaluate_custom_condition(condition_name, arrow_result)?
-                    }
-                    ConditionType::If => self.is_truthy(arrow_result),
-                    ConditionType::Expression(expr) => {
-                        let condition_result = self.evaluate(expr, env)?;
-                        self.is_truthy(&condition_result)
-                    }
-                };
+        }
 
-                if else_if_condition_result {
-                    else_if_result = self.evaluate(&else_if_branch.then_branch, env)?;
-                    else_if_taken = true;
-                    branch_name = "else-if";
-                    break;
+        // Evaluate convergent arguments - use parallel processing if available
+        #[cfg(feature = "parallel")]
+        {
+            if convergent_expressions.len() > 1 {
+                // Parallel evaluation using rayon - significant performance boost for convergent operations
+                let env_clone = env.clone();
+                let env_manager_clone = self.env_manager.clone();
+                let builtins_clone = self.builtins.clone();
+                let options = self.options.clone();
+                let budget = self.budget.clone();
+
+                let convergent_results: Result<Vec<Value>, SusumuError> = convergent_expressions
+                    .par_iter()
+                    .map(|expr| {
+                        // Each thread gets its own interpreter instance with shared state
+                        let mut temp_interpreter = Interpreter::with_options(options.clone());
+                        temp_interpreter.env_manager = env_manager_clone.clone();
+                        temp_interpreter.builtins = builtins_clone.clone();
+                        temp_interpreter.budget = budget.clone();
+                        temp_interpreter.evaluate(expr, &env_clone)
+                    })
+                    .collect();
+
+                args.extend(convergent_results?);
+
+                self.performance_stats.parallel_operations += 1;
+            } else {
+                // Single or no convergent arguments
+                for expr in convergent_expressions {
+                    let converging_value = self.evaluate(expr, env)?;
+                    args.push(converging_value);
                 }
             }
+        }
 
-            if else_if_taken {
-                result = else_if_result;
-            } else if let Some(else_expr) = else_branch {
-                result = self.evaluate(else_expr, env)?;
-                branch_name = "else";
-            } else {
-                result = Value::Null;
+        #[cfg(not(feature = "parallel"))]
+        {
+            // Sequential evaluation fallback
+            for expr in convergent_expressions {
+                let converging_value = self.evaluate(expr, env)?;
+                args.push(converging_value);
             }
         }
 
+        Ok((args, j))
+    }
+
+    fn evaluate_conditional_with_arrow_result(
+        &mut self,
+        condition_type: &ConditionType,
+        arrow_result: &Value,
+        then_branch: &Expression,
+        else_if_branches: &[ElseIfBranch],
+        else_branch: &Option<Box<Expression>>,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        let (branch, result) = self.evaluate_branch(
+            condition_type,
+            arrow_result,
+            then_branch,
+            else_if_branches,
+            else_branch,
+            env,
+        )?;
+
         // Add execution trace
         let trace = ExecutionTrace {
             expression: "arrow-conditional".to_string(),
             input_value: arrow_result.clone(),
             output_value: result.clone(),
             execution_time_ns: 0,
-            line: 1,
-            column: 1,
+            line: self.location.span.line,
+            column: self.location.span.column,
             step_type: ExecutionStepType::Conditional {
-                branch: branch_name.to_string(),
-                condition_result: branch_taken || branch_name != "none",
+                branch: branch.trace_name().to_string(),
+                condition_result: branch != Branch::Skipped,
             },
         };
         self.execution_traces.push(trace);
@@ -864,8 +1392,8 @@ impl Interpreter {
             input_value: Value::Array(arg_values.clone()),
             output_value: Value::Null,
             execution_time_ns: 0,
-            line: 1,
-            column: 1,
+            line: self.location.span.line,
+            column: self.location.span.column,
             step_type: ExecutionStepType::FunctionCall {
                 name: name.to_string(),
                 args: arg_values.clone(),
@@ -881,80 +1409,30 @@ impl Interpreter {
         condition_type: &ConditionType,
         condition: &Expression,
         then_branch: &Expression,
-        else_if_branches: &Vec<ElseIfBranch>,
+        else_if_branches: &[ElseIfBranch],
         else_branch: &Option<Box<Expression>>,
         env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
         let condition_value = self.evaluate(condition, env)?;
-        let mut branch_name = "none";
-        let result;
-
-        // Check main condition
-        let branch_taken = match condition_type {
-            ConditionType::Success => !matches!(condition_value, Value::Null),
-            ConditionType::AllValid => self.evaluate_all_valid_condition(&condition_value)?,
-            ConditionType::Custom(condition_name) => {
-                self.evaluate_custom_condition(condition_name, &condition_value)?
-            }
-            ConditionType::If => self.is_truthy(&condition_value),
-            ConditionType::Expression(expr) => {
-                let condition_result = self.evaluate(expr, env)?;
-                self.is_truthy(&condition_result)
-            }
-        };
-
-        if branch_taken {
-            result = self.evaluate(then_branch, env)?;
-            branch_name = "then";
-        } else {
-            // Check else-if branches
-            let mut else_if_taken = false;
-            let mut else_if_result = Value::Null;
-
-            for else_if_branch in else_if_branches {
-                let else_if_condition_result = match &else_if_branch.condition_type {
-                    ConditionType::Success => !matches!(condition_value, Value::Null),
-                    ConditionType::AllValid => {
-                        self.evaluate_all_valid_condition(&condition_value)?
-                    }
-                    ConditionType::Custom(condition_name) => {
-                        self.evaluate_custom_condition(condition_name, &condition_value)?
-                    }
-                    ConditionType::If => self.is_truthy(&condition_value),
-                    ConditionType::Expression(expr) => {
-                        let condition_result = self.evaluate(expr, env)?;
-                        self.is_truthy(&condition_result)
-                    }
-                };
-
-                if else_if_condition_result {
-                    else_if_result = self.evaluate(&else_if_branch.then_branch, env)?;
-                    else_if_taken = true;
-                    branch_name = "else-if";
-                    break;
-                }
-            }
-
-            if else_if_taken {
-                result = else_if_result;
-            } else if let Some(else_expr) = else_branch {
-                result = self.evaluate(else_expr, env)?;
-                branch_name = "else";
-            } else {
-                result = Value::Null;
-            }
-        }
+        let (branch, result) = self.evaluate_branch(
+            condition_type,
+            &condition_value,
+            then_branch,
+            else_if_branches,
+            else_branch,
+            env,
+        )?;
 
         let trace = ExecutionTrace {
             expression: "conditional".to_string(),
             input_value: condition_value,
             output_value: result.clone(),
             execution_time_ns: 0,
-            line: 1,
-            column: 1,
+            line: self.location.span.line,
+            column: self.location.span.column,
             step_type: ExecutionStepType::Conditional {
-                branch: branch_name.to_string(),
-                condition_result: branch_taken || branch_name != "none",
+                branch: branch.trace_name().to_string(),
+                condition_result: branch != Branch::Skipped,
             },
         };
         self.execution_traces.push(trace);
@@ -962,6 +1440,47 @@ impl Interpreter {
         Ok(result)
     }
 
+    /// Choose the branch of a conditional whose condition holds for `value`, report it
+    /// to observers, then evaluate it
+    fn evaluate_branch(
+        &mut self,
+        condition_type: &ConditionType,
+        value: &Value,
+        then_branch: &Expression,
+        else_if_branches: &[ElseIfBranch],
+        else_branch: &Option<Box<Expression>>,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<(Branch, Value)> {
+        let mut branch = match else_branch {
+            Some(_) => Branch::Else,
+            None => Branch::Skipped,
+        };
+        if self.check_condition(condition_type, value, env)? {
+            branch = Branch::Then;
+        } else {
+            for (index, else_if_branch) in else_if_branches.iter().enumerate() {
+                if self.check_condition(&else_if_branch.condition_type, value, env)? {
+                    branch = Branch::ElseIf(index);
+                    break;
+                }
+            }
+        }
+
+        let span = self.location.span;
+        self.notify(|observer, this| observer.on_branch(this, span, branch, env))?;
+        let body = match branch {
+            Branch::Then => Some(then_branch),
+            Branch::ElseIf(index) => Some(&else_if_branches[index].then_branch),
+            Branch::Else => else_branch.as_deref(),
+            Branch::Skipped => None,
+        };
+        let result = match body {
+            Some(body) => self.evaluate(body, env)?,
+            None => Value::Null,
+        };
+        Ok((branch, result))
+    }
+
     /// Evaluate object mutations with <~ operator
     fn evaluate_object_mutation(
         &mut self,
@@ -970,7 +1489,7 @@ impl Interpreter {
         env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
         // Check if target is a mutable variable identifier
-        if let Expression::Identifier(var_name) = target {
+        if let ExpressionKind::Identifier(var_name) = &target.kind {
             // Check if this is a mutable variable
             if env.is_mutable(var_name).unwrap_or(false) {
                 // In-place mutation for mutable variables
@@ -1021,12 +1540,7 @@ impl Interpreter {
     }
 
     /// Set a nested property value using dot notation
-    fn set_nested_property(
-        &self,
-        obj: &mut serde_json::Map<String, Value>,
-        path: &str,
-        value: Value,
-    ) -> SusumuResult<()> {
+    fn set_nested_property(&self, obj: &mut Map, path: &str, value: Value) -> SusumuResult<()> {
         let parts: Vec<&str> = path.split('.').collect();
 
         if parts.len() == 1 {
@@ -1046,7 +1560,7 @@ impl Interpreter {
                 // Navigate deeper or create intermediate objects
                 let entry = current
                     .entry(part.to_string())
-                    .or_insert_with(|| Value::Object(serde_json::Map::new()));
+                    .or_insert_with(|| Value::Object(Map::new()));
 
                 match entry {
                     Value::Object(ref mut nested_obj) => {
@@ -1067,104 +1581,194 @@ impl Interpreter {
 
     fn evaluate_foreach_with_debugging(
         &mut self,
-        variable: &str,
+        label: &Option<String>,
+        pattern: &Pattern,
         iterable: &Expression,
         body: &Expression,
         env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
-        let iterable_value = self.evaluate(iterable, env)?;
-
-        match iterable_value {
-            Value::Array(items) => {
-                let mut results = Vec::new();
-
-                for item in items {
-                    // Create new scope for loop iteration
-                    let new_scope = self.env_manager.push_scope();
-                    new_scope.define(variable.to_string(), item.clone());
-                    let loop_result = self.evaluate(body, &new_scope)?;
-                    self.env_manager.pop_scope()?;
+        // Ranges are counted through rather than built as arrays
+        let items: Box<dyn Iterator<Item = Value>> = match &iterable.kind {
+            ExpressionKind::BinaryOp {
+                left,
+                operator: BinaryOperator::Range,
+                right,
+            } => {
+                let start = self.evaluate(left, env)?;
+                let end = self.evaluate(right, env)?;
+                Box::new(Value::range(&start, &end)?.map(Value::Int))
+            }
+            _ => match self.evaluate(iterable, env)? {
+                Value::Array(items) => Box::new(items.into_iter()),
+                other => return Err(SusumuError::type_error("array", format!("{:?}", other))),
+            },
+        };
 
-                    results.push(loop_result);
+        let mut results = Vec::new();
+        for item in items {
+            // Create new scope for loop iteration
+            let new_scope = Arc::new(Environment::with_parent(env.clone()));
+            for (name, part) in self.destructure(pattern, &item)? {
+                new_scope.define(name, part);
+            }
+            match self.loop_iteration(label, body, &new_scope)? {
+                ControlFlow::Continue(result) => {
+                    results.extend(result);
+                    self.check_len(results.len())?;
                 }
-
-                Ok(Value::Array(results))
+                ControlFlow::Break(Some(value)) => return Ok(value),
+                ControlFlow::Break(None) => break,
             }
-            _ => Err(SusumuError::type_error(
-                "array",
-                &format!("{:?}", iterable_value),
-            )),
         }
+
+        Ok(Value::Array(results))
     }
 
     fn evaluate_while_with_debugging(
         &mut self,
+        label: &Option<String>,
         condition: &Expression,
         body: &Expression,
         env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
+        // Runaway loops are stopped by the execution limits
         let mut last_result = Value::Null;
-        let mut iteration_count = 0;
-        const MAX_ITERATIONS: usize = 10000; // Prevent infinite loops
 
         loop {
-            // Prevent infinite loops
-            if iteration_count >= MAX_ITERATIONS {
-                return Err(SusumuError::runtime_error(
-                    "While loop exceeded maximum iterations (10000). Possible infinite loop.",
-                ));
-            }
-
             // Evaluate condition
             let condition_value = self.evaluate(condition, env)?;
-            let should_continue = match condition_value {
-                Value::Bool(b) => b,
-                Value::Number(n) => n.as_f64().unwrap_or(0.0) != 0.0,
-                Value::String(s) => !s.is_empty(),
-                Value::Null => false,
-                Value::Array(ref arr) => !arr.is_empty(),
-                Value::Object(ref obj) => !obj.is_empty(),
-            };
-
-            if !should_continue {
+            if !self.is_truthy(&condition_value) {
                 break;
             }
 
             // Execute body in the same scope to preserve mutable variable changes
-            last_result = self.evaluate(body, env)?;
+            match self.loop_iteration(label, body, env)? {
+                ControlFlow::Continue(result) => {
+                    if let Some(result) = result {
+                        last_result = result;
+                    }
+                }
+                ControlFlow::Break(Some(value)) => return Ok(value),
+                ControlFlow::Break(None) => break,
+            }
+        }
+
+        Ok(last_result)
+    }
+
+    /// Run one pass of a loop body, stopping at a `break` or `continue` aimed at this
+    /// loop. Gives the pass's result, which a bare `continue` leaves out, or whether
+    /// the loop should stop with a value of its own.
+    fn loop_iteration(
+        &mut self,
+        label: &Option<String>,
+        body: &Expression,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<ControlFlow<Option<Value>, Option<Value>>> {
+        let aimed_here = |target: &Option<String>| target.is_none() || target == label;
+        match self.evaluate(body, env) {
+            Ok(value) => Ok(ControlFlow::Continue(Some(value))),
+            Err(SusumuError::Break { label, value }) if aimed_here(&label) => {
+                Ok(ControlFlow::Break(value))
+            }
+            Err(SusumuError::Continue { label, value }) if aimed_here(&label) => {
+                Ok(ControlFlow::Continue(value))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub(crate) fn call_function_with_args(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        // Handle module operations specially
+        match name {
+            "from" => return self.handle_module_from(args),
+            "import" => return self.handle_module_import(args, env),
+            "export" => return self.handle_module_export(args),
+            _ => {}
+        }
+
+        // Variables holding function values (lambdas, aliases) shadow named functions
+        if let Ok(value) = env.get(name) {
+            match &value {
+                Value::Lambda(closure) => return self.call_lambda(closure, args, env),
+                Value::Function(target) if target != name => {
+                    return self.call_function_with_args(target, args, env);
+                }
+                _ => {}
+            }
+        }
+
+        self.observed_call(name, args, |this| this.dispatch_call(name, args, env))
+    }
+
+    /// Call an anonymous function value
+    fn call_lambda(
+        &mut self,
+        closure: &Closure,
+        args: &[Value],
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        let name = closure.def.name.as_str();
+        self.observed_call(name, args, |this| match &closure.chunk {
+            Some(chunk) if this.options.backend == Backend::Bytecode => {
+                let function = CompiledFunction {
+                    chunk: chunk.clone(),
+                    env: closure.env.clone(),
+                };
+                this.nested_call(|this| vm::call(this, name, &function, args))
+            }
+            _ => this.nested_call(|this| this.call_user_function(closure, args, env)),
+        })
+    }
 
-            iteration_count += 1;
+    /// Run a call between the observers' `before_call` and `after_call` hooks
+    fn observed_call(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        call: impl FnOnce(&mut Self) -> SusumuResult<Value>,
+    ) -> SusumuResult<Value> {
+        self.notify(|observer, this| observer.before_call(this, name, args))?;
+        let mut result = call(self);
+        if let Err(error) = self.notify(|observer, this| observer.after_call(this, name, &result)) {
+            result = Err(error);
         }
-
-        Ok(last_result)
+        result
     }
 
-    fn call_function_with_args(
+    /// Call a builtin, native or user-defined function by its own name
+    fn dispatch_call(
         &mut self,
         name: &str,
         args: &[Value],
         env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
-        // Handle module operations specially
-        match name {
-            "from" => return self.handle_module_from(args),
-            "import" => return self.handle_module_import(args, env),
-            "export" => return self.handle_module_export(args),
-            _ => {}
-        }
-
-        // Try built-in functions first
-        if self.builtins.contains(name) {
-            return self.builtins.call(name, args);
+        // User-defined functions take precedence over builtins of the same name, so
+        // adding a builtin never changes what an existing program calls
+        if self.options.backend == Backend::Bytecode {
+            if let Some(function) = self.compiled_function(name) {
+                return self.nested_call(|this| vm::call(this, name, &function, args));
+            }
         }
 
-        // Try user-defined functions in global environment
         let global_env = self.env_manager.global();
-        if let Ok(func_def) = global_env.get_function(name) {
-            return self.call_user_function(&func_def, args, env);
+        if let Ok(closure) = global_env.get_closure(name) {
+            return self.nested_call(|this| this.call_user_function(&closure, args, env));
         }
 
-        Err(SusumuError::undefined_function(name))
+        match self.builtins.get(name).cloned() {
+            Some(Builtin::Function(func)) => func(args),
+            Some(Builtin::Native(func)) => {
+                let mut ctx = CallContext::new(self, env.clone(), name);
+                func(&mut ctx, args)
+            }
+            None => Err(SusumuError::undefined_function(name)),
+        }
     }
 
     fn handle_module_from(&mut self, args: &[Value]) -> SusumuResult<Value> {
@@ -1179,14 +1783,16 @@ impl Interpreter {
             .ok_or_else(|| SusumuError::runtime_error("Module name must be a string"))?;
 
         // Load the module
-        let loaded_module = self.module_loader.load_module(module_name)?;
+        let loaded_module = self
+            .module_loader
+            .load_module(module_name, &self.options.permissions)?;
 
         // Return a module reference that import() can use
-        Ok(serde_json::json!({
-            "type": "module_reference",
-            "module_name": module_name,
-            "exports": loaded_module.exports.clone()
-        }))
+        Ok(Value::object([
+            ("type", Value::from("module_reference")),
+            ("module_name", Value::from(module_name)),
+            ("exports", Value::from(loaded_module.exports.clone())),
+        ]))
     }
 
     fn handle_module_import(
@@ -1221,7 +1827,9 @@ impl Interpreter {
         let functions_to_import = self.parse_import_spec(import_spec)?;
 
         // Load the module (will use cache if already loaded)
-        let loaded_module = self.module_loader.load_module(module_name)?;
+        let loaded_module = self
+            .module_loader
+            .load_module(module_name, &self.options.permissions)?;
 
         // Add imported functions to the global environment
         let global_env = self.env_manager.global();
@@ -1229,6 +1837,7 @@ impl Interpreter {
             if let Some(func_def) = loaded_module.functions.get(func_name) {
                 if loaded_module.exports.contains(func_name) {
                     global_env.define_function(func_name.clone(), func_def.clone());
+                    self.compiled.remove(func_name);
                 } else {
                     return Err(SusumuError::runtime_error(format!(
                         "Function '{}' is not exported by module '{}'",
@@ -1243,20 +1852,20 @@ impl Interpreter {
             }
         }
 
-        Ok(serde_json::json!({
-            "type": "import_success",
-            "module": module_name,
-            "imported_functions": functions_to_import
-        }))
+        Ok(Value::object([
+            ("type", Value::from("import_success")),
+            ("module", Value::from(module_name)),
+            ("imported_functions", Value::from(functions_to_import)),
+        ]))
     }
 
     fn handle_module_export(&mut self, args: &[Value]) -> SusumuResult<Value> {
         // Export is mainly for module definition files
         // For now, return success - the actual export parsing happens during module loading
-        Ok(serde_json::json!({
-            "type": "export_declaration",
-            "functions": args
-        }))
+        Ok(Value::object([
+            ("type", Value::from("export_declaration")),
+            ("functions", Value::from(args.to_vec())),
+        ]))
     }
 
     fn parse_import_spec(&self, import_spec: &Value) -> SusumuResult<Vec<String>> {
@@ -1283,10 +1892,11 @@ impl Interpreter {
 
     fn call_user_function(
         &mut self,
-        func_def: &crate::ast::FunctionDef,
+        closure: &Closure,
         args: &[Value],
-        _parent_env: &Arc<Environment>,
+        caller_env: &Arc<Environment>,
     ) -> SusumuResult<Value> {
+        let func_def = &closure.def;
         if args.len() != func_def.params.len() {
             return Err(SusumuError::function_call_error(&format!(
                 "Function {} expects {} arguments, got {}",
@@ -1296,16 +1906,27 @@ impl Interpreter {
             )));
         }
 
-        // Create new scope for function execution
-        let func_scope = self.env_manager.push_scope();
-
-        // Bind parameters to arguments
-        for (param, arg) in func_def.params.iter().zip(args.iter()) {
-            func_scope.define(param.name.clone(), arg.clone());
-        }
-
-        // Execute function body
-        let result = match self.evaluate(&func_def.body, &func_scope) {
+        // Functions run in a child of the scope they were defined in, not the caller's
+        let defining_env = closure
+            .env
+            .clone()
+            .unwrap_or_else(|| self.env_manager.global());
+        let func_scope = Arc::new(Environment::with_parent(defining_env));
+
+        let call_site = self.location;
+        self.frames.push(CallFrame {
+            function: func_def.name.clone(),
+            call_site,
+            caller_env: caller_env.clone(),
+        });
+        // An argument that does not fit its pattern is reported at the definition, with
+        // the call in the stack trace
+        let result = self
+            .bind_params(func_def, args, &func_scope)
+            .map_err(|err| err.with_span(func_def.span))
+            .and_then(|()| self.evaluate_body(&func_def.body, &func_scope));
+        self.frames.pop();
+        match result {
             Ok(result) => Ok(result),
             Err(SusumuError::ReturnValue { value }) => Ok(value),
             Err(SusumuError::SuccessReturn { value }) => {
@@ -1314,13 +1935,91 @@ impl Interpreter {
             }
             Err(SusumuError::ErrorReturn { value }) => {
                 // For functions with typed returns, error return propagates as user error
-                Err(SusumuError::user_error(value))
+                Err(SusumuError::user_error(value).with_frame(func_def.name.as_str(), call_site))
             }
-            Err(other) => Err(other),
-        };
+            Err(other) => Err(other.with_frame(func_def.name.as_str(), call_site)),
+        }
+    }
 
-        self.env_manager.pop_scope()?;
-        result
+    /// Bind parameters to arguments, destructuring those with patterns
+    fn bind_params(
+        &mut self,
+        func_def: &FunctionDef,
+        args: &[Value],
+        scope: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        for (param, arg) in func_def.params.iter().zip(args.iter()) {
+            match &param.pattern {
+                Some(pattern) => {
+                    for (name, part) in self.destructure(pattern, arg)? {
+                        scope.define(name, part);
+                    }
+                }
+                None => scope.define(param.name.clone(), arg.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether a value can be invoked: a function value or the name of a function
+    pub(crate) fn is_callable(&self, value: &Value, env: &Arc<Environment>) -> bool {
+        match value {
+            Value::String(name) => self.builtins.contains(name) || env.contains_function(name),
+            _ => value.is_function(),
+        }
+    }
+
+    /// Invoke a function value or function name with the given arguments
+    pub(crate) fn call_callable(
+        &mut self,
+        callable: &Value,
+        args: &[Value],
+        env: &Arc<Environment>,
+    ) -> SusumuResult<Value> {
+        let name = match callable {
+            Value::Lambda(closure) => return self.call_lambda(closure, args, env),
+            Value::String(name) => name.as_str(),
+            _ => callable.as_function_name().ok_or_else(|| {
+                SusumuError::type_error("function", value_to_display_string(callable))
+            })?,
+        }
+        .to_string();
+        self.call_function_with_args(&name, args, env)
+    }
+
+    /// Decide whether a conditional branch is taken for the given condition value
+    fn check_condition(
+        &mut self,
+        condition_type: &ConditionType,
+        value: &Value,
+        env: &Arc<Environment>,
+    ) -> SusumuResult<bool> {
+        match condition_type {
+            ConditionType::Expression(expr) => {
+                let condition_result = self.evaluate(expr, env)?;
+                Ok(self.is_truthy(&condition_result))
+            }
+            _ => self.test_condition(condition_type, value),
+        }
+    }
+
+    /// Test a condition that depends only on the condition value
+    pub(crate) fn test_condition(
+        &self,
+        condition_type: &ConditionType,
+        value: &Value,
+    ) -> SusumuResult<bool> {
+        match condition_type {
+            ConditionType::Success => Ok(!matches!(value, Value::Null)),
+            ConditionType::AllValid => self.evaluate_all_valid_condition(value),
+            ConditionType::Custom(condition_name) => {
+                self.evaluate_custom_condition(condition_name, value)
+            }
+            ConditionType::If => Ok(self.is_truthy(value)),
+            ConditionType::Expression(_) => Err(SusumuError::runtime_error(
+                "Expression conditions must be evaluated, not tested",
+            )),
+        }
     }
 
     fn evaluate_custom_condition(&self, condition_name: &str, value: &Value) -> SusumuResult<bool> {
@@ -1346,64 +2045,41 @@ impl Interpreter {
                 // This supports convergent validation where multiple validation functions
                 // are combined and we want to ensure ALL succeed
                 for val in values {
-                    if let Value::Object(obj) = val {
-                        // Check if this is an error object (has type: "error")
-                        if let Some(Value::String(type_str)) = obj.get("type") {
-                            if type_str == "error" {
-                                return Ok(false); // Found an error, allValid is false
-                            }
-                        }
-                    }
-                    // For non-object values, check if they're null (indicating failure)
-                    if matches!(val, Value::Null) {
-                        return Ok(false);
+                    // Error results and nulls indicate failure
+                    if matches!(val, Value::Result(Err(_)) | Value::Null) {
+                        return Ok(false); // Found an error, allValid is false
                     }
                 }
                 Ok(true) // All values are valid
             }
             // For single values, check if it's not an error or null
-            Value::Object(obj) => {
-                if let Some(Value::String(type_str)) = obj.get("type") {
-                    Ok(type_str != "error")
-                } else {
-                    Ok(true) // Regular object, consider valid
-                }
-            }
+            Value::Result(Err(_)) => Ok(false),
             Value::Null => Ok(false), // Null is not valid
-            _ => Ok(true),            // Other types (string, number, bool) are valid
+            _ => Ok(true),            // Other values (including plain objects) are valid
         }
     }
 
-    fn is_truthy(&self, value: &Value) -> bool {
-        match value {
-            Value::Null => false,
-            Value::Bool(b) => *b,
-            Value::Number(n) => n.as_f64().unwrap_or(0.0) != 0.0,
-            Value::String(s) => !s.is_empty(),
-            Value::Array(a) => !a.is_empty(),
-            Value::Object(o) => !o.is_empty(),
-        }
+    pub(crate) fn is_truthy(&self, value: &Value) -> bool {
+        value.is_truthy()
     }
 
     fn value_to_string(&self, value: &Value) -> String {
         match value {
-            Value::Null => "null".to_string(),
-            Value::Bool(b) => b.to_string(),
-            Value::Number(n) => n.to_string(),
-            Value::String(s) => s.clone(),
             Value::Array(a) => format!("[{} items]", a.len()),
             Value::Object(o) => format!("{{object with {} fields}}", o.len()),
+            other => other.to_string(),
         }
     }
 
     fn expression_to_debug_string(&self, expr: &Expression) -> String {
-        match expr {
-            Expression::Identifier(name) => name.clone(),
-            Expression::Number(n) => n.to_string(),
-            Expression::String(s) => format!("\"{}\"", s),
-            Expression::Boolean(b) => b.to_string(),
-            Expression::Null => "null".to_string(),
-            Expression::ArrowChain {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::Integer(n) => n.to_string(),
+            ExpressionKind::Number(n) => n.to_string(),
+            ExpressionKind::String(s) => format!("\"{}\"", s),
+            ExpressionKind::Boolean(b) => b.to_string(),
+            ExpressionKind::Null => "null".to_string(),
+            ExpressionKind::ArrowChain {
                 expressions,
                 directions,
             } => {
@@ -1420,9 +2096,13 @@ impl Interpreter {
                 }
                 result
             }
-            Expression::FunctionCall { name, args } => {
+            ExpressionKind::FunctionCall { name, args } => {
                 format!("{}({} args)", name, args.len())
             }
+            ExpressionKind::Lambda { params, .. } => {
+                let names: Vec<&str> = params.iter().map(|p| p.name.as_str()).collect();
+                format!("({}) => {{...}}", names.join(", "))
+            }
             _ => "expression".to_string(),
         }
     }
@@ -1460,21 +2140,6 @@ impl Interpreter {
         (elapsed_ms * 1_000_000.0) as u64 // Convert ms to ns
     }
 
-    /// Helper function to create JSON numbers that preserve integer type when appropriate
-    fn create_number_value(&self, value: f64) -> Value {
-        if value.fract() == 0.0
-            && value.is_finite()
-            && value >= i64::MIN as f64
-            && value <= i64::MAX as f64
-        {
-            // Return as integer if it's a whole number within i64 range
-            serde_json::json!(value as i64)
-        } else {
-            // Return as float
-            serde_json::json!(value)
-        }
-    }
-
     fn evaluate_match_with_debugging(
         &mut self,
         expr: &Option<Box<Expression>>,
@@ -1519,7 +2184,7 @@ impl Interpreter {
         Err(SusumuError::runtime_error("No pattern matched"))
     }
 
-    fn evaluate_match_with_value(
+    pub(crate) fn evaluate_match_with_value(
         &mut self,
         value: &Value,
         cases: &[MatchCase],
@@ -1553,7 +2218,59 @@ impl Interpreter {
         Err(SusumuError::runtime_error("No pattern matched"))
     }
 
-    fn match_pattern(
+    /// Bindings of a destructuring pattern, failing when the value has another shape
+    pub(crate) fn destructure(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+    ) -> SusumuResult<std::collections::HashMap<String, Value>> {
+        self.match_pattern(pattern, value).ok_or_else(|| {
+            SusumuError::runtime_error(format!(
+                "Cannot destructure {}",
+                self.shape_mismatch(pattern, value)
+            ))
+        })
+    }
+
+    /// Describe the innermost part of `value` that does not fit `pattern`
+    fn shape_mismatch(&self, pattern: &Pattern, value: &Value) -> String {
+        match (pattern, value) {
+            (Pattern::Tuple(patterns), Value::Array(items)) if patterns.len() == items.len() => {
+                patterns
+                    .iter()
+                    .zip(items)
+                    .find(|(pattern, item)| self.match_pattern(pattern, item).is_none())
+                    .map(|(pattern, item)| self.shape_mismatch(pattern, item))
+                    .unwrap_or_default()
+            }
+            (Pattern::Tuple(patterns), Value::Array(items)) => format!(
+                "array of length {} into a tuple of {}",
+                items.len(),
+                patterns.len()
+            ),
+            (Pattern::Tuple(patterns), _) => format!(
+                "{} into a tuple of {}, expected an array",
+                value.type_name(),
+                patterns.len()
+            ),
+            (Pattern::Object(fields), Value::Object(map)) => {
+                for (key, field) in fields {
+                    match map.get(key) {
+                        None => return format!("object without field '{}'", key),
+                        Some(part) if self.match_pattern(field, part).is_none() => {
+                            return self.shape_mismatch(field, part)
+                        }
+                        Some(_) => {}
+                    }
+                }
+                String::new()
+            }
+            (Pattern::Object(_), _) => format!("{}, expected an object", value.type_name()),
+            _ => value.type_name().to_string(),
+        }
+    }
+
+    pub(crate) fn match_pattern(
         &self,
         pattern: &Pattern,
         value: &Value,
@@ -1575,6 +2292,23 @@ impl Interpreter {
                 }
             }
             Pattern::ArrowPattern { constructor, arg } => {
+                // Maybe and Result values match their constructors natively
+                let native = match (constructor.as_str(), value) {
+                    ("some", Value::Maybe(Some(inner))) => Some(Some(inner)),
+                    ("none", Value::Maybe(None)) => Some(None),
+                    ("success", Value::Result(Ok(inner))) => Some(Some(inner)),
+                    ("error", Value::Result(Err(inner))) => Some(Some(inner)),
+                    ("some" | "none" | "success" | "error", _) => return None,
+                    _ => None,
+                };
+                if let Some(inner) = native {
+                    if let Some(inner_value) = inner {
+                        bindings.extend(self.match_pattern(arg, inner_value)?);
+                    }
+                    return Some(bindings);
+                }
+
+                // Other constructors match objects tagged with a "type" field
                 if let Value::Object(map) = value {
                     if let Some(Value::String(type_str)) = map.get("type") {
                         if type_str == constructor {
@@ -1646,44 +2380,15 @@ impl Interpreter {
                 };
 
                 // Perform the comparison based on operator
-                let matches = match (operator.as_str(), value) {
-                    (">", Value::Number(n)) => {
-                        if let Value::Number(compare_val) = comparison_value {
-                            let n_f64 = n.as_f64().unwrap_or(0.0);
-                            let compare_f64 = compare_val.as_f64().unwrap_or(0.0);
-                            n_f64 > compare_f64
-                        } else {
-                            false
-                        }
-                    }
-                    (">=", Value::Number(n)) => {
-                        if let Value::Number(compare_val) = comparison_value {
-                            let n_f64 = n.as_f64().unwrap_or(0.0);
-                            let compare_f64 = compare_val.as_f64().unwrap_or(0.0);
-                            n_f64 >= compare_f64
-                        } else {
-                            false
-                        }
-                    }
-                    ("<", Value::Number(n)) => {
-                        if let Value::Number(compare_val) = comparison_value {
-                            let n_f64 = n.as_f64().unwrap_or(0.0);
-                            let compare_f64 = compare_val.as_f64().unwrap_or(0.0);
-                            n_f64 < compare_f64
-                        } else {
-                            false
-                        }
-                    }
-                    ("<=", Value::Number(n)) => {
-                        if let Value::Number(compare_val) = comparison_value {
-                            let n_f64 = n.as_f64().unwrap_or(0.0);
-                            let compare_f64 = compare_val.as_f64().unwrap_or(0.0);
-                            n_f64 <= compare_f64
-                        } else {
-                            false
-                        }
-                    }
-                    _ => false, // Unsupported comparison type or operator
+                let matches = match (value.as_f64(), comparison_value.as_f64()) {
+                    (Some(n), Some(compare)) => match operator.as_str() {
+                        ">" => n > compare,
+                        ">=" => n >= compare,
+                        "<" => n < compare,
+                        "<=" => n <= compare,
+                        _ => false, // Unsupported operator
+                    },
+                    _ => false, // Comparison patterns only apply to numbers
                 };
 
                 if matches {
@@ -1702,12 +2407,13 @@ impl Interpreter {
     ) -> SusumuResult<Value> {
         // For pattern matching, we evaluate expressions in a minimal global context
         // This is used for comparison patterns like > 100
-        match expr {
-            Expression::Number(n) => Ok(self.create_number_value(*n)),
-            Expression::String(s) => Ok(Value::String(s.clone())),
-            Expression::Boolean(b) => Ok(Value::Bool(*b)),
-            Expression::Null => Ok(Value::Null),
-            Expression::Identifier(name) => {
+        match &expr.kind {
+            ExpressionKind::Integer(n) => Ok(Value::Int(*n)),
+            ExpressionKind::Number(n) => Ok(Value::Float(*n)),
+            ExpressionKind::String(s) => Ok(Value::String(s.clone())),
+            ExpressionKind::Boolean(b) => Ok(Value::Bool(*b)),
+            ExpressionKind::Null => Ok(Value::Null),
+            ExpressionKind::Identifier(name) => {
                 // For pattern matching, identifiers should be resolved from global scope
                 let global_env = self.env_manager.global();
                 global_env.get(name)
@@ -1721,10 +2427,7 @@ impl Interpreter {
 
     fn literal_matches(&self, literal: &LiteralValue, value: &Value) -> bool {
         let result = match (literal, value) {
-            (LiteralValue::Number(n1), Value::Number(n2)) => {
-                let n2_f64 = n2.as_f64().unwrap_or(0.0);
-                n1 == &n2_f64
-            }
+            (LiteralValue::Number(n1), value) if value.is_number() => value.as_f64() == Some(*n1),
             (LiteralValue::String(s1), Value::String(s2)) => s1 == s2,
             (LiteralValue::Boolean(b1), Value::Bool(b2)) => b1 == b2,
             (LiteralValue::Null, Value::Null) => true,
@@ -1733,7 +2436,7 @@ impl Interpreter {
         result
     }
 
-    fn evaluate_binary_op(
+    pub(crate) fn evaluate_binary_op(
         &self,
         left: &Value,
         operator: &BinaryOperator,
@@ -1741,24 +2444,35 @@ impl Interpreter {
     ) -> SusumuResult<Value> {
         use BinaryOperator::*;
 
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => {
-                let a_val = a.as_f64().unwrap_or(0.0);
-                let b_val = b.as_f64().unwrap_or(0.0);
-
-                match operator {
-                    Add => Ok(self.create_number_value(a_val + b_val)),
-                    Subtract => Ok(self.create_number_value(a_val - b_val)),
-                    Multiply => Ok(self.create_number_value(a_val * b_val)),
-                    Divide => Ok(self.create_number_value(a_val / b_val)),
-                    Equal => Ok(Value::Bool(a_val == b_val)),
-                    NotEqual => Ok(Value::Bool(a_val != b_val)),
-                    Less => Ok(Value::Bool(a_val < b_val)),
-                    Greater => Ok(Value::Bool(a_val > b_val)),
-                    LessEq => Ok(Value::Bool(a_val <= b_val)),
-                    GreaterEq => Ok(Value::Bool(a_val >= b_val)),
-                }
+        match operator {
+            And => return Ok(Value::Bool(left.is_truthy() && right.is_truthy())),
+            Or => return Ok(Value::Bool(left.is_truthy() || right.is_truthy())),
+            Range => {
+                let range = Value::range(left, right)?;
+                // Refuse oversized ranges before building them
+                let len = usize::try_from(range.end.saturating_sub(range.start)).unwrap_or(0);
+                self.check_len(len)?;
+                return Ok(Value::Array(range.map(Value::Int).collect()));
             }
+            _ => {}
+        }
+
+        match (left, right) {
+            (l, r) if l.is_number() && r.is_number() => match operator {
+                Add => l.arithmetic(NumericOp::Add, r),
+                Subtract => l.arithmetic(NumericOp::Subtract, r),
+                Multiply => l.arithmetic(NumericOp::Multiply, r),
+                Divide => l.arithmetic(NumericOp::Divide, r),
+                Modulo => l.arithmetic(NumericOp::Modulo, r),
+                Power => l.arithmetic(NumericOp::Power, r),
+                Equal => Ok(Value::Bool(l == r)),
+                NotEqual => Ok(Value::Bool(l != r)),
+                Less => Ok(Value::Bool(l < r)),
+                Greater => Ok(Value::Bool(l > r)),
+                LessEq => Ok(Value::Bool(l <= r)),
+                GreaterEq => Ok(Value::Bool(l >= r)),
+                And | Or | Range => unreachable!("handled above"),
+            },
             (Value::String(a), Value::String(b)) => match operator {
                 Add => Ok(Value::String(format!("{}{}", a, b))),
                 Equal => Ok(Value::Bool(a == b)),
@@ -1768,23 +2482,23 @@ impl Interpreter {
                     operator
                 ))),
             },
-            (Value::String(a), Value::Number(b)) => match operator {
-                Add => Ok(Value::String(format!("{}{}", a, self.number_to_string(b)))),
+            (Value::String(a), b) if b.is_number() => match operator {
+                Add => Ok(Value::String(format!("{}{}", a, b))),
                 _ => Err(SusumuError::runtime_error(&format!(
                     "Unsupported operation {:?} on string and number",
                     operator
                 ))),
             },
-            (Value::Number(a), Value::String(b)) => match operator {
-                Add => Ok(Value::String(format!("{}{}", self.number_to_string(a), b))),
+            (a, Value::String(b)) if a.is_number() => match operator {
+                Add => Ok(Value::String(format!("{}{}", a, b))),
                 _ => Err(SusumuError::runtime_error(&format!(
                     "Unsupported operation {:?} on number and string",
                     operator
                 ))),
             },
             _ => match operator {
-                Equal => Ok(Value::Bool(format!("{:?}", left) == format!("{:?}", right))),
-                NotEqual => Ok(Value::Bool(format!("{:?}", left) != format!("{:?}", right))),
+                Equal => Ok(Value::Bool(left == right)),
+                NotEqual => Ok(Value::Bool(left != right)),
                 _ => Err(SusumuError::runtime_error(&format!(
                     "Unsupported operation {:?} on these types",
                     operator
@@ -1793,21 +2507,28 @@ impl Interpreter {
         }
     }
 
-    fn number_to_string(&self, number: &serde_json::Number) -> String {
-        if let Some(i) = number.as_i64() {
-            i.to_string()
-        } else if let Some(f) = number.as_f64() {
-            if f.fract() == 0.0 {
-                (f as i64).to_string()
-            } else {
-                f.to_string()
+    pub(crate) fn evaluate_unary_op(
+        &self,
+        operator: &UnaryOperator,
+        operand: &Value,
+    ) -> SusumuResult<Value> {
+        match (operator, operand) {
+            (UnaryOperator::Not, value) => Ok(Value::Bool(!value.is_truthy())),
+            (UnaryOperator::Negate, Value::Int(n)) => Ok(n
+                .checked_neg()
+                .map_or(Value::Float(-(*n as f64)), Value::Int)),
+            (UnaryOperator::Negate, Value::Float(n)) => Ok(Value::Float(-n)),
+            (UnaryOperator::Negate, other) => {
+                Err(SusumuError::type_error("number", other.type_name()))
             }
-        } else {
-            number.to_string()
         }
     }
 }
 
+/// Name of every anonymous function, in stack traces and observer callbacks. It
+/// cannot be written in source, so it never shadows or is found as a named function.
+pub(crate) const LAMBDA_NAME: &str = "<lambda>";
+
 impl Default for Interpreter {
     fn default() -> Self {
         Self::new()
@@ -1817,6 +2538,7 @@ impl Default for Interpreter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::StackFrame;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
 
@@ -1828,10 +2550,7 @@ mod tests {
         let mut interpreter = Interpreter::new();
 
         let result = interpreter.execute(&ast).unwrap();
-        assert_eq!(
-            result,
-            Value::Number(serde_json::Number::from_f64(8.0).unwrap())
-        );
+        assert_eq!(result, Value::Int(8));
     }
 
     #[test]
@@ -1842,10 +2561,7 @@ mod tests {
         let mut interpreter = Interpreter::new();
 
         let result = interpreter.execute(&ast).unwrap();
-        assert_eq!(
-            result,
-            Value::Number(serde_json::Number::from_f64(10.0).unwrap())
-        );
+        assert_eq!(result, Value::Int(10));
     }
 
     #[test]
@@ -1888,6 +2604,260 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_lambda_values() {
+        let source = r#"
+        double = (x) => { x -> multiply <- 2 }
+        handlers = {combine: (a, b) => a + b}
+        steps = [double, (x) => x - 1]
+        a = 5 -> double
+        b = 3 -> handlers.combine <- 4
+        c = double(a)
+        [a, b, c, 10 -> (x) => { x -> divide <- 2 }, steps -> length]
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.execute(&ast).unwrap();
+        assert_eq!(value_to_display_string(&result), "[10, 7, 20, 5, 2]");
+    }
+
+    #[test]
+    fn test_closures_capture_defining_scope() {
+        let source = r#"
+        makeAdder(n) {
+            return <- (x) => { x -> add <- n }
+        }
+        makeCounters(items) {
+            return <- items -> map <- ((item) => { (x) => { x -> add <- item } })
+        }
+        add5 = 5 -> makeAdder
+        addFirst = [1, 2] -> makeCounters -> first
+        n = 100
+        [3 -> add5, 10 -> addFirst]
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.execute(&ast).unwrap();
+        assert_eq!(value_to_display_string(&result), "[8, 11]");
+
+        // Callers' locals are not visible inside the functions they call
+        let source = r#"
+        readSecret() {
+            return <- secret
+        }
+        withSecret(secret) {
+            return <- readSecret()
+        }
+        "hidden" -> withSecret
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        assert!(matches!(
+            interpreter.execute(&ast),
+            Err(SusumuError::UndefinedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lambdas_do_not_accumulate_in_global_scope() {
+        let source = "mk(n) {\n return <- (x) => x + n\n}\n\
+                      fe k in 0..1000 { 1 -> mk(k) } -> length";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                backend,
+                ..Default::default()
+            });
+            let global = interpreter.global_env();
+            let size = || (global.variable_names().len(), global.function_names().len());
+            let before = size();
+
+            assert_eq!(interpreter.execute(&ast).unwrap(), Value::Int(1000));
+            // Only `mk` is left behind
+            assert_eq!(size(), (before.0, before.1 + 1), "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_lambda_bound_in_its_own_scope_still_works_after_it_escapes() {
+        let source = "make(n) {\n f = (x) => x + n\n f(1)\n return <- f\n}\ng = 5 -> make\ng(2)";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                backend,
+                ..Default::default()
+            });
+            assert_eq!(
+                interpreter.execute(&ast).unwrap(),
+                Value::Int(7),
+                "{:?}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors_report_source_location() {
+        let source = r#"
+        lookup(key) {
+            return <- key -> add <- missing
+        }
+        5 -> lookup
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let error = interpreter.execute(&ast).unwrap_err();
+        assert_eq!(error.span(), Some(Span::new(3, 37, 3, 44)));
+        assert!(error.to_string().ends_with("at line 3, column 37"));
+
+        // Calls that fail point at their arrow step
+        let source = "x = 1\nx -> add <- 2 -> nowhere";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let error = interpreter.execute(&ast).unwrap_err();
+        assert_eq!(error.span(), Some(Span::new(2, 18, 2, 25)));
+        assert_eq!(interpreter.get_execution_traces()[0].line, 1);
+    }
+
+    #[test]
+    fn test_runtime_errors_carry_stack_trace() {
+        let source = r#"
+        inner(x) {
+            return <- x -> add <- missing
+        }
+        outer(x) {
+            return <- x -> double -> inner
+        }
+        double(x) {
+            return <- x * 2
+        }
+        5 -> outer
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let error = interpreter.execute(&ast).unwrap_err();
+        assert_eq!(
+            error.stack_trace(),
+            &[
+                StackFrame {
+                    function: "inner".to_string(),
+                    call_site: Location::new(Span::new(6, 38, 6, 43), Some(2)),
+                },
+                StackFrame {
+                    function: "outer".to_string(),
+                    call_site: Location::new(Span::new(11, 14, 11, 19), Some(1)),
+                },
+            ]
+        );
+        assert!(error.report().ends_with(
+            "Stack trace (most recent call first):\n    \
+             in inner called at line 6, column 38, arrow step 2\n    \
+             in outer called at line 11, column 14, arrow step 1"
+        ));
+
+        // Errors raised at the top level have no frames
+        let error = crate::execute("1 -> nowhere").unwrap_err();
+        assert!(error.stack_trace().is_empty());
+        assert_eq!(error.report(), error.to_string());
+    }
+
+    #[test]
+    fn test_named_function_as_value() {
+        let source = r#"
+        square(x) {
+            return <- x * x
+        }
+        f = square
+        g = add
+        [4 -> f, 4 -> g <- 1]
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.execute(&ast).unwrap();
+        assert_eq!(value_to_display_string(&result), "[16, 5]");
+    }
+
+    #[test]
+    fn test_higher_order_functions() {
+        let source = r#"
+        double(x) {
+            return <- x * 2
+        }
+        words = ["apple", "avocado", "beet"]
+        [
+            [1, 2, 3] -> map <- double,
+            [1, 2, 3, 4] -> filter <- (x) => { x -> modulo <- 2 -> equals <- 0 },
+            [1, 2, 3, 4] -> reduce <- (acc, x) => acc + x <- 10,
+            [3, 1, 2] -> sortBy <- (x) => 0 - x,
+            [1, 2, 3] -> find <- (x) => x > 1,
+            [1, 2, 3] -> any <- (x) => x > 2,
+            [1, 2, 3] -> all <- (x) => x > 2,
+            [1, 2] -> flatMap <- (x) => [x, x],
+            words -> groupBy <- (word) => { word -> length }
+        ]
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.execute(&ast).unwrap();
+        assert_eq!(
+            value_to_display_string(&result),
+            "[[2, 4, 6], [2, 4], 20, [3, 2, 1], 2, true, false, [1, 1, 2, 2], \
+             {4: [beet], 5: [apple], 7: [avocado]}]"
+        );
+    }
+
+    #[test]
+    fn test_main_receives_command_line_arguments() {
+        let source = "#!/usr/bin/env susumu\nmain(args) {\n    args -> length\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        let args = ["a".to_string(), "--flag".to_string()];
+        let result = Interpreter::new().execute_with_args(&ast, &args);
+        assert_eq!(result.unwrap(), Value::Int(2));
+        // Without arguments `main(args)` still gets an (empty) array
+        assert_eq!(Interpreter::new().execute(&ast).unwrap(), Value::Int(0));
+    }
+
+    #[test]
+    fn test_uncaught_errors_map_to_exit_codes() {
+        let cases = [
+            ("main() {\n    3 -> exit\n    1\n}", 3),
+            ("x = exit() | 1", 0),
+            ("error <- 4", 4),
+            ("main() {\n    error <- {code: 5, message: \"bad\"}\n}", 5),
+            ("main() {\n    error <- \"bad\"\n}", 1),
+            ("error <- 300", 1),
+            ("1 -> missing", 1),
+        ];
+        for (source, expected) in cases {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let ast = Parser::new(tokens).parse().unwrap();
+            let error = Interpreter::new().execute(&ast).unwrap_err();
+            assert_eq!(error.exit_code(), expected, "{}", source);
+        }
+    }
+
     #[test]
     fn test_module_system_end_to_end() {
         // Create a test module file
@@ -1959,7 +2929,9 @@ impl Interpreter {
     ) -> SusumuResult<Value> {
         // Load the module and collect functions to import
         let functions_to_import = {
-            let loaded_module = self.module_loader.load_module(module_name)?;
+            let loaded_module = self
+                .module_loader
+                .load_module(module_name, &self.options.permissions)?;
 
             let mut functions_to_import = Vec::new();
 
@@ -1992,12 +2964,12 @@ impl Interpreter {
             imported_functions.push(func_def.name.clone());
         }
 
-        Ok(serde_json::json!({
-            "type": "imported_functions",
-            "module": module_name,
-            "functions": imported_functions,
-            "status": "success"
-        }))
+        Ok(Value::object([
+            ("type", Value::from("imported_functions")),
+            ("module", Value::from(module_name)),
+            ("functions", Value::from(imported_functions)),
+            ("status", Value::from("success")),
+        ]))
     }
 }
 
@@ -2021,21 +2993,26 @@ impl ModuleLoader {
         self.module_paths.push(path.as_ref().to_path_buf());
     }
 
-    pub fn load_module(&mut self, module_name: &str) -> SusumuResult<&LoadedModule> {
+    /// Load a module, which needs read access to its file like `readFile` does
+    pub fn load_module(
+        &mut self,
+        module_name: &str,
+        permissions: &Permissions,
+    ) -> SusumuResult<&LoadedModule> {
+        let module_file = match self.module_cache.get(module_name) {
+            Some(module) => module.file_path.clone(),
+            None => self.find_module_file(module_name)?,
+        };
+        let path = Value::from(module_file.to_string_lossy().as_ref());
+        permissions.check(Capability::Read, "import", &[path])?;
+
         // Check cache first
-        if self.module_cache.contains_key(module_name) {
-            return Ok(self.module_cache.get(module_name).unwrap());
+        if !self.module_cache.contains_key(module_name) {
+            // Load and parse module
+            let loaded_module = self.parse_module_file(module_name, &module_file)?;
+            self.module_cache
+                .insert(module_name.to_string(), loaded_module);
         }
-
-        // Find module file
-        let module_file = self.find_module_file(module_name)?;
-
-        // Load and parse module
-        let loaded_module = self.parse_module_file(module_name, &module_file)?;
-
-        // Cache and return
-        self.module_cache
-            .insert(module_name.to_string(), loaded_module);
         Ok(self.module_cache.get(module_name).unwrap())
     }
 
@@ -2148,20 +3125,20 @@ impl ModuleLoader {
         expr: &Expression,
         exports: &mut Vec<String>,
     ) -> SusumuResult<()> {
-        match expr {
-            Expression::ArrowChain {
+        match &expr.kind {
+            ExpressionKind::ArrowChain {
                 expressions,
                 directions: _,
             } => {
                 // Look for patterns like: func -> export or (func1, func2) -> export
                 ModuleLoader::find_export_patterns(expressions, exports)?;
             }
-            Expression::Block(exprs) => {
+            ExpressionKind::Block(exprs) => {
                 for expr in exprs {
                     ModuleLoader::find_exports_in_expression(expr, exports)?;
                 }
             }
-            Expression::Conditional {
+            ExpressionKind::Conditional {
                 then_branch,
                 else_if_branches,
                 else_branch,
@@ -2175,10 +3152,10 @@ impl ModuleLoader {
                     ModuleLoader::find_exports_in_expression(else_expr, exports)?;
                 }
             }
-            Expression::ForEach { body, .. } => {
+            ExpressionKind::ForEach { body, .. } => {
                 ModuleLoader::find_exports_in_expression(body, exports)?;
             }
-            Expression::Match { cases, .. } => {
+            ExpressionKind::Match { cases, .. } => {
                 for case in cases {
                     ModuleLoader::find_exports_in_expression(&case.body, exports)?;
                 }
@@ -2195,24 +3172,25 @@ impl ModuleLoader {
     ) -> SusumuResult<()> {
         // Look for patterns where the last expression is "export"
         if let Some(last_expr) = expressions.last() {
-            if let Expression::Identifier(name) = last_expr {
+            if let ExpressionKind::Identifier(name) = &last_expr.kind {
                 if name == "export" {
                     // Found an export statement - analyze what's being exported
                     if expressions.len() >= 2 {
-                        match &expressions[expressions.len() - 2] {
-                            Expression::Identifier(func_name) => {
+                        match &expressions[expressions.len() - 2].kind {
+                            ExpressionKind::Identifier(func_name) => {
                                 // Single function: func -> export
                                 exports.push(func_name.clone());
                             }
-                            Expression::Tuple(tuple_exprs) => {
+                            ExpressionKind::Tuple(tuple_exprs) => {
                                 // Multiple functions: (func1, func2) -> export
                                 for tuple_expr in tuple_exprs {
-                                    if let Expression::Identifier(func_name) = tuple_expr {
+                                    if let ExpressionKind::Identifier(func_name) = &tuple_expr.kind
+                                    {
                                         exports.push(func_name.clone());
                                     }
                                 }
                             }
-                            Expression::FunctionCall { name, .. } => {
+                            ExpressionKind::FunctionCall { name, .. } => {
                                 // Function call result: someFunction() -> export
                                 exports.push(name.clone());
                             }
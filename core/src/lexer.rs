@@ -1,5 +1,6 @@
 //! Lexer for Susumu arrow-flow language
 
+use crate::ast::Span;
 use crate::error::{SusumuError, SusumuResult};
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -9,6 +10,9 @@ pub enum TokenType {
     // Literals
     Number,
     String,
+    StringStart,  // "text{ (opens an interpolated string)
+    StringMiddle, // }text{
+    StringEnd,    // }text"
     Boolean,
     Null,
 
@@ -24,11 +28,14 @@ pub enum TokenType {
     ForEach,
     While,
     In,
+    Break,
+    Continue,
 
     // Arrows
     RightArrow,    // ->
     LeftArrow,     // <-
     MutationArrow, // <~
+    FatArrow,      // => (lambda literal)
 
     // Conditional keywords
     I,        // i (condition)
@@ -46,6 +53,8 @@ pub enum TokenType {
     Minus,        // -
     Multiply,     // *
     Divide,       // /
+    Modulo,       // %
+    Power,        // **
     Assign,       // =
     Equal,        // ==
     NotEqual,     // !=
@@ -53,7 +62,11 @@ pub enum TokenType {
     Greater,      // >
     LessEq,       // <=
     GreaterEq,    // >=
+    And,          // &&
+    Or,           // ||
+    Not,          // !
     Dot,          // .
+    DotDot,       // .. (range)
     Underscore,   // _
     At,           // @ (for annotations)
     Pipe,         // | (for union types)
@@ -84,6 +97,16 @@ pub struct Token {
     pub lexeme: String,
     pub line: usize,
     pub column: usize,
+    /// Position just past the token's last character
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Token {
+    /// Source range covered by this token
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.column, self.end_line, self.end_column)
+    }
 }
 
 pub struct Lexer {
@@ -91,7 +114,30 @@ pub struct Lexer {
     position: usize,  // Character position (not byte position)
     current_line: usize,
     current_column: usize,
+    /// Line and column where the token being scanned starts
+    token_start: (usize, usize),
     tokens: Vec<Token>,
+    /// Interpolated strings whose embedded expression is being scanned, innermost last
+    interpolations: Vec<Interpolation>,
+}
+
+/// How the string literal being scanned is quoted
+#[derive(Debug, Clone, Copy)]
+struct StringStyle {
+    /// `"""`, spanning lines
+    triple: bool,
+    /// `r"`, with no escapes or interpolation
+    raw: bool,
+    /// Indentation removed from each line of a `"""` string
+    indent: usize,
+}
+
+/// A string waiting for the `}` that ends its embedded expression
+#[derive(Debug)]
+struct Interpolation {
+    style: StringStyle,
+    /// Braces opened inside the expression and not yet closed
+    braces: usize,
 }
 
 impl Lexer {
@@ -101,15 +147,27 @@ impl Lexer {
             position: 0,
             current_line: 1,
             current_column: 1,
+            token_start: (1, 1),
             tokens: Vec::new(),
+            interpolations: Vec::new(),
         }
     }
 
     pub fn tokenize(&mut self) -> SusumuResult<Vec<Token>> {
         while !self.is_at_end() {
+            self.token_start = (self.current_line, self.current_column);
             self.scan_token()?;
         }
 
+        if !self.interpolations.is_empty() {
+            return Err(SusumuError::lexer_error(
+                self.current_line,
+                self.current_column,
+                "Unterminated string",
+            ));
+        }
+
+        self.token_start = (self.current_line, self.current_column);
         self.add_token(TokenType::EOF, "");
         Ok(self.tokens.clone())
     }
@@ -128,8 +186,25 @@ impl Lexer {
             }
             '(' => self.add_token(TokenType::LeftParen, "("),
             ')' => self.add_token(TokenType::RightParen, ")"),
-            '{' => self.add_token(TokenType::LeftBrace, "{"),
-            '}' => self.add_token(TokenType::RightBrace, "}"),
+            '{' => {
+                if let Some(open) = self.interpolations.last_mut() {
+                    open.braces += 1;
+                }
+                self.add_token(TokenType::LeftBrace, "{");
+            }
+            '}' => match self.interpolations.last_mut() {
+                // Ends an embedded expression, so the string carries on
+                Some(open) if open.braces == 0 => {
+                    let style = open.style;
+                    self.interpolations.pop();
+                    self.string_segment(style, true)?;
+                }
+                Some(open) => {
+                    open.braces -= 1;
+                    self.add_token(TokenType::RightBrace, "}");
+                }
+                None => self.add_token(TokenType::RightBrace, "}"),
+            },
             '[' => self.add_token(TokenType::LeftBracket, "["),
             ']' => self.add_token(TokenType::RightBracket, "]"),
             ',' => self.add_token(TokenType::Comma, ","),
@@ -157,6 +232,8 @@ impl Lexer {
                     self.add_token(TokenType::Less, "<");
                 }
             }
+            // A shebang line lexes as a comment, so formatting keeps it
+            '#' if self.position == 1 && self.peek() == '!' => self.line_comment()?,
             '/' => {
                 if self.peek() == '/' {
                     // Line comment
@@ -166,11 +243,22 @@ impl Lexer {
                 }
             }
             '+' => self.add_token(TokenType::Plus, "+"),
-            '*' => self.add_token(TokenType::Multiply, "*"),
+            '*' => {
+                if self.peek() == '*' {
+                    self.advance();
+                    self.add_token(TokenType::Power, "**");
+                } else {
+                    self.add_token(TokenType::Multiply, "*");
+                }
+            }
+            '%' => self.add_token(TokenType::Modulo, "%"),
             '=' => {
                 if self.peek() == '=' {
                     self.advance();
                     self.add_token(TokenType::Equal, "==");
+                } else if self.peek() == '>' {
+                    self.advance();
+                    self.add_token(TokenType::FatArrow, "=>");
                 } else {
                     self.add_token(TokenType::Assign, "=");
                 }
@@ -179,11 +267,19 @@ impl Lexer {
                 if self.peek() == '=' {
                     self.advance();
                     self.add_token(TokenType::NotEqual, "!=");
+                } else {
+                    self.add_token(TokenType::Not, "!");
+                }
+            }
+            '&' => {
+                if self.peek() == '&' {
+                    self.advance();
+                    self.add_token(TokenType::And, "&&");
                 } else {
                     return Err(SusumuError::lexer_error(
                         self.current_line,
                         self.current_column - 1,
-                        "Unexpected character '!'",
+                        "Unexpected character '&', did you mean '&&'?",
                     ));
                 }
             }
@@ -195,12 +291,30 @@ impl Lexer {
                     self.add_token(TokenType::Greater, ">");
                 }
             }
-            '.' => self.add_token(TokenType::Dot, "."),
+            '.' => {
+                if self.peek() == '.' {
+                    self.advance();
+                    self.add_token(TokenType::DotDot, "..");
+                } else {
+                    self.add_token(TokenType::Dot, ".");
+                }
+            }
             '_' => self.add_token(TokenType::Underscore, "_"),
             '@' => self.add_token(TokenType::At, "@"),
-            '|' => self.add_token(TokenType::Pipe, "|"),
+            '|' => {
+                if self.peek() == '|' {
+                    self.advance();
+                    self.add_token(TokenType::Or, "||");
+                } else {
+                    self.add_token(TokenType::Pipe, "|");
+                }
+            }
             '?' => self.add_token(TokenType::QuestionMark, "?"),
-            '"' => self.string_literal()?,
+            '"' => self.string_literal(false)?,
+            'r' if self.peek() == '"' => {
+                self.advance();
+                self.string_literal(true)?;
+            }
             c if c.is_ascii_digit() => self.number_literal()?,
             c if c.is_ascii_alphabetic() || c == '_' => self.identifier_or_keyword()?,
             _ => {
@@ -216,11 +330,7 @@ impl Lexer {
     }
 
     fn line_comment(&mut self) -> SusumuResult<()> {
-        let start = if self.position >= 2 {
-            self.position - 2
-        } else {
-            0
-        }; // Include the //
+        let start = self.position - 1; // Include the // (or #!)
         while self.peek() != '\n' && !self.is_at_end() {
             self.advance();
         }
@@ -229,35 +339,176 @@ impl Lexer {
         Ok(())
     }
 
-    fn string_literal(&mut self) -> SusumuResult<()> {
-        let start = self.position - 1; // Include opening quote
-
-        while self.peek() != '"' && !self.is_at_end() {
+    /// A string literal after its opening `"` or `r"`: plain, or `"""` for several
+    /// lines, whose content is dedented by the indentation of the closing `"""`
+    fn string_literal(&mut self, raw: bool) -> SusumuResult<()> {
+        let triple = self.peek() == '"' && self.peek_ahead(1) == '"';
+        let mut indent = 0;
+        if triple {
+            self.advance();
+            self.advance();
+            indent = self.closing_indent();
+            // Content starts on the line after an opening `"""` that ends its line
+            if self.peek() == '\r' && self.peek_ahead(1) == '\n' {
+                self.advance();
+            }
             if self.peek() == '\n' {
-                self.current_line += 1;
-                self.current_column = 1;
+                self.advance();
+                self.new_line();
+                self.skip_indent(indent);
             }
-            self.advance();
         }
+        self.string_segment(
+            StringStyle {
+                triple,
+                raw,
+                indent,
+            },
+            false,
+        )
+    }
 
-        if self.is_at_end() {
-            return Err(SusumuError::lexer_error(
-                self.current_line,
-                self.current_column,
-                "Unterminated string",
-            ));
+    /// String content up to the closing quote, or up to a `{` that starts an embedded
+    /// expression. `continued` is set after the `}` ending an embedded expression.
+    fn string_segment(&mut self, style: StringStyle, continued: bool) -> SusumuResult<()> {
+        let mut value = String::new();
+
+        loop {
+            if self.is_at_end() {
+                return Err(SusumuError::lexer_error(
+                    self.current_line,
+                    self.current_column,
+                    "Unterminated string",
+                ));
+            }
+            if self.at_string_end(style) {
+                self.advance_by(if style.triple { 3 } else { 1 });
+                let token_type = if continued {
+                    TokenType::StringEnd
+                } else {
+                    TokenType::String
+                };
+                self.add_token(token_type, &value);
+                return Ok(());
+            }
+
+            match self.advance() {
+                '\r' if style.triple && self.peek() == '\n' => {}
+                '\n' => {
+                    self.new_line();
+                    if style.triple {
+                        // The line break before the closing `"""` is not part of the content
+                        let rest = self.chars[self.position..]
+                            .iter()
+                            .position(|c| *c != ' ' && *c != '\t');
+                        if let Some(n) = rest.filter(|n| self.at_string_end_after(style, *n)) {
+                            self.advance_by(n);
+                            continue;
+                        }
+                        value.push('\n');
+                        self.skip_indent(style.indent);
+                    } else {
+                        value.push('\n');
+                    }
+                }
+                '\\' if !style.raw => value.push(self.escape_sequence()?),
+                '{' if !style.raw => {
+                    let token_type = if continued {
+                        TokenType::StringMiddle
+                    } else {
+                        TokenType::StringStart
+                    };
+                    self.add_token(token_type, &value);
+                    self.interpolations.push(Interpolation { style, braces: 0 });
+                    return Ok(());
+                }
+                c => value.push(c),
+            }
         }
+    }
 
-        // Consume closing quote
-        self.advance();
+    fn at_string_end(&self, style: StringStyle) -> bool {
+        self.at_string_end_after(style, 0)
+    }
 
-        // Get string content without quotes
-        let value: String = self.chars[(start + 1)..(self.position - 1)]
-            .iter()
-            .collect();
-        self.add_token(TokenType::String, &value);
+    /// Whether the closing quote starts `offset` characters ahead
+    fn at_string_end_after(&self, style: StringStyle, offset: usize) -> bool {
+        let quotes = if style.triple { 3 } else { 1 };
+        (0..quotes).all(|i| self.peek_ahead(offset + i) == '"')
+    }
 
-        Ok(())
+    /// Indentation of the line holding the closing `"""`, if only whitespace precedes
+    /// it there
+    fn closing_indent(&self) -> usize {
+        let mut i = self.position;
+        while i + 2 < self.chars.len() {
+            match self.chars[i] {
+                '\\' => i += 2,
+                '"' if self.chars[i + 1] == '"' && self.chars[i + 2] == '"' => {
+                    let line_start = self.chars[..i]
+                        .iter()
+                        .rposition(|c| *c == '\n')
+                        .map_or(0, |n| n + 1);
+                    let before = &self.chars[line_start..i];
+                    let blank = before.iter().all(|c| *c == ' ' || *c == '\t');
+                    return if blank { before.len() } else { 0 };
+                }
+                _ => i += 1,
+            }
+        }
+        0
+    }
+
+    /// Skip up to `indent` spaces or tabs at the start of a line
+    fn skip_indent(&mut self, indent: usize) {
+        for _ in 0..indent {
+            if self.peek() != ' ' && self.peek() != '\t' {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    /// The character a `\` escape stands for, after the backslash
+    fn escape_sequence(&mut self) -> SusumuResult<char> {
+        let (line, column) = (self.current_line, self.current_column - 1);
+        let invalid = |message: String| SusumuError::lexer_error(line, column, message);
+
+        if self.is_at_end() {
+            return Err(invalid("Unterminated string".to_string()));
+        }
+        let c = self.advance();
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' | '\'' | '{' | '}' => c,
+            'u' => {
+                if self.advance() != '{' {
+                    return Err(invalid("Expected '{' after '\\u'".to_string()));
+                }
+                let mut digits = String::new();
+                while self.peek().is_ascii_hexdigit() && digits.len() < 6 {
+                    digits.push(self.advance());
+                }
+                if self.advance() != '}' || digits.is_empty() {
+                    return Err(invalid(
+                        "Unicode escapes need 1 to 6 hex digits, as in '\\u{1F600}'".to_string(),
+                    ));
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| invalid(format!("Invalid unicode escape '\\u{{{}}}'", digits)))?
+            }
+            other => return Err(invalid(format!("Unknown escape sequence '\\{}'", other))),
+        })
+    }
+
+    fn new_line(&mut self) {
+        self.current_line += 1;
+        self.current_column = 1;
     }
 
     fn number_literal(&mut self) -> SusumuResult<()> {
@@ -300,6 +551,8 @@ impl Lexer {
             "fe" => TokenType::ForEach, // for-each abbreviation
             "w" => TokenType::While,    // while abbreviation
             "in" => TokenType::In,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
             "i" => TokenType::I,
             "e" => TokenType::E,
             "ei" => TokenType::Ei,
@@ -317,11 +570,14 @@ impl Lexer {
     }
 
     fn add_token(&mut self, token_type: TokenType, lexeme: &str) {
+        let (line, column) = self.token_start;
         self.tokens.push(Token {
             token_type,
             lexeme: lexeme.to_string(),
-            line: self.current_line,
-            column: self.current_column.saturating_sub(lexeme.chars().count()),
+            line,
+            column,
+            end_line: self.current_line,
+            end_column: self.current_column,
         });
     }
 
@@ -332,6 +588,12 @@ impl Lexer {
         c
     }
 
+    fn advance_by(&mut self, count: usize) {
+        for _ in 0..count {
+            self.advance();
+        }
+    }
+
     fn peek(&self) -> char {
         if self.is_at_end() {
             '\0'
@@ -406,5 +668,94 @@ mod tests {
 
         assert_eq!(tokens[0].token_type, TokenType::String);
         assert_eq!(tokens[0].lexeme, "hello world");
+        // Positions cover the quotes even though the lexeme does not
+        assert_eq!(tokens[0].span(), crate::ast::Span::new(1, 1, 1, 14));
+    }
+
+    #[test]
+    fn test_operator_tokens() {
+        let mut lexer = Lexer::new("!a && b || c ** 2 % 3 != x | y.z..0..1.5");
+        let types: Vec<TokenType> = lexer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type)
+            .filter(|t| *t != TokenType::Identifier && *t != TokenType::Number)
+            .collect();
+
+        assert_eq!(
+            types,
+            [
+                TokenType::Not,
+                TokenType::And,
+                TokenType::Or,
+                TokenType::Power,
+                TokenType::Modulo,
+                TokenType::NotEqual,
+                TokenType::Pipe,
+                TokenType::Dot,
+                TokenType::DotDot,
+                TokenType::DotDot,
+                TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_escapes_and_interpolation() {
+        let lexemes = |source: &str| -> Vec<(TokenType, String)> {
+            Lexer::new(source)
+                .tokenize()
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.token_type, t.lexeme))
+                .collect()
+        };
+        let token = |token_type, lexeme: &str| (token_type, lexeme.to_string());
+
+        assert_eq!(
+            lexemes(r#""a\t\"b\"\\ \u{e9}\{" r"raw\n{x}""#),
+            [
+                token(TokenType::String, "a\t\"b\"\\ é{"),
+                token(TokenType::String, r"raw\n{x}"),
+                token(TokenType::EOF, ""),
+            ]
+        );
+        assert_eq!(
+            lexemes(r#""x={ {a: 1}.a }, y={"{y}"}!""#),
+            [
+                token(TokenType::StringStart, "x="),
+                token(TokenType::LeftBrace, "{"),
+                token(TokenType::Identifier, "a"),
+                token(TokenType::Colon, ":"),
+                token(TokenType::Number, "1"),
+                token(TokenType::RightBrace, "}"),
+                token(TokenType::Dot, "."),
+                token(TokenType::Identifier, "a"),
+                token(TokenType::StringMiddle, ", y="),
+                token(TokenType::StringStart, ""),
+                token(TokenType::Identifier, "y"),
+                token(TokenType::StringEnd, ""),
+                token(TokenType::StringEnd, "!"),
+                token(TokenType::EOF, ""),
+            ]
+        );
+
+        // Triple-quoted strings lose the indentation of their closing quotes
+        let tokens = Lexer::new("  s = \"\"\"\n    one\n      two {n}\n    \"\"\"\n")
+            .tokenize()
+            .unwrap();
+        assert_eq!(tokens[2].lexeme, "one\n  two ");
+        assert_eq!(tokens[4].lexeme, "");
+        assert_eq!(tokens[4].span(), crate::ast::Span::new(3, 13, 4, 8));
+
+        for bad in [
+            r#""\q""#,
+            r#""\u{110000}""#,
+            "\"open {x",
+            "\"\"\"\nnever closed",
+        ] {
+            assert!(Lexer::new(bad).tokenize().is_err(), "{}", bad);
+        }
     }
 }
@@ -5,14 +5,26 @@
 
 pub mod ast;
 pub mod builtins;
+pub mod bytecode;
+pub mod cst;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dap;
 pub mod environment;
 pub mod error;
+pub mod format;
 pub mod interpreter;
 pub mod lexer;
+pub mod limits;
+pub mod native;
+pub mod observer;
 pub mod parser;
+pub mod permissions;
+pub mod testing;
 // External tests module removed - using inline tests instead
 pub mod types;
+pub mod value;
 pub mod visual_debug;
+pub mod vm;
 
 // #[cfg(feature = "parallel")]
 // pub mod parallel;
@@ -26,32 +38,77 @@ pub mod python;
 #[cfg(feature = "lsp")]
 pub mod lsp;
 
+#[cfg(feature = "repl")]
+pub mod repl;
+
 pub use ast::{Expression, Program, Statement};
-pub use error::{SusumuError, SusumuResult};
-pub use interpreter::Interpreter;
+pub use cst::{SyntaxToken, SyntaxTree, Trivia, TriviaKind};
+pub use environment::Environment;
+pub use error::{Location, StackFrame, SusumuError, SusumuResult};
+pub use format::format_source;
+pub use interpreter::{Backend, CallFrame, Interpreter, InterpreterOptions};
 pub use lexer::{Lexer, Token};
+pub use limits::{ExecutionLimits, Limit};
+pub use native::{CallContext, NativeFunction};
+pub use observer::{Branch, ExecutionObserver};
 pub use parser::Parser;
+pub use permissions::{Access, Capability, Permissions};
+pub use types::{TypeChecker, TypeError};
+pub use value::Value;
 
 /// Main entry point for executing Susumu code
-pub fn execute(source: &str) -> SusumuResult<serde_json::Value> {
+pub fn execute(source: &str) -> SusumuResult<Value> {
     let tokens = Lexer::new(source).tokenize()?;
     let ast = Parser::new(tokens).parse()?;
     let mut interpreter = Interpreter::new();
     interpreter.execute(&ast)
 }
 
+/// Parse Susumu code, reporting every syntax error rather than only the first
+pub fn parse(source: &str) -> Result<Program, Vec<SusumuError>> {
+    let tokens = Lexer::new(source).tokenize().map_err(|err| vec![err])?;
+    let (program, errors) = Parser::new(tokens).parse_with_recovery();
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Statically type check Susumu code without running it
+pub fn check(source: &str) -> SusumuResult<Vec<TypeError>> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let ast = Parser::new(tokens).parse()?;
+    Ok(TypeChecker::new().check_program(&ast).to_vec())
+}
+
 /// Execute Susumu code and return the result as a string
 pub fn execute_to_string(source: &str) -> String {
-    match execute(source) {
+    execute_to_string_with_options(source, InterpreterOptions::default())
+}
+
+/// Like [`execute_to_string`], with explicit options such as execution limits
+pub fn execute_to_string_with_options(source: &str, options: InterpreterOptions) -> String {
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(errors) => {
+            return errors
+                .iter()
+                .map(|err| format!("Error: {}", err))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+    match Interpreter::with_options(options).execute(&program) {
         Ok(value) => {
             // Don't output null results to match Python behavior
-            if value == serde_json::Value::Null {
+            if value.is_null() {
                 String::new()
             } else {
                 serde_json::to_string(&value).unwrap_or_else(|_| "null".to_string())
             }
         }
-        Err(err) => format!("Error: {}", err),
+        Err(err) => format!("Error: {}", err.report()),
     }
 }
 
@@ -0,0 +1,92 @@
+//! Execution limits for running untrusted Susumu code
+//!
+//! Limits are part of [`InterpreterOptions`](crate::InterpreterOptions) and apply to each
+//! top-level run (`execute`, `load` or `call_function`). Exceeding one aborts the run with
+//! [`SusumuError::LimitExceeded`](crate::SusumuError::LimitExceeded), which `| default`
+//! and `assertError` do not catch.
+//!
+//! ```
+//! use std::time::Duration;
+//! use susumu::{ExecutionLimits, Interpreter, InterpreterOptions, Lexer, Parser, SusumuError};
+//!
+//! let mut interpreter = Interpreter::with_options(InterpreterOptions {
+//!     limits: ExecutionLimits {
+//!         max_steps: Some(10_000),
+//!         timeout: Some(Duration::from_secs(1)),
+//!         max_call_depth: Some(64),
+//!         max_collection_size: Some(1_000),
+//!     },
+//!     ..Default::default()
+//! });
+//!
+//! let source = "loop(n) {\n    n -> loop\n}\n1 -> loop";
+//! let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+//! let error = interpreter.execute(&program).unwrap_err();
+//! assert!(matches!(error, SusumuError::LimitExceeded { .. }));
+//! ```
+
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// Resources a run may use. `None` leaves a resource unlimited, which is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExecutionLimits {
+    /// Evaluation steps: expressions evaluated by the tree-walker, instructions executed
+    /// by the bytecode VM
+    pub max_steps: Option<u64>,
+    /// Wall-clock time. It is checked between steps, so a single slow builtin such as an
+    /// HTTP request is not interrupted.
+    pub timeout: Option<Duration>,
+    /// Nesting of Susumu function calls. Deep calls move onto extra native stack, up to
+    /// 256 MiB per thread; calls nested past that fail with this limit even when it is
+    /// unset, at a depth that depends on the build (tens of thousands in release).
+    pub max_call_depth: Option<usize>,
+    /// Elements of an array or object, or bytes of a string, produced by any expression.
+    /// Ranges are refused before they are built and loop results as soon as they grow
+    /// past it.
+    pub max_collection_size: Option<usize>,
+}
+
+impl ExecutionLimits {
+    /// The limit `value` breaks, if any
+    pub(crate) fn check_size(&self, value: &Value) -> Option<Limit> {
+        match value {
+            Value::String(s) => self.check_len(s.len()),
+            Value::Bytes(b) => self.check_len(b.len()),
+            Value::Array(items) => self.check_len(items.len()),
+            Value::Object(fields) => self.check_len(fields.len()),
+            _ => None,
+        }
+    }
+
+    /// The limit a collection of `len` elements breaks, if any. Code that builds a
+    /// collection step by step checks this as it grows rather than once it is done.
+    pub(crate) fn check_len(&self, len: usize) -> Option<Limit> {
+        let max = self.max_collection_size?;
+        (len > max).then_some(Limit::CollectionSize(max))
+    }
+}
+
+/// The limit that stopped a run, with its configured value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Limit {
+    Steps(u64),
+    Timeout(Duration),
+    CallDepth(usize),
+    CollectionSize(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "more than {} evaluation steps", max),
+            Limit::Timeout(timeout) => write!(f, "ran longer than {:?}", timeout),
+            Limit::CallDepth(max) => write!(f, "function calls nested deeper than {}", max),
+            Limit::CollectionSize(max) => {
+                write!(f, "collection larger than {} elements", max)
+            }
+        }
+    }
+}
@@ -6,17 +6,17 @@ use lsp_server::{Connection, Message, Notification, Request, Response};
 use lsp_types::{
     CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse, Diagnostic,
     DiagnosticSeverity, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
-    DidSaveTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
-    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
-    InitializeParams, Location, MarkedString, Position, Range, ServerCapabilities, SymbolKind,
-    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
+    DidSaveTextDocumentParams, DocumentFormattingParams, DocumentSymbol, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, InitializeParams, Location, MarkedString, Position, Range, ServerCapabilities,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
 };
 use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
 
-use crate::ast::{Expression, Program};
-use crate::{Lexer, Parser, SusumuError};
+use crate::ast::{Expression, ExpressionKind, Program, Span, StringPart};
+use crate::{Lexer, Parser, SusumuError, TypeChecker, TypeError};
 
 pub struct SusumuLanguageServer {
     /// Stores the current state of open documents
@@ -124,8 +124,14 @@ impl SusumuLanguageServer {
                 self.handle_document_symbols(params)
             }
             "textDocument/formatting" => {
-                // Formatting not implemented yet
-                Ok(serde_json::to_value(Vec::<TextEdit>::new())?)
+                let params: DocumentFormattingParams = serde_json::from_value(req.params.clone())?;
+                let uri = params.text_document.uri.to_string();
+                let edits = self
+                    .documents
+                    .get(&uri)
+                    .map(|doc| format_susumu_code(&doc.content))
+                    .unwrap_or_default();
+                Ok(serde_json::to_value(edits)?)
             }
             _ => Ok(Value::Null),
         };
@@ -237,23 +243,27 @@ impl SusumuLanguageServer {
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
         let mut diagnostics = Vec::new();
 
-        // Try to lex and parse the document
+        // Lex and parse the document, collecting every syntax error
         match Lexer::new(content).tokenize() {
             Ok(tokens) => {
-                match Parser::new(tokens).parse() {
-                    Ok(ast) => {
-                        // Cache the AST for later use
-                        self.ast_cache.insert(uri.to_string(), ast.clone());
+                let (ast, errors) = Parser::new(tokens).parse_with_recovery();
 
-                        // Extract function definitions
-                        self.extract_function_definitions(uri, &ast);
+                // Cache what could be parsed so symbols stay available while editing
+                self.ast_cache.insert(uri.to_string(), ast.clone());
 
-                        // Run semantic validation for undefined functions
-                        self.validate_function_references(content, &ast, &mut diagnostics);
-                    }
-                    Err(e) => {
-                        diagnostics.push(create_diagnostic_from_error(&e));
+                // Extract function definitions
+                self.extract_function_definitions(uri, &ast);
+
+                if errors.is_empty() {
+                    // Run semantic validation for undefined functions
+                    self.validate_function_references(&ast, &mut diagnostics);
+
+                    // Report static type errors
+                    for error in TypeChecker::new().check_program(&ast) {
+                        diagnostics.push(create_diagnostic_from_type_error(error));
                     }
+                } else {
+                    diagnostics.extend(errors.iter().map(create_diagnostic_from_error));
                 }
             }
             Err(e) => {
@@ -284,14 +294,8 @@ impl SusumuLanguageServer {
         Ok(())
     }
 
-    fn validate_function_references(
-        &self,
-        content: &str,
-        ast: &Program,
-        diagnostics: &mut Vec<Diagnostic>,
-    ) {
+    fn validate_function_references(&self, ast: &Program, diagnostics: &mut Vec<Diagnostic>) {
         let builtin_functions = get_builtin_function_names();
-        let lines: Vec<&str> = content.lines().collect();
 
         // Collect all user-defined function names from this file and others
         let mut all_functions: std::collections::HashSet<String> = std::collections::HashSet::new();
@@ -313,19 +317,13 @@ impl SusumuLanguageServer {
 
         // Check function references in expressions
         if let Some(ref main_expr) = ast.main_expression {
-            self.check_expression_for_undefined_functions(
-                main_expr,
-                &lines,
-                &all_functions,
-                diagnostics,
-            );
+            self.check_expression_for_undefined_functions(main_expr, &all_functions, diagnostics);
         }
 
         // Check function references in function bodies
         for function in &ast.functions {
             self.check_expression_for_undefined_functions(
                 &function.body,
-                &lines,
                 &all_functions,
                 diagnostics,
             );
@@ -335,74 +333,71 @@ impl SusumuLanguageServer {
     fn check_expression_for_undefined_functions(
         &self,
         expr: &Expression,
-        lines: &[&str],
         defined_functions: &std::collections::HashSet<String>,
         diagnostics: &mut Vec<Diagnostic>,
     ) {
         // Expression already imported at top
 
-        match expr {
-            Expression::FunctionCall { name, args } => {
+        match &expr.kind {
+            ExpressionKind::FunctionCall { name, args } => {
                 if !defined_functions.contains(name) {
-                    // Find the line and column where this function is used
-                    if let Some((line_num, col)) = self.find_identifier_in_content(lines, name) {
-                        diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position {
-                                    line: line_num as u32,
-                                    character: col as u32,
-                                },
-                                end: Position {
-                                    line: line_num as u32,
-                                    character: (col + name.len()) as u32,
-                                },
-                            },
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            source: Some("susumu".to_string()),
-                            message: format!("Function '{name}' is not defined"),
-                            ..Default::default()
-                        });
-                    }
+                    diagnostics.push(Diagnostic {
+                        range: name_range(expr.span, name),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        source: Some("susumu".to_string()),
+                        message: format!("Function '{name}' is not defined"),
+                        ..Default::default()
+                    });
                 }
 
                 // Recursively check arguments
                 for arg in args {
                     self.check_expression_for_undefined_functions(
                         arg,
-                        lines,
                         defined_functions,
                         diagnostics,
                     );
                 }
             }
-            Expression::ArrowChain { expressions, .. } => {
+            ExpressionKind::ArrowChain { expressions, .. } => {
                 for expr in expressions {
                     self.check_expression_for_undefined_functions(
                         expr,
-                        lines,
                         defined_functions,
                         diagnostics,
                     );
                 }
             }
-            Expression::BinaryOp { left, right, .. } => {
+            ExpressionKind::BinaryOp { left, right, .. } => {
+                self.check_expression_for_undefined_functions(left, defined_functions, diagnostics);
                 self.check_expression_for_undefined_functions(
-                    left,
-                    lines,
+                    right,
                     defined_functions,
                     diagnostics,
                 );
+            }
+            ExpressionKind::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Expression(expr) = part {
+                        self.check_expression_for_undefined_functions(
+                            expr,
+                            defined_functions,
+                            diagnostics,
+                        );
+                    }
+                }
+            }
+            ExpressionKind::UnaryOp { operand, .. } => {
                 self.check_expression_for_undefined_functions(
-                    right,
-                    lines,
+                    operand,
                     defined_functions,
                     diagnostics,
                 );
             }
-            Expression::Assignment { value, .. } => {
+            ExpressionKind::Assignment { value, .. }
+            | ExpressionKind::Destructure { value, .. } => {
                 self.check_expression_for_undefined_functions(
                     value,
-                    lines,
                     defined_functions,
                     diagnostics,
                 );
@@ -412,31 +407,6 @@ impl SusumuLanguageServer {
         }
     }
 
-    fn find_identifier_in_content(
-        &self,
-        lines: &[&str],
-        identifier: &str,
-    ) -> Option<(usize, usize)> {
-        for (line_num, line) in lines.iter().enumerate() {
-            if let Some(col) = line.find(identifier) {
-                // Make sure it's a whole word, not part of another identifier
-                let start_ok =
-                    col == 0 || !line.chars().nth(col - 1).unwrap_or(' ').is_alphanumeric();
-                let end_ok = col + identifier.len() >= line.len()
-                    || !line
-                        .chars()
-                        .nth(col + identifier.len())
-                        .unwrap_or(' ')
-                        .is_alphanumeric();
-
-                if start_ok && end_ok {
-                    return Some((line_num, col));
-                }
-            }
-        }
-        None
-    }
-
     fn extract_function_definitions(&mut self, uri: &str, ast: &Program) {
         // Clear old definitions for this file
         self.function_defs
@@ -449,16 +419,7 @@ impl SusumuLanguageServer {
                 params: function.params.iter().map(|p| p.name.clone()).collect(),
                 location: Location {
                     uri: lsp_types::Url::parse(uri).unwrap(),
-                    range: Range {
-                        start: Position {
-                            line: 0,
-                            character: 0,
-                        }, // TODO: Track actual positions
-                        end: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                    },
+                    range: name_range(function.span, &function.name),
                 },
                 documentation: Some(format!(
                     "Function {} with {} parameters",
@@ -638,26 +599,8 @@ impl SusumuLanguageServer {
                             .join(", ")
                     )),
                     kind: SymbolKind::FUNCTION,
-                    range: Range {
-                        start: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                    },
-                    selection_range: Range {
-                        start: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: 0,
-                            character: 0,
-                        },
-                    },
+                    range: span_to_range(function.span),
+                    selection_range: name_range(function.span, &function.name),
                     children: None,
                     tags: None,
                     deprecated: Some(false),
@@ -672,28 +615,39 @@ impl SusumuLanguageServer {
     }
 }
 
+/// Convert a 1-based source span into a 0-based LSP range
+fn span_to_range(span: Span) -> Range {
+    let position = |line: usize, column: usize| Position {
+        line: (line as u32).saturating_sub(1),
+        character: (column as u32).saturating_sub(1),
+    };
+    Range {
+        start: position(span.line, span.column),
+        end: position(span.end_line, span.end_column),
+    }
+}
+
+/// Range of a name written at the start of `span`
+fn name_range(span: Span, name: &str) -> Range {
+    span_to_range(Span::new(
+        span.line,
+        span.column,
+        span.line,
+        span.column + name.chars().count(),
+    ))
+}
+
 fn create_diagnostic_from_error(error: &SusumuError) -> Diagnostic {
-    let (line, col, message) = match error {
-        SusumuError::LexerError {
-            line,
-            column,
-            message,
-        } => (*line, *column, message.clone()),
-        SusumuError::ParserError { line, message } => (*line, 0, message.clone()),
-        _ => (0, 0, error.to_string()),
+    let message = match error {
+        SusumuError::LexerError { message, .. } | SusumuError::ParserError { message, .. } => {
+            message.clone()
+        }
+        _ => error.to_string(),
     };
+    let span = error.span().unwrap_or_default();
 
     Diagnostic {
-        range: Range {
-            start: Position {
-                line: (line as u32).saturating_sub(1),
-                character: (col as u32).saturating_sub(1),
-            },
-            end: Position {
-                line: (line as u32).saturating_sub(1),
-                character: col as u32,
-            },
-        },
+        range: span_to_range(span),
         severity: Some(DiagnosticSeverity::ERROR),
         source: Some("susumu".to_string()),
         message,
@@ -701,6 +655,16 @@ fn create_diagnostic_from_error(error: &SusumuError) -> Diagnostic {
     }
 }
 
+fn create_diagnostic_from_type_error(error: &TypeError) -> Diagnostic {
+    Diagnostic {
+        range: span_to_range(error.span),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("susumu".to_string()),
+        message: format!("{}. {}", error.error_type, error.suggestion),
+        ..Default::default()
+    }
+}
+
 fn get_word_at_position(content: &str, position: Position) -> Option<String> {
     let lines: Vec<&str> = content.lines().collect();
 
@@ -741,18 +705,19 @@ fn get_word_at_position(content: &str, position: Position) -> Option<String> {
     None
 }
 
-fn format_susumu_code(code: &str) -> String {
-    // Simple formatter that ensures consistent spacing around arrows
-    code.lines()
-        .map(|line| {
-            line.replace("->", " -> ")
-                .replace("<-", " <- ")
-                .replace("  ", " ") // Remove double spaces
-                .trim()
-                .to_string()
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
+/// Edits that bring a document into canonical format: one replacing the whole text, or
+/// none when it is already formatted or does not parse
+fn format_susumu_code(code: &str) -> Vec<TextEdit> {
+    match crate::format_source(code) {
+        Ok(formatted) if formatted != code => vec![TextEdit {
+            range: Range {
+                start: Position::new(0, 0),
+                end: Position::new(code.lines().count() as u32 + 1, 0),
+            },
+            new_text: formatted,
+        }],
+        _ => Vec::new(),
+    }
 }
 
 fn get_builtin_documentation(name: &str) -> String {
@@ -773,11 +738,21 @@ fn get_builtin_documentation(name: &str) -> String {
         "length" => {
             "Returns the length of a string or array\nExample: \"hello\" -> length".to_string()
         }
+        "entries" => {
+            "Returns the [key, value] pairs of an object\nExample: fe (k, v) in entries(user) { k -> print }".to_string()
+        }
         "first" => "Returns the first element of an array\nExample: [1, 2, 3] -> first".to_string(),
         "last" => "Returns the last element of an array\nExample: [1, 2, 3] -> last".to_string(),
         "sort" => "Sorts an array in ascending order\nExample: [3, 1, 2] -> sort".to_string(),
         "reverse" => "Reverses an array or string\nExample: [1, 2, 3] -> reverse".to_string(),
         "print" => "Prints a value to the console\nExample: \"Hello\" -> print".to_string(),
+        "exit" => "Stops the program with an exit status (default 0)\nExample: 2 -> exit".to_string(),
+        "assertEqual" => {
+            "Fails the test unless both values are equal\nExample: total -> assertEqual <- 42"
+                .to_string()
+        }
+        "assertError" => "Fails the test unless the function raises an error\nExample: assertError(() => { error <- \"boom\" }, \"boom\")".to_string(),
+        "assertMatches" => "Fails the test unless the value matches the pattern\nExample: user -> assertMatches <- {role: \"admin\"}".to_string(),
         _ => format!("Built-in function: {name}"),
     }
 }
@@ -797,6 +772,7 @@ fn get_builtin_function_names() -> Vec<&'static str> {
         "to_upper",
         "to_lower",
         "length",
+        "entries",
         "trim",
         "split",
         "first",
@@ -811,6 +787,10 @@ fn get_builtin_function_names() -> Vec<&'static str> {
         "to_number",
         "print",
         "println",
+        "exit",
+        "assertEqual",
+        "assertError",
+        "assertMatches",
     ]
 }
 
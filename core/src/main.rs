@@ -2,238 +2,470 @@
 
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
 use std::process;
-use susumu::{execute_to_string, Interpreter, Lexer, Parser};
+use std::time::Duration;
+use susumu::testing::{self, ReportFormat};
+use susumu::{
+    format_source, Access, ExecutionLimits, Interpreter, InterpreterOptions, Permissions, Program,
+    SusumuError, TypeChecker, Value,
+};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    match args.len() {
-        1 => run_repl(),
-        2 => {
-            if args[1] == "--debug" || args[1] == "-d" {
-                eprintln!("Usage: {} [--debug] <script.susu>", args[0]);
-                process::exit(1);
-            }
-            run_file(&args[1], false)
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, rest) = match args.first().map(String::as_str) {
+        Some(command @ ("run" | "check" | "fmt" | "test" | "repl" | "ast" | "trace" | "help")) => {
+            (command, &args[1..])
+        }
+        // `--debug` predates the `trace` subcommand
+        Some("--debug" | "-d") => ("trace", &args[1..]),
+        Some("--help" | "-h") => ("help", &args[1..]),
+        Some("--version" | "-V") => {
+            println!("susumu {}", env!("CARGO_PKG_VERSION"));
+            return;
         }
-        3 => {
-            if args[1] == "--debug" || args[1] == "-d" {
-                run_file(&args[2], true)
+        // `susumu script.susu` and shebang lines run the script
+        Some(_) => ("run", &args[..]),
+        None if io::stdin().is_terminal() => ("repl", &args[..]),
+        None => ("run", &args[..]),
+    };
+
+    match command {
+        "run" | "trace" => {
+            let (options, rest) = take_options(rest, true);
+            let (path, program_args) = match rest.split_first() {
+                Some((path, program_args)) => (path.as_str(), program_args),
+                None => ("-", &[][..]),
+            };
+            if path.starts_with('-') && path != "-" {
+                usage_error(&format!("unknown option '{}'", path));
+            }
+            if command == "run" {
+                run_file(path, program_args, options)
             } else {
-                eprintln!("Usage: {} [--debug] <script.susu>", args[0]);
-                process::exit(1);
+                trace_file(path, program_args, options)
             }
         }
-        _ => {
-            eprintln!("Usage: {} [--debug] <script.susu>", args[0]);
-            process::exit(1);
+        "test" => {
+            let (options, rest) = take_options(rest, false);
+            run_tests(&rest, &options)
+        }
+        "repl" => {
+            let (options, rest) = take_options(rest, false);
+            if let Some(arg) = rest.first() {
+                usage_error(&format!("unexpected argument '{}'", arg));
+            }
+            process::exit(susumu::repl::run(options))
         }
+        "check" => for_each_file(rest, check_file),
+        "fmt" => match rest.split_first() {
+            Some((flag, files)) if flag == "--check" => {
+                for_each_file(files, |path| format_file(path, true))
+            }
+            _ => for_each_file(rest, |path| format_file(path, false)),
+        },
+        "ast" => for_each_file(rest, print_ast),
+        _ => print_usage(),
     }
 }
 
-fn run_file(filename: &str, debug_mode: bool) {
-    match fs::read_to_string(filename) {
-        Ok(source) => {
-            if debug_mode {
-                run_file_with_debug(&source);
-            } else {
-                let result = execute_to_string(&source);
-                println!("{}", result);
+fn print_usage() {
+    println!("Usage: susumu [run] [options] <script.susu | -> [args...]");
+    println!("       susumu <command> ...");
+    println!();
+    println!("Commands:");
+    println!("  run [options] <file> [args...]   Run a program; args are passed to main(args)");
+    println!("  trace [options] <file> [args...] Run a program and show its data flow");
+    println!("  check <file...>                  Type check without running");
+    println!("  fmt [--check] <file...>          Format in place, or only check formatting");
+    println!("  ast <file...>                    Print the syntax tree as JSON");
+    println!("  test [options] [--format human|json|junit] [path...]");
+    println!("                                   Run @test functions in *_test.susu files");
+    println!("  repl [options]                   Start the interactive REPL (the default)");
+    println!();
+    println!("A file of '-' reads the program from stdin, as does running with piped input.");
+    println!();
+    println!("Limits for running untrusted code (run, trace, test and repl):");
+    println!("  --max-steps <n>        Stop after n evaluation steps");
+    println!("  --timeout-ms <ms>      Stop after ms milliseconds");
+    println!("  --max-depth <n>        Allow at most n nested function calls");
+    println!("  --max-collection <n>   Allow arrays, objects and strings up to n long");
+    println!();
+    println!("Permissions (files, network, clock and environment are denied by default):");
+    println!("  --allow-read[=<paths>]   Read files, anywhere or under the comma-separated paths");
+    println!("  --allow-write[=<paths>]  Write files, anywhere or under the comma-separated paths");
+    println!("  --allow-net[=<hosts>]    Make HTTP requests, to any host or to host[:port] only");
+    println!("  --allow-clock            Read the current time");
+    println!("  --allow-env              Read environment variables");
+    println!("  -A, --allow-all          Allow everything");
+    println!();
+    println!("Exit status:");
+    println!("  0     The program finished");
+    println!("  n     The program called exit(n)");
+    println!("  1-255 An uncaught `error <- value` whose value, or value.code, is 1-255");
+    println!("  1     Any other uncaught error, or a syntax error");
+    println!("  2     Invalid command-line usage");
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("susumu: {}", message);
+    eprintln!("Run 'susumu help' for usage.");
+    process::exit(2);
+}
+
+/// Split the limit and permission flags off `args`. When `until_script` is set, flags
+/// are only read up to the script's path and everything from the path on is returned
+/// untouched, so scripts can take flags of their own.
+fn take_options(args: &[String], until_script: bool) -> (InterpreterOptions, Vec<String>) {
+    let mut limits = ExecutionLimits::default();
+    let mut permissions = Permissions::none();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            rest.extend(args.by_ref().cloned());
+            break;
+        }
+        if matches!(
+            arg.as_str(),
+            "--max-steps" | "--timeout-ms" | "--max-depth" | "--max-collection"
+        ) {
+            let value = match args.next().map(|value| value.parse::<u64>()) {
+                Some(Ok(value)) => value,
+                _ => usage_error(&format!("{} expects a non-negative whole number", arg)),
+            };
+            match arg.as_str() {
+                "--max-steps" => limits.max_steps = Some(value),
+                "--timeout-ms" => limits.timeout = Some(Duration::from_millis(value)),
+                "--max-depth" => limits.max_call_depth = Some(value as usize),
+                _ => limits.max_collection_size = Some(value as usize),
             }
+            continue;
         }
-        Err(err) => {
-            eprintln!("Error reading file '{}': {}", filename, err);
-            process::exit(1);
+        if grant_permission(&mut permissions, arg) {
+            continue;
+        }
+
+        rest.push(arg.clone());
+        if until_script && (arg == "-" || !arg.starts_with('-')) {
+            rest.extend(args.by_ref().cloned());
+            break;
         }
     }
+
+    let options = InterpreterOptions {
+        limits,
+        permissions,
+        ..Default::default()
+    };
+    (options, rest)
 }
 
-fn run_file_with_debug(source: &str) {
-    match execute_with_debugging(source) {
-        Ok((result, traces, stats)) => {
-            // Show the result
-            println!("🎯 Result: {:?}", result);
+/// Apply an `--allow-*` flag; false if `arg` is not one. Anything not granted is denied.
+fn grant_permission(permissions: &mut Permissions, arg: &str) -> bool {
+    let (flag, list) = match arg.split_once('=') {
+        Some((flag, list)) => (flag, Some(list)),
+        None => (arg, None),
+    };
+    let access = match flag {
+        "--allow-read" => &mut permissions.read,
+        "--allow-write" => &mut permissions.write,
+        "--allow-net" => &mut permissions.net,
+        "--allow-clock" => {
+            permissions.clock = true;
+            return true;
+        }
+        "--allow-env" => {
+            permissions.env = true;
+            return true;
+        }
+        "-A" | "--allow-all" => {
+            *permissions = Permissions::all();
+            return true;
+        }
+        _ => return false,
+    };
+    match (list, &mut *access) {
+        (None, _) => *access = Access::All,
+        (Some(list), Access::Only(entries)) => {
+            entries.extend(list.split(',').filter(|e| !e.is_empty()).map(String::from))
+        }
+        (Some(_), Access::All) => {}
+    }
+    true
+}
 
-            // Show performance stats
-            println!("\n📊 Performance Statistics:");
-            println!(
-                "   • Expressions evaluated: {}",
-                stats.total_expressions_evaluated
-            );
-            println!(
-                "   • Execution time: {}μs",
-                stats.total_execution_time_ns / 1000
-            );
-            println!("   • Arrow chains: {}", stats.arrow_chain_count);
-            println!("   • Function calls: {}", stats.function_call_count);
-            println!(
-                "   • Convergence operations: {}",
-                stats.convergence_operations
-            );
+/// Read a program from a file, or from stdin for `-`
+fn read_source(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|err| format!("Error reading stdin: {}", err))?;
+        return Ok(source);
+    }
+    fs::read_to_string(path).map_err(|err| format!("Error reading file '{}': {}", path, err))
+}
 
-            // Show execution traces
-            if !traces.is_empty() {
-                println!("\n🔍 Execution Flow:");
-                for (i, trace) in traces.iter().enumerate() {
-                    println!(
-                        "   {}. {} -> {}",
-                        i + 1,
-                        trace.expression,
-                        value_to_display_string(&trace.output_value)
-                    );
-                }
-            }
+/// Run `action` on every file (stdin when none are given) and exit non-zero if any
+/// of them failed
+fn for_each_file(paths: &[String], action: impl Fn(&str) -> bool) {
+    let mut ok = true;
+    if paths.is_empty() {
+        ok = action("-");
+    }
+    for path in paths {
+        if path.starts_with('-') && path != "-" {
+            usage_error(&format!("unknown option '{}'", path));
+        }
+        ok &= action(path);
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
 
-            // Generate flow diagram
-            let mut interpreter = Interpreter::new();
-            if let Ok(tokens) = Lexer::new(source).tokenize() {
-                if let Ok(ast) = Parser::new(tokens).parse() {
-                    let _ = interpreter.execute(&ast);
-                    let diagram = interpreter.generate_execution_diagram();
-                    println!("\n🏗️  {}", diagram);
-                }
-            }
+/// Read and parse a program, reporting every syntax error to stderr
+fn load_program(path: &str) -> Option<Program> {
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}", err);
+            return None;
         }
-        Err(e) => {
-            eprintln!("❌ Error: {}", e);
+    };
+    match susumu::parse(&source) {
+        Ok(program) => Some(program),
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}: {}", display_name(path), err);
+            }
+            None
         }
     }
 }
 
-fn execute_with_debugging(
-    source: &str,
-) -> Result<
-    (
-        serde_json::Value,
-        Vec<susumu::interpreter::ExecutionTrace>,
-        susumu::interpreter::PerformanceStats,
-    ),
-    Box<dyn std::error::Error>,
-> {
-    let tokens = Lexer::new(source).tokenize()?;
-    let ast = Parser::new(tokens).parse()?;
-    let mut interpreter = Interpreter::new();
+fn display_name(path: &str) -> &str {
+    if path == "-" {
+        "<stdin>"
+    } else {
+        path
+    }
+}
 
-    let result = interpreter.execute(&ast)?;
-    let traces = interpreter.get_execution_traces().to_vec();
-    let stats = interpreter.get_performance_stats().clone();
+/// Run a program and print its result; exits non-zero when it ends in an error
+fn run_file(path: &str, args: &[String], options: InterpreterOptions) {
+    let Some(program) = load_program(path) else {
+        process::exit(1);
+    };
+    match Interpreter::with_options(options).execute_with_args(&program, args) {
+        Ok(value) => {
+            if !value.is_null() {
+                println!(
+                    "{}",
+                    serde_json::to_string(&value).unwrap_or_else(|_| "null".to_string())
+                );
+            }
+        }
+        Err(err) => exit_with(&err, "Error"),
+    }
+}
 
-    Ok((result, traces, stats))
+/// Exit the process for a program that stopped with `err`, reporting it on stderr
+/// unless the program called `exit` itself
+fn exit_with(err: &SusumuError, label: &str) -> ! {
+    if !matches!(err, SusumuError::Exit { .. }) {
+        eprintln!("{}: {}", label, err.report());
+    }
+    process::exit(err.exit_code())
 }
 
-fn value_to_display_string(value: &serde_json::Value) -> String {
-    match value {
-        serde_json::Value::Null => "null".to_string(),
-        serde_json::Value::Bool(b) => b.to_string(),
-        serde_json::Value::Number(n) => n.to_string(),
-        serde_json::Value::String(s) => format!("\"{}\"", s),
-        serde_json::Value::Array(_) => "[array]".to_string(),
-        serde_json::Value::Object(_) => "{object}".to_string(),
+/// Run a program, then show its result, performance statistics and data flow
+fn trace_file(path: &str, args: &[String], options: InterpreterOptions) {
+    let Some(program) = load_program(path) else {
+        process::exit(1);
+    };
+    let mut interpreter = Interpreter::with_options(options);
+    let result = match interpreter.execute_with_args(&program, args) {
+        Ok(result) => result,
+        Err(err) => exit_with(&err, "❌ Error"),
+    };
+    let traces = interpreter.get_execution_traces();
+    let stats = interpreter.get_performance_stats();
+
+    // Show the result
+    println!("🎯 Result: {:?}", result);
+
+    // Show performance stats
+    println!("\n📊 Performance Statistics:");
+    println!(
+        "   • Expressions evaluated: {}",
+        stats.total_expressions_evaluated
+    );
+    println!(
+        "   • Execution time: {}μs",
+        stats.total_execution_time_ns / 1000
+    );
+    println!("   • Arrow chains: {}", stats.arrow_chain_count);
+    println!("   • Function calls: {}", stats.function_call_count);
+    println!(
+        "   • Convergence operations: {}",
+        stats.convergence_operations
+    );
+
+    // Show execution traces
+    if !traces.is_empty() {
+        println!("\n🔍 Execution Flow:");
+        for (i, trace) in traces.iter().enumerate() {
+            println!(
+                "   {}. {} -> {}",
+                i + 1,
+                trace.expression,
+                value_to_display_string(&trace.output_value)
+            );
+        }
     }
+
+    // Generate flow diagram
+    println!("\n🏗️  {}", interpreter.generate_execution_diagram());
 }
 
-fn run_repl() {
-    println!("Susumu Programming Language v0.1.0");
-    println!("Arrow-flow programming with visual data transformations");
-    println!("Type 'exit' or 'quit' to exit, 'help' for help");
-    println!();
+/// Print a program's syntax tree as JSON
+fn print_ast(path: &str) -> bool {
+    let Some(program) = load_program(path) else {
+        return false;
+    };
+    match serde_json::to_string_pretty(&program) {
+        Ok(json) => {
+            println!("{}", json);
+            true
+        }
+        Err(err) => {
+            eprintln!("{}: {}", display_name(path), err);
+            false
+        }
+    }
+}
 
-    let mut interpreter = Interpreter::new();
-    let mut line_number = 1;
-
-    loop {
-        print!("susumu:{:03}> ", line_number);
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                let input = input.trim();
-
-                if input.is_empty() {
-                    continue;
-                }
-
-                match input {
-                    "exit" | "quit" => {
-                        println!("Goodbye!");
-                        break;
-                    }
-                    "help" => {
-                        print_help();
-                        continue;
-                    }
-                    "clear" => {
-                        print!("\x1B[2J\x1B[1;1H");
-                        continue;
-                    }
-                    _ => {}
-                }
-
-                match execute_repl_line(input, &mut interpreter) {
-                    Ok(result) => {
-                        if result != "null" {
-                            println!("=> {}", result);
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("Error: {}", err);
-                    }
-                }
-
-                line_number += 1;
+/// Run the `@test` functions in the given files, or in `*_test.susu` files under the
+/// given directories (default: the current directory)
+fn run_tests(args: &[String], options: &InterpreterOptions) {
+    let mut format = ReportFormat::Human;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--format" {
+            match args.next().map(|f| f.parse::<ReportFormat>()) {
+                Some(Ok(parsed)) => format = parsed,
+                Some(Err(err)) => usage_error(&err.to_string()),
+                None => usage_error("--format expects human, json or junit"),
             }
-            Err(err) => {
-                eprintln!("Error reading input: {}", err);
-                break;
+        } else if arg.starts_with('-') {
+            usage_error(&format!("unknown option '{}'", arg));
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let files = match testing::discover(&paths) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("Error finding tests: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let report = testing::run_files(&files, options);
+    print!("{}", report.render(format));
+    if !report.success() {
+        process::exit(1);
+    }
+}
+
+/// Type check a program without running it; false when errors are found
+fn check_file(path: &str) -> bool {
+    let name = display_name(path);
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+
+    let mut checker = TypeChecker::new();
+    let errors = match susumu::parse(&source) {
+        Ok(ast) => checker.check_program(&ast).to_vec(),
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}: {}", name, err);
             }
+            eprintln!("{}: {} syntax error(s) found", name, errors.len());
+            return false;
         }
+    };
+
+    if errors.is_empty() {
+        println!("{}: no type errors found", name);
+        return true;
     }
+
+    for error in &errors {
+        eprintln!("{}: {}", name, checker.generate_error_message(error));
+    }
+    eprintln!("{}: {} type error(s) found", name, errors.len());
+    false
 }
 
-fn execute_repl_line(source: &str, interpreter: &mut Interpreter) -> Result<String, String> {
-    // Try to parse as an expression first
-    match Lexer::new(source).tokenize() {
-        Ok(tokens) => match Parser::new(tokens).parse() {
-            Ok(ast) => match interpreter.execute(&ast) {
-                Ok(result) => {
-                    Ok(serde_json::to_string(&result).unwrap_or_else(|_| "null".to_string()))
-                }
-                Err(err) => Err(err.to_string()),
-            },
-            Err(err) => Err(err.to_string()),
-        },
-        Err(err) => Err(err.to_string()),
+/// Rewrite a file in canonical format, or with `check` only report whether it is.
+/// Source from stdin is written formatted to stdout.
+fn format_file(path: &str, check: bool) -> bool {
+    let name = display_name(path);
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+
+    let formatted = match format_source(&source) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            return false;
+        }
+    };
+
+    if path == "-" && !check {
+        print!("{}", formatted);
+        return true;
+    }
+    if formatted == source {
+        return true;
     }
+    if check {
+        eprintln!("{}: not formatted", name);
+        return false;
+    }
+    if let Err(err) = fs::write(path, formatted) {
+        eprintln!("Error writing file '{}': {}", path, err);
+        return false;
+    }
+    println!("{}: formatted", name);
+    true
 }
 
-fn print_help() {
-    println!("Susumu Programming Language Help");
-    println!("================================");
-    println!();
-    println!("Basic Syntax:");
-    println!("  Arrow chains:     5 -> add <- 3 -> multiply <- 2");
-    println!("  Function calls:   multiply(5, 3)");
-    println!("  Conditionals:     i success {{ ... }} e {{ ... }}");
-    println!("  Functions:        myFunc(x) {{ x -> add <- 1 -> return }}");
-    println!();
-    println!("Built-in Functions:");
-    println!("  Math:             add, subtract, multiply, divide, power, sqrt");
-    println!("  Strings:          concat, length, substring, to_upper, to_lower");
-    println!("  Arrays:           first, last, push, pop, sort, reverse");
-    println!("  I/O:              print, println, debug");
-    println!("  Utility:          type_of, is_null, is_number, to_string");
-    println!();
-    println!("Examples:");
-    println!("  5 -> add <- 3                    # Basic arrow chain");
-    println!("  [1,2,3] -> first -> print        # Array operations");
-    println!("  \"hello\" -> to_upper -> print      # String operations");
-    println!();
-    println!("REPL Commands:");
-    println!("  help              Show this help message");
-    println!("  clear             Clear the screen");
-    println!("  exit, quit        Exit the REPL");
-    println!();
+fn value_to_display_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", s),
+        Value::Array(_) => "[array]".to_string(),
+        Value::Object(_) => "{object}".to_string(),
+        other => other.to_string(),
+    }
 }
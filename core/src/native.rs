@@ -0,0 +1,177 @@
+//! Interpreter-aware native functions for embedding Susumu in Rust applications
+//!
+//! Plain builtins are `fn(&[Value])` pointers and can only transform their arguments.
+//! Native functions are closures that receive a [`CallContext`], so they can carry host
+//! state, call back into Susumu functions, read variables and record execution traces.
+//!
+//! ```
+//! use susumu::{CallContext, Interpreter, Lexer, Parser, SusumuResult, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.register_native_function(
+//!     "twice",
+//!     |ctx: &mut CallContext, args: &[Value]| -> SusumuResult<Value> {
+//!         let once = ctx.call(&args[1], &args[..1])?;
+//!         ctx.call(&args[1], &[once])
+//!     },
+//! );
+//!
+//! let source = "3 -> twice <- ((x) => { x -> add <- 1 })";
+//! let tokens = Lexer::new(source).tokenize().unwrap();
+//! let program = Parser::new(tokens).parse().unwrap();
+//! assert_eq!(interpreter.execute(&program).unwrap(), Value::Int(5));
+//! ```
+
+use crate::environment::Environment;
+use crate::error::SusumuResult;
+use crate::interpreter::{ExecutionStepType, ExecutionTrace, Interpreter};
+use crate::permissions::Permissions;
+use crate::value::Value;
+use std::sync::Arc;
+
+/// A host function that can call back into the interpreter.
+///
+/// Native functions are shared with the workers that evaluate convergent arguments in
+/// parallel, so captured state must be `Send + Sync` (use a `Mutex` for mutable state).
+pub type NativeFunction =
+    Arc<dyn Fn(&mut CallContext<'_>, &[Value]) -> SusumuResult<Value> + Send + Sync>;
+
+/// Access to the running interpreter from inside a native function
+pub struct CallContext<'a> {
+    interpreter: &'a mut Interpreter,
+    env: Arc<Environment>,
+    function_name: &'a str,
+}
+
+impl<'a> CallContext<'a> {
+    pub(crate) fn new(
+        interpreter: &'a mut Interpreter,
+        env: Arc<Environment>,
+        function_name: &'a str,
+    ) -> Self {
+        Self {
+            interpreter,
+            env,
+            function_name,
+        }
+    }
+
+    /// Name the native function was called as
+    pub fn function_name(&self) -> &str {
+        self.function_name
+    }
+
+    /// Call a function value (or function name) with the given arguments
+    pub fn call(&mut self, callable: &Value, args: &[Value]) -> SusumuResult<Value> {
+        self.interpreter.call_callable(callable, args, &self.env)
+    }
+
+    /// Call a user-defined, builtin or native function by name
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> SusumuResult<Value> {
+        self.interpreter
+            .call_function_with_args(name, args, &self.env)
+    }
+
+    /// Check whether a value can be passed to [`CallContext::call`]
+    pub fn is_callable(&self, value: &Value) -> bool {
+        self.interpreter.is_callable(value, &self.env)
+    }
+
+    /// Look up a variable visible from the call site
+    pub fn get_variable(&self, name: &str) -> SusumuResult<Value> {
+        self.env.get(name)
+    }
+
+    /// What the running program may access, for natives that touch the outside world
+    pub fn permissions(&self) -> &Permissions {
+        &self.interpreter.options().permissions
+    }
+
+    /// Fail once a collection the native function is building grows past the run's
+    /// collection size limit, instead of finishing it first
+    pub fn check_size(&self, len: usize) -> SusumuResult<()> {
+        self.interpreter.check_len(len)
+    }
+
+    /// Apply Susumu's truthiness rules to a value
+    pub fn is_truthy(&self, value: &Value) -> bool {
+        self.interpreter.is_truthy(value)
+    }
+
+    /// Record an entry in the interpreter's execution trace for visual debugging
+    pub fn trace(&mut self, message: impl Into<String>, value: Value) {
+        let span = self.interpreter.current_span();
+        let trace = ExecutionTrace {
+            expression: message.into(),
+            input_value: Value::Null,
+            output_value: value,
+            execution_time_ns: 0,
+            line: span.line,
+            column: span.column,
+            step_type: ExecutionStepType::FunctionCall {
+                name: self.function_name.to_string(),
+                args: vec![],
+            },
+        };
+        self.interpreter.record_trace(trace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::value_to_display_string;
+    use crate::error::SusumuError;
+    use crate::{Lexer, Parser};
+    use std::sync::Mutex;
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> SusumuResult<Value> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let program = Parser::new(tokens).parse()?;
+        interpreter.execute(&program)
+    }
+
+    #[test]
+    fn test_native_function_state_and_callbacks() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_native_function(
+            "record",
+            move |ctx: &mut CallContext, args: &[Value]| -> SusumuResult<Value> {
+                recorded.lock().unwrap().push(args[0].clone());
+                ctx.trace("record", args[0].clone());
+                Ok(args[0].clone())
+            },
+        );
+        interpreter.register_native_function(
+            "applyWithOffset",
+            |ctx: &mut CallContext, args: &[Value]| -> SusumuResult<Value> {
+                if !ctx.is_callable(&args[1]) {
+                    return Err(SusumuError::function_call_error("expected a function"));
+                }
+                let offset = ctx.get_variable("offset")?;
+                let shifted = ctx.call_function("add", &[args[0].clone(), offset])?;
+                ctx.call(&args[1], &[shifted])
+            },
+        );
+
+        let result = run(
+            &mut interpreter,
+            "offset = 10\n5 -> applyWithOffset <- ((x) => x -> record)",
+        )
+        .unwrap();
+        assert_eq!(value_to_display_string(&result), "15");
+        assert_eq!(calls.lock().unwrap().len(), 1);
+        assert!(interpreter
+            .get_execution_traces()
+            .iter()
+            .any(|trace| trace.expression == "record"));
+
+        // Native functions are first-class values like any other builtin
+        let result = run(&mut interpreter, "[1, 2] -> map <- record").unwrap();
+        assert_eq!(value_to_display_string(&result), "[1, 2]");
+        assert_eq!(calls.lock().unwrap().len(), 3);
+    }
+}
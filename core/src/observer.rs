@@ -0,0 +1,289 @@
+//! Hooks for tools that watch a program run
+//!
+//! An [`ExecutionObserver`] added with [`Interpreter::add_observer`] is called as the
+//! tree-walking backend evaluates statements, expressions, arrow steps, function calls
+//! and conditional branches, so debuggers, profilers and coverage tools can be built
+//! outside the evaluator. While a callback runs, the observer can inspect the
+//! interpreter through [`Interpreter::call_frames`], the scope it is given and
+//! [`Interpreter::evaluate_source`]. It pauses the program by not returning until the
+//! program should go on, and stops it by returning an error such as
+//! [`SusumuError::aborted`], which the program cannot catch. The bytecode backend keeps
+//! function locals out of the scopes observers are given and reports no statements,
+//! steps or branches, so `add_observer` refuses observers when it is selected.
+//!
+//! ```
+//! use std::collections::BTreeSet;
+//! use std::sync::{Arc, Mutex};
+//! use susumu::{Environment, ExecutionObserver, Expression, Interpreter, SusumuResult};
+//!
+//! /// Records the lines of the statements that ran
+//! struct Coverage(Arc<Mutex<BTreeSet<usize>>>);
+//!
+//! impl ExecutionObserver for Coverage {
+//!     fn before_statement(
+//!         &mut self,
+//!         _interpreter: &mut Interpreter,
+//!         statement: &Expression,
+//!         _env: &Arc<Environment>,
+//!     ) -> SusumuResult<()> {
+//!         self.0.lock().unwrap().insert(statement.span.line);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let lines = Arc::new(Mutex::new(BTreeSet::new()));
+//! let mut interpreter = Interpreter::new();
+//! interpreter.add_observer(Coverage(lines.clone())).unwrap();
+//!
+//! let source = "x = 1\ni x > 5 {\n  print(\"big\")\n}\nx";
+//! let program = susumu::parse(source).unwrap();
+//! interpreter.execute(&program).unwrap();
+//! assert_eq!(*lines.lock().unwrap(), BTreeSet::from([1, 2, 5]));
+//! ```
+
+use crate::ast::{Expression, Span};
+use crate::environment::Environment;
+use crate::error::SusumuResult;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+use std::sync::Arc;
+
+/// Receives evaluation events from an [`Interpreter`]. Every method does nothing by
+/// default; returning an error from any of them stops the program with that error.
+pub trait ExecutionObserver: Send {
+    /// Before a statement of a block, or a program or function body that is a single
+    /// expression. Debuggers stop here for line breakpoints.
+    fn before_statement(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _statement: &Expression,
+        _env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    fn before_expression(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _expression: &Expression,
+        _env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    /// After an expression, with its value or the error it raised. `return` and the
+    /// typed returns reach enclosing expressions as errors until the function returns,
+    /// as do `break` and `continue` until they reach their loop.
+    fn after_expression(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _expression: &Expression,
+        _result: &SusumuResult<Value>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    /// Before step `index` of an arrow chain receives `input`. Steps are numbered from
+    /// 1; `<-` arguments belong to the step they converge on.
+    fn before_arrow_step(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _index: usize,
+        _step: &Expression,
+        _input: &Value,
+        _env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    fn after_arrow_step(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _index: usize,
+        _step: &Expression,
+        _output: &Value,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    /// Before a builtin, native or user-defined function runs. Anonymous functions are
+    /// named `<lambda>`.
+    fn before_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _function: &str,
+        _args: &[Value],
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    fn after_call(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _function: &str,
+        _result: &SusumuResult<Value>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+
+    /// When the conditional at `conditional` has chosen `branch`, before evaluating it
+    fn on_branch(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _conditional: Span,
+        _branch: Branch,
+        _env: &Arc<Environment>,
+    ) -> SusumuResult<()> {
+        Ok(())
+    }
+}
+
+/// Branch chosen by a conditional
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    Then,
+    /// The `ei` branch at this index
+    ElseIf(usize),
+    Else,
+    /// No condition held and there is no `e` branch
+    Skipped,
+}
+
+impl Branch {
+    /// Name used in execution traces
+    pub(crate) fn trace_name(self) -> &'static str {
+        match self {
+            Branch::Then => "then",
+            Branch::ElseIf(_) => "else-if",
+            Branch::Else => "else",
+            Branch::Skipped => "none",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SusumuError;
+    use std::sync::Mutex;
+
+    /// Logs events and aborts the program before calling `forbidden`
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn log(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    impl ExecutionObserver for Recorder {
+        fn before_statement(
+            &mut self,
+            _interpreter: &mut Interpreter,
+            statement: &Expression,
+            _env: &Arc<Environment>,
+        ) -> SusumuResult<()> {
+            self.log(format!("statement {}", statement.span.line));
+            Ok(())
+        }
+
+        fn before_arrow_step(
+            &mut self,
+            _interpreter: &mut Interpreter,
+            index: usize,
+            _step: &Expression,
+            input: &Value,
+            _env: &Arc<Environment>,
+        ) -> SusumuResult<()> {
+            self.log(format!("step {} <- {}", index, input));
+            Ok(())
+        }
+
+        fn before_call(
+            &mut self,
+            interpreter: &mut Interpreter,
+            function: &str,
+            args: &[Value],
+        ) -> SusumuResult<()> {
+            if function == "forbidden" {
+                return Err(SusumuError::aborted("forbidden() was called"));
+            }
+            let depth = interpreter.call_frames().len();
+            self.log(format!("call {} {:?} at depth {}", function, args, depth));
+            Ok(())
+        }
+
+        fn after_call(
+            &mut self,
+            _interpreter: &mut Interpreter,
+            function: &str,
+            result: &SusumuResult<Value>,
+        ) -> SusumuResult<()> {
+            if let Ok(value) = result {
+                self.log(format!("return {} {}", function, value));
+            }
+            Ok(())
+        }
+
+        fn on_branch(
+            &mut self,
+            interpreter: &mut Interpreter,
+            _conditional: Span,
+            branch: Branch,
+            env: &Arc<Environment>,
+        ) -> SusumuResult<()> {
+            let n = interpreter.evaluate_source("n", env)?;
+            self.log(format!("branch {:?} for n = {}", branch, n));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_steps_calls_and_branches() {
+        let source = "half(n) {\n  i n > 10 { return <- n / 2 } e { return <- n }\n}\n\
+                      x = 30 -> half -> half\n\
+                      y = forbidden() | 0\n\
+                      x -> print";
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.add_observer(Recorder(events.clone())).unwrap();
+
+        let program = crate::parse(source).unwrap();
+        let error = interpreter.execute(&program).unwrap_err();
+        assert!(matches!(error, SusumuError::Aborted { .. }), "{}", error);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "statement 4",
+                "step 1 <- 30",
+                "call half [Int(30)] at depth 0",
+                "statement 2",
+                "branch Then for n = 30",
+                "return half 15",
+                "step 2 <- 15",
+                "call half [Int(15)] at depth 0",
+                "statement 2",
+                "branch Then for n = 15",
+                "return half 7.5",
+                "statement 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bytecode_backend_refuses_observers() {
+        use crate::{Backend, InterpreterOptions};
+
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            backend: Backend::Bytecode,
+            ..Default::default()
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let error = interpreter.add_observer(Recorder(events)).unwrap_err();
+        assert!(
+            error.to_string().contains("tree-walking backend"),
+            "{}",
+            error
+        );
+    }
+}
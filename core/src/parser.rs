@@ -1,18 +1,51 @@
-//! Parser for Susumu arrow-flow language with type checking and visual debugging
+//! Parser for Susumu arrow-flow language with visual debugging
 
 use crate::ast::*;
 use crate::error::{SusumuError, SusumuResult};
 use crate::lexer::{Token, TokenType};
-use crate::types::{SusumuType, TypeChecker, TypeError, TypeErrorKind};
+use crate::types::{SusumuType, TypeChecker};
 // use std::collections::HashMap;
 
-/// Enhanced parser with type checking and visual debugging
+/// Binary operators from the loosest to the tightest binding. `**` binds tighter
+/// still and is parsed with the prefix operators; `..` sits between comparisons and
+/// arithmetic so that `0..n + 1` is a range up to `n + 1`.
+const BINARY_PRECEDENCE: &[&[(TokenType, BinaryOperator)]] = &[
+    &[(TokenType::Or, BinaryOperator::Or)],
+    &[(TokenType::And, BinaryOperator::And)],
+    &[
+        (TokenType::Equal, BinaryOperator::Equal),
+        (TokenType::NotEqual, BinaryOperator::NotEqual),
+    ],
+    &[
+        (TokenType::Less, BinaryOperator::Less),
+        (TokenType::Greater, BinaryOperator::Greater),
+        (TokenType::LessEq, BinaryOperator::LessEq),
+        (TokenType::GreaterEq, BinaryOperator::GreaterEq),
+    ],
+    &[(TokenType::DotDot, BinaryOperator::Range)],
+    &[
+        (TokenType::Plus, BinaryOperator::Add),
+        (TokenType::Minus, BinaryOperator::Subtract),
+    ],
+    &[
+        (TokenType::Multiply, BinaryOperator::Multiply),
+        (TokenType::Divide, BinaryOperator::Divide),
+        (TokenType::Modulo, BinaryOperator::Modulo),
+    ],
+];
+
+/// Enhanced parser that records arrow flows for visual debugging
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     type_checker: TypeChecker,
     /// For visual debugging: track arrow flow paths
     arrow_flow_paths: Vec<ArrowFlowPath>,
+    /// Syntax errors recovered from so far
+    errors: Vec<SusumuError>,
+    /// Labels of the loops around the code being parsed, innermost last; `None` for
+    /// unlabelled loops
+    loop_labels: Vec<Option<String>>,
 }
 
 /// Visual debugging information for arrow flows
@@ -42,157 +75,202 @@ impl Parser {
             current: 0,
             type_checker: TypeChecker::new(),
             arrow_flow_paths: Vec::new(),
+            errors: Vec::new(),
+            loop_labels: Vec::new(),
         }
     }
 
+    /// Parse a whole program, stopping at the first syntax error
     pub fn parse(&mut self) -> SusumuResult<Program> {
+        let (program, mut errors) = self.parse_with_recovery();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse a whole program, recovering from syntax errors at function and statement
+    /// boundaries. Returns what could be parsed together with every error, in source order.
+    pub fn parse_with_recovery(&mut self) -> (Program, Vec<SusumuError>) {
         let mut program = Program::new();
 
         // Skip initial newlines and comments
         self.skip_newlines_and_comments();
 
         while !self.is_at_end() {
-            if self.match_token(&TokenType::Function) {
-                let func = self.function_definition()?;
-                // println!("DEBUG: Parsed function: {}", func.name);
+            let start = self.current;
+            match self.top_level_item(&mut program) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(error) => self.recover(error, start),
+            }
+
+            self.skip_newlines_and_comments();
+        }
+
+        (program, std::mem::take(&mut self.errors))
+    }
+
+    /// Parse one function definition or top-level statement into `program`.
+    /// Returns false when nothing more should be parsed.
+    fn top_level_item(&mut self, program: &mut Program) -> SusumuResult<bool> {
+        if self.match_token(&TokenType::Function) {
+            let func = self.function_definition()?;
+            // println!("DEBUG: Parsed function: {}", func.name);
+            program.add_function(func);
+        } else if self.check(&TokenType::At) {
+            // Check if this is an annotated function definition
+            if self.is_annotated_function_definition() {
+                let func = self.parse_annotated_function_definition()?;
                 program.add_function(func);
-            } else if self.check(&TokenType::At) {
-                // Check if this is an annotated function definition
-                if self.is_annotated_function_definition() {
-                    let func = self.parse_annotated_function_definition()?;
-                    program.add_function(func);
-                } else {
-                    // It's an annotated expression - treat as main expression
-                    let expr = self.expression()?;
-                    program.set_main_expression(expr);
-                    break;
-                }
             } else {
-                // Check if this might be a function definition (identifier followed by parentheses and then brace)
-                if self.check(&TokenType::Identifier)
-                    && self
-                        .tokens
-                        .get(self.current + 1)
-                        .map_or(false, |t| t.token_type == TokenType::LeftParen)
-                {
-                    // Look ahead to see if this is a function definition (has braces) or function call
-                    let mut lookahead = self.current + 2; // Skip identifier and opening paren
-                    let mut paren_count = 1;
-
-                    // Skip to closing paren
-                    while lookahead < self.tokens.len() && paren_count > 0 {
-                        match self.tokens[lookahead].token_type {
-                            TokenType::LeftParen => paren_count += 1,
-                            TokenType::RightParen => paren_count -= 1,
-                            _ => {}
-                        }
-                        lookahead += 1;
+                // It's an annotated expression - treat as main expression
+                let expr = self.expression()?;
+                Self::append_main_expression(program, expr);
+            }
+        } else {
+            // Check if this might be a function definition (identifier followed by parentheses and then brace)
+            if self.check(&TokenType::Identifier)
+                && self
+                    .tokens
+                    .get(self.current + 1)
+                    .map_or(false, |t| t.token_type == TokenType::LeftParen)
+            {
+                // Look ahead to see if this is a function definition (has braces) or function call
+                let mut lookahead = self.current + 2; // Skip identifier and opening paren
+                let mut paren_count = 1;
+
+                // Skip to closing paren
+                while lookahead < self.tokens.len() && paren_count > 0 {
+                    match self.tokens[lookahead].token_type {
+                        TokenType::LeftParen => paren_count += 1,
+                        TokenType::RightParen => paren_count -= 1,
+                        _ => {}
                     }
+                    lookahead += 1;
+                }
 
-                    // Check if there's a brace after the closing paren, possibly with return type
-                    let mut brace_pos = lookahead;
+                // Check if there's a brace after the closing paren, possibly with return type
+                let mut brace_pos = lookahead;
 
-                    // Skip past potential return type annotation (-> ...)
-                    if brace_pos < self.tokens.len()
-                        && self.tokens[brace_pos].token_type == TokenType::RightArrow
+                // Skip past potential return type annotation (-> ...)
+                if brace_pos < self.tokens.len()
+                    && self.tokens[brace_pos].token_type == TokenType::RightArrow
+                {
+                    brace_pos += 1;
+                    // Skip past return type tokens until we find a brace
+                    while brace_pos < self.tokens.len()
+                        && self.tokens[brace_pos].token_type != TokenType::LeftBrace
                     {
                         brace_pos += 1;
-                        // Skip past return type tokens until we find a brace
-                        while brace_pos < self.tokens.len()
-                            && self.tokens[brace_pos].token_type != TokenType::LeftBrace
-                        {
-                            brace_pos += 1;
-                        }
                     }
+                }
 
-                    let is_function_def = brace_pos < self.tokens.len()
-                        && self.tokens[brace_pos].token_type == TokenType::LeftBrace;
+                let is_function_def = brace_pos < self.tokens.len()
+                    && self.tokens[brace_pos].token_type == TokenType::LeftBrace;
 
-                    if is_function_def {
-                        let func = self.function_definition()?;
-                        // println!("DEBUG: Parsed function (no keyword): {}", func.name);
-                        program.add_function(func);
-                    } else {
-                        // It's a function call or other expression - treat as main expression
-                        let expr = self.expression()?;
-                        program.set_main_expression(expr);
-                        break;
-                    }
+                if is_function_def {
+                    let func = self.function_definition()?;
+                    // println!("DEBUG: Parsed function (no keyword): {}", func.name);
+                    program.add_function(func);
                 } else {
-                    // Check if this is a variable assignment (identifier = value or mut identifier = value)
-                    let is_assignment = if self.check(&TokenType::Mut) {
-                        // mut identifier = value
-                        self.tokens
-                            .get(self.current + 1)
-                            .map_or(false, |t| t.token_type == TokenType::Identifier)
-                            && self
-                                .tokens
-                                .get(self.current + 2)
-                                .map_or(false, |t| t.token_type == TokenType::Assign)
-                    } else if self.check(&TokenType::Identifier) {
-                        // identifier = value
-                        self.tokens
-                            .get(self.current + 1)
-                            .map_or(false, |t| t.token_type == TokenType::Assign)
-                    } else {
-                        false
-                    };
+                    // It's a function call or other expression - treat as main expression
+                    let expr = self.expression()?;
+                    Self::append_main_expression(program, expr);
+                }
+            } else {
+                let statement = self.statement_or_expression()?;
+                Self::append_main_expression(program, statement);
+                // Only stop if this was the last statement (no more tokens)
+                if self.is_at_end() {
+                    return Ok(false);
+                }
+            }
+        }
 
-                    if is_assignment {
-                        let assignment = self.assignment_statement()?;
-                        // Don't break - allow multiple top-level statements
-                        if program.main_expression.is_none() {
-                            program.set_main_expression(assignment);
-                        } else {
-                            // Create or extend a block expression for multiple statements
-                            match program.main_expression.as_mut() {
-                                Some(Expression::Block(exprs)) => {
-                                    exprs.push(assignment);
-                                }
-                                Some(existing) => {
-                                    let existing_expr = existing.clone();
-                                    *existing = Expression::Block(vec![existing_expr, assignment]);
-                                }
-                                None => {
-                                    program.set_main_expression(assignment);
-                                }
-                            }
-                        }
-                    } else {
-                        // Main expression
-                        let expr = self.expression()?;
-                        if program.main_expression.is_none() {
-                            program.set_main_expression(expr);
-                        } else {
-                            // Create or extend a block expression for multiple statements
-                            match program.main_expression.as_mut() {
-                                Some(Expression::Block(exprs)) => {
-                                    exprs.push(expr);
-                                }
-                                Some(existing) => {
-                                    let existing_expr = existing.clone();
-                                    *existing = Expression::Block(vec![existing_expr, expr]);
-                                }
-                                None => {
-                                    program.set_main_expression(expr);
-                                }
-                            }
-                        }
-                        // Only break if this was the last expression (no more tokens)
-                        if self.is_at_end() {
-                            break;
-                        }
-                    }
+        Ok(true)
+    }
+
+    /// Record a syntax error and skip past the statement that started at token `start`
+    fn recover(&mut self, error: SusumuError, start: usize) {
+        self.errors.push(error);
+        self.synchronize(start);
+    }
+
+    /// Skip the rest of a statement that failed to parse: close any brackets it opened,
+    /// then stop after the next newline or before a bracket closing the enclosing block
+    fn synchronize(&mut self, start: usize) {
+        // Always make progress, so a stray closing bracket cannot stall the parser
+        if self.current == start && !self.is_at_end() {
+            self.advance();
+        }
+
+        let mut open = Vec::new();
+        for token in &self.tokens[start..self.current] {
+            Self::track_bracket(&mut open, &token.token_type);
+        }
+        while !self.is_at_end() {
+            let token_type = &self.peek().token_type;
+            if open.is_empty() {
+                if Self::closing_bracket(token_type).is_some() {
+                    return;
+                }
+                if *token_type == TokenType::Newline {
+                    self.advance();
+                    return;
                 }
             }
+            Self::track_bracket(&mut open, token_type);
+            self.advance();
+        }
+    }
 
-            self.skip_newlines_and_comments();
+    /// Update the stack of open brackets; closers that match nothing open are ignored
+    fn track_bracket(open: &mut Vec<TokenType>, token_type: &TokenType) {
+        match token_type {
+            TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBracket => {
+                open.push(token_type.clone())
+            }
+            closer => {
+                if let Some(opener) = Self::closing_bracket(closer) {
+                    if open.last() == Some(&opener) {
+                        open.pop();
+                    }
+                }
+            }
         }
+    }
 
-        // Perform final type checking
-        self.validate_program_types(&program)?;
+    /// The opening bracket a closing bracket token matches
+    fn closing_bracket(token_type: &TokenType) -> Option<TokenType> {
+        match token_type {
+            TokenType::RightBrace => Some(TokenType::LeftBrace),
+            TokenType::RightParen => Some(TokenType::LeftParen),
+            TokenType::RightBracket => Some(TokenType::LeftBracket),
+            _ => None,
+        }
+    }
 
-        Ok(program)
+    /// Add a top-level statement, creating or extending a block for multiple statements
+    fn append_main_expression(program: &mut Program, expr: Expression) {
+        match program.main_expression.as_mut() {
+            Some(Expression {
+                kind: ExpressionKind::Block(exprs),
+                span,
+            }) => {
+                *span = span.to(expr.span);
+                exprs.push(expr);
+            }
+            Some(existing) => {
+                let span = existing.span.to(expr.span);
+                let existing_expr = existing.clone();
+                *existing = Expression::new(ExpressionKind::Block(vec![existing_expr, expr]), span);
+            }
+            None => {
+                program.set_main_expression(expr);
+            }
+        }
     }
 
     /// Get visual debugging information for arrow flows
@@ -285,12 +363,17 @@ impl Parser {
         if is_assignment {
             return self.assignment_statement();
         }
+        if self.is_destructuring_assignment() {
+            return self.destructuring_assignment();
+        }
 
         // Otherwise, parse as expression
         self.expression()
     }
 
     fn assignment_statement(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
+
         // Check for mut keyword
         let is_mutable = if self.match_token(&TokenType::Mut) {
             true
@@ -309,14 +392,86 @@ impl Parser {
 
         let value = self.expression()?;
 
-        Ok(Expression::Assignment {
-            target: target_name,
-            value: Box::new(value),
-            mutable: is_mutable,
-        })
+        Ok(self.spanned(
+            start,
+            ExpressionKind::Assignment {
+                target: target_name,
+                value: Box::new(value),
+                mutable: is_mutable,
+            },
+        ))
+    }
+
+    /// Look ahead from `(` or `{`, after an optional `mut`, for a pattern followed by `=`
+    fn is_destructuring_assignment(&self) -> bool {
+        let mut pos = self.current;
+        if self.check(&TokenType::Mut) {
+            pos += 1;
+        }
+        if !self
+            .tokens
+            .get(pos)
+            .is_some_and(|t| matches!(t.token_type, TokenType::LeftParen | TokenType::LeftBrace))
+        {
+            return false;
+        }
+
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(pos) {
+            match token.token_type {
+                TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+                TokenType::EOF => return false,
+                _ => {}
+            }
+            pos += 1;
+            if depth == 0 {
+                break;
+            }
+        }
+
+        depth == 0
+            && self
+                .tokens
+                .get(pos)
+                .is_some_and(|t| t.token_type == TokenType::Assign)
+    }
+
+    fn destructuring_assignment(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
+        let mutable = self.match_token(&TokenType::Mut);
+        let pattern = self.destructuring_pattern()?;
+        self.consume(&TokenType::Assign, "Expected '=' after pattern")?;
+        self.skip_newlines_and_comments();
+
+        let value = self.expression()?;
+
+        Ok(self.spanned(
+            start,
+            ExpressionKind::Destructure {
+                pattern,
+                value: Box::new(value),
+                mutable,
+            },
+        ))
+    }
+
+    /// A pattern binding names in an assignment, parameter or `fe` loop. Only names,
+    /// `_`, tuples and objects are allowed, since the value has to match.
+    fn destructuring_pattern(&mut self) -> SusumuResult<Pattern> {
+        let start = self.peek_span();
+        let pattern = self.pattern()?;
+        if !is_binding_pattern(&pattern) {
+            return Err(SusumuError::parser_error(
+                start.to(self.previous().span()),
+                "Only names, '_', tuples and objects can be destructured",
+            ));
+        }
+        Ok(pattern)
     }
 
     fn function_definition(&mut self) -> SusumuResult<FunctionDef> {
+        let start = self.peek_span();
         let name = if self.check(&TokenType::Identifier) {
             self.advance().lexeme.clone()
         } else {
@@ -326,14 +481,65 @@ impl Parser {
         };
 
         self.consume(&TokenType::LeftParen, "Expected '(' after function name")?;
+        let params = self.parameter_list()?;
+        self.consume(&TokenType::RightParen, "Expected ')' after parameters")?;
+
+        // Parse return type annotation
+        let return_type = if self.match_token(&TokenType::RightArrow) {
+            Some(self.parse_return_type()?)
+        } else {
+            None
+        };
+        let body_start = self.peek_span();
+        self.consume(&TokenType::LeftBrace, "Expected '{' before function body")?;
+
+        self.skip_newlines_and_comments();
+
+        // Parse multiple statements/expressions in function body
+        let mut expressions = Vec::new();
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let start = self.current;
+            match self.statement_or_expression() {
+                Ok(expr) => expressions.push(expr),
+                Err(error) => self.recover(error, start),
+            }
+            self.skip_newlines_and_comments();
+        }
+
+        self.consume(&TokenType::RightBrace, "Expected '}' after function body")?;
+
+        let body = if expressions.len() == 1 {
+            expressions.into_iter().next().unwrap()
+        } else {
+            self.spanned(body_start, ExpressionKind::Block(expressions))
+        };
 
+        Ok(FunctionDef {
+            name,
+            params,
+            return_type,
+            body,
+            span: start.to(self.previous().span()),
+        })
+    }
+
+    /// Parse a comma-separated parameter list, stopping before the closing ')'
+    fn parameter_list(&mut self) -> SusumuResult<Vec<FunctionParam>> {
         let mut params = Vec::new();
         if !self.check(&TokenType::RightParen) {
             loop {
-                let param_name = self
-                    .consume(&TokenType::Identifier, "Expected parameter name")?
-                    .lexeme
-                    .clone();
+                let (param_name, pattern) =
+                    if self.check(&TokenType::LeftParen) || self.check(&TokenType::LeftBrace) {
+                        let pattern = self.destructuring_pattern()?;
+                        (binding_source(&pattern), Some(pattern))
+                    } else {
+                        let name = self
+                            .consume(&TokenType::Identifier, "Expected parameter name")?
+                            .lexeme
+                            .clone();
+                        (name, None)
+                    };
 
                 // Check for type annotation
                 let type_annotation = if self.match_token(&TokenType::Colon) {
@@ -344,6 +550,7 @@ impl Parser {
 
                 params.push(FunctionParam {
                     name: param_name,
+                    pattern,
                     type_annotation,
                 });
 
@@ -352,41 +559,76 @@ impl Parser {
                 }
             }
         }
+        Ok(params)
+    }
 
-        self.consume(&TokenType::RightParen, "Expected ')' after parameters")?;
+    /// Parse a lambda literal: (x, y) => { body } or (x) => x * 2
+    fn lambda(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
+        self.consume(
+            &TokenType::LeftParen,
+            "Expected '(' before lambda parameters",
+        )?;
+        let params = self.parameter_list()?;
+        self.consume(
+            &TokenType::RightParen,
+            "Expected ')' after lambda parameters",
+        )?;
+        self.consume(
+            &TokenType::FatArrow,
+            "Expected '=>' after lambda parameters",
+        )?;
+        self.skip_newlines_and_comments();
 
-        // Parse return type annotation
-        let return_type = if self.match_token(&TokenType::RightArrow) {
-            Some(self.parse_return_type()?)
+        // Block bodies may hold full arrow chains; bare bodies stop at the next arrow
+        // so that `xs -> map <- (x) => x * 2 -> sum` keeps flowing. `break` and
+        // `continue` cannot leave the lambda.
+        let loops = std::mem::take(&mut self.loop_labels);
+        let body = if self.match_token(&TokenType::LeftBrace) {
+            self.skip_newlines_and_comments();
+            let content = self.parse_block_content();
+            self.skip_newlines_and_comments();
+            content.and_then(|content| {
+                self.consume(&TokenType::RightBrace, "Expected '}' after lambda body")?;
+                Ok(content)
+            })
         } else {
-            None
+            self.postfix()
         };
-        self.consume(&TokenType::LeftBrace, "Expected '{' before function body")?;
+        self.loop_labels = loops;
+        let body = body?;
 
-        self.skip_newlines_and_comments();
-
-        // Parse multiple statements/expressions in function body
-        let mut expressions = Vec::new();
+        Ok(self.spanned(
+            start,
+            ExpressionKind::Lambda {
+                params,
+                body: Box::new(body),
+            },
+        ))
+    }
 
-        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            expressions.push(self.statement_or_expression()?);
-            self.skip_newlines_and_comments();
+    /// Look ahead from a '(' to see whether it opens a lambda parameter list
+    fn is_lambda_literal(&self) -> bool {
+        if !self.check(&TokenType::LeftParen) {
+            return false;
         }
 
-        let body = if expressions.len() == 1 {
-            expressions.into_iter().next().unwrap()
-        } else {
-            Expression::Block(expressions)
-        };
-
-        self.consume(&TokenType::RightBrace, "Expected '}' after function body")?;
+        let mut pos = self.current + 1;
+        let mut paren_count = 1;
+        while pos < self.tokens.len() && paren_count > 0 {
+            match self.tokens[pos].token_type {
+                TokenType::LeftParen => paren_count += 1,
+                TokenType::RightParen => paren_count -= 1,
+                _ => {}
+            }
+            pos += 1;
+        }
 
-        Ok(FunctionDef {
-            name,
-            params,
-            return_type,
-            body,
-        })
+        paren_count == 0
+            && self
+                .tokens
+                .get(pos)
+                .is_some_and(|t| t.token_type == TokenType::FatArrow)
     }
 
     fn expression(&mut self) -> SusumuResult<Expression> {
@@ -394,14 +636,18 @@ impl Parser {
     }
 
     fn annotation(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
         if self.match_token(&TokenType::At) {
             let annotation = self.parse_annotation()?;
             self.skip_newlines_and_comments(); // Allow newlines after annotation
             let expression = self.conditional()?;
-            Ok(Expression::Annotated {
-                annotation,
-                expression: Box::new(expression),
-            })
+            Ok(self.spanned(
+                start,
+                ExpressionKind::Annotated {
+                    annotation,
+                    expression: Box::new(expression),
+                },
+            ))
         } else {
             self.conditional()
         }
@@ -411,23 +657,23 @@ impl Parser {
         let annotation_name =
             self.consume(&TokenType::Identifier, "Expected annotation name after '@'")?;
         let annotation_type = annotation_name.lexeme.clone();
-        let line = annotation_name.line;
+        let name_span = annotation_name.span();
 
         match annotation_type.as_str() {
             "trace" => {
                 if self.match_token(&TokenType::LeftArrow) {
                     let value = self.primary()?;
-                    if let Expression::String(trace_name) = value {
+                    if let ExpressionKind::String(trace_name) = value.kind {
                         Ok(Annotation::Trace(trace_name))
                     } else {
                         Err(SusumuError::parser_error(
-                            line,
+                            value.span,
                             "Expected string value for @trace annotation",
                         ))
                     }
                 } else {
                     Err(SusumuError::parser_error(
-                        line,
+                        self.peek().span(),
                         "Expected '<-' after @trace",
                     ))
                 }
@@ -435,14 +681,14 @@ impl Parser {
             "monitor" => {
                 if self.match_token(&TokenType::LeftArrow) {
                     let value = self.primary()?;
-                    if let Expression::Array(items) = value {
+                    if let ExpressionKind::Array(items) = value.kind {
                         let mut monitor_items = Vec::new();
                         for item in items {
-                            if let Expression::String(s) = item {
+                            if let ExpressionKind::String(s) = item.kind {
                                 monitor_items.push(s);
                             } else {
                                 return Err(SusumuError::parser_error(
-                                    line,
+                                    item.span,
                                     "Expected array of strings for @monitor annotation",
                                 ));
                             }
@@ -450,13 +696,13 @@ impl Parser {
                         Ok(Annotation::Monitor(monitor_items))
                     } else {
                         Err(SusumuError::parser_error(
-                            line,
+                            value.span,
                             "Expected array value for @monitor annotation",
                         ))
                     }
                 } else {
                     Err(SusumuError::parser_error(
-                        line,
+                        self.peek().span(),
                         "Expected '<-' after @monitor",
                     ))
                 }
@@ -464,7 +710,7 @@ impl Parser {
             "config" => {
                 if self.match_token(&TokenType::LeftArrow) {
                     let value = self.primary()?;
-                    if let Expression::Object(pairs) = value {
+                    if let ExpressionKind::Object(pairs) = value.kind {
                         // Convert to serde_json::Value
                         let mut map = serde_json::Map::new();
                         for (key, expr) in pairs {
@@ -474,13 +720,13 @@ impl Parser {
                         Ok(Annotation::Config(serde_json::Value::Object(map)))
                     } else {
                         Err(SusumuError::parser_error(
-                            line,
+                            value.span,
                             "Expected object value for @config annotation",
                         ))
                     }
                 } else {
                     Err(SusumuError::parser_error(
-                        line,
+                        self.peek().span(),
                         "Expected '<-' after @config",
                     ))
                 }
@@ -488,18 +734,18 @@ impl Parser {
             "parallel" => {
                 if self.match_token(&TokenType::LeftArrow) {
                     let value = self.primary()?;
-                    if let Expression::Number(cores) = value {
-                        if cores > 0.0 && cores.fract() == 0.0 {
+                    if let ExpressionKind::Integer(cores) = value.kind {
+                        if cores > 0 {
                             Ok(Annotation::Parallel(Some(cores as usize)))
                         } else {
                             return Err(SusumuError::parser_error(
-                                line,
+                                value.span,
                                 "Parallel cores must be a positive integer",
                             ));
                         }
                     } else {
                         return Err(SusumuError::parser_error(
-                            line,
+                            value.span,
                             "Expected number after @parallel <-",
                         ));
                     }
@@ -510,11 +756,11 @@ impl Parser {
             "debug" => {
                 if self.match_token(&TokenType::LeftArrow) {
                     let value = self.primary()?;
-                    if let Expression::String(debug_label) = value {
+                    if let ExpressionKind::String(debug_label) = value.kind {
                         Ok(Annotation::Debug(Some(debug_label)))
                     } else {
                         Err(SusumuError::parser_error(
-                            line,
+                            value.span,
                             "Expected string value for @debug annotation",
                         ))
                     }
@@ -522,27 +768,43 @@ impl Parser {
                     Ok(Annotation::Debug(None))
                 }
             }
+            "test" => {
+                if self.match_token(&TokenType::LeftArrow) {
+                    let value = self.primary()?;
+                    if let ExpressionKind::String(description) = value.kind {
+                        Ok(Annotation::Test(Some(description)))
+                    } else {
+                        Err(SusumuError::parser_error(
+                            value.span,
+                            "Expected string value for @test annotation",
+                        ))
+                    }
+                } else {
+                    Ok(Annotation::Test(None))
+                }
+            }
             _ => Err(SusumuError::parser_error(
-                line,
+                name_span,
                 format!("Unknown annotation type: @{}", annotation_type),
             )),
         }
     }
 
     fn expression_to_json_value(&self, expr: Expression) -> SusumuResult<serde_json::Value> {
-        match expr {
-            Expression::String(s) => Ok(serde_json::Value::String(s)),
-            Expression::Number(n) => Ok(serde_json::json!(n)),
-            Expression::Boolean(b) => Ok(serde_json::Value::Bool(b)),
-            Expression::Null => Ok(serde_json::Value::Null),
-            Expression::Array(items) => {
+        match expr.kind {
+            ExpressionKind::String(s) => Ok(serde_json::Value::String(s)),
+            ExpressionKind::Integer(n) => Ok(serde_json::json!(n)),
+            ExpressionKind::Number(n) => Ok(serde_json::json!(n)),
+            ExpressionKind::Boolean(b) => Ok(serde_json::Value::Bool(b)),
+            ExpressionKind::Null => Ok(serde_json::Value::Null),
+            ExpressionKind::Array(items) => {
                 let mut json_items = Vec::new();
                 for item in items {
                     json_items.push(self.expression_to_json_value(item)?);
                 }
                 Ok(serde_json::Value::Array(json_items))
             }
-            Expression::Object(pairs) => {
+            ExpressionKind::Object(pairs) => {
                 let mut map = serde_json::Map::new();
                 for (key, value) in pairs {
                     map.insert(key, self.expression_to_json_value(value)?);
@@ -556,6 +818,7 @@ impl Parser {
     }
 
     fn conditional(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
         let mut expr = self.arrow_chain()?;
 
         if self.match_token(&TokenType::I) {
@@ -649,25 +912,23 @@ impl Parser {
                 None
             };
 
-            // Type check conditional
-            self.type_check_conditional(&expr, &condition_type, &then_branch, &else_branch)?;
-
-            expr = Expression::Conditional {
-                condition_type,
-                condition: Box::new(expr),
-                then_branch: Box::new(then_branch),
-                else_if_branches,
-                else_branch,
-            };
+            expr = self.spanned(
+                start,
+                ExpressionKind::Conditional {
+                    condition_type,
+                    condition: Box::new(expr),
+                    then_branch: Box::new(then_branch),
+                    else_if_branches,
+                    else_branch,
+                },
+            );
         }
 
         Ok(expr)
     }
 
     fn arrow_chain(&mut self) -> SusumuResult<Expression> {
-        let start_token = self.peek();
-        let start_line = start_token.line;
-        let start_column = start_token.column;
+        let start = self.peek_span();
 
         let mut expressions = vec![self.postfix()?];
         let mut directions = Vec::new();
@@ -699,8 +960,8 @@ impl Parser {
                 direction: direction.clone(),
                 input_type: current_type.clone(),
                 output_type: SusumuType::Unknown, // Will be inferred
-                line: self.previous().line,
-                column: self.previous().column,
+                line: next_expr.span.line,
+                column: next_expr.span.column,
             };
             flow_steps.push(step);
 
@@ -711,24 +972,25 @@ impl Parser {
         if directions.is_empty() {
             Ok(expressions.into_iter().next().unwrap())
         } else {
-            // Type check the arrow chain
-            let (expected_types, actual_types) =
-                self.type_check_arrow_chain(&expressions, &directions)?;
+            let (expected_types, actual_types) = self.arrow_flow_types(&expressions, &directions);
 
             // Create visual debugging path
             let flow_path = ArrowFlowPath {
-                start_line,
-                start_column,
+                start_line: start.line,
+                start_column: start.column,
                 steps: flow_steps,
                 expected_types,
                 actual_types,
             };
             self.arrow_flow_paths.push(flow_path);
 
-            Ok(Expression::ArrowChain {
-                expressions,
-                directions,
-            })
+            Ok(self.spanned(
+                start,
+                ExpressionKind::ArrowChain {
+                    expressions,
+                    directions,
+                },
+            ))
         }
     }
 
@@ -736,26 +998,24 @@ impl Parser {
         let mut expr = self.binary_op()?;
 
         loop {
-            if self.match_token(&TokenType::Dot) {
-                // Handle property access: obj.property
-                let property =
-                    self.consume(&TokenType::Identifier, "Expected property name after '.'")?;
-                expr = Expression::PropertyAccess {
-                    object: Box::new(expr),
-                    property: property.lexeme.clone(),
-                };
-            } else if self.match_token(&TokenType::QuestionMark) {
+            if self.match_token(&TokenType::QuestionMark) {
                 // Handle error propagation: expr?
-                expr = Expression::ErrorPropagation {
-                    expression: Box::new(expr),
-                };
+                expr = self.spanned(
+                    expr.span,
+                    ExpressionKind::ErrorPropagation {
+                        expression: Box::new(expr),
+                    },
+                );
             } else if self.match_token(&TokenType::Pipe) {
                 // Handle default value: expr | default
                 let default = self.binary_op()?; // Parse right side with same precedence
-                expr = Expression::DefaultValue {
-                    expression: Box::new(expr),
-                    default: Box::new(default),
-                };
+                expr = self.spanned(
+                    expr.span,
+                    ExpressionKind::DefaultValue {
+                        expression: Box::new(expr),
+                        default: Box::new(default),
+                    },
+                );
             } else {
                 break;
             }
@@ -765,147 +1025,346 @@ impl Parser {
     }
 
     fn binary_op(&mut self) -> SusumuResult<Expression> {
-        let mut expr = self.unary()?;
-
-        while let Some(op) = self.match_binary_operator() {
-            let right = self.unary()?;
-            expr = Expression::BinaryOp {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+        self.binary_level(0)
+    }
+
+    /// Operators of `BINARY_PRECEDENCE[level]` and every tighter level, grouping to the left
+    fn binary_level(&mut self, level: usize) -> SusumuResult<Expression> {
+        let Some(operators) = BINARY_PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut expr = self.binary_level(level + 1)?;
+
+        while let Some(op) = self.match_binary_operator(operators) {
+            let right = self.binary_level(level + 1)?;
+            let span = expr.span.to(right.span);
+            expr = Expression::new(
+                ExpressionKind::BinaryOp {
+                    left: Box::new(expr),
+                    operator: op,
+                    right: Box::new(right),
+                },
+                span,
+            );
         }
 
         Ok(expr)
     }
 
     fn unary(&mut self) -> SusumuResult<Expression> {
-        if self.match_token(&TokenType::Minus) {
-            let right = self.unary()?;
-            Ok(Expression::BinaryOp {
-                left: Box::new(Expression::Number(0.0)), // -x becomes 0 - x
-                operator: BinaryOperator::Subtract,
-                right: Box::new(right),
-            })
+        let start = self.peek_span();
+        let operator = if self.match_token(&TokenType::Minus) {
+            UnaryOperator::Negate
+        } else if self.match_token(&TokenType::Not) {
+            UnaryOperator::Not
         } else if self.match_token(&TokenType::Plus) {
             // Unary plus: +x just returns x
-            self.unary()
-        } else {
-            self.foreach()
-        }
-    }
-
-    fn match_binary_operator(&mut self) -> Option<BinaryOperator> {
-        if self.match_token(&TokenType::Plus) {
-            Some(BinaryOperator::Add)
-        } else if self.match_token(&TokenType::Minus) {
-            Some(BinaryOperator::Subtract)
-        } else if self.match_token(&TokenType::Multiply) {
-            Some(BinaryOperator::Multiply)
-        } else if self.match_token(&TokenType::Divide) {
-            Some(BinaryOperator::Divide)
-        } else if self.match_token(&TokenType::Equal) {
-            Some(BinaryOperator::Equal)
-        } else if self.match_token(&TokenType::NotEqual) {
-            Some(BinaryOperator::NotEqual)
-        } else if self.match_token(&TokenType::Less) {
-            Some(BinaryOperator::Less)
-        } else if self.match_token(&TokenType::Greater) {
-            Some(BinaryOperator::Greater)
-        } else if self.match_token(&TokenType::LessEq) {
-            Some(BinaryOperator::LessEq)
-        } else if self.match_token(&TokenType::GreaterEq) {
-            Some(BinaryOperator::GreaterEq)
+            return self.unary();
         } else {
+            return self.power();
+        };
+        let operand = self.unary()?;
+        Ok(self.spanned(
+            start,
+            ExpressionKind::UnaryOp {
+                operator,
+                operand: Box::new(operand),
+            },
+        ))
+    }
+
+    /// `**` groups to the right and binds tighter than a prefix operator on its left,
+    /// so `-2 ** 2` is `-(2 ** 2)` while `2 ** -1` still parses
+    fn power(&mut self) -> SusumuResult<Expression> {
+        let base = self.access()?;
+        if !self.match_token(&TokenType::Power) {
+            return Ok(base);
+        }
+        let exponent = self.unary()?;
+        let span = base.span.to(exponent.span);
+        Ok(Expression::new(
+            ExpressionKind::BinaryOp {
+                left: Box::new(base),
+                operator: BinaryOperator::Power,
+                right: Box::new(exponent),
+            },
+            span,
+        ))
+    }
+
+    /// `.name`, `[index]` and `[start:end]` after an operand, each of which can be made
+    /// optional with a leading `?` so that a null or none operand gives null
+    fn access(&mut self) -> SusumuResult<Expression> {
+        let mut expr = self.foreach()?;
+
+        loop {
+            let start = expr.span;
+            let optional = self.check(&TokenType::QuestionMark)
+                && matches!(
+                    self.tokens.get(self.current + 1).map(|t| &t.token_type),
+                    Some(TokenType::Dot | TokenType::LeftBracket)
+                );
+            if optional {
+                self.advance();
+            }
+
+            let kind = if self.match_token(&TokenType::Dot) {
+                let property = self
+                    .consume(&TokenType::Identifier, "Expected property name after '.'")?
+                    .lexeme
+                    .clone();
+                ExpressionKind::PropertyAccess {
+                    object: Box::new(expr),
+                    property,
+                    optional,
+                }
+            } else if self.match_token(&TokenType::LeftBracket) {
+                self.index_or_slice(expr, optional)?
+            } else {
+                break;
+            };
+            expr = self.spanned(start, kind);
+        }
+
+        Ok(expr)
+    }
+
+    /// The rest of `object[index]` or `object[start:end]` after the opening bracket
+    fn index_or_slice(
+        &mut self,
+        object: Expression,
+        optional: bool,
+    ) -> SusumuResult<ExpressionKind> {
+        let start = if self.check(&TokenType::Colon) {
             None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+
+        let kind = match start {
+            Some(index) if !self.check(&TokenType::Colon) => ExpressionKind::Index {
+                object: Box::new(object),
+                index,
+                optional,
+            },
+            start => {
+                self.advance(); // ':'
+                let end = if self.check(&TokenType::RightBracket) {
+                    None
+                } else {
+                    Some(Box::new(self.expression()?))
+                };
+                ExpressionKind::Slice {
+                    object: Box::new(object),
+                    start,
+                    end,
+                    optional,
+                }
+            }
+        };
+
+        self.consume(&TokenType::RightBracket, "Expected ']' after index")?;
+        Ok(kind)
+    }
+
+    /// The rest of an interpolated string after its `StringStart` token
+    fn interpolated_string(&mut self, start: Span) -> SusumuResult<Expression> {
+        let mut parts = Vec::new();
+        loop {
+            let text = &self.previous().lexeme;
+            if !text.is_empty() {
+                parts.push(StringPart::Text(text.clone()));
+            }
+            if self.previous().token_type == TokenType::StringEnd {
+                break;
+            }
+
+            parts.push(StringPart::Expression(self.expression()?));
+            if !self.match_token(&TokenType::StringMiddle) {
+                self.consume(
+                    &TokenType::StringEnd,
+                    "Expected '}' after expression in string",
+                )?;
+            }
         }
+        Ok(self.spanned(start, ExpressionKind::InterpolatedString(parts)))
+    }
+
+    fn match_binary_operator(
+        &mut self,
+        operators: &[(TokenType, BinaryOperator)],
+    ) -> Option<BinaryOperator> {
+        operators
+            .iter()
+            .find(|(token_type, _)| self.match_token(token_type))
+            .map(|(_, operator)| operator.clone())
     }
 
     fn foreach(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
+        // `outer: fe ...` or `outer: w ...` labels the loop for `break outer`
+        let label = if self.check(&TokenType::Identifier)
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|t| t.token_type == TokenType::Colon)
+            && self
+                .tokens
+                .get(self.current + 2)
+                .is_some_and(|t| matches!(t.token_type, TokenType::ForEach | TokenType::While))
+        {
+            let label = self.advance().lexeme.clone();
+            self.advance(); // consume ':'
+            Some(label)
+        } else {
+            None
+        };
+
         if self.match_token(&TokenType::Match) {
             self.match_expression()
         } else if self.match_token(&TokenType::ForEach) {
-            let variable = self
-                .consume(&TokenType::Identifier, "Expected variable name after 'fe'")?
-                .lexeme
-                .clone();
+            let pattern = if self.check(&TokenType::LeftParen) || self.check(&TokenType::LeftBrace)
+            {
+                self.destructuring_pattern()?
+            } else {
+                let name = self
+                    .consume(&TokenType::Identifier, "Expected variable name after 'fe'")?
+                    .lexeme
+                    .clone();
+                Pattern::Identifier(name)
+            };
             self.consume(&TokenType::In, "Expected 'in' after foreach variable")?;
 
-            let iterable = self.primary()?;
+            // Operators are allowed so that `fe i in 0..n + 1` is a range
+            let iterable = self.binary_op()?;
 
             self.consume(&TokenType::LeftBrace, "Expected '{' after iterable")?;
-            self.skip_newlines_and_comments();
-
-            let body = self.parse_block_content()?;
-
-            self.skip_newlines_and_comments();
+            let body = self.loop_body(&label)?;
             self.consume(&TokenType::RightBrace, "Expected '}' after foreach body")?;
 
-            Ok(Expression::ForEach {
-                variable,
-                iterable: Box::new(iterable),
-                body: Box::new(body),
-            })
+            Ok(self.spanned(
+                start,
+                ExpressionKind::ForEach {
+                    label,
+                    pattern,
+                    iterable: Box::new(iterable),
+                    body: Box::new(body),
+                },
+            ))
         } else if self.match_token(&TokenType::While) {
             let condition = self.expression()?;
             self.consume(&TokenType::LeftBrace, "Expected '{' after while condition")?;
-            self.skip_newlines_and_comments();
-
-            let body = self.parse_block_content()?;
-
-            self.skip_newlines_and_comments();
+            let body = self.loop_body(&label)?;
             self.consume(&TokenType::RightBrace, "Expected '}' after while body")?;
 
-            Ok(Expression::While {
-                condition: Box::new(condition),
-                body: Box::new(body),
-            })
+            Ok(self.spanned(
+                start,
+                ExpressionKind::While {
+                    label,
+                    condition: Box::new(condition),
+                    body: Box::new(body),
+                },
+            ))
         } else {
             self.flow_control()
         }
     }
 
+    /// The statements of a loop body, which `break` and `continue` may leave
+    fn loop_body(&mut self, label: &Option<String>) -> SusumuResult<Expression> {
+        self.skip_newlines_and_comments();
+        self.loop_labels.push(label.clone());
+        let body = self.parse_block_content();
+        self.loop_labels.pop();
+        self.skip_newlines_and_comments();
+        body
+    }
+
+    /// `break` or `continue`, then an optional loop label and `<- value`
+    fn loop_control(&mut self, start: Span) -> SusumuResult<Expression> {
+        let keyword = self.advance().clone();
+        let label = if self.check(&TokenType::Identifier) {
+            Some(self.advance().lexeme.clone())
+        } else {
+            None
+        };
+        match &label {
+            Some(name) if !self.loop_labels.iter().any(|l| l.as_ref() == Some(name)) => {
+                return Err(SusumuError::parser_error(
+                    self.previous().span(),
+                    format!("Unknown loop label '{}'", name),
+                ));
+            }
+            None if self.loop_labels.is_empty() => {
+                return Err(SusumuError::parser_error(
+                    keyword.span(),
+                    format!("'{}' outside of a loop", keyword.lexeme),
+                ));
+            }
+            _ => {}
+        }
+
+        let value = if self.match_token(&TokenType::LeftArrow) {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+        let kind = if keyword.token_type == TokenType::Break {
+            ExpressionKind::Break { label, value }
+        } else {
+            ExpressionKind::Continue { label, value }
+        };
+        Ok(self.spanned(start, kind))
+    }
+
     fn flow_control(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
         if self.match_token(&TokenType::Return) {
             self.consume(&TokenType::LeftArrow, "Expected '<-' after 'return'")?;
             let value = self.expression()?; // Parse full expression, not just primary
-            Ok(Expression::Return(Box::new(value)))
+            Ok(self.spanned(start, ExpressionKind::Return(Box::new(value))))
         } else if self.match_token(&TokenType::Success) {
             self.consume(&TokenType::LeftArrow, "Expected '<-' after 'success'")?;
             let value = self.expression()?;
-            Ok(Expression::Success(Box::new(value)))
+            Ok(self.spanned(start, ExpressionKind::Success(Box::new(value))))
         } else if self.match_token(&TokenType::Error) {
             self.consume(&TokenType::LeftArrow, "Expected '<-' after 'error'")?;
             let value = self.expression()?;
-            Ok(Expression::ErrorReturn(Box::new(value)))
+            Ok(self.spanned(start, ExpressionKind::ErrorReturn(Box::new(value))))
+        } else if self.check(&TokenType::Break) || self.check(&TokenType::Continue) {
+            self.loop_control(start)
         } else {
             self.primary()
         }
     }
 
     fn primary(&mut self) -> SusumuResult<Expression> {
+        let start = self.peek_span();
         if self.match_token(&TokenType::Number) {
             let lexeme = &self.previous().lexeme;
-            // Try to parse as integer first, fall back to float
-            let value = if lexeme.contains('.') {
-                lexeme.parse::<f64>().map_err(|_| {
-                    SusumuError::parser_error(self.previous().line, "Invalid number format")
-                })?
+            // Integers and floats are distinct values at runtime
+            if lexeme.contains('.') {
+                let value = lexeme.parse::<f64>().map_err(|_| {
+                    SusumuError::parser_error(self.previous().span(), "Invalid number format")
+                })?;
+                Ok(self.spanned(start, ExpressionKind::Number(value)))
             } else {
-                // Parse as integer, but store as f64 for compatibility
-                lexeme.parse::<i64>().map_err(|_| {
-                    SusumuError::parser_error(self.previous().line, "Invalid number format")
-                })? as f64
-            };
-            Ok(Expression::Number(value))
+                let value = lexeme.parse::<i64>().map_err(|_| {
+                    SusumuError::parser_error(self.previous().span(), "Invalid number format")
+                })?;
+                Ok(self.spanned(start, ExpressionKind::Integer(value)))
+            }
         } else if self.match_token(&TokenType::String) {
-            Ok(Expression::String(self.previous().lexeme.clone()))
+            let value = self.previous().lexeme.clone();
+            Ok(self.spanned(start, ExpressionKind::String(value)))
+        } else if self.match_token(&TokenType::StringStart) {
+            self.interpolated_string(start)
         } else if self.match_token(&TokenType::True) {
-            Ok(Expression::Boolean(true))
+            Ok(self.spanned(start, ExpressionKind::Boolean(true)))
         } else if self.match_token(&TokenType::False) {
-            Ok(Expression::Boolean(false))
+            Ok(self.spanned(start, ExpressionKind::Boolean(false)))
         } else if self.match_token(&TokenType::Null) {
-            Ok(Expression::Null)
+            Ok(self.spanned(start, ExpressionKind::Null))
         } else if self.match_token(&TokenType::Identifier) {
             let name = self.previous().lexeme.clone();
 
@@ -927,16 +1386,18 @@ impl Parser {
                     "Expected ')' after function arguments",
                 )?;
 
-                Ok(Expression::FunctionCall { name, args })
+                Ok(self.spanned(start, ExpressionKind::FunctionCall { name, args }))
             } else {
-                Ok(Expression::Identifier(name))
+                Ok(self.spanned(start, ExpressionKind::Identifier(name)))
             }
+        } else if self.is_lambda_literal() {
+            self.lambda()
         } else if self.match_token(&TokenType::LeftParen) {
             // Tuple or grouped expression
             if self.check(&TokenType::RightParen) {
                 // Empty tuple
                 self.advance();
-                Ok(Expression::Tuple(Vec::new()))
+                Ok(self.spanned(start, ExpressionKind::Tuple(Vec::new())))
             } else {
                 let first_expr = self.expression()?;
 
@@ -954,7 +1415,7 @@ impl Parser {
                     }
 
                     self.consume(&TokenType::RightParen, "Expected ')' after tuple elements")?;
-                    Ok(Expression::Tuple(elements))
+                    Ok(self.spanned(start, ExpressionKind::Tuple(elements)))
                 } else {
                     // Grouped expression
                     self.consume(&TokenType::RightParen, "Expected ')' after expression")?;
@@ -978,7 +1439,7 @@ impl Parser {
                             self.advance().lexeme.clone()
                         } else {
                             return Err(SusumuError::parser_error(
-                                self.peek().line,
+                                self.peek().span(),
                                 "Expected property name",
                             ));
                         };
@@ -1001,7 +1462,7 @@ impl Parser {
                 self.skip_newlines_and_comments();
 
                 self.consume(&TokenType::RightBrace, "Expected '}' after object literal")?;
-                Ok(Expression::Object(pairs))
+                Ok(self.spanned(start, ExpressionKind::Object(pairs)))
             } else {
                 // Block expression: { expr1; expr2; expr3 }
                 self.parse_block_expression()
@@ -1032,7 +1493,7 @@ impl Parser {
                 &TokenType::RightBracket,
                 "Expected ']' after array elements",
             )?;
-            Ok(Expression::Array(elements))
+            Ok(self.spanned(start, ExpressionKind::Array(elements)))
         } else if self.match_token(&TokenType::I) {
             // Standalone conditional: i condition { ... } ei condition { ... } e { ... }
             // Check for special keywords first, then fall back to expressions
@@ -1103,7 +1564,7 @@ impl Parser {
                 // For standalone conditionals, we use a null condition placeholder
                 else_if_branches.push(ElseIfBranch {
                     condition_type: else_if_condition_type,
-                    condition: Expression::Null,
+                    condition: ExpressionKind::Null.into(),
                     then_branch: else_if_then_branch,
                 });
             }
@@ -1125,197 +1586,45 @@ impl Parser {
 
             // For standalone conditionals, we need a placeholder condition
             // The actual condition will be determined by context (usually a preceding arrow chain)
-            Ok(Expression::Conditional {
-                condition_type,
-                condition: Box::new(Expression::Null), // Placeholder - will be replaced by arrow chain evaluation
-                then_branch: Box::new(then_branch),
-                else_if_branches,
-                else_branch,
-            })
+            Ok(self.spanned(
+                start,
+                ExpressionKind::Conditional {
+                    condition_type,
+                    condition: Box::new(ExpressionKind::Null.into()), // Placeholder - will be replaced by arrow chain evaluation
+                    then_branch: Box::new(then_branch),
+                    else_if_branches,
+                    else_branch,
+                },
+            ))
         } else {
             Err(self.error_with_suggestion("Unexpected token"))
         }
     }
 
-    // Type checking methods
-    fn type_check_conditional(
-        &mut self,
-        condition: &Expression,
-        condition_type: &ConditionType,
-        _then_branch: &Expression,
-        _else_branch: &Option<Box<Expression>>,
-    ) -> SusumuResult<()> {
-        match condition_type {
-            ConditionType::Success => {
-                // For 'i success', the condition should be a result type
-                let condition_type = self.infer_expression_type(condition)?;
-                if !condition_type.is_result() {
-                    return Err(SusumuError::parser_error(
-                        self.peek().line,
-                        &format!(
-                            "Expected result type for 'i success', found: {}",
-                            condition_type.description()
-                        ),
-                    ));
-                }
-            }
-            ConditionType::AllValid => {
-                // For 'i allValid', the condition should be an array of results from convergent validation
-                // This is primarily used with convergent arrow chains where multiple validation functions
-                // are combined and we want to ensure ALL succeed
-            }
-            ConditionType::Custom(_name) => {
-                // Custom conditions - validate against known condition functions
-                // This could be extended with a registry of valid condition functions
-            }
-            ConditionType::If => {
-                // Traditional if - condition should be boolean-like
-            }
-            ConditionType::Expression(_expr) => {
-                // Expression conditions - validate that the expression evaluates to a boolean-like value
-                // Type checking can be enhanced here if needed
-            }
-        }
-
-        Ok(())
-    }
-
-    fn type_check_arrow_chain(
+    /// Types flowing through an arrow chain, for the visual debugger: the type of each
+    /// expression, and the type each step expects to receive
+    fn arrow_flow_types(
         &mut self,
         expressions: &[Expression],
         directions: &[ArrowDirection],
-    ) -> SusumuResult<(Vec<SusumuType>, Vec<SusumuType>)> {
-        let mut expected_types = Vec::new();
-        let mut actual_types = Vec::new();
-
-        // Infer types for all expressions
-        for expr in expressions {
-            let expr_type = self.infer_expression_type(expr)?;
-            actual_types.push(expr_type);
-        }
-
-        // Check arrow chain compatibility
-        let mut current_type = actual_types[0].clone();
-        expected_types.push(current_type.clone());
+    ) -> (Vec<SusumuType>, Vec<SusumuType>) {
+        let actual_types: Vec<SusumuType> = expressions
+            .iter()
+            .map(|expr| self.type_checker.infer_expression_type(expr))
+            .collect();
 
+        let mut expected_types = vec![actual_types[0].clone()];
         for (i, direction) in directions.iter().enumerate() {
-            let target_expr = &expressions[i + 1];
-            let target_type = &actual_types[i + 1];
-
-            match direction {
-                ArrowDirection::Forward => {
-                    // For forward arrows: current -> function
-                    // The function should accept the current type
-                    if let Expression::Identifier(func_name) = target_expr {
-                        let expected_input = self.get_function_input_type(func_name)?;
-                        expected_types.push(expected_input.clone());
-
-                        if !current_type.is_assignable_to(&expected_input) {
-                            self.add_type_error(TypeError {
-                                line: self.peek().line,
-                                column: self.peek().column,
-                                error_type: TypeErrorKind::ArrowChainError {
-                                    step: i + 1,
-                                    expected_input: expected_input.clone(),
-                                    actual_input: current_type.clone(),
-                                    function_name: func_name.clone(),
-                                },
-                                suggestion: format!(
-                                    "Convert {} to {} or use a different function",
-                                    current_type.description(),
-                                    expected_input.description()
-                                ),
-                            });
-                        }
-
-                        current_type = self.get_function_output_type(func_name)?;
-                    }
-                }
-                ArrowDirection::Backward => {
-                    // For backward arrows: function <- argument
-                    // Handled in convergence analysis
-                    expected_types.push(target_type.clone());
-                }
-            }
-        }
-
-        Ok((expected_types, actual_types))
-    }
-
-    fn infer_expression_type(&self, expr: &Expression) -> SusumuResult<SusumuType> {
-        match expr {
-            Expression::Number(_) => Ok(SusumuType::Number),
-            Expression::String(_) => Ok(SusumuType::String),
-            Expression::Boolean(_) => Ok(SusumuType::Boolean),
-            Expression::Null => Ok(SusumuType::Null),
-            Expression::Identifier(name) => {
-                // Look up variable or function type
-                if let Some(var_type) = self.type_checker.env.get_variable(name) {
-                    Ok(var_type.clone())
-                } else if let Some(func_type) = self.type_checker.env.get_function(name) {
-                    Ok(func_type.clone())
-                } else {
-                    Ok(SusumuType::Unknown)
-                }
-            }
-            Expression::Tuple(elements) => {
-                let element_types: Result<Vec<_>, _> = elements
-                    .iter()
-                    .map(|e| self.infer_expression_type(e))
-                    .collect();
-                Ok(SusumuType::Tuple(element_types?))
-            }
-            Expression::Array(elements) => {
-                if elements.is_empty() {
-                    Ok(SusumuType::Array(Box::new(SusumuType::Unknown)))
-                } else {
-                    let first_type = self.infer_expression_type(&elements[0])?;
-                    Ok(SusumuType::Array(Box::new(first_type)))
-                }
-            }
-            Expression::Object(pairs) => {
-                let mut field_types = Vec::new();
-                for (key, value_expr) in pairs {
-                    let value_type = self.infer_expression_type(value_expr)?;
-                    field_types.push((key.clone(), value_type));
+            let target = &expressions[i + 1];
+            match (direction, &target.kind) {
+                (ArrowDirection::Forward, ExpressionKind::Identifier(name)) => {
+                    expected_types.push(self.type_checker.function_input_type(name))
                 }
-                Ok(SusumuType::Object(field_types))
+                _ => expected_types.push(actual_types[i + 1].clone()),
             }
-            Expression::FunctionCall { name, args: _ } => self.get_function_output_type(name),
-            _ => Ok(SusumuType::Unknown),
         }
-    }
 
-    fn get_function_input_type(&self, name: &str) -> SusumuResult<SusumuType> {
-        // Simplified - in reality, would lookup from type environment
-        match name {
-            "add" | "multiply" | "subtract" | "divide" => Ok(SusumuType::Number),
-            "print" => Ok(SusumuType::Unknown), // Accepts anything
-            "length" => Ok(SusumuType::Union(vec![
-                SusumuType::String,
-                SusumuType::Array(Box::new(SusumuType::Unknown)),
-            ])),
-            _ => Ok(SusumuType::Unknown),
-        }
-    }
-
-    fn get_function_output_type(&self, name: &str) -> SusumuResult<SusumuType> {
-        match name {
-            "add" | "multiply" | "subtract" | "divide" => Ok(SusumuType::Number),
-            "print" => Ok(SusumuType::Unknown),
-            "length" => Ok(SusumuType::Number),
-            "first" => Ok(SusumuType::Union(vec![
-                SusumuType::Unknown,
-                SusumuType::Null,
-            ])),
-            _ => Ok(SusumuType::Unknown),
-        }
-    }
-
-    fn validate_program_types(&self, _program: &Program) -> SusumuResult<()> {
-        // Perform global type validation
-        // This would include checking function definitions, main expression, etc.
-        Ok(())
+        (expected_types, actual_types)
     }
 
     /// Look ahead to determine if this is an object literal or block expression
@@ -1354,6 +1663,7 @@ impl Parser {
 
     /// Parse a block expression: { expr1; expr2; expr3 }
     fn parse_block_expression(&mut self) -> SusumuResult<Expression> {
+        let start = self.previous().span(); // The opening '{'
         let mut expressions = Vec::new();
 
         self.skip_newlines_and_comments();
@@ -1371,7 +1681,7 @@ impl Parser {
         if expressions.len() == 1 {
             Ok(expressions.into_iter().next().unwrap())
         } else {
-            Ok(Expression::Block(expressions))
+            Ok(self.spanned(start, ExpressionKind::Block(expressions)))
         }
     }
 
@@ -1386,16 +1696,17 @@ impl Parser {
         if expressions.len() == 1 {
             Ok(expressions.into_iter().next().unwrap())
         } else {
-            Ok(Expression::Block(expressions))
+            let span = match (expressions.first(), expressions.last()) {
+                (Some(first), Some(last)) => first.span.to(last.span),
+                _ => self.peek_span(),
+            };
+            Ok(Expression::new(ExpressionKind::Block(expressions), span))
         }
     }
 
-    fn add_type_error(&mut self, error: TypeError) {
-        // In a real implementation, would collect and report type errors
-        eprintln!("{}", self.type_checker.generate_error_message(&error));
-    }
-
     fn match_expression(&mut self) -> SusumuResult<Expression> {
+        let start = self.previous().span(); // The 'match' keyword
+
         // Check if there's an expression to match on
         let expr = if self.check(&TokenType::LeftBrace) {
             // No expression, match on previous arrow chain result
@@ -1425,7 +1736,7 @@ impl Parser {
 
         self.consume(&TokenType::RightBrace, "Expected '}' after match cases")?;
 
-        Ok(Expression::Match { expr, cases })
+        Ok(self.spanned(start, ExpressionKind::Match { expr, cases }))
     }
 
     fn match_case(&mut self) -> SusumuResult<MatchCase> {
@@ -1564,8 +1875,12 @@ impl Parser {
                         .consume(&TokenType::Identifier, "Expected field name")?
                         .lexeme
                         .clone();
-                    self.consume(&TokenType::Colon, "Expected ':' after field name")?;
-                    let pattern = self.pattern()?;
+                    // `{name}` is short for `{name: name}`
+                    let pattern = if self.match_token(&TokenType::Colon) {
+                        self.pattern()?
+                    } else {
+                        Pattern::Identifier(key.clone())
+                    };
                     field_patterns.push((key, pattern));
 
                     if !self.match_token(&TokenType::Comma) {
@@ -1580,7 +1895,7 @@ impl Parser {
             // Literal pattern
             let literal = if self.match_token(&TokenType::Number) {
                 let value = self.previous().lexeme.parse::<f64>().map_err(|_| {
-                    SusumuError::parser_error(self.previous().line, "Invalid number in pattern")
+                    SusumuError::parser_error(self.previous().span(), "Invalid number in pattern")
                 })?;
                 LiteralValue::Number(value)
             } else if self.match_token(&TokenType::String) {
@@ -1593,7 +1908,7 @@ impl Parser {
                 LiteralValue::Null
             } else {
                 return Err(SusumuError::parser_error(
-                    self.peek().line,
+                    self.peek().span(),
                     "Expected pattern",
                 ));
             };
@@ -1649,10 +1964,13 @@ impl Parser {
             mutations.push((prop_path, value));
         }
 
-        Ok(Expression::ObjectMutation {
-            target: Box::new(target),
-            mutations,
-        })
+        Ok(self.spanned(
+            target.span,
+            ExpressionKind::ObjectMutation {
+                target: Box::new(target),
+                mutations,
+            },
+        ))
     }
 
     fn parse_property_path(&mut self) -> SusumuResult<String> {
@@ -1743,12 +2061,13 @@ impl Parser {
     }
 
     fn expression_to_string(&self, expr: &Expression) -> String {
-        match expr {
-            Expression::Identifier(name) => name.clone(),
-            Expression::Number(n) => n.to_string(),
-            Expression::String(s) => format!("\"{}\"", s),
-            Expression::Boolean(b) => b.to_string(),
-            Expression::Null => "null".to_string(),
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::Integer(n) => n.to_string(),
+            ExpressionKind::Number(n) => n.to_string(),
+            ExpressionKind::String(s) => format!("\"{}\"", s),
+            ExpressionKind::Boolean(b) => b.to_string(),
+            ExpressionKind::Null => "null".to_string(),
             _ => "expression".to_string(),
         }
     }
@@ -1795,6 +2114,16 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
+    /// Span of the next token to be consumed
+    fn peek_span(&self) -> Span {
+        self.peek().span()
+    }
+
+    /// Build an expression spanning from `start` to the end of the last consumed token
+    fn spanned(&self, start: Span, kind: ExpressionKind) -> Expression {
+        Expression::new(kind, start.to(self.previous().span()))
+    }
+
     fn consume(&mut self, token_type: &TokenType, message: &str) -> SusumuResult<&Token> {
         if self.check(token_type) {
             Ok(self.advance())
@@ -1815,15 +2144,10 @@ impl Parser {
             self.generate_detailed_error(&current_token.token_type, message, prev_token);
 
         SusumuError::parser_error(
-            current_token.line,
+            current_token.span(),
             &format!(
-                "{} at column {}\n💡 {}\n🔍 Context: Previous token was '{}' ({}:{})",
-                detailed_message,
-                current_token.column,
-                suggestion,
-                prev_token.lexeme,
-                prev_token.line,
-                prev_token.column
+                "{}\n💡 {}\n🔍 Context: Previous token was '{}' ({}:{})",
+                detailed_message, suggestion, prev_token.lexeme, prev_token.line, prev_token.column
             ),
         )
     }
@@ -1995,15 +2319,50 @@ impl Parser {
         let mut func_def = self.function_definition()?;
 
         // Wrap the function body with the annotation
-        func_def.body = Expression::Annotated {
-            annotation,
-            expression: Box::new(func_def.body),
-        };
+        let span = func_def.body.span;
+        func_def.body = Expression::new(
+            ExpressionKind::Annotated {
+                annotation,
+                expression: Box::new(func_def.body),
+            },
+            span,
+        );
 
         Ok(func_def)
     }
 }
 
+fn is_binding_pattern(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Identifier(_) | Pattern::Wildcard => true,
+        Pattern::Tuple(patterns) => patterns.iter().all(is_binding_pattern),
+        Pattern::Object(fields) => fields.iter().all(|(_, field)| is_binding_pattern(field)),
+        _ => false,
+    }
+}
+
+/// Source of a binding pattern, naming destructured parameters in signatures
+fn binding_source(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Identifier(name) => name.clone(),
+        Pattern::Tuple(patterns) => {
+            let patterns: Vec<String> = patterns.iter().map(binding_source).collect();
+            format!("({})", patterns.join(", "))
+        }
+        Pattern::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, field)| match field {
+                    Pattern::Identifier(name) if name == key => key.clone(),
+                    _ => format!("{}: {}", key, binding_source(field)),
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        _ => "_".to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2018,10 +2377,10 @@ mod tests {
         let ast = parser.parse().unwrap();
 
         assert!(ast.main_expression.is_some());
-        if let Some(Expression::ArrowChain {
+        if let Some(ExpressionKind::ArrowChain {
             expressions,
             directions,
-        }) = ast.main_expression
+        }) = ast.main_expression.map(|expr| expr.kind)
         {
             assert_eq!(expressions.len(), 3);
             assert_eq!(directions.len(), 2);
@@ -2072,6 +2431,31 @@ mod tests {
         // In a real implementation, would check for type errors
     }
 
+    #[test]
+    fn test_lambda_parsing() {
+        let source = "[1, 2] -> map <- (x, y: number) => { x -> add <- y } -> print";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        if let Some(ExpressionKind::ArrowChain { expressions, .. }) =
+            ast.main_expression.as_ref().map(|expr| &expr.kind)
+        {
+            assert_eq!(expressions.len(), 4);
+            match &expressions[2].kind {
+                ExpressionKind::Lambda { params, .. } => {
+                    assert_eq!(params.len(), 2);
+                    assert_eq!(params[1].name, "y");
+                    assert!(params[1].type_annotation.is_some());
+                }
+                other => panic!("Expected lambda, got {:?}", other),
+            }
+        } else {
+            panic!("Expected arrow chain");
+        }
+    }
+
     #[test]
     fn test_module_arrow_chain() {
         // Test that import/export are treated as regular arrow chains
@@ -2085,10 +2469,10 @@ mod tests {
         assert!(ast.main_expression.is_some());
 
         // Verify it parsed as an arrow chain with the expected structure
-        if let Some(Expression::ArrowChain {
+        if let Some(ExpressionKind::ArrowChain {
             expressions,
             directions,
-        }) = &ast.main_expression
+        }) = ast.main_expression.as_ref().map(|expr| &expr.kind)
         {
             assert_eq!(expressions.len(), 4); // payment_module, from, import, (processPayment, refundPayment)
             assert_eq!(directions.len(), 3); // ->, <-, <-
@@ -2112,10 +2496,10 @@ mod tests {
         // Should parse as regular arrow chain expression
         assert!(ast.main_expression.is_some());
 
-        if let Some(Expression::ArrowChain {
+        if let Some(ExpressionKind::ArrowChain {
             expressions,
             directions,
-        }) = &ast.main_expression
+        }) = ast.main_expression.as_ref().map(|expr| &expr.kind)
         {
             assert_eq!(expressions.len(), 2); // (func1, func2), export
             assert_eq!(directions.len(), 1); // ->
@@ -2124,4 +2508,112 @@ mod tests {
             panic!("Expected arrow chain for export statement");
         }
     }
+
+    #[test]
+    fn test_expression_spans() {
+        let source = "double(x) {\n    return <- x * 2\n}\nresult = \"ab\" -> double";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        let function = &ast.functions[0];
+        assert_eq!(function.span, Span::new(1, 1, 3, 2));
+        assert_eq!(function.body.span, Span::new(2, 5, 2, 20));
+        if let ExpressionKind::Return(value) = &function.body.kind {
+            assert_eq!(value.span, Span::new(2, 15, 2, 20));
+        } else {
+            panic!("Expected return statement");
+        }
+
+        let main = ast.main_expression.unwrap();
+        assert_eq!(main.span, Span::new(4, 1, 4, 24));
+        if let ExpressionKind::Assignment { value, .. } = &main.kind {
+            assert_eq!(value.span, Span::new(4, 10, 4, 24));
+            if let ExpressionKind::ArrowChain { expressions, .. } = &value.kind {
+                assert_eq!(expressions[0].span, Span::new(4, 10, 4, 14));
+                assert_eq!(expressions[1].span, Span::new(4, 18, 4, 24));
+            } else {
+                panic!("Expected arrow chain");
+            }
+        } else {
+            panic!("Expected assignment");
+        }
+    }
+
+    #[test]
+    fn test_recovers_from_multiple_syntax_errors() {
+        let source = r#"good(x) {
+    return <- x
+}
+bad(x) {
+    i x > 1 {
+        z = ]
+    }
+    return <- x
+}
+a = = 1
+b = 2 -> good
+)
+c = b -> good"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let (program, errors) = Parser::new(tokens).parse_with_recovery();
+
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|error| error.span().unwrap().line)
+            .collect();
+        assert_eq!(lines, vec![6, 10, 12]);
+
+        // Each error points at the token it was raised at, not the start of its line
+        let spans: Vec<Span> = errors.iter().map(|error| error.span().unwrap()).collect();
+        assert_eq!(spans[0], Span::new(6, 13, 6, 14));
+        assert_eq!(spans[1], Span::new(10, 5, 10, 6));
+        assert!(errors[1]
+            .to_string()
+            .starts_with("Parser error at line 10, column 5: "));
+
+        // Statements around the errors are still parsed
+        let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["good", "bad"]);
+        match program.main_expression.as_ref().map(|expr| &expr.kind) {
+            Some(ExpressionKind::Block(statements)) => assert_eq!(statements.len(), 2),
+            other => panic!("Expected two recovered statements, got {:?}", other),
+        }
+
+        // `parse` still reports the first error
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error.span().unwrap().line, 6);
+    }
+
+    #[test]
+    fn test_loop_control_needs_its_loop() {
+        let errors = [
+            ("break", "'break' outside of a loop"),
+            ("f = () => {\n continue\n}", "'continue' outside of a loop"),
+            (
+                "fe x in xs {\n break inner\n}",
+                "Unknown loop label 'inner'",
+            ),
+            (
+                "outer: fe x in xs {\n f = () => {\n  break outer\n }\n}",
+                "Unknown loop label 'outer'",
+            ),
+        ];
+        for (source, expected) in errors {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let error = Parser::new(tokens).parse().unwrap_err();
+            assert!(
+                error.to_string().contains(expected),
+                "{}: {}",
+                source,
+                error
+            );
+        }
+
+        let source = "outer: w true {\n fe x in 0..3 {\n  continue outer <- x\n }\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
 }
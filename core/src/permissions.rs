@@ -0,0 +1,479 @@
+//! Capability-based permissions for builtins that reach outside the interpreter
+//!
+//! Builtins that read or write files, make HTTP requests, read the clock or read
+//! environment variables check the interpreter's [`Permissions`] before doing anything.
+//! A denied call fails with [`SusumuError::PermissionDenied`](crate::SusumuError::PermissionDenied),
+//! which `| default` and `assertError` do not catch. Permissions are part of
+//! [`InterpreterOptions`](crate::InterpreterOptions) and allow everything by default, so
+//! embedders opt in to sandboxing.
+//!
+//! ```
+//! use susumu::{Access, Interpreter, InterpreterOptions, Lexer, Parser, Permissions, SusumuError};
+//!
+//! let mut interpreter = Interpreter::with_options(InterpreterOptions {
+//!     permissions: Permissions {
+//!         read: Access::only(["./data"]),
+//!         net: Access::only(["localhost"]),
+//!         ..Permissions::none()
+//!     },
+//!     ..Default::default()
+//! });
+//!
+//! let source = "\"/etc/passwd\" -> readFile";
+//! let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+//! let error = interpreter.execute(&program).unwrap_err();
+//! assert!(matches!(error, SusumuError::PermissionDenied { .. }));
+//! ```
+
+use crate::error::{SusumuError, SusumuResult};
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// A kind of access to the world outside the interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Capability {
+    /// Reading files and listing directories
+    Read,
+    /// Creating, writing and appending to files
+    Write,
+    /// HTTP requests
+    Net,
+    /// The current time
+    Clock,
+    /// Environment variables
+    Env,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Read => "read",
+            Capability::Write => "write",
+            Capability::Net => "net",
+            Capability::Clock => "clock",
+            Capability::Env => "env",
+        })
+    }
+}
+
+/// Which resources a capability reaches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+    All,
+    /// Paths (a directory grants everything beneath it) or hosts (`host` for any port,
+    /// `host:port` for one). An empty list grants nothing.
+    Only(Vec<String>),
+}
+
+impl Access {
+    pub fn none() -> Self {
+        Access::Only(Vec::new())
+    }
+
+    pub fn only<I, S>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Access::Only(entries.into_iter().map(Into::into).collect())
+    }
+
+    /// Whether `path` is one of the allowed paths or inside one of them. Paths are
+    /// resolved against the working directory, including `..` and symlinks, before
+    /// they are compared.
+    pub fn allows_path(&self, path: &str) -> bool {
+        match self {
+            Access::All => true,
+            Access::Only(roots) => {
+                let path = resolve(Path::new(path));
+                roots
+                    .iter()
+                    .any(|root| path.starts_with(resolve(Path::new(root))))
+            }
+        }
+    }
+
+    /// Whether `host`, optionally with the `port` being connected to, is allowed
+    pub fn allows_host(&self, host: &str, port: Option<u16>) -> bool {
+        match self {
+            Access::All => true,
+            Access::Only(entries) => entries.iter().any(|entry| {
+                let (allowed_host, allowed_port) = split_port(entry);
+                allowed_host.eq_ignore_ascii_case(host)
+                    && allowed_port.is_none_or(|allowed| Some(allowed) == port)
+            }),
+        }
+    }
+}
+
+/// What a program may access
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    pub read: Access,
+    pub write: Access,
+    /// Hosts that HTTP builtins may connect to, checked for the requested URL and
+    /// again for every redirect it answers with
+    pub net: Access,
+    pub clock: bool,
+    pub env: bool,
+}
+
+impl Permissions {
+    /// Allow everything, as before permissions existed
+    pub fn all() -> Self {
+        Self {
+            read: Access::All,
+            write: Access::All,
+            net: Access::All,
+            clock: true,
+            env: true,
+        }
+    }
+
+    /// Allow nothing outside the interpreter
+    pub fn none() -> Self {
+        Self {
+            read: Access::none(),
+            write: Access::none(),
+            net: Access::none(),
+            clock: false,
+            env: false,
+        }
+    }
+
+    /// Check a call to the builtin `function`, which needs `capability` for the
+    /// resources named by its first argument: a path or URL, an array of them, or a
+    /// request config object with a `url` field.
+    pub fn check(
+        &self,
+        capability: Capability,
+        function: &str,
+        args: &[Value],
+    ) -> SusumuResult<()> {
+        let allows = |target: &str| match capability {
+            Capability::Read => self.read.allows_path(target),
+            Capability::Write => self.write.allows_path(target),
+            Capability::Net => self.allows_url(target),
+            Capability::Clock => self.clock,
+            Capability::Env => self.env,
+        };
+
+        if matches!(capability, Capability::Clock | Capability::Env) {
+            return match allows("") {
+                true => Ok(()),
+                false => Err(SusumuError::permission_denied(
+                    capability,
+                    format!("{}() needs {} access", function, capability),
+                )),
+            };
+        }
+
+        let mut targets = Vec::new();
+        if let Some(first) = args.first() {
+            collect_targets(first, &mut targets);
+        }
+        match targets.into_iter().find(|target| !allows(target)) {
+            Some(target) => Err(SusumuError::permission_denied(
+                capability,
+                format!("{}() needs {} access to '{}'", function, capability, target),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Redirect policy for HTTP clients that checks every hop against `net`, so an
+    /// allowed host cannot send a request on to one that is not
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn redirect_policy(&self) -> reqwest::redirect::Policy {
+        use reqwest::redirect::Policy;
+
+        if self.net == Access::All {
+            return Policy::default();
+        }
+        let permissions = self.clone();
+        Policy::custom(move |attempt| {
+            if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else if permissions.allows_url(attempt.url().as_str()) {
+                attempt.follow()
+            } else {
+                let message = format!("redirect needs net access to '{}'", attempt.url());
+                attempt.error(message)
+            }
+        })
+    }
+
+    fn allows_url(&self, url: &str) -> bool {
+        if self.net == Access::All {
+            return true;
+        }
+        match reqwest::Url::parse(url) {
+            Ok(parsed) => parsed
+                .host_str()
+                .is_some_and(|host| self.net.allows_host(host, parsed.port_or_known_default())),
+            Err(_) => false,
+        }
+    }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+fn collect_targets<'v>(value: &'v Value, targets: &mut Vec<&'v str>) {
+    match value {
+        Value::String(target) => targets.push(target),
+        Value::Array(items) => items.iter().for_each(|item| collect_targets(item, targets)),
+        Value::Object(fields) => {
+            if let Some(url) = fields.get("url") {
+                collect_targets(url, targets);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Split `host:port`, leaving bracketed IPv6 addresses such as `[::1]` intact
+fn split_port(entry: &str) -> (&str, Option<u16>) {
+    match entry.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (entry, None),
+        },
+        _ => (entry, None),
+    }
+}
+
+/// Absolute form of `path` with `.` and `..` removed and symlinks in its existing
+/// part followed, so neither can be used to step outside an allowed directory
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+
+    // Symlinks are followed as soon as they are reached, so a `..` after one leaves
+    // its target rather than the directory holding the link
+    let mut resolved = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => {
+                resolved.push(other);
+                if let Ok(canonical) = resolved.canonicalize() {
+                    resolved = canonical;
+                }
+            }
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_paths_hosts_and_flags() {
+        let dir = std::env::temp_dir().join(format!("susumu_permissions_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        let data = dir.join("data").to_string_lossy().into_owned();
+        let permissions = Permissions {
+            read: Access::only([data.as_str()]),
+            net: Access::only(["localhost", "api.example.com:8443"]),
+            clock: true,
+            ..Permissions::none()
+        };
+        let path = |p: &str| vec![Value::from(dir.join(p).to_string_lossy().into_owned())];
+
+        assert!(permissions
+            .check(Capability::Read, "readFile", &path("data/a.txt"))
+            .is_ok());
+        assert!(permissions
+            .check(Capability::Read, "readFile", &path("data/new/b"))
+            .is_ok());
+        assert!(permissions
+            .check(Capability::Read, "readFile", &path("data/../x"))
+            .is_err());
+        assert!(permissions
+            .check(Capability::Read, "readFile", &path("database"))
+            .is_err());
+        assert!(permissions
+            .check(Capability::Write, "writeFile", &path("data/a"))
+            .is_err());
+
+        let net = |target: Value| permissions.check(Capability::Net, "httpRequest", &[target]);
+        assert!(net(Value::from("http://localhost:3000/api")).is_ok());
+        assert!(net(Value::from("https://API.example.com:8443/v1")).is_ok());
+        assert!(net(Value::from("https://api.example.com/v1")).is_err());
+        assert!(net(Value::from("http://evil.com\\@localhost/")).is_err());
+        let configs = Value::from(serde_json::json!([
+            {"url": "http://localhost/a"},
+            {"url": "http://example.org/b"}
+        ]));
+        let error = net(configs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Permission denied: httpRequest() needs net access to 'http://example.org/b'"
+        );
+
+        assert!(permissions.check(Capability::Clock, "now", &[]).is_ok());
+        assert!(permissions.check(Capability::Env, "getEnv", &[]).is_err());
+        assert!(Permissions::default()
+            .check(
+                Capability::Write,
+                "writeFile",
+                &[Value::from("/etc/passwd")]
+            )
+            .is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dot_dot_after_symlink_leaves_its_target() {
+        let dir = std::env::temp_dir().join(format!("susumu_symlink_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("allowed")).unwrap();
+        std::fs::create_dir_all(dir.join("outside/sub")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside/sub"), dir.join("allowed/link")).unwrap();
+        let read = Access::only([dir.join("allowed").to_string_lossy().into_owned()]);
+        let path = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+        assert!(!read.allows_path(&path("allowed/link/../x")));
+        assert!(read.allows_path(&path("allowed/x/../y")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_redirects_are_checked_on_every_hop() {
+        use crate::{execute_to_string_with_options, InterpreterOptions};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        // Redirects everything to `localhost` on the same port, which serves "secret"
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let size = stream.read(&mut request).unwrap_or(0);
+                let host = String::from_utf8_lossy(&request[..size]).to_lowercase();
+                let response = if host.contains("host: localhost") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nsecret"
+                        .to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/\r\n\
+                         Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        port
+                    )
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        let source = format!("(\"http://127.0.0.1:{}/\" -> httpGet).body", port);
+        let run = |hosts: &[&str]| {
+            let options = InterpreterOptions {
+                permissions: Permissions {
+                    net: Access::only(hosts.iter().copied()),
+                    ..Permissions::none()
+                },
+                ..Default::default()
+            };
+            execute_to_string_with_options(&source, options)
+        };
+
+        let output = run(&["127.0.0.1"]);
+        assert!(
+            output.contains(&format!(
+                "redirect needs net access to 'http://localhost:{}/'",
+                port
+            )),
+            "{}",
+            output
+        );
+        assert_eq!(run(&["127.0.0.1", "localhost"]), "\"secret\"");
+    }
+
+    #[test]
+    fn test_imports_need_read_access() {
+        use crate::{Interpreter, InterpreterOptions};
+
+        let dir = std::env::temp_dir().join(format!("susumu_imports_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("lib.susu");
+        std::fs::write(
+            &module,
+            "double(x) {\n return <- x * 2\n}\n(double) -> export\n",
+        )
+        .unwrap();
+
+        let import = |read: Access| {
+            let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                permissions: Permissions {
+                    read,
+                    ..Permissions::none()
+                },
+                ..Default::default()
+            });
+            interpreter.add_module_path(&dir);
+            interpreter.import_from_module("lib", &["double".to_string()])
+        };
+
+        let error = import(Access::only([std::env::temp_dir()
+            .join("elsewhere")
+            .to_string_lossy()
+            .into_owned()]))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Permission denied: import() needs read access to '{}'",
+                module.display()
+            )
+        );
+        assert!(import(Access::only([dir.to_string_lossy().into_owned()])).is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_denials_cannot_be_caught() {
+        use crate::{execute_to_string_with_options, InterpreterOptions};
+
+        let options = InterpreterOptions {
+            permissions: Permissions::none(),
+            ..Default::default()
+        };
+        for source in [
+            "x = (\"/etc/hosts\" -> readFile) | \"fallback\"\nx",
+            "() => { now() } -> assertError",
+            "\"HOME\" -> getEnv",
+        ] {
+            let output = execute_to_string_with_options(source, options.clone());
+            assert!(
+                output.contains("Permission denied"),
+                "{}: {}",
+                source,
+                output
+            );
+        }
+        let output =
+            execute_to_string_with_options("\"/etc/hosts\" -> fileExists", options.clone());
+        assert!(
+            output.contains("needs read access to '/etc/hosts'"),
+            "{}",
+            output
+        );
+    }
+}
@@ -10,7 +10,8 @@ use serde_json::Value;
 
 use crate::error::SusumuError;
 use crate::interpreter::{ExecutionTrace, PerformanceStats};
-use crate::{Interpreter, Lexer, Parser};
+use crate::{Access, ExecutionLimits, Interpreter, InterpreterOptions, Lexer, Parser, Permissions};
+use std::time::Duration;
 
 /// Python exception for Susumu errors
 #[derive(Debug)]
@@ -18,10 +19,27 @@ struct SusumuPythonError(SusumuError);
 
 impl From<SusumuError> for PyErr {
     fn from(err: SusumuError) -> PyErr {
-        PyRuntimeError::new_err(err.to_string())
+        PyRuntimeError::new_err(err.report())
     }
 }
 
+/// Convert an error's stack trace to a list of dicts, innermost call first
+fn stack_trace_to_python(py: Python, err: &SusumuError) -> PyResult<Vec<PyObject>> {
+    err.stack_trace()
+        .iter()
+        .map(|frame| {
+            let dict = PyDict::new(py);
+            dict.set_item("function", &frame.function)?;
+            dict.set_item("line", frame.call_site.span.line)?;
+            dict.set_item("column", frame.call_site.span.column)?;
+            dict.set_item("end_line", frame.call_site.span.end_line)?;
+            dict.set_item("end_column", frame.call_site.span.end_column)?;
+            dict.set_item("arrow_step", frame.call_site.arrow_step)?;
+            Ok(dict.to_object(py))
+        })
+        .collect()
+}
+
 /// Convert serde_json::Value to Python object
 fn json_to_python(py: Python, value: &Value) -> PyResult<PyObject> {
     match value {
@@ -95,6 +113,9 @@ pub struct ExecutionResult {
     pub result: PyObject,
     #[pyo3(get)]
     pub error: Option<String>,
+    /// Susumu calls the error unwound through, innermost first
+    #[pyo3(get)]
+    pub stack_trace: Vec<PyObject>,
     #[pyo3(get)]
     pub execution_time_ms: f64,
 }
@@ -119,10 +140,57 @@ pub struct SusumuEngine {
 
 #[pymethods]
 impl SusumuEngine {
+    /// Create an engine, optionally limiting the resources untrusted code may use.
+    /// Exceeding a limit fails the execution with an "Execution limit exceeded" error.
+    ///
+    /// Files, hosts, the clock and environment variables are all accessible by default.
+    /// Passing a list to `allow_read`, `allow_write` or `allow_net` restricts that access
+    /// to the given paths or `host[:port]`s (an empty list denies it), and module imports
+    /// need read access like `readFile` does. Denied access fails with "Permission denied".
     #[new]
-    fn new() -> Self {
+    #[pyo3(signature = (
+        max_steps=None,
+        timeout_ms=None,
+        max_call_depth=None,
+        max_collection_size=None,
+        allow_read=None,
+        allow_write=None,
+        allow_net=None,
+        allow_clock=true,
+        allow_env=true
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        max_steps: Option<u64>,
+        timeout_ms: Option<u64>,
+        max_call_depth: Option<usize>,
+        max_collection_size: Option<usize>,
+        allow_read: Option<Vec<String>>,
+        allow_write: Option<Vec<String>>,
+        allow_net: Option<Vec<String>>,
+        allow_clock: bool,
+        allow_env: bool,
+    ) -> Self {
+        let limits = ExecutionLimits {
+            max_steps,
+            timeout: timeout_ms.map(Duration::from_millis),
+            max_call_depth,
+            max_collection_size,
+        };
+        let access = |entries: Option<Vec<String>>| entries.map_or(Access::All, Access::only);
+        let permissions = Permissions {
+            read: access(allow_read),
+            write: access(allow_write),
+            net: access(allow_net),
+            clock: allow_clock,
+            env: allow_env,
+        };
         Self {
-            interpreter: Interpreter::new(),
+            interpreter: Interpreter::with_options(InterpreterOptions {
+                limits,
+                permissions,
+                ..Default::default()
+            }),
         }
     }
 
@@ -143,6 +211,7 @@ impl SusumuEngine {
                     success: true,
                     result: json_to_python(py, &result)?,
                     error: None,
+                    stack_trace: Vec::new(),
                     execution_time_ms: execution_time,
                 })
             }
@@ -153,6 +222,7 @@ impl SusumuEngine {
                     success: false,
                     result: py.None(),
                     error: Some(e.to_string()),
+                    stack_trace: stack_trace_to_python(py, &e)?,
                     execution_time_ms: execution_time,
                 })
             }
@@ -184,6 +254,7 @@ impl SusumuEngine {
                     success: true,
                     result: json_to_python(py, &result)?,
                     error: None,
+                    stack_trace: Vec::new(),
                     execution_time_ms: execution_time,
                 };
 
@@ -196,6 +267,7 @@ impl SusumuEngine {
                     success: false,
                     result: py.None(),
                     error: Some(e.to_string()),
+                    stack_trace: stack_trace_to_python(py, &e)?,
                     execution_time_ms: execution_time,
                 };
 
@@ -204,20 +276,17 @@ impl SusumuEngine {
         }
     }
 
-    /// Check syntax without executing
-    fn check_syntax(&self, source: &str) -> PyResult<(bool, Option<String>)> {
-        match Lexer::new(source).tokenize() {
-            Ok(tokens) => match Parser::new(tokens).parse() {
-                Ok(_) => Ok((true, None)),
-                Err(e) => Ok((false, Some(e.to_string()))),
-            },
-            Err(e) => Ok((false, Some(e.to_string()))),
+    /// Check syntax without executing; returns whether it is valid and every error found
+    fn check_syntax(&self, source: &str) -> PyResult<(bool, Vec<String>)> {
+        match crate::parse(source) {
+            Ok(_) => Ok((true, Vec::new())),
+            Err(errors) => Ok((false, errors.iter().map(|e| e.to_string()).collect())),
         }
     }
 
-    /// Reset the interpreter state
+    /// Reset the interpreter state, keeping its limits
     fn reset(&mut self) {
-        self.interpreter = Interpreter::new();
+        self.interpreter = Interpreter::with_options(self.interpreter.options().clone());
     }
 }
 
@@ -225,7 +294,8 @@ impl SusumuEngine {
     fn execute_internal(&mut self, source: &str) -> Result<Value, SusumuError> {
         let tokens = Lexer::new(source).tokenize()?;
         let ast = Parser::new(tokens).parse()?;
-        self.interpreter.execute(&ast)
+        // Python sees results through their JSON form
+        Ok(self.interpreter.execute(&ast)?.to_json())
     }
 
     fn format_trace(&self, trace: &ExecutionTrace) -> String {
@@ -248,14 +318,11 @@ impl SusumuEngine {
         Ok(dict.to_object(py))
     }
 
-    fn value_to_string(&self, value: &Value) -> String {
+    fn value_to_string(&self, value: &crate::value::Value) -> String {
         match value {
-            Value::Null => "null".to_string(),
-            Value::Bool(b) => b.to_string(),
-            Value::Number(n) => n.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Array(_) => "[array]".to_string(),
-            Value::Object(_) => "{object}".to_string(),
+            crate::value::Value::Array(_) => "[array]".to_string(),
+            crate::value::Value::Object(_) => "{object}".to_string(),
+            other => other.to_string(),
         }
     }
 }
@@ -263,7 +330,9 @@ impl SusumuEngine {
 /// Simple function API for quick execution
 #[pyfunction]
 fn execute(py: Python, source: &str) -> PyResult<PyObject> {
-    let mut engine = SusumuEngine::new();
+    let mut engine = SusumuEngine {
+        interpreter: Interpreter::new(),
+    };
     let result = engine.execute(py, source)?;
 
     if result.success {
@@ -278,7 +347,9 @@ fn execute(py: Python, source: &str) -> PyResult<PyObject> {
 /// Simple syntax check function
 #[pyfunction]
 fn check_syntax(source: &str) -> PyResult<bool> {
-    let engine = SusumuEngine::new();
+    let engine = SusumuEngine {
+        interpreter: Interpreter::new(),
+    };
     let (valid, _) = engine.check_syntax(source)?;
     Ok(valid)
 }
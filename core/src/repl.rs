@@ -0,0 +1,485 @@
+//! Interactive read-eval-print loop behind `susumu` with no arguments
+//!
+//! Input continues onto further lines while a bracket or string is open or the last
+//! token is an arrow, so functions and `i { } e { }` blocks can be typed as in a file.
+//! Lines starting with `:` are meta-commands (see [`COMMANDS`]). History is kept in
+//! `$SUSUMU_HISTORY`, or `~/.susumu_history` by default.
+
+use crate::error::SusumuError;
+use crate::interpreter::{Interpreter, InterpreterOptions};
+use crate::lexer::{Lexer, TokenType};
+use crate::parser::Parser;
+use crate::value::Value;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Context, Editor, Helper};
+use std::path::PathBuf;
+
+/// Meta-commands with their usage and description
+pub const COMMANDS: &[(&str, &str)] = &[
+    (":help", "Show this help"),
+    (":type <expr>", "Evaluate an expression and show its type"),
+    (":ast <code>", "Show the syntax tree of code"),
+    (":trace <code>", "Run code and show each step of its flow"),
+    (":env", "List the functions and variables defined"),
+    (":load <file>", "Run a file and keep its definitions"),
+    (":reset", "Forget all definitions"),
+    (":clear", "Clear the screen"),
+    (":quit", "Exit the REPL (also Ctrl-D)"),
+];
+
+/// What the REPL should do after handling some input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Output(String),
+    Error(String),
+    Nothing,
+    Clear,
+    /// End the session, exiting the process with this status
+    Quit(i32),
+}
+
+/// Interpreter state shared by everything entered in one REPL session
+pub struct Session {
+    options: InterpreterOptions,
+    interpreter: Interpreter,
+}
+
+impl Session {
+    pub fn new(options: InterpreterOptions) -> Self {
+        Self {
+            interpreter: Interpreter::with_options(options.clone()),
+            options,
+        }
+    }
+
+    /// Handle one complete input: a meta-command or Susumu code
+    pub fn handle(&mut self, input: &str) -> Reply {
+        let input = input.trim();
+        let (command, argument) = match input.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (input, ""),
+        };
+
+        match command {
+            "" => Reply::Nothing,
+            ":quit" | ":q" | "exit" | "quit" => Reply::Quit(0),
+            ":help" | "help" => Reply::Output(help()),
+            ":clear" | "clear" => Reply::Clear,
+            ":reset" => {
+                self.interpreter = Interpreter::with_options(self.options.clone());
+                Reply::Output("Session reset".to_string())
+            }
+            ":env" => Reply::Output(self.env()),
+            ":type" => match self.eval(argument) {
+                Ok(value) => Reply::Output(value.type_name().to_string()),
+                Err(reply) => reply,
+            },
+            ":ast" => match parse(argument) {
+                Ok(program) => {
+                    Reply::Output(serde_json::to_string_pretty(&program).unwrap_or_default())
+                }
+                Err(err) => Reply::Error(err),
+            },
+            ":trace" => self.trace(argument),
+            ":load" => self.load(argument),
+            _ if command.starts_with(':') => Reply::Error(format!(
+                "Unknown command '{}'; type :help for the list",
+                command
+            )),
+            _ => match self.eval(input) {
+                Ok(Value::Null) => Reply::Nothing,
+                Ok(value) => Reply::Output(format!("=> {}", show(&value))),
+                Err(reply) => reply,
+            },
+        }
+    }
+
+    /// Names worth completing: builtins, then defined functions and variables
+    pub fn completions(&self) -> Vec<String> {
+        let mut names = self.interpreter.builtin_names();
+        names.extend(
+            self.interpreter
+                .user_functions()
+                .into_iter()
+                .map(|f| f.name),
+        );
+        names.extend(
+            self.interpreter
+                .global_variables()
+                .into_iter()
+                .map(|(name, _)| name),
+        );
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Run code in the session. Calling `exit` ends the session with its status.
+    fn eval(&mut self, source: &str) -> Result<Value, Reply> {
+        let program = parse(source).map_err(Reply::Error)?;
+        // Defining `main` in the REPL should not run it, so load rather than execute
+        self.interpreter.load(&program).map_err(|err| match err {
+            SusumuError::Exit { code } => Reply::Quit(code),
+            err => Reply::Error(err.report()),
+        })
+    }
+
+    fn trace(&mut self, source: &str) -> Reply {
+        let seen = self.interpreter.get_execution_traces().len();
+        let result = self.eval(source);
+        let mut lines: Vec<String> = self.interpreter.get_execution_traces()[seen..]
+            .iter()
+            .enumerate()
+            .map(|(i, trace)| {
+                format!(
+                    "  {}. {} -> {}",
+                    i + 1,
+                    trace.expression,
+                    show(&trace.output_value)
+                )
+            })
+            .collect();
+        match result {
+            Ok(value) => {
+                lines.push(format!("=> {}", show(&value)));
+                Reply::Output(lines.join("\n"))
+            }
+            Err(Reply::Error(err)) => {
+                lines.push(err);
+                Reply::Error(lines.join("\n"))
+            }
+            Err(reply) => reply,
+        }
+    }
+
+    fn load(&mut self, path: &str) -> Reply {
+        if path.is_empty() {
+            return Reply::Error("Usage: :load <file>".to_string());
+        }
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => return Reply::Error(format!("Error reading file '{}': {}", path, err)),
+        };
+        let program = match parse(&source) {
+            Ok(program) => program,
+            Err(err) => return Reply::Error(err),
+        };
+        match self.interpreter.load(&program) {
+            Ok(_) => {
+                let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+                Reply::Output(format!(
+                    "Loaded {} ({} functions: {})",
+                    path,
+                    names.len(),
+                    names.join(", ")
+                ))
+            }
+            Err(err) => Reply::Error(err.report()),
+        }
+    }
+
+    fn env(&self) -> String {
+        let mut lines = Vec::new();
+        for function in self.interpreter.user_functions() {
+            let params: Vec<&str> = function.params.iter().map(|p| p.name.as_str()).collect();
+            lines.push(format!("{}({})", function.name, params.join(", ")));
+        }
+        for (name, value) in self.interpreter.global_variables() {
+            lines.push(format!("{} = {}", name, show(&value)));
+        }
+        if lines.is_empty() {
+            "Nothing defined yet".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+}
+
+/// Whether `input` can be run as it is, rather than needing more lines: every bracket
+/// and string is closed and it does not end with an arrow
+pub fn is_complete(input: &str) -> bool {
+    let tokens = match Lexer::new(input).tokenize() {
+        Ok(tokens) => tokens,
+        // A string still open at the end of the input is the only recoverable lexer error
+        Err(err) => return !err.to_string().contains("Unterminated string"),
+    };
+
+    let mut depth = 0i32;
+    let mut last = None;
+    for token in &tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            TokenType::Newline | TokenType::Comment | TokenType::EOF => continue,
+            _ => {}
+        }
+        last = Some(&token.token_type);
+    }
+
+    let open_arrow = matches!(
+        last,
+        Some(TokenType::RightArrow | TokenType::LeftArrow | TokenType::MutationArrow)
+    );
+    depth <= 0 && !open_arrow
+}
+
+/// Run the REPL on the terminal until the user quits
+pub fn run(options: InterpreterOptions) -> i32 {
+    println!("Susumu Programming Language v{}", env!("CARGO_PKG_VERSION"));
+    println!("Arrow-flow programming with visual data transformations");
+    println!("Type :help for commands, :quit or Ctrl-D to exit");
+    println!();
+
+    let mut session = Session::new(options);
+    let config = Config::builder().max_history_size(1000).map_or_else(
+        |_| Config::default(),
+        |builder| builder.auto_add_history(false).build(),
+    );
+    let mut editor: Editor<ReplHelper, DefaultHistory> = match Editor::with_config(config) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("Error starting the REPL: {}", err);
+            return 1;
+        }
+    };
+    editor.set_helper(Some(ReplHelper {
+        names: session.completions(),
+    }));
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut entry = 1;
+    let mut status = 0;
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            format!("susumu:{:03}> ", entry)
+        } else {
+            "       ...> ".to_string()
+        };
+        match editor.readline(&prompt) {
+            Ok(line) => {
+                // Without a terminal the validator does not run, so lines are joined here
+                input.push_str(&line);
+                if !is_complete(&input) {
+                    input.push('\n');
+                    continue;
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Error reading input: {}", err);
+                break;
+            }
+        }
+
+        let source = std::mem::take(&mut input);
+        if source.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(source.trim());
+
+        match session.handle(&source) {
+            Reply::Output(output) => println!("{}", output),
+            Reply::Error(err) => eprintln!("Error: {}", err),
+            Reply::Nothing => {}
+            Reply::Clear => {
+                let _ = editor.clear_screen();
+            }
+            Reply::Quit(code) => {
+                status = code;
+                break;
+            }
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = session.completions();
+        }
+        entry += 1;
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    println!("Goodbye!");
+    status
+}
+
+fn parse(source: &str) -> Result<crate::ast::Program, String> {
+    let tokens = Lexer::new(source)
+        .tokenize()
+        .map_err(|err| err.to_string())?;
+    Parser::new(tokens).parse().map_err(|err| err.to_string())
+}
+
+/// Values print as JSON, so strings are quoted and nested values stay readable
+fn show(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| value.to_string())
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("SUSUMU_HISTORY")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".susumu_history"))
+        })
+}
+
+fn help() -> String {
+    let mut help = String::from(
+        "Enter Susumu code to run it; definitions are kept for later entries.\n\
+         Input continues while a bracket or string is open or a line ends with an arrow.\n\
+         \n\
+         Examples:\n  \
+         5 -> add <- 3 -> multiply <- 2\n  \
+         double(x) {\n    x -> multiply <- 2\n  }\n  \
+         [1, 2, 3] -> map <- double\n\
+         \n\
+         Commands:\n",
+    );
+    for (usage, description) in COMMANDS {
+        help.push_str(&format!("  {:<16}{}\n", usage, description));
+    }
+    help.push_str("\nTab completes builtins, functions and variables.");
+    help
+}
+
+/// Line editor hooks: multiline input and tab completion
+struct ReplHelper {
+    names: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &line[start..pos];
+
+        // Meta-commands are only recognised at the start of the input
+        if start == 1 && line.starts_with(':') {
+            let commands = COMMANDS
+                .iter()
+                .filter_map(|(usage, _)| usage.split(' ').next())
+                .filter(|command| command[1..].starts_with(word))
+                .map(String::from)
+                .collect();
+            return Ok((0, commands));
+        }
+
+        if word.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        let names = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .cloned()
+            .collect();
+        Ok((start, names))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(if is_complete(ctx.input()) {
+            ValidationResult::Valid(None)
+        } else {
+            ValidationResult::Incomplete
+        })
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_incomplete_input() {
+        assert!(is_complete("5 -> add <- 3"));
+        assert!(is_complete("double(x) {\n    x -> multiply <- 2\n}"));
+        assert!(is_complete("i x > 1 {\n 1\n} e {\n 2\n}"));
+        assert!(!is_complete("double(x) {"));
+        assert!(!is_complete("i x > 1 {\n 1\n} e {"));
+        assert!(!is_complete("[1, 2,"));
+        assert!(!is_complete("\"unterminated"));
+        assert!(!is_complete("5 -> add <- 3 ->"));
+        assert!(!is_complete("5 -> add <-  // comment"));
+        // Extra closing brackets are a syntax error for the parser to report
+        assert!(is_complete("5 }"));
+    }
+
+    #[test]
+    fn test_session_keeps_definitions_and_handles_commands() {
+        let mut session = Session::new(InterpreterOptions::default());
+        assert_eq!(
+            session.handle("double(x) {\n    x -> multiply <- 2\n}"),
+            Reply::Nothing
+        );
+        assert_eq!(session.handle("n = 4"), Reply::Output("=> 4".to_string()));
+        assert_eq!(
+            session.handle("n -> double"),
+            Reply::Output("=> 8".to_string())
+        );
+        assert_eq!(
+            session.handle(":type \"hi\""),
+            Reply::Output("string".to_string())
+        );
+        assert_eq!(
+            session.handle(":env"),
+            Reply::Output("double(x)\nn = 4".to_string())
+        );
+        assert!(session.completions().contains(&"double".to_string()));
+        assert!(session.completions().contains(&"map".to_string()));
+
+        let Reply::Output(trace) = session.handle(":trace 3 -> double") else {
+            panic!("expected trace output");
+        };
+        assert!(trace.ends_with("=> 6"), "{}", trace);
+        let Reply::Output(ast) = session.handle(":ast 1 -> add <- 2") else {
+            panic!("expected ast output");
+        };
+        assert!(ast.contains("main_expression"));
+
+        assert!(matches!(session.handle(":nope"), Reply::Error(_)));
+        assert!(matches!(session.handle("1 -> missing"), Reply::Error(_)));
+
+        assert_eq!(
+            session.handle(":reset"),
+            Reply::Output("Session reset".to_string())
+        );
+        assert_eq!(
+            session.handle(":env"),
+            Reply::Output("Nothing defined yet".to_string())
+        );
+        assert_eq!(session.handle(":quit"), Reply::Quit(0));
+        assert_eq!(session.handle("exit(3)"), Reply::Quit(3));
+    }
+}
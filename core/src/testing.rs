@@ -0,0 +1,469 @@
+//! Test runner for tests written in Susumu
+//!
+//! Tests are functions annotated with `@test` in files named `*_test.susu`. Each test
+//! runs in a fresh [`Interpreter`] that has loaded its file, so tests cannot see each
+//! other's state. A test passes when it returns, fails when an assertion builtin
+//! (`assertEqual`, `assertError`, `assertMatches`) raises an assertion error, and is an
+//! error when anything else goes wrong.
+//!
+//! ```susumu
+//! @test <- "adds two numbers"
+//! addsNumbers() {
+//!     2 -> add <- 3 -> assertEqual <- 5
+//! }
+//! ```
+
+use crate::ast::{Annotation, ExpressionKind, FunctionDef};
+use crate::error::SusumuError;
+use crate::interpreter::{Interpreter, InterpreterOptions};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Suffix of the files `susumu test` looks for in directories
+pub const TEST_FILE_SUFFIX: &str = "_test.susu";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestResult {
+    pub file: String,
+    /// Function name, or the file name when the file itself could not be loaded
+    pub name: String,
+    /// Text given as `@test <- "..."`
+    pub description: Option<String>,
+    pub status: TestStatus,
+    pub message: Option<String>,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+}
+
+impl TestResult {
+    fn display_name(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.name)
+    }
+}
+
+fn millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Output formats of `susumu test --format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Human,
+    Json,
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "junit" => Ok(Self::Junit),
+            other => Err(format!(
+                "Unknown report format '{}', expected human, json or junit",
+                other
+            )),
+        }
+    }
+}
+
+/// Results of a test run
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+    pub duration: Duration,
+}
+
+impl TestReport {
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.results.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Whether every test passed
+    pub fn success(&self) -> bool {
+        self.results.iter().all(|r| r.status == TestStatus::Passed)
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Human => self.to_human(),
+            ReportFormat::Json => self.to_json(),
+            ReportFormat::Junit => self.to_junit(),
+        }
+    }
+
+    pub fn to_human(&self) -> String {
+        let mut out = String::new();
+        let mut file = None;
+        for result in &self.results {
+            if file != Some(&result.file) {
+                file = Some(&result.file);
+                let _ = writeln!(out, "{}", result.file);
+            }
+            let status = match result.status {
+                TestStatus::Passed => "ok",
+                TestStatus::Failed => "FAILED",
+                TestStatus::Error => "ERROR",
+            };
+            let _ = writeln!(out, "    test {} ... {}", result.display_name(), status);
+        }
+
+        let problems: Vec<&TestResult> = self
+            .results
+            .iter()
+            .filter(|r| r.status != TestStatus::Passed)
+            .collect();
+        if !problems.is_empty() {
+            out.push_str("\nfailures:\n");
+            for result in problems {
+                let _ = writeln!(out, "\n---- {} ({}) ----", result.name, result.file);
+                let _ = writeln!(out, "{}", result.message.as_deref().unwrap_or_default());
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "\ntest result: {}. {} passed; {} failed; {} errors; finished in {:.2}s",
+            if self.success() { "ok" } else { "FAILED" },
+            self.count(TestStatus::Passed),
+            self.count(TestStatus::Failed),
+            self.count(TestStatus::Error),
+            self.duration.as_secs_f64()
+        );
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let report = serde_json::json!({
+            "passed": self.count(TestStatus::Passed),
+            "failed": self.count(TestStatus::Failed),
+            "errors": self.count(TestStatus::Error),
+            "duration_ms": self.duration.as_secs_f64() * 1000.0,
+            "tests": self.results,
+        });
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    }
+
+    /// JUnit XML, one `<testsuite>` per file
+    pub fn to_junit(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            "<testsuites name=\"susumu\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+            self.results.len(),
+            self.count(TestStatus::Failed),
+            self.count(TestStatus::Error),
+            self.duration.as_secs_f64()
+        );
+
+        let mut start = 0;
+        while start < self.results.len() {
+            let file = &self.results[start].file;
+            let end = start
+                + self.results[start..]
+                    .iter()
+                    .take_while(|r| &r.file == file)
+                    .count();
+            let suite = &self.results[start..end];
+            let count = |status| suite.iter().filter(|r| r.status == status).count();
+            let time: Duration = suite.iter().map(|r| r.duration).sum();
+            let _ = writeln!(
+                out,
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+                xml_escape(file),
+                suite.len(),
+                count(TestStatus::Failed),
+                count(TestStatus::Error),
+                time.as_secs_f64()
+            );
+            for result in suite {
+                let _ = write!(
+                    out,
+                    "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                    xml_escape(result.display_name()),
+                    xml_escape(file),
+                    result.duration.as_secs_f64()
+                );
+                let tag = match result.status {
+                    TestStatus::Passed => {
+                        out.push_str("/>\n");
+                        continue;
+                    }
+                    TestStatus::Failed => "failure",
+                    TestStatus::Error => "error",
+                };
+                // The attribute gets the error itself, the body the full report with its trace
+                let report = result.message.as_deref().unwrap_or_default();
+                let message = xml_escape(report.lines().next().unwrap_or_default());
+                let _ = writeln!(
+                    out,
+                    ">\n      <{tag} message=\"{message}\">{}</{tag}>\n    </testcase>",
+                    xml_escape(report)
+                );
+            }
+            out.push_str("  </testsuite>\n");
+            start = end;
+        }
+
+        out.push_str("</testsuites>\n");
+        out
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Test files under the given paths: files are taken as given, directories are searched
+/// recursively for `*_test.susu`
+pub fn discover(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            discover_in(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: no such file or directory", path.display()),
+            ));
+        }
+    }
+    Ok(files)
+}
+
+fn discover_in(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if path.is_dir() {
+            discover_in(&path, files)?;
+        } else if name.ends_with(TEST_FILE_SUFFIX) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Run the tests in each file
+pub fn run_files(files: &[PathBuf], options: &InterpreterOptions) -> TestReport {
+    let start = Instant::now();
+    let mut results = Vec::new();
+    for path in files {
+        let file = path.display().to_string();
+        match fs::read_to_string(path) {
+            Ok(source) => results.extend(run_source(&file, &source, options)),
+            Err(err) => results.push(file_error(&file, err.to_string())),
+        }
+    }
+    TestReport {
+        results,
+        duration: start.elapsed(),
+    }
+}
+
+/// Run every `@test` function in `source`, each in its own interpreter
+pub fn run_source(file: &str, source: &str, options: &InterpreterOptions) -> Vec<TestResult> {
+    let program = match crate::parse(source) {
+        Ok(program) => program,
+        Err(errors) => {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return vec![file_error(file, messages.join("\n"))];
+        }
+    };
+
+    program
+        .functions
+        .iter()
+        .filter_map(|function| test_description(function).map(|d| (function, d)))
+        .map(|(function, description)| {
+            let start = Instant::now();
+            let outcome = if function.params.is_empty() {
+                let mut interpreter = Interpreter::with_options(options.clone());
+                interpreter
+                    .load(&program)
+                    .and_then(|_| interpreter.call_function(&function.name, &[]))
+            } else {
+                Err(SusumuError::function_call_error(
+                    "test functions cannot take parameters",
+                ))
+            };
+
+            let (status, message) = match outcome {
+                Ok(_) => (TestStatus::Passed, None),
+                Err(err @ SusumuError::AssertionError { .. }) => {
+                    (TestStatus::Failed, Some(err.report()))
+                }
+                Err(err) => (TestStatus::Error, Some(err.report())),
+            };
+            TestResult {
+                file: file.to_string(),
+                name: function.name.clone(),
+                description,
+                status,
+                message,
+                duration: start.elapsed(),
+            }
+        })
+        .collect()
+}
+
+/// `Some(description)` for functions annotated with `@test`
+fn test_description(function: &FunctionDef) -> Option<Option<String>> {
+    match &function.body.kind {
+        ExpressionKind::Annotated {
+            annotation: Annotation::Test(description),
+            ..
+        } => Some(description.clone()),
+        _ => None,
+    }
+}
+
+fn file_error(file: &str, message: String) -> TestResult {
+    TestResult {
+        file: file.to_string(),
+        name: file.to_string(),
+        description: None,
+        status: TestStatus::Error,
+        message: Some(message),
+        duration: Duration::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+counter = 0
+
+@test <- "adds & compares"
+addsNumbers() {
+    counter = 5
+    2 -> add <- 3 -> assertEqual <- 5
+    {name: "ada", role: "admin"} -> assertMatches <- {role: "admin"}
+}
+
+@test
+catchesErrors() {
+    assertError(() => { error <- "boom" }, "boom")
+    counter -> assertEqual <- 0
+}
+
+@test
+wrongSum() {
+    2 -> add <- 2 -> assertEqual <- 5
+}
+
+@test
+missingError() {
+    assertError(() => 1)
+}
+
+@test
+crashes() {
+    undefinedThing -> print
+}
+
+helper() {
+    1
+}
+"#;
+
+    #[test]
+    fn test_runs_annotated_functions_in_isolation() {
+        let results = run_source("math_test.susu", SOURCE, &InterpreterOptions::default());
+        let outcomes: Vec<(&str, TestStatus)> = results
+            .iter()
+            .map(|r| (r.name.as_str(), r.status))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("addsNumbers", TestStatus::Passed),
+                ("catchesErrors", TestStatus::Passed),
+                ("wrongSum", TestStatus::Failed),
+                ("missingError", TestStatus::Failed),
+                ("crashes", TestStatus::Error),
+            ]
+        );
+        assert_eq!(results[0].description.as_deref(), Some("adds & compares"));
+        assert!(results[2]
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("expected 5, found 4"));
+
+        let broken = run_source(
+            "broken_test.susu",
+            "@test\nbroken() {\n    x = (\n}",
+            &InterpreterOptions::default(),
+        );
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].status, TestStatus::Error);
+    }
+
+    #[test]
+    fn test_renders_reports() {
+        let report = TestReport {
+            results: run_source("math_test.susu", SOURCE, &InterpreterOptions::default()),
+            duration: Duration::from_millis(12),
+        };
+        assert!(!report.success());
+
+        let human = report.to_human();
+        assert!(human.contains("    test adds & compares ... ok"));
+        assert!(human.contains("---- wrongSum (math_test.susu) ----"));
+        assert!(human.contains("test result: FAILED. 2 passed; 2 failed; 1 errors"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["failed"], 2);
+        assert_eq!(json["tests"][2]["status"], "failed");
+        assert_eq!(json["tests"][0]["description"], "adds & compares");
+
+        let junit = report.to_junit();
+        assert!(junit.contains(
+            "<testsuite name=\"math_test.susu\" tests=\"5\" failures=\"2\" errors=\"1\""
+        ));
+        assert!(junit.contains("<testcase name=\"adds &amp; compares\""));
+        assert!(junit.contains("<failure message=\"Assertion failed: expected 5, found 4"));
+        assert!(junit.contains("<error message=\"Undefined variable: undefinedThing"));
+    }
+}
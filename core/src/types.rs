@@ -1,6 +1,10 @@
 //! Type system for Susumu with compile-time safety and inference
 
 // use crate::error::{SusumuError, SusumuResult};
+use crate::ast::{
+    ArrowDirection, BinaryOperator, ConditionType, Expression, ExpressionKind, FunctionDef,
+    FunctionParam, MatchCase, Pattern, Program, Span, StringPart, TypeAnnotation, UnaryOperator,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -61,6 +65,9 @@ impl SusumuType {
             // Unknown can be assigned to anything
             (SusumuType::Unknown, _) | (_, SusumuType::Unknown) => true,
 
+            // Generic parameters are not instantiated, so they accept anything
+            (SusumuType::Generic(_), _) | (_, SusumuType::Generic(_)) => true,
+
             // Union type compatibility
             (SusumuType::Union(types), target) => types.iter().all(|t| t.is_assignable_to(target)),
             (source, SusumuType::Union(types)) => types.iter().any(|t| source.is_assignable_to(t)),
@@ -68,6 +75,12 @@ impl SusumuType {
             // Array covariance
             (SusumuType::Array(a), SusumuType::Array(b)) => a.is_assignable_to(b),
 
+            // Objects are structural: every field the target names must be present
+            (SusumuType::Object(a), SusumuType::Object(b)) => b.iter().all(|(name, tb)| {
+                a.iter()
+                    .any(|(field, ta)| field == name && ta.is_assignable_to(tb))
+            }),
+
             // Tuple covariance
             (SusumuType::Tuple(a), SusumuType::Tuple(b)) => {
                 a.len() == b.len()
@@ -226,6 +239,45 @@ impl SusumuType {
         }
     }
 
+    /// Type named by a source annotation, in either casing (`number` or `Number`); names
+    /// the checker does not know stay unchecked
+    pub fn from_annotation(annotation: &TypeAnnotation) -> Self {
+        match annotation {
+            TypeAnnotation::Simple(name) => match name.to_ascii_lowercase().as_str() {
+                "number" | "int" | "integer" | "float" => SusumuType::Number,
+                "string" | "str" => SusumuType::String,
+                "bool" | "boolean" => SusumuType::Boolean,
+                "null" => SusumuType::Null,
+                "array" | "list" => SusumuType::Array(Box::new(SusumuType::Unknown)),
+                "object" => SusumuType::Object(Vec::new()),
+                _ => SusumuType::Unknown,
+            },
+            TypeAnnotation::Generic(name, args) => {
+                let arg = |index: usize| {
+                    args.get(index)
+                        .map_or(SusumuType::Unknown, SusumuType::from_annotation)
+                };
+                match name.to_ascii_lowercase().as_str() {
+                    "array" | "list" => SusumuType::Array(Box::new(arg(0))),
+                    "result" => SusumuType::result(arg(0), arg(1)),
+                    _ => SusumuType::Unknown,
+                }
+            }
+            TypeAnnotation::Union(types) => {
+                SusumuType::Union(types.iter().map(SusumuType::from_annotation).collect())
+            }
+        }
+    }
+
+    /// Whether some value of this type could be used where `other` is expected. Unlike
+    /// [`is_assignable_to`](Self::is_assignable_to), a union only needs one fitting member.
+    pub fn may_flow_into(&self, other: &SusumuType) -> bool {
+        match self {
+            SusumuType::Union(types) => types.iter().any(|t| t.may_flow_into(other)),
+            _ => self.is_assignable_to(other),
+        }
+    }
+
     /// Create an arrow chain type
     pub fn arrow_chain(
         input_type: SusumuType,
@@ -310,13 +362,24 @@ impl Default for TypeEnvironment {
 pub struct TypeChecker {
     pub env: TypeEnvironment,
     pub errors: Vec<TypeError>,
+    /// Declared result of the function whose body is being checked
+    returns: Option<ReturnContext>,
+    /// Whether a `break` or `continue` was seen since the enclosing loop began, which
+    /// makes the loop's result unpredictable
+    loop_control: bool,
+}
+
+/// Return types a function body is checked against
+struct ReturnContext {
+    function: String,
+    success: Option<SusumuType>,
+    errors: Vec<SusumuType>,
 }
 
 /// Rich type error with suggestions for fixes
 #[derive(Debug, Clone)]
 pub struct TypeError {
-    pub line: usize,
-    pub column: usize,
+    pub span: Span,
     pub error_type: TypeErrorKind,
     pub suggestion: String,
 }
@@ -352,6 +415,73 @@ pub enum TypeErrorKind {
         expected_result: bool,
         actual_type: SusumuType,
     },
+    ArityMismatch {
+        function_name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeErrorKind::TypeMismatch {
+                expected,
+                found,
+                context,
+            } => write!(f, "{}: expected {}, found {}", context, expected, found),
+            TypeErrorKind::ArrowChainError {
+                step,
+                expected_input,
+                actual_input,
+                function_name,
+            } => write!(
+                f,
+                "Arrow step {} passes {} to '{}', which expects {}",
+                step, actual_input, function_name, expected_input
+            ),
+            TypeErrorKind::ConvergenceError {
+                function_name,
+                expected_types,
+                actual_types,
+            } => {
+                let list = |types: &[SusumuType]| {
+                    types
+                        .iter()
+                        .map(|t| t.description())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(
+                    f,
+                    "Arguments converging on '{}' are ({}), expected ({})",
+                    function_name,
+                    list(actual_types),
+                    list(expected_types)
+                )
+            }
+            TypeErrorKind::UndefinedVariable { name, .. } => {
+                write!(f, "Undefined variable '{}'", name)
+            }
+            TypeErrorKind::UndefinedFunction { name, .. } => {
+                write!(f, "Undefined function '{}'", name)
+            }
+            TypeErrorKind::ResultTypeError {
+                context,
+                actual_type,
+                ..
+            } => write!(f, "Result type error in {}: found {}", context, actual_type),
+            TypeErrorKind::ArityMismatch {
+                function_name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' expects {} argument(s), got {}",
+                function_name, expected, found
+            ),
+        }
+    }
 }
 
 impl TypeChecker {
@@ -363,6 +493,8 @@ impl TypeChecker {
         Self {
             env,
             errors: Vec::new(),
+            returns: None,
+            loop_control: false,
         }
     }
 
@@ -453,14 +585,691 @@ impl TypeChecker {
         );
     }
 
+    /// Statically check a program: values flowing through arrow chains against the
+    /// functions they reach, convergence arity, and declared parameter and return types.
+    /// Values whose type cannot be inferred are never reported.
+    pub fn check_program(&mut self, program: &Program) -> &[TypeError] {
+        self.errors.clear();
+        for function in &program.functions {
+            self.env
+                .define_function(function.name.clone(), Self::function_type(function));
+        }
+
+        // Top-level assignments live in the global scope that function bodies see
+        if let Some(main) = &program.main_expression {
+            self.infer_expression_type(main);
+        }
+        for function in &program.functions {
+            self.check_function(function);
+        }
+
+        &self.errors
+    }
+
+    /// Type of the value `expr` produces, recording any type errors found inside it
+    pub fn infer_expression_type(&mut self, expr: &Expression) -> SusumuType {
+        match &expr.kind {
+            ExpressionKind::Integer(_) | ExpressionKind::Number(_) => SusumuType::Number,
+            ExpressionKind::String(_) => SusumuType::String,
+            ExpressionKind::InterpolatedString(parts) => {
+                for part in parts {
+                    if let StringPart::Expression(expression) = part {
+                        self.infer_expression_type(expression);
+                    }
+                }
+                SusumuType::String
+            }
+            ExpressionKind::Boolean(_) => SusumuType::Boolean,
+            ExpressionKind::Null => SusumuType::Null,
+            ExpressionKind::Identifier(name) => self
+                .env
+                .get_variable(name)
+                .or_else(|| self.env.get_function(name))
+                .cloned()
+                .unwrap_or(SusumuType::Unknown),
+            ExpressionKind::Tuple(elements) => SusumuType::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.infer_expression_type(e))
+                    .collect(),
+            ),
+            ExpressionKind::Array(elements) => {
+                let types: Vec<SusumuType> = elements
+                    .iter()
+                    .map(|e| self.infer_expression_type(e))
+                    .collect();
+                let element = match types.split_first() {
+                    Some((first, rest)) if rest.iter().all(|t| t == first) => first.clone(),
+                    _ => SusumuType::Unknown,
+                };
+                SusumuType::Array(Box::new(element))
+            }
+            ExpressionKind::Object(pairs) => SusumuType::Object(
+                pairs
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.infer_expression_type(value)))
+                    .collect(),
+            ),
+            ExpressionKind::ObjectMutation { target, mutations } => {
+                for (_, value) in mutations {
+                    self.infer_expression_type(value);
+                }
+                self.infer_expression_type(target)
+            }
+            ExpressionKind::ArrowChain {
+                expressions,
+                directions,
+            } => self.infer_arrow_chain(expressions, directions),
+            ExpressionKind::FunctionCall { name, args } => {
+                let arg_types: Vec<SusumuType> = args
+                    .iter()
+                    .map(|arg| self.infer_expression_type(arg))
+                    .collect();
+                self.check_call(name, args, &arg_types, expr.span)
+            }
+            ExpressionKind::Lambda { params, body } => {
+                let param_types = Self::param_types(params);
+                let outer_returns = self.returns.take();
+                self.in_scope(|checker| {
+                    for (param, param_type) in params.iter().zip(&param_types) {
+                        checker.bind_param(param, param_type);
+                    }
+                    checker.infer_expression_type(body);
+                });
+                self.returns = outer_returns;
+                SusumuType::function(param_types, SusumuType::Unknown, false)
+            }
+            ExpressionKind::Conditional {
+                condition_type,
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+            } => {
+                self.infer_condition(condition_type, condition);
+                let mut branches = vec![self.infer_expression_type(then_branch)];
+                for branch in else_if_branches {
+                    self.infer_condition(&branch.condition_type, &branch.condition);
+                    branches.push(self.infer_expression_type(&branch.then_branch));
+                }
+                match else_branch {
+                    Some(else_branch) => branches.push(self.infer_expression_type(else_branch)),
+                    None => branches.push(SusumuType::Null),
+                }
+                Self::common_type(&branches)
+            }
+            ExpressionKind::Return(value) | ExpressionKind::Success(value) => {
+                let value_type = self.infer_expression_type(value);
+                let expected = self.returns.as_ref().and_then(|r| r.success.clone());
+                if let Some(expected) = expected {
+                    self.check_return(&value_type, &expected, value.span);
+                }
+                SusumuType::Unknown
+            }
+            ExpressionKind::Error(value) | ExpressionKind::ErrorReturn(value) => {
+                let value_type = self.infer_expression_type(value);
+                let declared = self
+                    .returns
+                    .as_ref()
+                    .map(|r| r.errors.clone())
+                    .unwrap_or_default();
+                if !declared.is_empty() {
+                    let expected = match declared.len() {
+                        1 => declared[0].clone(),
+                        _ => SusumuType::Union(declared),
+                    };
+                    self.check_return(&value_type, &expected, value.span);
+                }
+                SusumuType::Unknown
+            }
+            ExpressionKind::ForEach {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                let element = match self.infer_expression_type(iterable) {
+                    SusumuType::Array(element) => *element,
+                    _ => SusumuType::Unknown,
+                };
+                let outer_control = std::mem::take(&mut self.loop_control);
+                // Each iteration runs in its own scope
+                let body_type = self.in_scope(|checker| {
+                    match pattern {
+                        Pattern::Identifier(variable) => {
+                            checker.env.define_variable(variable.clone(), element)
+                        }
+                        _ => checker.bind_pattern(pattern),
+                    }
+                    checker.infer_expression_type(body)
+                });
+                // A labelled `break` may leave the enclosing loops as well
+                let controlled = self.loop_control;
+                self.loop_control |= outer_control;
+                if controlled {
+                    SusumuType::Unknown
+                } else {
+                    SusumuType::Array(Box::new(body_type))
+                }
+            }
+            ExpressionKind::While {
+                condition, body, ..
+            } => {
+                self.infer_expression_type(condition);
+                self.infer_expression_type(body);
+                SusumuType::Unknown
+            }
+            ExpressionKind::Break { value, .. } | ExpressionKind::Continue { value, .. } => {
+                if let Some(value) = value {
+                    self.infer_expression_type(value);
+                }
+                self.loop_control = true;
+                SusumuType::Unknown
+            }
+            ExpressionKind::Block(expressions) => {
+                let mut last = SusumuType::Null;
+                for expression in expressions {
+                    last = self.infer_expression_type(expression);
+                }
+                last
+            }
+            ExpressionKind::Match { expr, cases } => {
+                if let Some(expr) = expr {
+                    self.infer_expression_type(expr);
+                }
+                self.infer_match_cases(cases);
+                SusumuType::Unknown
+            }
+            ExpressionKind::Maybe { value } => {
+                if let Some(value) = value {
+                    self.infer_expression_type(value);
+                }
+                SusumuType::Unknown
+            }
+            ExpressionKind::Result { is_success, value } => {
+                let value_type = self.infer_expression_type(value);
+                if *is_success {
+                    SusumuType::result(value_type, SusumuType::Unknown)
+                } else {
+                    SusumuType::result(SusumuType::Unknown, value_type)
+                }
+            }
+            ExpressionKind::Assignment { target, value, .. } => {
+                let value_type = self.infer_expression_type(value);
+                // A variable reassigned with another type could hold either
+                let stored = match self.env.get_variable(target) {
+                    Some(existing) if *existing != value_type => SusumuType::Unknown,
+                    _ => value_type.clone(),
+                };
+                self.env.define_variable(target.clone(), stored);
+                value_type
+            }
+            ExpressionKind::Destructure { pattern, value, .. } => {
+                let value_type = self.infer_expression_type(value);
+                self.bind_pattern(pattern);
+                value_type
+            }
+            ExpressionKind::PropertyAccess {
+                object, property, ..
+            } => match self.infer_expression_type(object) {
+                SusumuType::Object(fields) => fields
+                    .into_iter()
+                    .find(|(name, _)| name == property)
+                    .map_or(SusumuType::Unknown, |(_, field_type)| field_type),
+                _ => SusumuType::Unknown,
+            },
+            ExpressionKind::Index { object, .. } => match self.infer_expression_type(object) {
+                SusumuType::Array(item_type) => *item_type,
+                SusumuType::String => SusumuType::String,
+                _ => SusumuType::Unknown,
+            },
+            ExpressionKind::Slice { object, .. } => match self.infer_expression_type(object) {
+                sliced @ (SusumuType::Array(_) | SusumuType::String) => sliced,
+                _ => SusumuType::Unknown,
+            },
+            ExpressionKind::BinaryOp {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.infer_expression_type(left);
+                let right = self.infer_expression_type(right);
+                match operator {
+                    BinaryOperator::Add
+                        if left == SusumuType::String && right == SusumuType::String =>
+                    {
+                        SusumuType::String
+                    }
+                    BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide
+                    | BinaryOperator::Modulo
+                    | BinaryOperator::Power => {
+                        if left == SusumuType::Number && right == SusumuType::Number {
+                            SusumuType::Number
+                        } else {
+                            SusumuType::Unknown
+                        }
+                    }
+                    BinaryOperator::Range => SusumuType::Array(Box::new(SusumuType::Number)),
+                    BinaryOperator::Equal
+                    | BinaryOperator::NotEqual
+                    | BinaryOperator::Less
+                    | BinaryOperator::Greater
+                    | BinaryOperator::LessEq
+                    | BinaryOperator::GreaterEq
+                    | BinaryOperator::And
+                    | BinaryOperator::Or => SusumuType::Boolean,
+                }
+            }
+            ExpressionKind::UnaryOp { operator, operand } => match operator {
+                UnaryOperator::Not => SusumuType::Boolean,
+                UnaryOperator::Negate => match self.infer_expression_type(operand) {
+                    SusumuType::Number => SusumuType::Number,
+                    _ => SusumuType::Unknown,
+                },
+            },
+            ExpressionKind::Annotated { expression, .. } => self.infer_expression_type(expression),
+            ExpressionKind::ErrorPropagation { expression } => {
+                match self.infer_expression_type(expression) {
+                    SusumuType::Result { success_type, .. } => *success_type,
+                    other => other,
+                }
+            }
+            ExpressionKind::DefaultValue {
+                expression,
+                default,
+            } => {
+                let value = self.infer_expression_type(expression);
+                let default = self.infer_expression_type(default);
+                Self::common_type(&[value, default])
+            }
+        }
+    }
+
+    /// Type a function accepts as its first (arrow-fed) argument
+    pub fn function_input_type(&self, name: &str) -> SusumuType {
+        match self.callable_type(name) {
+            Some(SusumuType::Function { params, .. }) => {
+                params.first().cloned().unwrap_or(SusumuType::Unknown)
+            }
+            _ => SusumuType::Unknown,
+        }
+    }
+
+    fn check_function(&mut self, function: &FunctionDef) {
+        let param_types = Self::param_types(&function.params);
+        let (success, errors) = match &function.return_type {
+            Some(return_type) => (
+                return_type
+                    .success_type
+                    .as_ref()
+                    .map(SusumuType::from_annotation),
+                return_type
+                    .error_types
+                    .iter()
+                    .map(SusumuType::from_annotation)
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+
+        let outer_returns = self.returns.replace(ReturnContext {
+            function: function.name.clone(),
+            success: success.clone(),
+            errors,
+        });
+        let body_type = self.in_scope(|checker| {
+            for (param, param_type) in function.params.iter().zip(&param_types) {
+                checker.bind_param(param, param_type);
+            }
+            checker.infer_expression_type(&function.body)
+        });
+        // A body that does not `return` produces its last value
+        if let Some(success) = success {
+            self.check_return(&body_type, &success, function.body.span);
+        }
+        self.returns = outer_returns;
+    }
+
+    fn function_type(function: &FunctionDef) -> SusumuType {
+        let return_type = function
+            .return_type
+            .as_ref()
+            .and_then(|r| r.success_type.as_ref())
+            .map_or(SusumuType::Unknown, SusumuType::from_annotation);
+        SusumuType::function(Self::param_types(&function.params), return_type, false)
+    }
+
+    fn param_types(params: &[FunctionParam]) -> Vec<SusumuType> {
+        params
+            .iter()
+            .map(|param| {
+                param
+                    .type_annotation
+                    .as_ref()
+                    .map_or(SusumuType::Unknown, SusumuType::from_annotation)
+            })
+            .collect()
+    }
+
+    /// Function type a name refers to when called; variables holding functions shadow
+    /// named functions, like at runtime
+    fn callable_type(&self, name: &str) -> Option<SusumuType> {
+        match self.env.get_variable(name) {
+            Some(function @ SusumuType::Function { .. }) => Some(function.clone()),
+            _ => self.env.get_function(name).cloned(),
+        }
+    }
+
+    /// `x -> f <- a <- b` calls `f(x, a, b)`; mirrors the interpreter's chain rules
+    fn infer_arrow_chain(
+        &mut self,
+        expressions: &[Expression],
+        directions: &[ArrowDirection],
+    ) -> SusumuType {
+        let Some(first) = expressions.first() else {
+            return SusumuType::Null;
+        };
+        let mut current = self.infer_expression_type(first);
+
+        let mut i = 0;
+        while i < directions.len() {
+            let step = &expressions[i + 1];
+            if directions[i] == ArrowDirection::Backward {
+                // Fails at runtime before any call is made
+                for expression in &expressions[i + 1..] {
+                    self.infer_expression_type(expression);
+                }
+                return SusumuType::Unknown;
+            }
+
+            let mut end = i + 1;
+            while end < directions.len() && directions[end] == ArrowDirection::Backward {
+                end += 1;
+            }
+            let converging = &expressions[i + 2..=end];
+
+            match &step.kind {
+                ExpressionKind::Identifier(name) => {
+                    let mut args = vec![current];
+                    args.extend(converging.iter().map(|e| self.infer_expression_type(e)));
+                    current = self.check_flow(name, i + 1, step.span, converging, &args);
+                    i = end;
+                }
+                ExpressionKind::Match { expr: None, cases } => {
+                    self.infer_match_cases(cases);
+                    current = SusumuType::Unknown;
+                    i += 1;
+                }
+                ExpressionKind::Match { .. } | ExpressionKind::Conditional { .. } => {
+                    current = self.infer_expression_type(step);
+                    i += 1;
+                }
+                _ => match self.infer_expression_type(step) {
+                    SusumuType::Function { return_type, .. } => {
+                        for expression in converging {
+                            self.infer_expression_type(expression);
+                        }
+                        current = *return_type;
+                        i = end;
+                    }
+                    SusumuType::Unknown => {
+                        // Could be a function value receiving the flow
+                        for expression in converging {
+                            self.infer_expression_type(expression);
+                        }
+                        current = SusumuType::Unknown;
+                        i = end;
+                    }
+                    other => {
+                        current = other;
+                        i += 1;
+                    }
+                },
+            }
+        }
+
+        current
+    }
+
+    /// Check the values flowing into `name` at arrow step `step`: the piped value
+    /// followed by the `<-` arguments converging on it
+    fn check_flow(
+        &mut self,
+        name: &str,
+        step: usize,
+        span: Span,
+        converging: &[Expression],
+        args: &[SusumuType],
+    ) -> SusumuType {
+        let Some(SusumuType::Function {
+            params,
+            return_type,
+            supports_convergence,
+        }) = self.callable_type(name)
+        else {
+            return SusumuType::Unknown;
+        };
+
+        if !supports_convergence && params.len() != args.len() {
+            self.errors.push(TypeError {
+                span,
+                error_type: TypeErrorKind::ConvergenceError {
+                    function_name: name.to_string(),
+                    expected_types: params.clone(),
+                    actual_types: args.to_vec(),
+                },
+                suggestion: format!(
+                    "'{}' takes {} argument(s); the arrow flow supplies {}",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+            });
+            return *return_type;
+        }
+
+        let expected = |index: usize| {
+            params
+                .get(index)
+                .or(params.last())
+                .cloned()
+                .unwrap_or(SusumuType::Unknown)
+        };
+        if !args[0].may_flow_into(&expected(0)) {
+            self.errors.push(TypeError {
+                span,
+                error_type: TypeErrorKind::ArrowChainError {
+                    step,
+                    expected_input: expected(0),
+                    actual_input: args[0].clone(),
+                    function_name: name.to_string(),
+                },
+                suggestion: format!(
+                    "Convert {} to {} or use a different function",
+                    args[0].description(),
+                    expected(0).description()
+                ),
+            });
+        }
+        let mismatch = (1..args.len()).find(|&index| !args[index].may_flow_into(&expected(index)));
+        if let Some(index) = mismatch {
+            self.errors.push(TypeError {
+                span: converging[index - 1].span,
+                error_type: TypeErrorKind::ConvergenceError {
+                    function_name: name.to_string(),
+                    expected_types: (0..args.len()).map(expected).collect(),
+                    actual_types: args.to_vec(),
+                },
+                suggestion: format!(
+                    "Argument {} of '{}' should be {}",
+                    index + 1,
+                    name,
+                    expected(index).description()
+                ),
+            });
+        }
+
+        *return_type
+    }
+
+    /// Check a direct call `name(args)` against the function's parameters
+    fn check_call(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        arg_types: &[SusumuType],
+        span: Span,
+    ) -> SusumuType {
+        let Some(SusumuType::Function {
+            params,
+            return_type,
+            supports_convergence,
+        }) = self.callable_type(name)
+        else {
+            return SusumuType::Unknown;
+        };
+
+        if !supports_convergence && params.len() != args.len() {
+            self.errors.push(TypeError {
+                span,
+                error_type: TypeErrorKind::ArityMismatch {
+                    function_name: name.to_string(),
+                    expected: params.len(),
+                    found: args.len(),
+                },
+                suggestion: format!("Call '{}' with {} argument(s)", name, params.len()),
+            });
+            return *return_type;
+        }
+
+        for (index, (arg, arg_type)) in args.iter().zip(arg_types).enumerate() {
+            let expected = params
+                .get(index)
+                .or(params.last())
+                .cloned()
+                .unwrap_or(SusumuType::Unknown);
+            if !arg_type.may_flow_into(&expected) {
+                self.errors.push(TypeError {
+                    span: arg.span,
+                    error_type: TypeErrorKind::TypeMismatch {
+                        expected: expected.clone(),
+                        found: arg_type.clone(),
+                        context: format!("argument {} of '{}'", index + 1, name),
+                    },
+                    suggestion: format!(
+                        "Pass {} as argument {} of '{}'",
+                        expected.description(),
+                        index + 1,
+                        name
+                    ),
+                });
+            }
+        }
+
+        *return_type
+    }
+
+    fn check_return(&mut self, found: &SusumuType, expected: &SusumuType, span: Span) {
+        if found.may_flow_into(expected) {
+            return;
+        }
+        let function = self
+            .returns
+            .as_ref()
+            .map_or_else(String::new, |r| r.function.clone());
+        self.errors.push(TypeError {
+            span,
+            error_type: TypeErrorKind::TypeMismatch {
+                expected: expected.clone(),
+                found: found.clone(),
+                context: format!("return value of '{}'", function),
+            },
+            suggestion: format!(
+                "Return {} or change the declared return type of '{}'",
+                expected.description(),
+                function
+            ),
+        });
+    }
+
+    fn infer_condition(&mut self, condition_type: &ConditionType, condition: &Expression) {
+        self.infer_expression_type(condition);
+        if let ConditionType::Expression(expr) = condition_type {
+            self.infer_expression_type(expr);
+        }
+    }
+
+    fn infer_match_cases(&mut self, cases: &[MatchCase]) {
+        for case in cases {
+            self.in_scope(|checker| {
+                checker.bind_pattern(&case.pattern);
+                if let Some(guard) = &case.guard {
+                    checker.infer_expression_type(guard);
+                }
+                checker.infer_expression_type(&case.body);
+            });
+        }
+    }
+
+    /// Define the variables a match pattern binds; their types depend on the matched value
+    fn bind_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Identifier(name) => {
+                self.env.define_variable(name.clone(), SusumuType::Unknown)
+            }
+            Pattern::Tuple(patterns) => {
+                for pattern in patterns {
+                    self.bind_pattern(pattern);
+                }
+            }
+            Pattern::Object(fields) => {
+                for (_, pattern) in fields {
+                    self.bind_pattern(pattern);
+                }
+            }
+            Pattern::ArrowPattern { arg, .. } => self.bind_pattern(arg),
+            Pattern::Comparison { value, .. } => {
+                self.infer_expression_type(value);
+            }
+            Pattern::Literal(_) | Pattern::Wildcard => {}
+        }
+    }
+
+    fn bind_param(&mut self, param: &FunctionParam, param_type: &SusumuType) {
+        match &param.pattern {
+            Some(pattern) => self.bind_pattern(pattern),
+            None => self
+                .env
+                .define_variable(param.name.clone(), param_type.clone()),
+        }
+    }
+
+    /// Run `check` in a child scope of the current type environment
+    fn in_scope<T>(&mut self, check: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::take(&mut self.env);
+        self.env = TypeEnvironment::with_parent(outer);
+        let result = check(self);
+        let scope = std::mem::take(&mut self.env);
+        self.env = scope.parent.map(|parent| *parent).unwrap_or_default();
+        result
+    }
+
+    /// The type all branches share, or unknown when they differ
+    fn common_type(types: &[SusumuType]) -> SusumuType {
+        match types.split_first() {
+            Some((first, rest)) if rest.iter().all(|t| t == first) => first.clone(),
+            _ => SusumuType::Unknown,
+        }
+    }
+
     /// Generate a helpful error message with fix suggestions
     pub fn generate_error_message(&self, error: &TypeError) -> String {
         let mut message = String::new();
 
-        message.push_str(&format!(
-            "Type Error at line {}, column {}:\n",
-            error.line, error.column
-        ));
+        message.push_str(&format!("Type Error at {}:\n", error.span));
 
         match &error.error_type {
             TypeErrorKind::TypeMismatch {
@@ -580,6 +1389,17 @@ impl TypeChecker {
                     message.push_str(&format!("  Found:    {}\n", actual_type.description()));
                 }
             }
+
+            TypeErrorKind::ArityMismatch {
+                function_name,
+                expected,
+                found,
+            } => {
+                message.push_str(&format!(
+                    "  Function '{}' expects {} argument(s), but is called with {}\n",
+                    function_name, expected, found
+                ));
+            }
         }
 
         message.push_str(&format!("\n  💡 {}\n", error.suggestion));
@@ -641,6 +1461,14 @@ impl Default for TypeChecker {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(source: &str) -> Vec<TypeError> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        TypeChecker::new().check_program(&program).to_vec()
+    }
 
     #[test]
     fn test_type_assignability() {
@@ -670,4 +1498,107 @@ mod tests {
         assert!(result_type.is_result());
         assert_eq!(result_type.success_type(), Some(&SusumuType::String));
     }
+
+    #[test]
+    fn test_check_arrow_chain_steps() {
+        let errors = check("double(x: number) {\n return <- x * 2\n}\n\"five\" -> double -> print");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span::new(4, 11, 4, 17));
+        match &errors[0].error_type {
+            TypeErrorKind::ArrowChainError {
+                step,
+                expected_input,
+                actual_input,
+                function_name,
+            } => {
+                assert_eq!(*step, 1);
+                assert_eq!(expected_input, &SusumuType::Number);
+                assert_eq!(actual_input, &SusumuType::String);
+                assert_eq!(function_name, "double");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Declared return types flow on to the next step
+        let errors = check("name(x) -> string {\n return <- \"n\"\n}\n1 -> name -> add <- 2");
+        assert!(matches!(
+            errors[..],
+            [TypeError {
+                error_type: TypeErrorKind::ArrowChainError { step: 2, .. },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_capitalised_annotations_are_checked() {
+        let errors = check("shout(s: String) {\n return <- s\n}\n5 -> shout");
+        assert!(matches!(
+            &errors[..],
+            [TypeError { error_type: TypeErrorKind::ArrowChainError { expected_input, .. }, .. }]
+                if expected_input == &SusumuType::String
+        ));
+        assert_eq!(
+            SusumuType::from_annotation(&TypeAnnotation::Simple("Number".to_string())),
+            SusumuType::Number
+        );
+    }
+
+    #[test]
+    fn test_check_convergence_and_calls() {
+        let errors = check("pair(a, b) {\n return <- [a, b]\n}\n1 -> pair <- 2 <- 3");
+        assert!(matches!(
+            &errors[..],
+            [TypeError { error_type: TypeErrorKind::ConvergenceError { actual_types, .. }, .. }]
+                if actual_types.len() == 3
+        ));
+
+        let errors = check("5 -> add <- 1 <- \"two\"");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span::new(1, 18, 1, 23));
+
+        let errors = check("f(x: string) {\n return <- x\n}\n[f(1), f(\"a\", \"b\")]");
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0].error_type,
+            TypeErrorKind::TypeMismatch { .. }
+        ));
+        assert!(matches!(
+            errors[1].error_type,
+            TypeErrorKind::ArityMismatch {
+                expected: 1,
+                found: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_check_declared_return_types() {
+        let errors = check("count(x) -> number {\n return <- \"many\"\n}\ncount(1)");
+        assert!(matches!(
+            &errors[..],
+            [TypeError { error_type: TypeErrorKind::TypeMismatch { context, .. }, .. }]
+                if context == "return value of 'count'"
+        ));
+
+        let errors =
+            check("check(x) -> success: number, error: string {\n error <- 42\n}\ncheck(1)");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_check_leaves_unknown_types_alone() {
+        let source = r#"
+        process(items, f) {
+            return <- items -> map <- f
+        }
+        total = [1, 2, 3] -> process <- (x) => x * 2
+        label = total -> length
+        fe item in ["a", "b"] {
+            item -> length -> add <- 1
+        }
+        "#;
+        assert!(check(source).is_empty());
+    }
 }
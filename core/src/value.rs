@@ -0,0 +1,747 @@
+//! Runtime values for Susumu programs
+//!
+//! Values keep integers and floats apart, represent Maybe/Result natively and can hold
+//! function references and raw bytes. JSON is only used at the API boundary: plain JSON
+//! converts losslessly in both directions, and the native variants are written as the
+//! tagged objects Susumu has always produced (`{"type": "some", "value": ...}`).
+
+use crate::environment::Closure;
+use crate::error::{SusumuError, SusumuResult};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Object fields, kept in sorted key order
+pub type Map = BTreeMap<String, Value>;
+
+/// A Susumu runtime value
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Object(Map),
+    /// `some(value)` or `none`
+    Maybe(Option<Box<Value>>),
+    /// `success(value)` or `error(value)`
+    Result(Result<Box<Value>, Box<Value>>),
+    /// Reference to a builtin or user-defined function by name
+    Function(String),
+    /// An anonymous function. It keeps the scope it closes over alive, except while it is
+    /// assigned to a variable of that same scope, where it holds the scope weakly so the
+    /// two are freed together. A lambda kept in a collection in its own scope still
+    /// keeps that scope alive.
+    Lambda(Arc<Closure>),
+}
+
+/// Arithmetic operators shared by the binary operators and the math builtins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+}
+
+impl Value {
+    /// Wrap a value in `some`
+    pub fn some(value: Value) -> Self {
+        Value::Maybe(Some(Box::new(value)))
+    }
+
+    /// The `none` value
+    pub fn none() -> Self {
+        Value::Maybe(None)
+    }
+
+    /// Wrap a value in `success`
+    pub fn success(value: Value) -> Self {
+        Value::Result(Ok(Box::new(value)))
+    }
+
+    /// Wrap a value in `error`
+    pub fn error(value: Value) -> Self {
+        Value::Result(Err(Box::new(value)))
+    }
+
+    /// Reference a function by name
+    pub fn function(name: impl Into<String>) -> Self {
+        Value::Function(name.into())
+    }
+
+    /// Build an object from key/value pairs
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Value)>) -> Self {
+        Value::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Name of the value's type as reported by `type()`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) | Value::Float(_) => "number",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+            Value::Maybe(_) => "maybe",
+            Value::Result(_) => "result",
+            Value::Function(_) | Value::Lambda(_) => "function",
+        }
+    }
+
+    /// Susumu truthiness: null, false, zero and empty collections are false
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
+            Value::Array(a) => !a.is_empty(),
+            Value::Object(o) => !o.is_empty(),
+            Value::Maybe(m) => m.is_some(),
+            Value::Result(r) => r.is_ok(),
+            Value::Function(_) | Value::Lambda(_) => true,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Int(_) | Value::Float(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+
+    pub fn is_boolean(&self) -> bool {
+        matches!(self, Value::Bool(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Value::Array(_))
+    }
+
+    pub fn is_object(&self) -> bool {
+        matches!(self, Value::Object(_))
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer; floats are not truncated
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().and_then(|i| u64::try_from(i).ok())
+    }
+
+    /// The value as a float, widening integers
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Map> {
+        match self {
+            Value::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a named or anonymous function value
+    pub fn is_function(&self) -> bool {
+        matches!(self, Value::Function(_) | Value::Lambda(_))
+    }
+
+    /// Name of the referenced function if this is a named function value
+    pub fn as_function_name(&self) -> Option<&str> {
+        match self {
+            Value::Function(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Look up an object field
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_object().and_then(|o| o.get(key))
+    }
+
+    /// Null or `none`, which `?.` and `?[` pass on instead of accessing
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Value::Null | Value::Maybe(None))
+    }
+
+    /// `value.name`: an object field, or null if the object has no such field
+    pub fn property(&self, name: &str) -> SusumuResult<Value> {
+        match self {
+            Value::Object(fields) => Ok(fields.get(name).cloned().unwrap_or(Value::Null)),
+            other => Err(SusumuError::runtime_error(format!(
+                "Cannot read property '{}' of {}",
+                name,
+                other.type_name()
+            ))),
+        }
+    }
+
+    /// `value[index]`: an array item or a string's character, counting from the end
+    /// for negative indices, or an object field by name
+    pub fn index(&self, index: &Value) -> SusumuResult<Value> {
+        match (self, index) {
+            (Value::Array(items), Value::Int(i)) => {
+                let position = resolve_index(*i, items.len(), self, false)?;
+                Ok(items[position].clone())
+            }
+            (Value::String(s), Value::Int(i)) => {
+                let chars: Vec<char> = s.chars().collect();
+                let position = resolve_index(*i, chars.len(), self, false)?;
+                Ok(Value::String(chars[position].to_string()))
+            }
+            (Value::Object(fields), Value::String(key)) => {
+                Ok(fields.get(key).cloned().unwrap_or(Value::Null))
+            }
+            (Value::Array(_) | Value::String(_), other) => {
+                Err(SusumuError::type_error("integer index", other.type_name()))
+            }
+            (Value::Object(_), other) => {
+                Err(SusumuError::type_error("string key", other.type_name()))
+            }
+            (other, _) => Err(SusumuError::runtime_error(format!(
+                "Cannot index into {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    /// `value[start:end]`: the items or characters from `start` up to but not including
+    /// `end`. Negative bounds count from the end and missing ones take in everything.
+    pub fn slice(&self, start: Option<&Value>, end: Option<&Value>) -> SusumuResult<Value> {
+        let len = match self {
+            Value::Array(items) => items.len(),
+            Value::String(s) => s.chars().count(),
+            other => {
+                return Err(SusumuError::runtime_error(format!(
+                    "Cannot slice {}",
+                    other.type_name()
+                )))
+            }
+        };
+        let bound = |bound: Option<&Value>, default: usize| match bound {
+            None => Ok(default),
+            Some(Value::Int(i)) => resolve_index(*i, len, self, true),
+            Some(other) => Err(SusumuError::type_error("integer index", other.type_name())),
+        };
+        let (start, end) = (bound(start, 0)?, bound(end, len)?);
+        if start > end {
+            return Err(SusumuError::runtime_error(format!(
+                "Slice start {} is after its end {}",
+                start, end
+            )));
+        }
+        Ok(match self {
+            Value::Array(items) => Value::Array(items[start..end].to_vec()),
+            Value::String(s) => Value::String(s.chars().skip(start).take(end - start).collect()),
+            _ => unreachable!("only arrays and strings have a length"),
+        })
+    }
+
+    /// `start..end`: the integers from `start` up to but not including `end`, empty
+    /// when `end` is not past `start`
+    pub fn range(start: &Value, end: &Value) -> SusumuResult<std::ops::Range<i64>> {
+        match (start, end) {
+            (Value::Int(start), Value::Int(end)) => Ok(*start..*end),
+            (Value::Int(_), other) | (other, _) => Err(SusumuError::type_error(
+                "integer range bound",
+                other.type_name(),
+            )),
+        }
+    }
+
+    /// Apply an arithmetic operator to two numbers. Integer operands give an integer
+    /// result unless a division does not come out even or the result overflows an i64;
+    /// any float operand gives a float.
+    pub fn arithmetic(&self, op: NumericOp, other: &Value) -> SusumuResult<Value> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => {
+                let (a, b) = (*a, *b);
+                if b == 0 && matches!(op, NumericOp::Divide | NumericOp::Modulo) {
+                    return Err(zero_divisor(op));
+                }
+                // Results that do not fit an i64 are promoted to floats
+                let result = match op {
+                    NumericOp::Add => a.checked_add(b),
+                    NumericOp::Subtract => a.checked_sub(b),
+                    NumericOp::Multiply => a.checked_mul(b),
+                    NumericOp::Divide => match a.checked_rem(b) {
+                        Some(0) => a.checked_div(b),
+                        _ => None,
+                    },
+                    NumericOp::Modulo => Some(a.wrapping_rem(b)),
+                    // A negative exponent gives a fraction
+                    NumericOp::Power => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+                };
+                Ok(result.map_or_else(
+                    || Value::Float(float_arithmetic(op, a as f64, b as f64)),
+                    Value::Int,
+                ))
+            }
+            _ => {
+                let (a, b) = match (self.as_f64(), other.as_f64()) {
+                    (Some(a), Some(b)) => (a, b),
+                    (None, _) => return Err(SusumuError::type_error("number", self.type_name())),
+                    (_, None) => return Err(SusumuError::type_error("number", other.type_name())),
+                };
+                if b == 0.0 && matches!(op, NumericOp::Divide | NumericOp::Modulo) {
+                    return Err(zero_divisor(op));
+                }
+                Ok(Value::Float(float_arithmetic(op, a, b)))
+            }
+        }
+    }
+
+    /// Convert to JSON, writing Maybe, Result, function and byte values as tagged objects
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => json!(b),
+            Value::Int(i) => json!(i),
+            Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Value::String(s) => json!(s),
+            Value::Bytes(bytes) => json!({ "type": "bytes", "value": bytes }),
+            Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(Value::to_json).collect())
+            }
+            Value::Object(fields) => serde_json::Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.to_json()))
+                    .collect(),
+            ),
+            Value::Maybe(Some(value)) => json!({ "type": "some", "value": value.to_json() }),
+            Value::Maybe(None) => json!({ "type": "none" }),
+            Value::Result(Ok(value)) => json!({ "type": "success", "value": value.to_json() }),
+            Value::Result(Err(value)) => json!({ "type": "error", "value": value.to_json() }),
+            Value::Function(name) => json!({ "type": "function", "name": name }),
+            Value::Lambda(closure) => json!({ "type": "function", "name": closure.def.name }),
+        }
+    }
+
+    /// Convert from JSON, decoding the tagged objects written by [`Value::to_json`].
+    /// Use `Value::from` instead when the JSON is user data whose objects should stay objects.
+    pub fn from_tagged_json(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Array(items) => {
+                Value::Array(items.into_iter().map(Value::from_tagged_json).collect())
+            }
+            serde_json::Value::Object(fields) => {
+                let tag = fields
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .map(str::to_string);
+                let expected_len = match tag.as_deref() {
+                    Some("none") => 1,
+                    Some("some" | "success" | "error" | "bytes" | "function") => 2,
+                    _ => 0,
+                };
+                if expected_len > 0 && fields.len() == expected_len {
+                    let value = fields.get("value").cloned();
+                    let name = fields.get("name").cloned();
+                    let decoded = match (tag.as_deref(), value, name) {
+                        (Some("none"), _, _) => Some(Value::none()),
+                        (Some("some"), Some(v), _) => Some(Value::some(Value::from_tagged_json(v))),
+                        (Some("success"), Some(v), _) => {
+                            Some(Value::success(Value::from_tagged_json(v)))
+                        }
+                        (Some("error"), Some(v), _) => {
+                            Some(Value::error(Value::from_tagged_json(v)))
+                        }
+                        (Some("function"), _, Some(serde_json::Value::String(name))) => {
+                            Some(Value::Function(name))
+                        }
+                        (Some("bytes"), Some(serde_json::Value::Array(items)), _) => items
+                            .iter()
+                            .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+                            .collect::<Option<Vec<u8>>>()
+                            .map(Value::Bytes),
+                        _ => None,
+                    };
+                    if let Some(decoded) = decoded {
+                        return decoded;
+                    }
+                }
+                Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(k, v)| (k, Value::from_tagged_json(v)))
+                        .collect(),
+                )
+            }
+            other => Value::from(other),
+        }
+    }
+}
+
+/// Numbers compare by value regardless of representation, so `1 == 1.0`
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                self.as_f64() == other.as_f64()
+            }
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::Maybe(a), Value::Maybe(b)) => a == b,
+            (Value::Result(a), Value::Result(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Lambda(a), Value::Lambda(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Position of a possibly negative index into something of length `len`. A slice
+/// bound may also point just past the end.
+fn resolve_index(index: i64, len: usize, indexed: &Value, bound: bool) -> SusumuResult<usize> {
+    let position = if index < 0 {
+        index.checked_add(len as i64)
+    } else {
+        Some(index)
+    };
+    match position {
+        Some(position) if (0..len as i64 + bound as i64).contains(&position) => {
+            Ok(position as usize)
+        }
+        _ => Err(SusumuError::runtime_error(format!(
+            "Index {} is out of range for {} of length {}",
+            index,
+            indexed.type_name(),
+            len
+        ))),
+    }
+}
+
+fn float_arithmetic(op: NumericOp, a: f64, b: f64) -> f64 {
+    match op {
+        NumericOp::Add => a + b,
+        NumericOp::Subtract => a - b,
+        NumericOp::Multiply => a * b,
+        NumericOp::Divide => a / b,
+        NumericOp::Modulo => a % b,
+        NumericOp::Power => a.powf(b),
+    }
+}
+
+fn zero_divisor(op: NumericOp) -> SusumuError {
+    match op {
+        NumericOp::Modulo => SusumuError::runtime_error("Modulo by zero"),
+        _ => SusumuError::runtime_error("Division by zero"),
+    }
+}
+
+/// Numbers, strings and booleans are ordered; other values are unordered
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                self.as_f64()?.partial_cmp(&other.as_f64()?)
+            }
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            _ if self == other => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+/// Human-readable form used by `print` and string conversion
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bytes(b) => write!(f, "<{} bytes>", b.len()),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Maybe(Some(value)) => write!(f, "some({})", value),
+            Value::Maybe(None) => write!(f, "none"),
+            Value::Result(Ok(value)) => write!(f, "success({})", value),
+            Value::Result(Err(value)) => write!(f, "error({})", value),
+            Value::Function(name) => write!(f, "<function {}>", name),
+            Value::Lambda(closure) => write!(f, "<function {}>", closure.def.name),
+        }
+    }
+}
+
+impl From<serde_json::Value> for Value {
+    fn from(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Array(items) => {
+                Value::Array(items.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> Self {
+        value.to_json()
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i64::from(i))
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        i64::try_from(n).map_or(Value::Float(n as f64), Value::Int)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        i64::try_from(n).map_or(Value::Float(n as f64), Value::Int)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<Map> for Value {
+    fn from(fields: Map) -> Self {
+        Value::Object(fields)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(items) => serializer.collect_seq(items),
+            Value::Object(fields) => serializer.collect_map(fields),
+            _ => self.to_json().serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde_json::Value::deserialize(deserializer).map(Value::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_round_trip() {
+        let json = json!({"count": 3, "ratio": 0.5, "whole": 2.0, "tags": ["a", null, true]});
+        let value = Value::from(json.clone());
+
+        assert_eq!(value.get("count"), Some(&Value::Int(3)));
+        assert!(matches!(value.get("whole"), Some(Value::Float(_))));
+        assert_eq!(value.to_json(), json);
+        assert_eq!(serde_json::to_string(&value).unwrap(), json.to_string());
+    }
+
+    #[test]
+    fn test_native_variants_use_tagged_json() {
+        let value = Value::Array(vec![
+            Value::some(Value::Int(1)),
+            Value::none(),
+            Value::error(Value::from("boom")),
+            Value::function("double"),
+            Value::Bytes(vec![0, 255]),
+        ]);
+        let json = value.to_json();
+
+        assert_eq!(json[0], json!({"type": "some", "value": 1}));
+        assert_eq!(json[2], json!({"type": "error", "value": "boom"}));
+        assert_eq!(Value::from_tagged_json(json.clone()), value);
+
+        // Plain conversion leaves user objects alone even if they look tagged
+        assert!(Value::from(json).as_array().unwrap()[0].is_object());
+    }
+
+    #[test]
+    fn test_arithmetic_keeps_integers() {
+        let seven = Value::Int(7);
+        assert!(matches!(
+            seven.arithmetic(NumericOp::Multiply, &Value::Int(6)),
+            Ok(Value::Int(42))
+        ));
+        assert!(matches!(
+            seven.arithmetic(NumericOp::Divide, &Value::Int(2)),
+            Ok(Value::Float(f)) if f == 3.5
+        ));
+        assert!(matches!(
+            seven.arithmetic(NumericOp::Add, &Value::Float(0.5)),
+            Ok(Value::Float(f)) if f == 7.5
+        ));
+        assert!(seven.arithmetic(NumericOp::Modulo, &Value::Int(0)).is_err());
+    }
+
+    #[test]
+    fn test_integer_overflow_promotes_to_float() {
+        let max = Value::Int(i64::MAX);
+        assert!(matches!(
+            max.arithmetic(NumericOp::Add, &Value::Int(1)),
+            Ok(Value::Float(f)) if f == 9223372036854775808.0
+        ));
+        assert!(matches!(
+            max.arithmetic(NumericOp::Multiply, &Value::Int(2)),
+            Ok(Value::Float(_))
+        ));
+
+        let min = Value::Int(i64::MIN);
+        assert!(matches!(
+            min.arithmetic(NumericOp::Divide, &Value::Int(-1)),
+            Ok(Value::Float(f)) if f == 9223372036854775808.0
+        ));
+        assert!(matches!(
+            min.arithmetic(NumericOp::Modulo, &Value::Int(-1)),
+            Ok(Value::Int(0))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_reads_plain_json() {
+        let value: Value = serde_json::from_str(r#"{"type": "some", "value": 1}"#).unwrap();
+        assert_eq!(value.get("type"), Some(&Value::from("some")));
+        assert_eq!(value.get("value"), Some(&Value::Int(1)));
+    }
+
+    #[test]
+    fn test_numbers_compare_by_value() {
+        assert_eq!(Value::Int(2), Value::Float(2.0));
+        assert_ne!(Value::Int(2), Value::from("2"));
+        assert_eq!(Value::Float(5.0).to_string(), "5");
+        assert_eq!(serde_json::to_string(&Value::Float(5.0)).unwrap(), "5.0");
+    }
+}
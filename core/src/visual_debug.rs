@@ -4,7 +4,7 @@ use crate::interpreter::{ExecutionStepType, ExecutionTrace};
 use crate::parser::ArrowFlowPath;
 // use crate::types::SusumuType;
 // use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use crate::value::Value;
 use std::collections::HashMap;
 
 /// Visual debugging session that tracks execution flow
@@ -518,12 +518,10 @@ impl VisualDebugger {
 
     fn value_to_display(&self, value: &Value) -> String {
         match value {
-            Value::Null => "null".to_string(),
-            Value::Bool(b) => b.to_string(),
-            Value::Number(n) => n.to_string(),
             Value::String(s) => format!("\"{}\"", s),
             Value::Array(a) => format!("[{} items]", a.len()),
             Value::Object(o) => format!("{{object: {} fields}}", o.len()),
+            other => other.to_string(),
         }
     }
 }
@@ -0,0 +1,1080 @@
+//! Stack virtual machine for compiled Susumu bytecode
+//!
+//! Each call runs its [`Chunk`] in a fresh [`Frame`]: an operand stack, the function's
+//! local slots and the stack of scope environments. Calls between functions go through
+//! the interpreter, so builtins, native functions and the tree-walker fallbacks all
+//! reach compiled user functions the same way.
+
+use crate::ast::ExpressionKind;
+use crate::bytecode::{Chunk, Instruction, RaiseKind, Target};
+use crate::environment::Environment;
+use crate::error::{Location, SusumuError, SusumuResult};
+use crate::interpreter::Interpreter;
+use crate::value::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Run a call with the interpreter pointing traces, errors and stack frames at `location`
+fn located<T>(
+    interpreter: &mut Interpreter,
+    location: Location,
+    call: impl FnOnce(&mut Interpreter) -> T,
+) -> T {
+    let outer = interpreter.enter_location(location);
+    let result = call(interpreter);
+    interpreter.restore_location(outer);
+    result
+}
+
+/// A compiled function together with the scope it closes over
+#[derive(Debug)]
+pub(crate) struct CompiledFunction {
+    pub chunk: Arc<Chunk>,
+    /// Captured defining scope; `None` for top-level functions, which see the global scope
+    pub env: Option<Arc<Environment>>,
+}
+
+/// Call a compiled function, unwinding `return` and friends like the tree-walker does
+pub(crate) fn call(
+    interpreter: &mut Interpreter,
+    name: &str,
+    function: &CompiledFunction,
+    args: &[Value],
+) -> SusumuResult<Value> {
+    let chunk = &function.chunk;
+    if args.len() != chunk.params.len() {
+        return Err(SusumuError::function_call_error(format!(
+            "Function {} expects {} arguments, got {}",
+            name,
+            chunk.params.len(),
+            args.len()
+        )));
+    }
+
+    let defining_env = function
+        .env
+        .clone()
+        .unwrap_or_else(|| interpreter.global_env());
+    let env = if chunk.function_env {
+        Arc::new(Environment::with_parent(defining_env))
+    } else {
+        defining_env
+    };
+
+    let mut frame = Frame::new(chunk, env);
+    for (target, arg) in chunk.params.iter().zip(args) {
+        frame.bind(*target, arg.clone(), false);
+    }
+
+    let call_site = interpreter.current_location();
+    match frame.run(interpreter) {
+        Ok(value) => Ok(value),
+        Err(SusumuError::ReturnValue { value }) => Ok(value),
+        Err(SusumuError::SuccessReturn { value }) => Ok(value),
+        Err(SusumuError::ErrorReturn { value }) => {
+            Err(SusumuError::user_error(value).with_frame(name, call_site))
+        }
+        Err(other) => Err(other.with_frame(name, call_site)),
+    }
+}
+
+/// Run a main-expression chunk in the given (global) scope
+pub(crate) fn run_main(
+    interpreter: &mut Interpreter,
+    chunk: &Chunk,
+    env: Arc<Environment>,
+) -> SusumuResult<Value> {
+    Frame::new(chunk, env).run(interpreter)
+}
+
+/// State of a running `fe` or `w` loop
+struct Loop {
+    label: Option<u32>,
+    /// Items a `fe` loop has yet to visit; `w` loops have none
+    items: Option<Box<dyn Iterator<Item = Value>>>,
+    results: Vec<Value>,
+    /// Value of a `break <- value` that ended a `fe` loop
+    broken: Option<Value>,
+    /// Where `continue` resumes and where `break` leaves the loop
+    next: usize,
+    exit: usize,
+    stack_len: usize,
+    envs_len: usize,
+    handlers_len: usize,
+}
+
+/// Where to resume when an error reaches a `|` default
+struct Handler {
+    target: usize,
+    stack_len: usize,
+    envs_len: usize,
+    loops_len: usize,
+}
+
+/// Execution state of one chunk invocation
+struct Frame<'c> {
+    chunk: &'c Chunk,
+    stack: Vec<Value>,
+    slots: Vec<Option<Value>>,
+    envs: Vec<Arc<Environment>>,
+    loops: Vec<Loop>,
+    handlers: Vec<Handler>,
+    /// Bindings of the last successful pattern match
+    bindings: HashMap<String, Value>,
+}
+
+impl<'c> Frame<'c> {
+    fn new(chunk: &'c Chunk, env: Arc<Environment>) -> Self {
+        Self {
+            chunk,
+            stack: Vec::with_capacity(16),
+            slots: vec![None; chunk.slot_count()],
+            envs: vec![env],
+            loops: Vec::new(),
+            handlers: Vec::new(),
+            bindings: HashMap::new(),
+        }
+    }
+
+    fn env(&self) -> &Arc<Environment> {
+        self.envs.last().expect("frame without environment")
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    fn pop_n(&mut self, count: u32) -> Vec<Value> {
+        let at = self.stack.len() - count as usize;
+        self.stack.split_off(at)
+    }
+
+    fn name(&self, index: u32) -> &'c str {
+        &self.chunk.names[index as usize]
+    }
+
+    fn bind(&mut self, target: Target, value: Value, mutable: bool) {
+        match target {
+            Target::Local(slot) => self.slots[slot as usize] = Some(value),
+            Target::Name(name) => {
+                self.env()
+                    .define_with_mutability(self.name(name).to_string(), value, mutable)
+            }
+        }
+    }
+
+    /// Value of a slot, or of the slot it shadows while it is unset
+    fn local(&self, slot: u32) -> Result<&Value, &'c str> {
+        let mut slot = slot as usize;
+        loop {
+            if let Some(value) = &self.slots[slot] {
+                return Ok(value);
+            }
+            let info = &self.chunk.slots[slot];
+            match info.shadows {
+                Some(outer) => slot = outer as usize,
+                None => return Err(self.name(info.name)),
+            }
+        }
+    }
+
+    fn run(&mut self, interpreter: &mut Interpreter) -> SusumuResult<Value> {
+        let mut pc = 0;
+        loop {
+            let at = pc;
+            let result = self.step(interpreter, &mut pc).and_then(|done| {
+                // Every value an instruction produces passes through the top of the stack
+                let top = self.stack.last();
+                match top.and_then(|value| interpreter.options().limits.check_size(value)) {
+                    Some(limit) => Err(SusumuError::limit_exceeded(limit)),
+                    None => Ok(done),
+                }
+            });
+
+            match result {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                // Running out of a resource or permission is not a failure `|` defaults
+                // can handle
+                Err(error) if error.is_uncatchable() => {
+                    return Err(error.with_span(self.chunk.locations[at].span))
+                }
+                // `break` and `continue` pass `|` defaults by on their way to the loop
+                Err(error) if error.is_loop_control() => match self.unwind_to_loop(error) {
+                    Ok(target) => pc = target,
+                    Err(error) => return Err(error.with_span(self.chunk.locations[at].span)),
+                },
+                Err(error) => match self.handlers.pop() {
+                    Some(handler) => {
+                        self.stack.truncate(handler.stack_len);
+                        self.envs.truncate(handler.envs_len);
+                        self.loops.truncate(handler.loops_len);
+                        pc = handler.target;
+                    }
+                    None => return Err(error.with_span(self.chunk.locations[at].span)),
+                },
+            }
+        }
+    }
+
+    fn start_loop(
+        &mut self,
+        label: Option<u32>,
+        items: Option<Box<dyn Iterator<Item = Value>>>,
+        next: usize,
+        exit: u32,
+    ) {
+        self.loops.push(Loop {
+            label,
+            items,
+            results: Vec::new(),
+            broken: None,
+            next,
+            exit: exit as usize,
+            stack_len: self.stack.len(),
+            envs_len: self.envs.len(),
+            handlers_len: self.handlers.len(),
+        });
+    }
+
+    /// Unwind to the loop a `break` or `continue` is aimed at and give where it goes on,
+    /// or give the error back when no loop of this frame is its target
+    fn unwind_to_loop(&mut self, error: SusumuError) -> Result<usize, SusumuError> {
+        let label = match &error {
+            SusumuError::Break { label, .. } | SusumuError::Continue { label, .. } => label,
+            _ => return Err(error),
+        };
+        let target = self.loops.iter().rposition(|current| {
+            label.is_none() || current.label.map(|name| self.name(name)) == label.as_deref()
+        });
+        let Some(index) = target else {
+            return Err(error);
+        };
+
+        self.loops.truncate(index + 1);
+        let current = self.loops.last_mut().expect("no active loop");
+        self.stack.truncate(current.stack_len);
+        self.envs.truncate(current.envs_len);
+        self.handlers.truncate(current.handlers_len);
+
+        let is_break = matches!(error, SusumuError::Break { .. });
+        let value = match error {
+            SusumuError::Break { value, .. } | SusumuError::Continue { value, .. } => value,
+            _ => None,
+        };
+        match (&current.items, value) {
+            (Some(_), Some(value)) if is_break => current.broken = Some(value),
+            (Some(_), Some(value)) => current.results.push(value),
+            // A `w` loop's last result sits below its body
+            (None, Some(value)) => *self.stack.last_mut().expect("no loop result") = value,
+            (_, None) => {}
+        }
+        Ok(if is_break { current.exit } else { current.next })
+    }
+
+    /// Execute one instruction; returns the result when the chunk finishes
+    fn step(
+        &mut self,
+        interpreter: &mut Interpreter,
+        pc: &mut usize,
+    ) -> SusumuResult<Option<Value>> {
+        let chunk = self.chunk;
+        let instruction = &chunk.code[*pc];
+        *pc += 1;
+        interpreter.tick()?;
+
+        match instruction {
+            Instruction::Constant(index) => self.push(chunk.constants[*index as usize].clone()),
+            Instruction::Pop => {
+                self.pop();
+            }
+            Instruction::Dup => {
+                let top = self.stack.last().expect("operand stack underflow").clone();
+                self.push(top);
+            }
+            Instruction::Swap => {
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - 2);
+            }
+
+            Instruction::LoadLocal(slot) => {
+                let value = match self.local(*slot) {
+                    Ok(value) => value.clone(),
+                    Err(name) => {
+                        let env = self.env().clone();
+                        interpreter.lookup_identifier(name, &env)?
+                    }
+                };
+                self.push(value);
+            }
+            Instruction::StoreLocal(slot) => {
+                let value = self.pop();
+                self.slots[*slot as usize] = Some(value);
+            }
+            Instruction::LoadName(name) => {
+                let env = self.env().clone();
+                let value = interpreter.lookup_identifier(self.name(*name), &env)?;
+                self.push(value);
+            }
+            Instruction::DefineName { name, mutable } => {
+                let value = self.pop();
+                self.env()
+                    .define_with_mutability(self.name(*name).to_string(), value, *mutable);
+            }
+
+            Instruction::Interpolate(count) => {
+                let parts = self.pop_n(*count);
+                let text: String = parts.iter().map(Value::to_string).collect();
+                self.push(Value::String(text));
+            }
+            Instruction::MakeArray(count) => {
+                let items = self.pop_n(*count);
+                self.push(Value::Array(items));
+            }
+            Instruction::MakeObject(shape) => {
+                let keys = &chunk.shapes[*shape as usize];
+                let values = self.pop_n(keys.len() as u32);
+                let object: Map = keys.iter().cloned().zip(values).collect();
+                self.push(Value::Object(object));
+            }
+            Instruction::MakeSome => {
+                let value = self.pop();
+                self.push(Value::some(value));
+            }
+            Instruction::MakeSuccess => {
+                let value = self.pop();
+                self.push(Value::success(value));
+            }
+            Instruction::MakeError => {
+                let value = self.pop();
+                self.push(Value::error(value));
+            }
+            Instruction::MakeLambda(index) => {
+                let lambda = &chunk.lambdas[*index as usize];
+                let env = self.env().clone();
+                let value = interpreter.define_compiled_lambda(lambda, env);
+                self.push(value);
+            }
+
+            Instruction::GetProperty(name) => {
+                let value = self.pop().property(self.name(*name))?;
+                self.push(value);
+            }
+            Instruction::GetIndex => {
+                let index = self.pop();
+                let value = self.pop().index(&index)?;
+                self.push(value);
+            }
+            Instruction::GetSlice { start, end } => {
+                let end = end.then(|| self.pop());
+                let start = start.then(|| self.pop());
+                let value = self.pop().slice(start.as_ref(), end.as_ref())?;
+                self.push(value);
+            }
+            Instruction::SkipIfAbsent(target) => {
+                if self.stack.last().is_some_and(Value::is_absent) {
+                    self.pop();
+                    self.push(Value::Null);
+                    *pc = *target as usize;
+                }
+            }
+            Instruction::Binary(operator) => {
+                let right = self.pop();
+                let left = self.pop();
+                let value = interpreter.evaluate_binary_op(&left, operator, &right)?;
+                self.push(value);
+            }
+            Instruction::Unary(operator) => {
+                let operand = self.pop();
+                let value = interpreter.evaluate_unary_op(operator, &operand)?;
+                self.push(value);
+            }
+
+            Instruction::CallName { name, argc } => {
+                let args = self.pop_n(*argc);
+                let env = self.env().clone();
+                let name = self.name(*name);
+                let value = located(interpreter, chunk.locations[*pc - 1], |interpreter| {
+                    interpreter.call_function_with_args(name, &args, &env)
+                })?;
+                self.push(value);
+            }
+            Instruction::CallLocal { slot, name, argc } => {
+                let args = self.pop_n(*argc);
+                // A local holding a function value shadows the function of the same name
+                let callee = self
+                    .local(*slot)
+                    .ok()
+                    .filter(|value| value.is_function())
+                    .cloned();
+                let name = self.name(*name);
+                let env = self.env().clone();
+                let value =
+                    located(
+                        interpreter,
+                        chunk.locations[*pc - 1],
+                        |interpreter| match &callee {
+                            Some(callee) => interpreter.call_callable(callee, &args, &env),
+                            None => interpreter.call_function_with_args(name, &args, &env),
+                        },
+                    )?;
+                self.push(value);
+            }
+            Instruction::CallValue { argc } => {
+                let args = self.pop_n(*argc);
+                let callee = self.pop();
+                let env = self.env().clone();
+                let value = located(interpreter, chunk.locations[*pc - 1], |interpreter| {
+                    interpreter.call_callable(&callee, &args, &env)
+                })?;
+                self.push(value);
+            }
+            Instruction::FlowInto(target) => {
+                let value = self.pop();
+                if value.is_function() {
+                    self.push(value);
+                } else {
+                    self.pop();
+                    self.push(value);
+                    *pc = *target as usize;
+                }
+            }
+            Instruction::UnexpectedBackwardArrow => {
+                return Err(SusumuError::arrow_chain_error(
+                    "Unexpected backward arrow - convergence should be handled by forward arrow processing"
+                ));
+            }
+
+            Instruction::Jump(target) => *pc = *target as usize,
+            Instruction::JumpIfFalse(target) => {
+                if !self.pop().is_truthy() {
+                    *pc = *target as usize;
+                }
+            }
+            Instruction::TestCondition(index) => {
+                let value = self.stack.last().expect("operand stack underflow");
+                let passed =
+                    interpreter.test_condition(&chunk.conditions[*index as usize], value)?;
+                self.push(Value::Bool(passed));
+            }
+            Instruction::Return => return Ok(Some(self.pop())),
+            Instruction::Raise(kind) => {
+                let value = self.pop();
+                return Err(match kind {
+                    RaiseKind::Return => SusumuError::return_value(value),
+                    RaiseKind::Success => SusumuError::success_return(value),
+                    RaiseKind::Error => SusumuError::user_error(value),
+                    RaiseKind::ErrorReturn => SusumuError::error_return(value),
+                });
+            }
+
+            Instruction::EnterScope(index) => {
+                let scope = &chunk.scopes[*index as usize];
+                let first = scope.first_slot as usize;
+                for slot in &mut self.slots[first..first + scope.slot_count as usize] {
+                    *slot = None;
+                }
+                if scope.has_env {
+                    let env = Arc::new(Environment::with_parent(self.env().clone()));
+                    self.envs.push(env);
+                }
+            }
+            Instruction::ExitScope(index) => {
+                if chunk.scopes[*index as usize].has_env {
+                    self.envs.pop();
+                }
+            }
+
+            Instruction::ForEachStart { label, exit } => match self.pop() {
+                Value::Array(items) => {
+                    self.start_loop(*label, Some(Box::new(items.into_iter())), *pc, *exit)
+                }
+                other => return Err(SusumuError::type_error("array", format!("{:?}", other))),
+            },
+            Instruction::ForEachRange { label, exit } => {
+                let end = self.pop();
+                let start = self.pop();
+                let range = Value::range(&start, &end)?.map(Value::Int);
+                self.start_loop(*label, Some(Box::new(range)), *pc, *exit);
+            }
+            Instruction::ForEachNext(exit) => {
+                let current = self.loops.last_mut().expect("no active loop");
+                match current.items.as_mut().and_then(Iterator::next) {
+                    Some(item) => self.push(item),
+                    None => *pc = *exit as usize,
+                }
+            }
+            Instruction::ForEachCollect => {
+                let value = self.pop();
+                let current = self.loops.last_mut().expect("no active loop");
+                current.results.push(value);
+                interpreter.check_len(current.results.len())?;
+            }
+            Instruction::ForEachEnd => {
+                let finished = self.loops.pop().expect("no active loop");
+                let result = finished.broken.unwrap_or(Value::Array(finished.results));
+                self.push(result);
+            }
+            Instruction::WhileStart { label, exit } => self.start_loop(*label, None, *pc, *exit),
+            Instruction::WhileEnd => {
+                self.loops.pop();
+            }
+            Instruction::Break { label, value } | Instruction::Continue { label, value } => {
+                let label = label.map(|name| self.name(name).to_string());
+                let value = value.then(|| self.pop());
+                return Err(match instruction {
+                    Instruction::Break { .. } => SusumuError::loop_break(label, value),
+                    _ => SusumuError::loop_continue(label, value),
+                });
+            }
+
+            Instruction::MatchPattern { pattern, fail } => {
+                let value = self.stack.last().expect("operand stack underflow");
+                match interpreter.match_pattern(&chunk.patterns[*pattern as usize], value) {
+                    Some(bindings) => self.bindings = bindings,
+                    None => *pc = *fail as usize,
+                }
+            }
+            Instruction::Destructure(pattern) => {
+                let value = self.stack.last().expect("operand stack underflow");
+                self.bindings =
+                    interpreter.destructure(&chunk.patterns[*pattern as usize], value)?;
+            }
+            Instruction::Bind {
+                name,
+                target,
+                mutable,
+            } => {
+                if let Some(value) = self.bindings.remove(self.name(*name)) {
+                    self.bind(*target, value, *mutable);
+                }
+            }
+            Instruction::NoMatch => return Err(SusumuError::runtime_error("No pattern matched")),
+
+            Instruction::PushHandler(target) => self.handlers.push(Handler {
+                target: *target as usize,
+                stack_len: self.stack.len(),
+                envs_len: self.envs.len(),
+                loops_len: self.loops.len(),
+            }),
+            Instruction::PopHandler => {
+                self.handlers.pop();
+            }
+
+            Instruction::Eval(index) => {
+                let env = self.env().clone();
+                let value = interpreter.evaluate(&chunk.fallbacks[*index as usize], &env)?;
+                self.push(value);
+            }
+            Instruction::EvalMatch(index) => {
+                let value = self.pop();
+                let env = self.env().clone();
+                let cases = match &chunk.fallbacks[*index as usize].kind {
+                    ExpressionKind::Match { cases, .. } => cases,
+                    other => unreachable!("EvalMatch on {:?}", other),
+                };
+                let value = interpreter.evaluate_match_with_value(&value, cases, &env)?;
+                self.push(value);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SusumuError;
+    use crate::interpreter::{Backend, Interpreter, InterpreterOptions};
+    use crate::lexer::Lexer;
+    use crate::limits::{ExecutionLimits, Limit};
+    use crate::parser::Parser;
+
+    fn run(source: &str, backend: Backend) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            backend,
+            ..Default::default()
+        });
+        match interpreter.execute(&ast) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("error: {}", error.report()),
+        }
+    }
+
+    /// Run on both backends, check they agree and return the shared result
+    fn run_both(source: &str) -> String {
+        let expected = run(source, Backend::TreeWalker);
+        assert_eq!(run(source, Backend::Bytecode), expected, "{}", source);
+        expected
+    }
+
+    #[test]
+    fn test_backends_agree_on_core_language() {
+        let programs = [
+            ("5 -> add <- 3 <- 2", "10"),
+            (
+                "x = 4\ny = x * 2 + 1\n[x, y, y > x, x == 4]",
+                "[4, 9, true, true]",
+            ),
+            (
+                "p = {name: \"Ada\", tags: [1, 2]}\n[p.name, p.tags -> length]",
+                "[Ada, 2]",
+            ),
+            (
+                "square(x) {\n return <- x * x\n}\nf = square\n[4 -> f, 4 -> add <- 1, square(3)]",
+                "[16, 5, 9]",
+            ),
+            (
+                "makeAdder(n) {\n return <- (x) => { x -> add <- n }\n}\n\
+                 add5 = 5 -> makeAdder\nn = 100\n3 -> add5",
+                "8",
+            ),
+            (
+                "double = (x) => { x -> multiply <- 2 }\n\
+                 [[1, 2, 3] -> map <- double, [1, 2, 3, 4] -> reduce <- (acc, x) => acc + x <- 10]",
+                "[[2, 4, 6], 20]",
+            ),
+            (
+                "fact(n) {\n n -> i n <= 1 {\n  return <- 1\n }\n \
+                 return <- n * fact(n - 1)\n}\n10 -> fact",
+                "3628800",
+            ),
+            (
+                "missing -> print",
+                "error: Undefined variable: missing at line 1, column 1",
+            ),
+            ("square(x) {\n return <- x * x\n}\nsquare(1, 2)", ""),
+        ];
+
+        for (source, expected) in programs {
+            let result = run_both(source);
+            if !expected.is_empty() {
+                assert_eq!(result, expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_operators() {
+        let programs = [
+            ("1 + 2 * 3 - 4 / 2", "5"),
+            (
+                "[2 ** 3 ** 2, -2 ** 2, (-2) ** 2, 2 ** -1]",
+                "[512, -4, 4, 0.5]",
+            ),
+            ("[17 % 5, -17 % 5, 7.5 % 2, 10 - 4 - 3]", "[2, -2, 1.5, 3]"),
+            (
+                "x = 5
+[x > 3 && x < 10, x < 3 || x == 5, !(x == 5), !0, -x]",
+                "[true, true, false, true, -5]",
+            ),
+            ("1 < 2 == 3 < 4 && !false", "true"),
+            (
+                "[false && missing, true || missing, null || \"set\" == \"set\"]",
+                "[false, true, true]",
+            ),
+            (
+                "true && missing",
+                "error: Undefined variable: missing at line 1, column 9",
+            ),
+            (
+                "5 % 0",
+                "error: Runtime error: Modulo by zero at line 1, column 1",
+            ),
+            ("-\"text\"", ""),
+            ("(-9223372036854775807 - 1) / -1", "9223372036854776000"),
+        ];
+
+        for (source, expected) in programs {
+            let result = run_both(source);
+            if !expected.is_empty() {
+                assert_eq!(result, expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_power_operator_and_builtin_agree() {
+        let programs = [
+            ("[2 ** 10, 2 -> power <- 10]", "[1024, 1024]"),
+            (
+                "[2 ** 100, 2 -> power <- 100]",
+                "[1267650600228229400000000000000, 1267650600228229400000000000000]",
+            ),
+            ("[2 ** -2, 2 -> power <- -2]", "[0.25, 0.25]"),
+            (
+                "x = 3 -> power <- 39\n[3 ** 39 == x, x]",
+                "[true, 4052555153018976267]",
+            ),
+        ];
+
+        for (source, expected) in programs {
+            assert_eq!(run_both(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_access() {
+        let programs = [
+            (
+                "xs = [10, 20, 30, 40]\n[xs[0], xs[-1], xs[1:3], xs[:-3], xs[2:], xs[0] + xs[1]]",
+                "[10, 40, [20, 30], [10], [30, 40], 30]",
+            ),
+            (
+                "o = {name: \"Ada\", \"a key\": {n: 1}}\nk = \"a key\"\n[o[k].n, o.name[0], \"hello\"[-4:-1], o.missing]",
+                "[1, A, ell, null]",
+            ),
+            (
+                "o = {a: {b: null}}\nn = null\n[o?.a?.b?.c, n?.x?.y, n?[0], o.a?[\"b\"], n?[1:]]",
+                "[null, null, null, null, null]",
+            ),
+            ("xs = [1, 2]\nxs[2]", "error: Runtime error: Index 2 is out of range for array of length 2 at line 2, column 1"),
+            ("\"abc\"[1:5]", "error: Runtime error: Index 5 is out of range for string of length 3 at line 1, column 1"),
+            ("[1, 2][\"a\"]", "error: Type error: expected integer index, found string at line 1, column 1"),
+            ("x = 5\nx.name", "error: Runtime error: Cannot read property 'name' of number at line 2, column 1"),
+            ("n = null\nn[0]", "error: Runtime error: Cannot index into null at line 2, column 1"),
+        ];
+
+        for (source, expected) in programs {
+            assert_eq!(run_both(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_strings() {
+        let programs = [
+            (
+                "user = {name: \"Ada\", langs: [\"en\", \"fr\"]}\n\
+                 \"{user.name} speaks {user.langs -> length}: {user.langs[0]}, {user.langs[1]}\"",
+                "Ada speaks 2: en, fr",
+            ),
+            (
+                "n = 2\n\"{n} * {n} = {n * n}, {\"nested {n + 1}\"}, {{k: n}.k}, \\{n\\}\"",
+                "2 * 2 = 4, nested 3, 2, {n}",
+            ),
+            (
+                "greet(name) {\n return <- \"\"\"\n  Hello {name},\n    welcome\n  \"\"\"\n}\n\"Bo\" -> greet",
+                "Hello Bo,\n  welcome",
+            ),
+            (r#"r"\d+{x}" + "\t\u{41}""#, "\\d+{x}\tA"),
+            ("\"{missing}\"", "error: Undefined variable: missing at line 1, column 3"),
+        ];
+
+        for (source, expected) in programs {
+            assert_eq!(run_both(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_destructuring() {
+        let programs = [
+            ("(a, (b, _)) = [1, [2, 3]]\na + b", "3"),
+            (
+                "user = {name: \"Ada\", langs: [\"en\"]}\n{name, langs: (first)} = user\n\
+                 \"{name}: {first}\"",
+                "Ada: en",
+            ),
+            (
+                "swap((a, b)) {\n return <- [b, a]\n}\n[1, 2] -> swap",
+                "[2, 1]",
+            ),
+            ("[[1, 2], [3, 4]] -> map <- ((x, y)) => x * y", "[2, 12]"),
+            (
+                "fe (key, value) in entries({a: 1, b: 2}) { \"{key}={value}\" }",
+                "[a=1, b=2]",
+            ),
+            ("fe {id} in [{id: 7}, {id: 8}] { id * 2 }", "[14, 16]"),
+            (
+                "(a, b) = [1, 2, 3]",
+                "error: Runtime error: Cannot destructure array of length 3 into a tuple of 2 at line 1, column 1",
+            ),
+            (
+                "{name, age} = {name: \"Ada\"}",
+                "error: Runtime error: Cannot destructure object without field 'age' at line 1, column 1",
+            ),
+            (
+                "fe (k, v) in [[1, 2], 3] { k }",
+                "error: Runtime error: Cannot destructure number into a tuple of 2, expected an array at line 1, column 1",
+            ),
+            (
+                "swap((a, b)) {\n return <- [b, a]\n}\n\n5 -> swap",
+                "error: Runtime error: Cannot destructure number into a tuple of 2, expected an array \
+                 at line 1, column 1\nStack trace (most recent call first):\n    \
+                 in swap called at line 5, column 6, arrow step 1",
+            ),
+        ];
+
+        for (source, expected) in programs {
+            assert_eq!(run_both(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_user_functions_shadow_builtins() {
+        let source = "find(items, key) {\n return <- \"mine: \" + key\n}\n\
+                      any(x) {\n return <- x * 2\n}\n\
+                      [[1, 2] -> find <- \"k\", 3 -> any, [1, 2] -> map <- any]";
+        assert_eq!(run_both(source), "[mine: k, 6, [2, 4]]");
+    }
+
+    #[test]
+    fn test_backends_agree_on_stack_traces() {
+        let programs = [
+            "inner(x) {\n return <- x -> add <- missing\n}\n\
+             outer(x) {\n return <- x -> add <- 1 -> inner\n}\n5 -> outer",
+            "check(x) {\n x -> i x > 1 {\n  error <- \"too big\"\n }\n return <- x\n}\n\
+             [1, 2] -> map <- (x) => { x -> check }",
+            "nested(x) {\n return <- add(x, missing)\n}\nwrap(x) {\n return <- nested(x)\n}\nwrap(1)",
+        ];
+
+        for source in programs {
+            let result = run_both(source);
+            assert!(result.contains("Stack trace"), "{}", result);
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion_report_stays_short() {
+        let programs = [
+            (
+                "down(n) {\n n -> i n == 0 {\n  return <- missing\n }\n return <- (n - 1) -> down\n}\n\
+                 500 -> down",
+                "\n    ... previous frame repeated 499 times\n",
+            ),
+            (
+                "ping(n) {\n n -> i n == 0 {\n  return <- missing\n }\n return <- (n - 1) -> pong\n}\n\
+                 pong(n) {\n return <- n -> ping\n}\n500 -> ping",
+                "\n    ... 981 more frames\n",
+            ),
+        ];
+
+        for (source, elided) in programs {
+            let result = run_both(source);
+            assert!(result.lines().count() < 30, "{}", result);
+            assert!(result.contains(elided), "{}", result);
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_control_flow() {
+        let programs = [
+            ("fe x in [1, 2, 3] {\n x * 10\n}", "[10, 20, 30]"),
+            (
+                "firstBig(items) {\n fe x in items {\n  x -> i x > 2 {\n   return <- x\n  }\n }\n \
+                 return <- 0\n}\n[[1, 5, 3] -> firstBig, [1] -> firstBig]",
+                "[5, 0]",
+            ),
+            ("n = 0\nw n < 5 {\n n = n + 1\n}\nn", "5"),
+            (
+                "classify(n) {\n n -> i n > 10 {\n  return <- \"big\"\n } ei n > 5 {\n  return <- \"medium\"\n } e {\n  return <- \"small\"\n }\n}\n\
+                 [12 -> classify, 7 -> classify, 1 -> classify]",
+                "[big, medium, small]",
+            ),
+            (
+                "describe(v) {\n v -> match {\n  1 -> {\n   \"one\"\n  }\n  _ -> {\n   v * 2\n  }\n }\n}\n\
+                 [1 -> describe, 4 -> describe]",
+                "[one, 8]",
+            ),
+            (
+                "check(x) {\n x -> i x > 0 {\n  success <- x\n } e {\n  error <- \"negative\"\n }\n}\n\
+                 [5 -> check, -1 -> check]",
+                "",
+            ),
+        ];
+
+        for (source, expected) in programs {
+            let result = run_both(source);
+            if !expected.is_empty() {
+                assert_eq!(result, expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_loop_control() {
+        let programs = [
+            ("fe n in 0..6 {\n i n % 2 == 0 {\n  continue\n }\n n\n}", "[1, 3, 5]"),
+            ("fe n in 1..10 {\n i n > 3 {\n  break\n }\n n * n\n}", "[1, 4, 9]"),
+            ("fe n in [4, 7, 9] {\n i n % 7 == 0 {\n  break <- n\n }\n}", "7"),
+            (
+                "fe n in 0..4 {\n i n == 2 {\n  continue <- \"two\"\n }\n n\n}",
+                "[0, 1, two, 3]",
+            ),
+            (
+                "outer: fe a in 1..4 {\n fe b in 1..4 {\n  i a * b == 6 {\n   break outer <- [a, b]\n  }\n }\n}",
+                "[2, 3]",
+            ),
+            (
+                "outer: fe a in 0..3 {\n fe b in 0..3 {\n  i b > a {\n   continue outer <- a * 10\n  }\n  b\n }\n}",
+                "[0, 10, [0, 1, 2]]",
+            ),
+            ("n = 0\nw true {\n n = n + 1\n i n == 5 {\n  break\n }\n}\nn", "5"),
+            ("n = 0\nw true {\n n = n + 1\n i n * n > 50 {\n  break <- n\n }\n}", "8"),
+            (
+                "n = 0\ntotal = 0\nw n < 6 {\n n = n + 1\n i n % 2 == 1 {\n  continue\n }\n total = total + n\n}\ntotal",
+                "12",
+            ),
+            // `|` defaults let loop control through
+            ("fe n in 0..5 {\n (i n == 2 { break }) | -1\n n\n}", "[0, 1]"),
+            // Annotated expressions run in the tree-walker inside compiled loops
+            (
+                "fe n in 0..5 {\n @trace <- \"stop\"\n i n == 3 {\n  break <- n\n }\n n\n}",
+                "3",
+            ),
+            ("n = 3\n[0..n + 1, 3..1]", "[[0, 1, 2, 3], []]"),
+            (
+                "fe n in 0..2.5 {\n n\n}",
+                "error: Type error: expected integer range bound, found number at line 1, column 1",
+            ),
+        ];
+
+        for (source, expected) in programs {
+            assert_eq!(run_both(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_backends_enforce_limits() {
+        let limits = ExecutionLimits {
+            max_steps: Some(5000),
+            timeout: None,
+            max_call_depth: Some(20),
+            max_collection_size: Some(3),
+        };
+        let programs = [
+            (
+                "down(n) {\n return <- (n - 1) -> down\n}\n100 -> down",
+                "function calls nested deeper than 20",
+            ),
+            ("w true {\n 1\n}", "more than 5000 evaluation steps"),
+            ("0..1000000000", "collection larger than 3 elements"),
+            // Loops stop as soon as their results outgrow the limit
+            (
+                "fe k in 0..1000000000 {\n k\n}",
+                "collection larger than 3 elements",
+            ),
+            (
+                "[1, 2] -> flatMap <- ((x) => [x, x])",
+                "collection larger than 3 elements",
+            ),
+            (
+                "[\"a\", \"b\"] -> join_array <- \"--\"",
+                "collection larger than 3 elements",
+            ),
+            // `|` defaults do not catch exhausted limits
+            ("x = [1, 2, 3, 4] | []", "collection larger than 3 elements"),
+        ];
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            for (source, expected) in programs {
+                let tokens = Lexer::new(source).tokenize().unwrap();
+                let ast = Parser::new(tokens).parse().unwrap();
+                let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                    backend,
+                    limits,
+                    ..Default::default()
+                });
+                let error = interpreter.execute(&ast).unwrap_err();
+                assert!(
+                    error.to_string().contains(expected),
+                    "{:?} {}: {}",
+                    backend,
+                    source,
+                    error
+                );
+            }
+
+            let ast = Parser::new(Lexer::new("[1, 2, 3] -> first").tokenize().unwrap())
+                .parse()
+                .unwrap();
+            let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                backend,
+                limits: ExecutionLimits {
+                    timeout: Some(std::time::Duration::ZERO),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            // The clock is only read every few hundred steps, so short programs finish
+            assert!(interpreter.execute(&ast).is_ok());
+            let ast = Parser::new(Lexer::new("w true {\n 1\n}").tokenize().unwrap())
+                .parse()
+                .unwrap();
+            assert!(matches!(
+                interpreter.execute(&ast),
+                Err(SusumuError::LimitExceeded {
+                    limit: Limit::Timeout(_),
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unbounded_recursion_stops_within_the_stack_budget() {
+        let sources = [
+            "down(n) {\n return <- (n + 1) -> down\n}\n0 -> down",
+            "deep(n) {\n return <- [n, deep(n + 1)]\n}\ndeep(0)",
+        ];
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            for source in sources {
+                let tokens = Lexer::new(source).tokenize().unwrap();
+                let ast = Parser::new(tokens).parse().unwrap();
+                let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                    backend,
+                    ..Default::default()
+                });
+                let error = interpreter.execute(&ast).unwrap_err();
+                assert!(
+                    matches!(
+                        error,
+                        SusumuError::LimitExceeded {
+                            limit: Limit::CallDepth(depth),
+                            ..
+                        } if depth > 1000
+                    ),
+                    "{:?} {}: {}",
+                    backend,
+                    source,
+                    error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_on_examples() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "susu") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                let Ok(tokens) = Lexer::new(&source).tokenize() else {
+                    continue;
+                };
+                if Parser::new(tokens).parse().is_ok() {
+                    run_both(&source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_locals_use_slots() {
+        let tokens = Lexer::new("f(a) {\n b = a + 1\n return <- b\n}")
+            .tokenize()
+            .unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let chunk = crate::bytecode::Compiler::compile_function(&ast.functions[0]);
+
+        assert_eq!(chunk.slot_count(), 2);
+        assert!(!chunk.function_env);
+        assert!(chunk.disassemble().contains("LoadLocal"));
+    }
+}
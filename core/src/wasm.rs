@@ -3,8 +3,9 @@
 //! This module provides JavaScript bindings for running Susumu code in the browser
 //! with full visual debugging and performance monitoring capabilities.
 
-use crate::{Interpreter, Lexer, Parser};
+use crate::{ExecutionLimits, Interpreter, Lexer, Parser, StackFrame, SusumuError, Value};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
 
 // Import the `console.log` function from the browser
@@ -41,10 +42,44 @@ pub struct ExecutionResult {
     pub success: bool,
     pub result: String,
     pub error: Option<String>,
+    /// Susumu calls the error unwound through, innermost first
+    pub stack_trace: Vec<JsStackFrame>,
     pub execution_time_ms: f64,
     pub debug_info: Option<DebugInfo>,
 }
 
+impl ExecutionResult {
+    fn failure(error: &SusumuError, execution_time_ms: f64) -> Self {
+        ExecutionResult {
+            success: false,
+            result: "null".to_string(),
+            error: Some(error.to_string()),
+            stack_trace: error.stack_trace().iter().map(JsStackFrame::from).collect(),
+            execution_time_ms,
+            debug_info: None,
+        }
+    }
+}
+
+/// One Susumu call in an error's stack trace, located by its call site
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsStackFrame {
+    pub function: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<&StackFrame> for JsStackFrame {
+    fn from(frame: &StackFrame) -> Self {
+        JsStackFrame {
+            function: frame.function.clone(),
+            line: frame.call_site.span.line,
+            column: frame.call_site.span.column,
+        }
+    }
+}
+
 /// Visual debugging information for the browser
 #[derive(Serialize, Deserialize, Clone)]
 #[wasm_bindgen(getter_with_clone)]
@@ -100,6 +135,7 @@ impl SusumuEngine {
                     success: true,
                     result: serde_json::to_string(&result).unwrap_or_else(|_| "null".to_string()),
                     error: None,
+                    stack_trace: Vec::new(),
                     execution_time_ms: execution_time,
                     debug_info: None,
                 };
@@ -109,13 +145,7 @@ impl SusumuEngine {
             Err(e) => {
                 let execution_time = js_sys::Date::now() - start_time;
 
-                let exec_result = ExecutionResult {
-                    success: false,
-                    result: "null".to_string(),
-                    error: Some(e.to_string()),
-                    execution_time_ms: execution_time,
-                    debug_info: None,
-                };
+                let exec_result = ExecutionResult::failure(&e, execution_time);
 
                 console_error!("Susumu execution error: {}", e);
                 serde_wasm_bindgen::to_value(&exec_result).unwrap_or(JsValue::NULL)
@@ -146,6 +176,7 @@ impl SusumuEngine {
                     success: true,
                     result: serde_json::to_string(&result).unwrap_or_else(|_| "null".to_string()),
                     error: None,
+                    stack_trace: Vec::new(),
                     execution_time_ms: execution_time,
                     debug_info: Some(debug_info),
                 };
@@ -156,13 +187,7 @@ impl SusumuEngine {
             Err(e) => {
                 let execution_time = js_sys::Date::now() - start_time;
 
-                let exec_result = ExecutionResult {
-                    success: false,
-                    result: "null".to_string(),
-                    error: Some(e.to_string()),
-                    execution_time_ms: execution_time,
-                    debug_info: None,
-                };
+                let exec_result = ExecutionResult::failure(&e, execution_time);
 
                 console_error!("❌ Susumu execution error: {}", e);
                 serde_wasm_bindgen::to_value(&exec_result).unwrap_or(JsValue::NULL)
@@ -196,34 +221,50 @@ impl SusumuEngine {
         serde_wasm_bindgen::to_value(&builtins).unwrap_or(JsValue::NULL)
     }
 
-    /// Check syntax without executing
+    /// Check syntax without executing, reporting every syntax error found
     #[wasm_bindgen]
     pub fn check_syntax(&self, source: &str) -> JsValue {
-        match Lexer::new(source).tokenize() {
-            Ok(tokens) => match Parser::new(tokens).parse() {
-                Ok(_) => {
-                    let result = serde_json::json!({
-                        "valid": true,
-                        "error": null
-                    });
-                    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
-                }
-                Err(e) => {
-                    let result = serde_json::json!({
-                        "valid": false,
-                        "error": e.to_string()
-                    });
-                    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
-                }
-            },
-            Err(e) => {
-                let result = serde_json::json!({
-                    "valid": false,
-                    "error": e.to_string()
-                });
-                serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
-            }
-        }
+        let errors = match crate::parse(source) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors,
+        };
+        let result = serde_json::json!({
+            "valid": errors.is_empty(),
+            "error": errors.first().map(|e| e.to_string()),
+            "errors": errors
+                .iter()
+                .map(|e| {
+                    let span = e.span().unwrap_or_default();
+                    serde_json::json!({
+                        "message": e.to_string(),
+                        "line": span.line,
+                        "column": span.column,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        });
+        serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
+    }
+
+    /// Limit later executions of untrusted code; leave an argument `undefined` for no
+    /// limit. An execution that exceeds a limit fails with an "Execution limit exceeded"
+    /// error instead of hanging the page.
+    #[wasm_bindgen]
+    pub fn set_limits(
+        &mut self,
+        max_steps: Option<f64>,
+        timeout_ms: Option<f64>,
+        max_call_depth: Option<u32>,
+        max_collection_size: Option<u32>,
+    ) {
+        self.interpreter.set_limits(ExecutionLimits {
+            max_steps: max_steps.map(|steps| steps as u64),
+            // `Infinity` and timeouts too large for a `Duration` mean no timeout
+            timeout: timeout_ms
+                .and_then(|ms| Duration::try_from_secs_f64(ms.max(0.0) / 1000.0).ok()),
+            max_call_depth: max_call_depth.map(|depth| depth as usize),
+            max_collection_size: max_collection_size.map(|size| size as usize),
+        });
     }
 
     /// Get version information
@@ -234,10 +275,7 @@ impl SusumuEngine {
 }
 
 impl SusumuEngine {
-    fn execute_internal(
-        &mut self,
-        source: &str,
-    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    fn execute_internal(&mut self, source: &str) -> Result<Value, SusumuError> {
         let tokens = Lexer::new(source).tokenize()?;
         let ast = Parser::new(tokens).parse()?;
         let result = self.interpreter.execute(&ast)?;
@@ -369,3 +407,27 @@ pub fn main() {
     console_log!("🌐 Susumu WASM module loaded successfully!");
     console_log!("📚 Use SusumuEngine.new() to create an interpreter instance");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_exposes_stack_frames() {
+        let source = "inner(x) {\n return <- x -> missing\n}\nouter(x) {\n return <- x -> inner\n}\n1 -> outer";
+        let error = crate::execute(source).unwrap_err();
+        let result = ExecutionResult::failure(&error, 0.0);
+
+        assert!(!result.success);
+        let functions: Vec<_> = result
+            .stack_trace
+            .iter()
+            .map(|f| f.function.as_str())
+            .collect();
+        assert_eq!(functions, ["inner", "outer"]);
+        assert_eq!(
+            (result.stack_trace[0].line, result.stack_trace[0].column),
+            (5, 17)
+        );
+    }
+}
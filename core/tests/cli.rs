@@ -0,0 +1,35 @@
+//! End-to-end tests of the `susumu` binary
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn susumu(args: &[&str], stdin: &str) -> Output {
+    let history = std::env::temp_dir().join(format!("susumu_cli_test_{}", std::process::id()));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_susumu"))
+        .args(args)
+        .env("SUSUMU_HISTORY", &history)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start susumu");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_file(history);
+    output
+}
+
+#[test]
+fn test_repl_exit_sets_process_status() {
+    let output = susumu(&["repl"], "x = 1\nexit(3)\n\"unreached\" -> print\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("unreached"));
+
+    assert_eq!(susumu(&["repl"], ":quit\n").status.code(), Some(0));
+    assert_eq!(susumu(&["repl"], "1 + 1\n").status.code(), Some(0));
+}
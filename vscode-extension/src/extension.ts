@@ -18,6 +18,9 @@ export function activate(context: vscode.ExtensionContext) {
     // Start the language server
     startLanguageServer(context);
 
+    // Launch the debug adapter for Susumu debug sessions
+    registerDebugAdapter(context);
+
     // Register commands
     registerCommands(context);
 
@@ -112,6 +115,20 @@ function startLanguageServer(context: vscode.ExtensionContext) {
     });
 }
 
+function registerDebugAdapter(context: vscode.ExtensionContext) {
+    const factory: vscode.DebugAdapterDescriptorFactory = {
+        createDebugAdapterDescriptor() {
+            // Prefer the bundled adapter, like the language server
+            const bundledAdapterPath = context.asAbsolutePath('bin/susumu-dap');
+            const adapterPath = fs.existsSync(bundledAdapterPath)
+                ? bundledAdapterPath
+                : vscode.workspace.getConfiguration('susumu').get<string>('dap.adapterPath', 'susumu-dap');
+            return new vscode.DebugAdapterExecutable(adapterPath);
+        }
+    };
+    context.subscriptions.push(vscode.debug.registerDebugAdapterDescriptorFactory('susumu', factory));
+}
+
 function registerCommands(context: vscode.ExtensionContext) {
     // Show AST command
     const showASTCommand = vscode.commands.registerCommand('susumu.showAST', async () => {